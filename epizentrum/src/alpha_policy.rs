@@ -0,0 +1,109 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource};
+
+/// Frames [AlphaPolicy::skipped_pixels] looks at before it gives up
+pub const SKIPPED_PIXELS_FRAMES: usize = 1000;
+
+/// How pixels that are not fully opaque are sent
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AlphaPolicy {
+    /// Leaves out pixels with an alpha below the threshold, others keep their alpha
+    Skip { threshold: u8 },
+    /// Sends every pixel without alpha
+    Opaque,
+    /// Sends the alpha of every pixel that is not fully opaque
+    PassThrough,
+}
+
+impl Default for AlphaPolicy {
+    fn default() -> Self {
+        AlphaPolicy::Skip { threshold: 1 }
+    }
+}
+
+impl AlphaPolicy {
+    /// Whether a pixel with this alpha is left out
+    #[inline]
+    pub fn skips(&self, alpha: u8) -> bool {
+        matches!(self, AlphaPolicy::Skip { threshold } if alpha < *threshold)
+    }
+
+    /// Pixels skipped in every frame of one cycle of `source`, indexed row by row
+    ///
+    /// Processors leave these out up front, pixels that are only skipped in some
+    /// frames are left out per frame.
+    /// Requests every frame of the source once, sources that can not be
+    /// replayed (like streams) should not be passed in. Decoding or rendering
+    /// the frames is the cost, so it stops after [SKIPPED_PIXELS_FRAMES] frames
+    /// and skips nothing for longer cycles.
+    pub fn skipped_pixels<Src: FrameSource + ?Sized>(&self, source: &mut Src) -> Box<[bool]> {
+        let (w, h) = source.size();
+        let mut skipped = vec![matches!(self, AlphaPolicy::Skip { .. }); w as usize * h as usize];
+        let cycle_time = source.cycle_time();
+
+        let mut delta = Duration::ZERO;
+        for frames in 0.. {
+            if !skipped.contains(&true) {
+                break;
+            }
+            if frames == SKIPPED_PIXELS_FRAMES {
+                // the frames not looked at may show any pixel
+                skipped.fill(false);
+                break;
+            }
+
+            let timing = source.frame(delta);
+            let (Frame::Rgba(pixels) | Frame::Bgra(pixels)) = timing.frame;
+            for (skipped, pixel) in skipped.iter_mut().zip(pixels.iter()) {
+                *skipped &= self.skips(pixel[3]);
+            }
+
+            let end = delta + timing.time_left;
+            if end >= cycle_time {
+                break;
+            }
+            delta = end + Duration::from_nanos(1);
+        }
+
+        skipped.into_boxed_slice()
+    }
+}
+
+impl Display for AlphaPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlphaPolicy::Skip { threshold: 1 } => f.write_str("skip"),
+            AlphaPolicy::Skip { threshold } => write!(f, "skip,{threshold}"),
+            AlphaPolicy::Opaque => f.write_str("opaque"),
+            AlphaPolicy::PassThrough => f.write_str("pass"),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("invalid alpha policy: {0}")]
+    Invalid(String),
+}
+
+impl FromStr for AlphaPolicy {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(',') {
+            None if s == "skip" => AlphaPolicy::Skip { threshold: 1 },
+            None if s == "opaque" => AlphaPolicy::Opaque,
+            None if s == "pass" => AlphaPolicy::PassThrough,
+            Some(("skip", threshold)) => match u8::from_str(threshold) {
+                Ok(threshold) => AlphaPolicy::Skip { threshold },
+                Err(_) => return Err(ParseError::Invalid(s.into())),
+            },
+            _ => return Err(ParseError::Invalid(s.into())),
+        })
+    }
+}
@@ -0,0 +1,197 @@
+use std::num::NonZeroU64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AssignmentPolicy {
+    /// Every connection moves on to the next source after each completed write
+    RoundRobin,
+    /// Every connection sticks to one source, connections are split by weight
+    Dedicated,
+    /// Sources are picked proportionally to their weight after each completed write
+    Weighted,
+    /// Sources are picked proportionally to their visible pixels after each completed write
+    Pixels,
+}
+
+/// Scheduling weights of a command buffer source
+#[derive(Debug, Copy, Clone)]
+pub struct SourceWeights {
+    /// Pixels of the source visible on the canvas
+    pub pixels: NonZeroU64,
+    /// Explicitly configured weight
+    pub weight: NonZeroU64,
+    /// Policy of this source, instead of the one given for all sources
+    pub policy: Option<AssignmentPolicy>,
+}
+
+/// Decides which command buffer source a connection writes next
+///
+/// Policies can differ between sources. Dedicated sources get connections of their own, all other
+/// sources share the remaining connections and are picked after every write. For splitting
+/// connections each shared source counts with weight 1. Shared sources move on round-robin if
+/// all of them are [AssignmentPolicy::RoundRobin], otherwise they are picked by weight, where
+/// round-robin sources count with weight 1.
+#[derive(Debug)]
+pub struct Assignment {
+    policies: Box<[AssignmentPolicy]>,
+    /// Sources that are picked after every write and their weights
+    shared: Box<[(usize, NonZeroU64)]>,
+    /// Whether all shared sources are round-robin
+    cycling: bool,
+    shared_credits: Box<[i128]>,
+    /// Dedicated sources and the shared ones as a whole (`None`) with their share of connections
+    groups: Box<[(Option<usize>, NonZeroU64)]>,
+    group_credits: Box<[i128]>,
+}
+
+impl Assignment {
+    /// Same policy for all sources, `weights` are what the policy weighs
+    pub fn new(policy: AssignmentPolicy, weights: Box<[NonZeroU64]>) -> Self {
+        Self::mixed(weights.iter().map(|&weight| (policy, weight)))
+    }
+
+    pub fn round_robin(sources: usize) -> Self {
+        Self::new(
+            AssignmentPolicy::RoundRobin,
+            vec![NonZeroU64::MIN; sources].into_boxed_slice(),
+        )
+    }
+
+    /// Policy of every source from its weights, `default` applies to sources without one
+    pub fn from_weights(default: AssignmentPolicy, sources: &[SourceWeights]) -> Self {
+        Self::mixed(sources.iter().map(|source| {
+            let policy = source.policy.unwrap_or(default);
+            let weight = match policy {
+                AssignmentPolicy::RoundRobin => NonZeroU64::MIN,
+                AssignmentPolicy::Dedicated | AssignmentPolicy::Weighted => source.weight,
+                AssignmentPolicy::Pixels => source.pixels,
+            };
+            (policy, weight)
+        }))
+    }
+
+    /// Policy and the weight it uses of every source
+    pub fn mixed(sources: impl IntoIterator<Item = (AssignmentPolicy, NonZeroU64)>) -> Self {
+        let sources = sources.into_iter().collect::<Vec<_>>();
+        let cycling = sources.iter().all(|(policy, _)| {
+            matches!(
+                policy,
+                AssignmentPolicy::RoundRobin | AssignmentPolicy::Dedicated
+            )
+        });
+
+        let shared = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, (policy, _))| *policy != AssignmentPolicy::Dedicated)
+            .map(|(index, &(policy, weight))| match (policy, cycling) {
+                (AssignmentPolicy::RoundRobin, false) => (index, NonZeroU64::MIN),
+                _ => (index, weight),
+            })
+            .collect::<Box<[_]>>();
+
+        let mut groups = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, (policy, _))| *policy == AssignmentPolicy::Dedicated)
+            .map(|(index, &(_, weight))| (Some(index), weight))
+            .collect::<Vec<_>>();
+        if let Some(count) = NonZeroU64::new(shared.len() as u64) {
+            groups.push((None, count));
+        }
+
+        Self {
+            policies: sources.iter().map(|(policy, _)| *policy).collect(),
+            shared_credits: vec![0; shared.len()].into_boxed_slice(),
+            shared,
+            cycling,
+            group_credits: vec![0; groups.len()].into_boxed_slice(),
+            groups: groups.into_boxed_slice(),
+        }
+    }
+
+    pub fn sources(&self) -> usize {
+        self.policies.len()
+    }
+
+    /// Connections it takes to draw every source, one per dedicated source and one for the rest
+    pub fn groups(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Source for the first write of a freshly opened connection
+    pub fn first(&mut self, connection_id: usize) -> usize {
+        let group = match self.groups.len() {
+            1 => 0,
+            _ => pick(&mut self.group_credits, self.groups.iter().map(|g| g.1)),
+        };
+
+        match self.groups[group].0 {
+            Some(source) => source,
+            None if self.cycling => self.shared[connection_id % self.shared.len()].0,
+            None => self.pick_shared(),
+        }
+    }
+
+    /// Source for the write following a completed write of `source_index`
+    pub fn next(&mut self, source_index: usize) -> usize {
+        if self.policies[source_index] == AssignmentPolicy::Dedicated {
+            source_index
+        } else if self.cycling {
+            let position = self
+                .shared
+                .iter()
+                .position(|(index, _)| *index == source_index)
+                .expect("shared source");
+            self.shared[(position + 1) % self.shared.len()].0
+        } else {
+            self.pick_shared()
+        }
+    }
+
+    /// Sources a connection writing `source_index` may write instead, starting with it
+    ///
+    /// Dedicated sources keep their connections to themselves, shared sources follow in the
+    /// order round-robin visits them.
+    pub fn candidates(&self, source_index: usize) -> Vec<usize> {
+        if self.policies[source_index] == AssignmentPolicy::Dedicated {
+            return vec![source_index];
+        }
+
+        let position = self
+            .shared
+            .iter()
+            .position(|(index, _)| *index == source_index)
+            .expect("shared source");
+        self.shared[position..]
+            .iter()
+            .chain(&self.shared[..position])
+            .map(|(index, _)| *index)
+            .collect()
+    }
+
+    fn pick_shared(&mut self) -> usize {
+        let index = pick(
+            &mut self.shared_credits,
+            self.shared.iter().map(|(_, weight)| *weight),
+        );
+        self.shared[index].0
+    }
+}
+
+// smooth weighted round-robin, spreads picks of heavy entries evenly over time
+fn pick(credits: &mut [i128], weights: impl Iterator<Item = NonZeroU64>) -> usize {
+    let mut total = 0;
+    for (credit, weight) in credits.iter_mut().zip(weights) {
+        *credit += weight.get() as i128;
+        total += weight.get() as i128;
+    }
+
+    let (index, credit) = credits
+        .iter_mut()
+        .enumerate()
+        .max_by(|(a_idx, a), (b_idx, b)| a.cmp(b).then(b_idx.cmp(a_idx)))
+        .expect("at least one source");
+    *credit -= total;
+
+    index
+}
@@ -5,7 +5,7 @@ use rand::prelude::SliceRandom;
 use rand::thread_rng;
 use thiserror::Error;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum DrawStrategy {
     Random,
     Rows { reversed: bool },
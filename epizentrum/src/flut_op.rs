@@ -1,10 +1,10 @@
 use std::cmp::min;
+use std::error::Error;
 use std::fmt::{Debug, Formatter};
 use std::iter::zip;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, TcpStream};
 use std::num::NonZeroUsize;
 use std::os::fd::AsRawFd;
-use std::rc::Rc;
 use std::time::{Duration, Instant};
 
 use os_socketaddr::OsSocketAddr;
@@ -15,8 +15,19 @@ use rummelplatz::{io_uring, ControlFlow, RingOperation, SubmissionQueueSubmitter
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
 use tracing::{debug, error, info, warn};
 
+use crate::assignment::Assignment;
 use crate::breadth_flatten::BreadthFlatten;
-use crate::{CommandBufferSource, ControlFlowError, ControlFlowWarn, SetupError, TeardownError};
+use crate::frame_source::STILL_TIME;
+use crate::{
+    CommandBuffer, CommandBufferSource, ControlFlowError, ControlFlowWarn, SetupError,
+    TeardownError,
+};
+
+pub mod epoll;
+pub mod sink;
+
+/// Shortest wait for a source to have commands again
+const MIN_WAIT: Duration = Duration::from_millis(1);
 
 pub struct DebugShield<T>(pub T);
 
@@ -44,6 +55,15 @@ impl<T> DebugShield<T> {
     }
 }
 
+/// What a connection sends next
+#[derive(Debug)]
+enum NextBuffer {
+    /// Commands and the source whose turn is after them
+    Ready(CommandBuffer, usize),
+    /// No source has commands right now, how long to wait and whose turn it is then
+    Wait(Duration, usize),
+}
+
 #[derive(Debug)]
 pub struct FlutOp {
     reuse_connections: Vec<TcpStream>,
@@ -57,6 +77,7 @@ pub struct FlutOp {
 
     time_anchor: Instant,
     command_buffer_sources: Box<[Box<dyn CommandBufferSource>]>,
+    assignment: Assignment,
 }
 
 impl Drop for FlutOp {
@@ -77,6 +98,7 @@ impl FlutOp {
         reconnect_limit: Option<usize>,
         reuse_connections: Vec<TcpStream>,
         time_anchor: Instant,
+        assignment: Assignment,
     ) -> Self {
         Self {
             reuse_connections,
@@ -88,42 +110,11 @@ impl FlutOp {
             connections: 0,
             time_anchor,
             command_buffer_sources,
+            assignment,
         }
     }
-}
-
-#[derive(Debug)]
-pub enum FlutOpData {
-    ConnectionEstablished {
-        connection_id: usize,
-        socket: Socket,
-        addr: OsSocketAddr,
-        source_index: usize,
-        last_buffer: Option<(DebugShield<Rc<[u8]>>, usize)>,
-    },
-    Reconnecting {
-        connection_id: usize,
-        socket: Socket,
-        addr: OsSocketAddr,
-        source_index: usize,
-        backoff: Duration,
-        backoff_timespec: Timespec,
-        reconnects: usize,
-    },
-    Backoff(Entry, Box<FlutOpData>),
-}
-
-impl RingOperation for FlutOp {
-    type RingData = FlutOpData;
-    type SetupError = SetupError;
-    type TeardownError = TeardownError;
-    type ControlFlowWarn = ControlFlowWarn;
-    type ControlFlowError = ControlFlowError;
 
-    fn setup<W: Fn(&mut Entry, Self::RingData)>(
-        &mut self,
-        mut submitter: SubmissionQueueSubmitter<Self::RingData, W>,
-    ) -> Result<(), Self::SetupError> {
+    fn open_connections(&mut self) -> Vec<TcpStream> {
         let open_connections = self.reuse_connections.len();
         let local_interfaces = match &self.local_interfaces {
             Some(local_interfaces) if local_interfaces.len() > 0 => local_interfaces.clone(),
@@ -198,26 +189,188 @@ impl RingOperation for FlutOp {
             Some(limit) => connections.take(limit.get()).collect(),
         };
         info!("opened {} connections", connections.len());
+        if connections.len() < self.assignment.groups() {
+            warn!(
+                "{} connections for {} dedicated connection groups, some sources will not be drawn",
+                connections.len(),
+                self.assignment.groups()
+            );
+        }
+
+        connections
+    }
+
+    /// Command buffer for a connection whose turn is at `source_index`
+    ///
+    /// Sources without commands, e.g. fully transparent frames or objects off the canvas, are
+    /// skipped. Writing 0 bytes looks just like a closed connection. Weighted picks can land on
+    /// the same empty source again and again, so every other source the connection may write
+    /// gets a turn before it waits.
+    fn next_buffer(
+        &mut self,
+        source_index: usize,
+    ) -> Result<NextBuffer, Box<dyn Error + Send + Sync>> {
+        let delta = self.time_anchor.elapsed();
+        let mut wait = STILL_TIME;
+
+        for index in self.assignment.candidates(source_index) {
+            let buffer = self.command_buffer_sources[index].command_buffer(delta)?;
+            if !buffer.frame.is_empty() {
+                return Ok(NextBuffer::Ready(buffer.frame, self.assignment.next(index)));
+            }
+            wait = wait.min(buffer.time_left);
+        }
+
+        Ok(NextBuffer::Wait(
+            wait.max(MIN_WAIT),
+            self.assignment.next(source_index),
+        ))
+    }
+
+    /// Writes the next command buffer of a connection or waits until there is one
+    fn next_submission(
+        &mut self,
+        connection_id: usize,
+        socket: Socket,
+        addr: OsSocketAddr,
+        source_index: usize,
+    ) -> Result<(Entry, FlutOpData), Box<dyn Error + Send + Sync>> {
+        match self.next_buffer(source_index)? {
+            NextBuffer::Ready(buffer, source_index) => {
+                let socket_write = opcode::Write::new(
+                    Fd(socket.as_raw_fd()),
+                    buffer.as_ptr(),
+                    buffer.len() as u32,
+                )
+                .build();
+                Ok((
+                    socket_write,
+                    FlutOpData::ConnectionEstablished {
+                        connection_id,
+                        socket,
+                        addr,
+                        source_index,
+                        last_buffer: Some((buffer.into(), 0)),
+                    },
+                ))
+            }
+            NextBuffer::Wait(wait, source_index) => {
+                debug!("connection {connection_id} has nothing to draw for {wait:?}");
+                let timespec = Box::new(Timespec::from(wait));
+                let timeout = opcode::Timeout::new(&*timespec).build();
+                Ok((
+                    timeout,
+                    FlutOpData::Waiting {
+                        connection_id,
+                        socket,
+                        addr,
+                        source_index,
+                        timespec,
+                    },
+                ))
+            }
+        }
+    }
+
+    fn submit_next<W: Fn(&mut Entry, FlutOpData)>(
+        &mut self,
+        connection_id: usize,
+        socket: Socket,
+        addr: OsSocketAddr,
+        source_index: usize,
+        mut submitter: SubmissionQueueSubmitter<FlutOpData, W>,
+    ) -> (
+        ControlFlow<ControlFlowWarn, ControlFlowError>,
+        Option<FlutOpData>,
+    ) {
+        match self.next_submission(connection_id, socket, addr, source_index) {
+            Ok((entry, data)) => match submitter.push(entry, data) {
+                Ok(()) => (ControlFlow::Continue, None),
+                Err(e) => (ControlFlow::Error(ControlFlowError::SqeSubmission(e)), None),
+            },
+            Err(e) => (ControlFlow::Error(ControlFlowError::Any(e)), None),
+        }
+    }
+
+    /// Whether a connection is given up after `reconnects` failed reconnect attempts
+    fn gives_up(&self, reconnects: usize) -> bool {
+        matches!(self.reconnect_limit, Some(limit) if reconnects >= limit)
+    }
+
+    fn backoff(&self, previous: Option<Duration>) -> Duration {
+        let backoff = match previous {
+            None => Duration::from_secs(1),
+            Some(backoff) => backoff * 2,
+        };
+
+        match self.reconnect_backoff_limit {
+            None => backoff,
+            Some(limit) => min(backoff, limit),
+        }
+    }
+
+    /// Returns true if no connection is left
+    fn connection_died(&mut self, connection_id: usize) -> bool {
+        error!("connection {connection_id} died");
+        self.connections -= 1;
+
+        if self.connections == 0 {
+            error!("all connections died, exiting..");
+            return true;
+        }
+
+        false
+    }
+}
+
+#[derive(Debug)]
+pub enum FlutOpData {
+    ConnectionEstablished {
+        connection_id: usize,
+        socket: Socket,
+        addr: OsSocketAddr,
+        source_index: usize,
+        last_buffer: Option<(DebugShield<CommandBuffer>, usize)>,
+    },
+    Reconnecting {
+        connection_id: usize,
+        socket: Socket,
+        addr: OsSocketAddr,
+        source_index: usize,
+        backoff: Duration,
+        backoff_timespec: Timespec,
+        reconnects: usize,
+    },
+    /// Waiting for the next frame, no source had commands
+    Waiting {
+        connection_id: usize,
+        socket: Socket,
+        addr: OsSocketAddr,
+        source_index: usize,
+        /// boxed to keep its address while the timeout is in flight
+        timespec: Box<Timespec>,
+    },
+    Backoff(Entry, Box<FlutOpData>),
+}
+
+impl RingOperation for FlutOp {
+    type RingData = FlutOpData;
+    type SetupError = SetupError;
+    type TeardownError = TeardownError;
+    type ControlFlowWarn = ControlFlowWarn;
+    type ControlFlowError = ControlFlowError;
+
+    fn setup<W: Fn(&mut Entry, Self::RingData)>(
+        &mut self,
+        mut submitter: SubmissionQueueSubmitter<Self::RingData, W>,
+    ) -> Result<(), Self::SetupError> {
+        let connections = self.open_connections();
 
         for (i, c) in connections.into_iter().enumerate() {
-            let buffer = self.command_buffer_sources[i % self.command_buffer_sources.len()]
-                .command_buffer(self.time_anchor.elapsed())?;
-            let socket_write = opcode::Write::new(
-                Fd(c.as_raw_fd()),
-                buffer.frame.as_ptr(),
-                buffer.frame.len() as u32,
-            )
-            .build();
-            submitter.push(
-                socket_write,
-                FlutOpData::ConnectionEstablished {
-                    connection_id: i,
-                    addr: c.peer_addr().unwrap().into(),
-                    socket: c.into(),
-                    source_index: (i + 1) % self.command_buffer_sources.len(),
-                    last_buffer: Some((buffer.frame.into(), 0)),
-                },
-            )?;
+            let source_index = self.assignment.first(i);
+            let addr = c.peer_addr().unwrap().into();
+            let (entry, data) = self.next_submission(i, c.into(), addr, source_index)?;
+            submitter.push(entry, data)?;
             self.connections += 1;
         }
 
@@ -253,18 +406,14 @@ impl RingOperation for FlutOp {
                         warn!("connection {connection_id} failed: {e}");
                     }
 
-                    if let Some(limit) = self.reconnect_limit {
-                        if limit == 0 {
-                            error!("connection {connection_id} died");
-                            self.connections -= 1;
-
-                            if self.connections == 0 {
-                                error!("all connections died, exiting..",);
-                                return (ControlFlow::Exit, None);
-                            }
-                        }
+                    if self.gives_up(0) {
+                        return if self.connection_died(connection_id) {
+                            (ControlFlow::Exit, None)
+                        } else {
+                            (ControlFlow::Continue, None)
+                        };
                     }
-                    let backoff = Duration::from_secs(1);
+                    let backoff = self.backoff(None);
                     let backoff_timespec = Timespec::from(backoff);
 
                     info!(
@@ -319,31 +468,7 @@ impl RingOperation for FlutOp {
                 (n, Some((last_buffer, written)))
                     if written + n as usize == last_buffer.0.len() =>
                 {
-                    let buffer = match self.command_buffer_sources[source_index]
-                        .command_buffer(self.time_anchor.elapsed())
-                    {
-                        Ok(buffer) => buffer,
-                        Err(e) => return (ControlFlow::Error(ControlFlowError::Any(e)), None),
-                    };
-                    let socket_write = opcode::Write::new(
-                        Fd(socket.as_raw_fd()),
-                        buffer.frame.as_ptr(),
-                        buffer.frame.len() as u32,
-                    )
-                    .build();
-                    match submitter.push(
-                        socket_write,
-                        FlutOpData::ConnectionEstablished {
-                            connection_id,
-                            socket,
-                            addr,
-                            source_index: (source_index + 1) % self.command_buffer_sources.len(),
-                            last_buffer: Some((buffer.frame.into(), 0)),
-                        },
-                    ) {
-                        Ok(()) => (ControlFlow::Continue, None),
-                        Err(e) => (ControlFlow::Error(ControlFlowError::SqeSubmission(e)), None),
-                    }
+                    self.submit_next(connection_id, socket, addr, source_index, submitter)
                 }
                 (n, Some((last_buffer, written))) if n > 0 => {
                     let socket_write = opcode::Write::new(
@@ -383,22 +508,15 @@ impl RingOperation for FlutOp {
                         debug!("connection {connection_id} reconnect failed: {e}");
                     }
 
-                    if let Some(limit) = self.reconnect_limit {
-                        if limit >= reconnects {
-                            error!("connection {connection_id} died");
-                            self.connections -= 1;
-
-                            if self.connections == 0 {
-                                error!("all connections died, exiting..",);
-                                return (ControlFlow::Exit, None);
-                            }
-                        }
+                    if self.gives_up(reconnects) {
+                        return if self.connection_died(connection_id) {
+                            (ControlFlow::Exit, None)
+                        } else {
+                            (ControlFlow::Continue, None)
+                        };
                     }
 
-                    let backoff = match self.reconnect_backoff_limit {
-                        None => backoff * 2,
-                        Some(limit) => min(backoff * 2, limit),
-                    };
+                    let backoff = self.backoff(Some(backoff));
                     let backoff_timespec = Timespec::from(backoff);
 
                     info!(
@@ -434,34 +552,17 @@ impl RingOperation for FlutOp {
                 0 => {
                     info!("connection {connection_id} reconnected");
 
-                    let buffer = match self.command_buffer_sources[source_index]
-                        .command_buffer(self.time_anchor.elapsed())
-                    {
-                        Ok(buffer) => buffer,
-                        Err(e) => return (ControlFlow::Error(ControlFlowError::Any(e)), None),
-                    };
-                    let socket_write = opcode::Write::new(
-                        Fd(socket.as_raw_fd()),
-                        buffer.frame.as_ptr(),
-                        buffer.frame.len() as u32,
-                    )
-                    .build();
-                    match submitter.push(
-                        socket_write,
-                        FlutOpData::ConnectionEstablished {
-                            connection_id,
-                            addr,
-                            socket,
-                            source_index: (source_index + 1) % self.command_buffer_sources.len(),
-                            last_buffer: Some((buffer.frame.into(), 0)),
-                        },
-                    ) {
-                        Ok(()) => (ControlFlow::Continue, None),
-                        Err(e) => (ControlFlow::Error(ControlFlowError::SqeSubmission(e)), None),
-                    }
+                    self.submit_next(connection_id, socket, addr, source_index, submitter)
                 }
                 _ => unreachable!(),
             },
+            FlutOpData::Waiting {
+                connection_id,
+                socket,
+                addr,
+                source_index,
+                ..
+            } => self.submit_next(connection_id, socket, addr, source_index, submitter),
             FlutOpData::Backoff(entry, data) => match submitter.push(entry, *data) {
                 Ok(()) => (ControlFlow::Continue, None),
                 Err(e) => (ControlFlow::Error(ControlFlowError::SqeSubmission(e)), None),
@@ -478,6 +579,7 @@ impl RingOperation for FlutOp {
         match ring_data {
             FlutOpData::ConnectionEstablished { .. } => self.connections -= 1,
             FlutOpData::Reconnecting { .. } => self.connections -= 1,
+            FlutOpData::Waiting { .. } => self.connections -= 1,
             FlutOpData::Backoff(_, _) => self.connections -= 1,
         }
 
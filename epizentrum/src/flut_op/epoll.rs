@@ -0,0 +1,413 @@
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use tracing::{debug, error, info, warn};
+
+use crate::flut_op::{FlutOp, NextBuffer};
+use crate::{CommandBuffer, ControlFlowError};
+
+const MAX_EVENTS: usize = 128;
+
+#[derive(Debug)]
+enum Connection {
+    Established {
+        socket: Socket,
+        addr: SocketAddr,
+        source_index: usize,
+        buffer: CommandBuffer,
+        written: usize,
+    },
+    Reconnecting {
+        socket: Socket,
+        addr: SocketAddr,
+        source_index: usize,
+        backoff: Duration,
+        reconnects: usize,
+    },
+    /// No source has commands, not registered until `until`
+    Waiting {
+        until: Instant,
+        socket: Socket,
+        addr: SocketAddr,
+        source_index: usize,
+    },
+    Backoff {
+        until: Instant,
+        socket: Socket,
+        addr: SocketAddr,
+        source_index: usize,
+        backoff: Duration,
+        reconnects: usize,
+    },
+}
+
+/// Portable alternative to the io_uring ring for systems without io_uring support
+///
+/// Drives the connections of a [FlutOp] with non-blocking sockets and epoll.
+#[derive(Debug)]
+pub struct EpollLoop {
+    flut_op: FlutOp,
+    epoll: OwnedFd,
+    connections: Vec<Option<Connection>>,
+}
+
+impl EpollLoop {
+    pub fn new(flut_op: FlutOp) -> std::io::Result<Self> {
+        let epoll = match unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) } {
+            -1 => return Err(std::io::Error::last_os_error()),
+            fd => unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+
+        Ok(Self {
+            flut_op,
+            epoll,
+            connections: vec![],
+        })
+    }
+
+    pub fn run(&mut self) -> Result<(), ControlFlowError> {
+        let connections = self.flut_op.open_connections();
+
+        for (i, c) in connections.into_iter().enumerate() {
+            c.set_nonblocking(true)?;
+
+            let source_index = self.flut_op.assignment.first(i);
+            let addr = c.peer_addr()?;
+            self.register(c.as_raw_fd(), i)?;
+            let connection = self.established(i, c.into(), addr, source_index)?;
+            self.connections.push(Some(connection));
+            self.flut_op.connections += 1;
+        }
+
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        while self.flut_op.connections > 0 {
+            let timeout = match self.next_deadline() {
+                None => -1,
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_millis()
+                    .saturating_add(1)
+                    .min(i32::MAX as u128) as i32,
+            };
+
+            let n = match unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as i32,
+                    timeout,
+                )
+            } {
+                -1 => match std::io::Error::last_os_error() {
+                    e if e.kind() == ErrorKind::Interrupted => continue,
+                    e => return Err(e.into()),
+                },
+                n => n as usize,
+            };
+
+            for event in &events[..n] {
+                let connection_id = event.u64 as usize;
+                if let Some(connection) = self.connections[connection_id].take() {
+                    self.connections[connection_id] = self.on_ready(connection_id, connection)?;
+                }
+            }
+
+            let now = Instant::now();
+            for connection_id in 0..self.connections.len() {
+                match &self.connections[connection_id] {
+                    Some(Connection::Backoff { until, .. }) if *until <= now => {
+                        let connection = self.connections[connection_id].take().unwrap();
+                        self.connections[connection_id] =
+                            self.on_backoff_elapsed(connection_id, connection)?;
+                    }
+                    Some(Connection::Waiting { until, .. }) if *until <= now => {
+                        let connection = self.connections[connection_id].take().unwrap();
+                        self.connections[connection_id] =
+                            self.on_wait_elapsed(connection_id, connection)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_ready(
+        &mut self,
+        connection_id: usize,
+        connection: Connection,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        match connection {
+            Connection::Established {
+                socket,
+                addr,
+                source_index,
+                buffer,
+                written,
+            } => match socket.send(&buffer[written..]) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(Some(Connection::Established {
+                    socket,
+                    addr,
+                    source_index,
+                    buffer,
+                    written,
+                })),
+                Ok(0) => {
+                    warn!(
+                        "connection {connection_id} {} -> {addr} closed",
+                        socket.local_addr()?.as_socket().unwrap(),
+                    );
+                    self.on_connection_lost(connection_id, addr, source_index)
+                }
+                Err(e) => {
+                    warn!("connection {connection_id} failed: {e}");
+                    self.on_connection_lost(connection_id, addr, source_index)
+                }
+                Ok(n) if written + n == buffer.len() => self
+                    .established(connection_id, socket, addr, source_index)
+                    .map(Some),
+                Ok(n) => Ok(Some(Connection::Established {
+                    socket,
+                    addr,
+                    source_index,
+                    buffer,
+                    written: written + n,
+                })),
+            },
+            Connection::Reconnecting {
+                socket,
+                addr,
+                source_index,
+                backoff,
+                reconnects,
+            } => match socket.take_error() {
+                Ok(None) => {
+                    info!("connection {connection_id} reconnected");
+                    self.established(connection_id, socket, addr, source_index)
+                        .map(Some)
+                }
+                Ok(Some(e)) | Err(e) => {
+                    debug!("connection {connection_id} reconnect failed: {e}");
+                    self.on_reconnect_failed(connection_id, addr, source_index, backoff, reconnects)
+                }
+            },
+            Connection::Waiting { .. } | Connection::Backoff { .. } => Ok(Some(connection)),
+        }
+    }
+
+    /// Sends the next command buffer on a registered socket or waits until there is one
+    fn established(
+        &mut self,
+        connection_id: usize,
+        socket: Socket,
+        addr: SocketAddr,
+        source_index: usize,
+    ) -> Result<Connection, ControlFlowError> {
+        match self.flut_op.next_buffer(source_index)? {
+            NextBuffer::Ready(buffer, source_index) => Ok(Connection::Established {
+                socket,
+                addr,
+                source_index,
+                buffer,
+                written: 0,
+            }),
+            NextBuffer::Wait(wait, source_index) => {
+                debug!("connection {connection_id} has nothing to draw for {wait:?}");
+                // a writable socket would wake up epoll all the time
+                self.unregister(socket.as_raw_fd())?;
+                Ok(Connection::Waiting {
+                    until: Instant::now() + wait,
+                    socket,
+                    addr,
+                    source_index,
+                })
+            }
+        }
+    }
+
+    fn on_wait_elapsed(
+        &mut self,
+        connection_id: usize,
+        connection: Connection,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        let Connection::Waiting {
+            socket,
+            addr,
+            source_index,
+            ..
+        } = connection
+        else {
+            return Ok(Some(connection));
+        };
+
+        self.register(socket.as_raw_fd(), connection_id)?;
+        self.established(connection_id, socket, addr, source_index)
+            .map(Some)
+    }
+
+    fn on_connection_lost(
+        &mut self,
+        connection_id: usize,
+        addr: SocketAddr,
+        source_index: usize,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        if self.flut_op.gives_up(0) {
+            return self.on_connection_died(connection_id);
+        }
+
+        let backoff = self.flut_op.backoff(None);
+        info!(
+            "connection -> {addr} reconnecting in {} seconds",
+            backoff.as_secs()
+        );
+
+        Ok(Some(Connection::Backoff {
+            until: Instant::now() + backoff,
+            socket: new_socket(&addr)?,
+            addr,
+            source_index,
+            backoff,
+            reconnects: 1,
+        }))
+    }
+
+    fn on_reconnect_failed(
+        &mut self,
+        connection_id: usize,
+        addr: SocketAddr,
+        source_index: usize,
+        backoff: Duration,
+        reconnects: usize,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        if self.flut_op.gives_up(reconnects) {
+            return self.on_connection_died(connection_id);
+        }
+
+        let backoff = self.flut_op.backoff(Some(backoff));
+        info!(
+            "connection {connection_id} reconnecting in {} seconds",
+            backoff.as_secs()
+        );
+
+        Ok(Some(Connection::Backoff {
+            until: Instant::now() + backoff,
+            socket: new_socket(&addr)?,
+            addr,
+            source_index,
+            backoff,
+            reconnects: reconnects + 1,
+        }))
+    }
+
+    fn on_connection_died(
+        &mut self,
+        connection_id: usize,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        self.flut_op.connection_died(connection_id);
+        Ok(None)
+    }
+
+    fn on_backoff_elapsed(
+        &mut self,
+        connection_id: usize,
+        connection: Connection,
+    ) -> Result<Option<Connection>, ControlFlowError> {
+        let Connection::Backoff {
+            socket,
+            addr,
+            source_index,
+            backoff,
+            reconnects,
+            ..
+        } = connection
+        else {
+            return Ok(Some(connection));
+        };
+
+        match socket.connect(&SockAddr::from(addr)) {
+            Err(e) if e.raw_os_error() != Some(libc::EINPROGRESS) => {
+                debug!("connection {connection_id} reconnect failed: {e}");
+                self.on_reconnect_failed(connection_id, addr, source_index, backoff, reconnects)
+            }
+            _ => {
+                self.register(socket.as_raw_fd(), connection_id)?;
+                Ok(Some(Connection::Reconnecting {
+                    socket,
+                    addr,
+                    source_index,
+                    backoff,
+                    reconnects,
+                }))
+            }
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.connections
+            .iter()
+            .filter_map(|c| match c {
+                Some(Connection::Backoff { until, .. } | Connection::Waiting { until, .. }) => {
+                    Some(*until)
+                }
+                _ => None,
+            })
+            .min()
+    }
+
+    fn register(&self, fd: RawFd, connection_id: usize) -> std::io::Result<()> {
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLOUT as u32,
+            u64: connection_id as u64,
+        };
+
+        match unsafe {
+            libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event)
+        } {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    fn unregister(&self, fd: RawFd) -> std::io::Result<()> {
+        match unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                fd,
+                std::ptr::null_mut(),
+            )
+        } {
+            -1 => Err(std::io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for EpollLoop {
+    fn drop(&mut self) {
+        self.flut_op.connections -= self.connections.drain(..).flatten().count();
+    }
+}
+
+fn new_socket(addr: &SocketAddr) -> Result<Socket, ControlFlowError> {
+    let socket = match addr {
+        SocketAddr::V4(_) => Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)),
+        SocketAddr::V6(_) => Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP)),
+    }
+    .and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+
+    match socket {
+        Ok(socket) => Ok(socket),
+        Err(e) => {
+            error!("unable to create a new socket to reconnect: {e:?}");
+            Err(ControlFlowError::Io(e))
+        }
+    }
+}
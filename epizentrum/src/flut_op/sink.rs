@@ -0,0 +1,73 @@
+use std::io::{ErrorKind, Write};
+use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::flut_op::FlutOp;
+use crate::ControlFlowError;
+
+/// Writes the command buffers of a [FlutOp] to a file or pipe instead of network targets
+///
+/// Buffers are written in the order a single connection would send them.
+/// A cycle is one command buffer per source.
+#[derive(Debug)]
+pub struct SinkLoop<W: Write> {
+    flut_op: FlutOp,
+    output: W,
+    cycles: Option<NonZeroUsize>,
+    duration: Option<Duration>,
+}
+
+impl<W: Write> SinkLoop<W> {
+    pub fn new(
+        flut_op: FlutOp,
+        output: W,
+        cycles: Option<NonZeroUsize>,
+        duration: Option<Duration>,
+    ) -> Self {
+        Self {
+            flut_op,
+            output,
+            cycles,
+            duration,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), ControlFlowError> {
+        let sources = self.flut_op.assignment.sources();
+        if self.flut_op.assignment.groups() > 1 {
+            warn!("output has a single connection, sources with dedicated connections starve the others");
+        }
+
+        let started = Instant::now();
+        let mut source_index = self.flut_op.assignment.first(0);
+        let mut writes = 0usize;
+        loop {
+            if matches!(self.cycles, Some(cycles) if writes >= cycles.get() * sources) {
+                info!("wrote {} cycles", writes / sources);
+                break;
+            }
+            if matches!(self.duration, Some(duration) if started.elapsed() >= duration) {
+                info!("wrote {writes} command buffers");
+                break;
+            }
+
+            let buffer = self.flut_op.command_buffer_sources[source_index]
+                .command_buffer(self.flut_op.time_anchor.elapsed())?;
+            match self.output.write_all(&buffer.frame) {
+                Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                    info!("output closed");
+                    return Ok(());
+                }
+                r => r?,
+            }
+
+            source_index = self.flut_op.assignment.next(source_index);
+            writes += 1;
+        }
+
+        self.output.flush()?;
+        Ok(())
+    }
+}
@@ -10,13 +10,17 @@ use krnl::macros::module;
 use thiserror::Error;
 use tracing::error;
 
+use crate::alpha_policy::AlphaPolicy;
 use crate::draw_strategy::DrawStrategy;
 use crate::flut_op::DebugShield;
 use crate::frame_processing::FrameProcessor;
 use crate::frame_source::Frame;
 
-const COMMAND_BUFFER_COLOR_LENGTH: usize = 8;
+/// Room for the longest command, `PX 65535 65535 rrggbbaa\n`
+const LINE_LENGTH: usize = 24;
 
+// offset, canvas, frame layout and alpha threshold are push constants
+#[allow(clippy::too_many_arguments)]
 #[module]
 mod kernels {
     // The device crate will be linked to krnl-core.
@@ -24,65 +28,101 @@ mod kernels {
     use krnl::krnl_core;
     use krnl_core::macros::kernel;
 
+    /// Writes the command of the `global_id`th pixel to draw into a line of 24 bytes
+    ///
+    /// The offset is a push constant, moving objects need no new buffers. Pixels outside of the
+    /// canvas and pixels with an alpha below `threshold` get a length of 0.
     #[kernel]
-    pub fn fill_rbga(
+    pub fn fill(
+        offset_x: i32,
+        offset_y: i32,
+        canvas_width: u32,
+        canvas_height: u32,
+        width: u32,
+        bgra: u32,
+        threshold: u32,
         #[global] color: Slice<u8>,
-        #[global] command_buffer: UnsafeSlice<u8>,
-        #[global] color_idx: Slice<u32>,
+        #[global] draw_order: Slice<u32>,
         #[global] digit_lookup: Slice<u8>,
+        #[global] commands: UnsafeSlice<u8>,
+        #[global] lengths: UnsafeSlice<u32>,
     ) {
         use krnl_core::buffer::UnsafeIndex;
 
         let idx = kernel.global_id as usize;
-        let color_idx = color_idx[idx] as usize;
+        let pixel = draw_order[idx];
+        let x = (pixel % width) as i32 + offset_x;
+        let y = (pixel / width) as i32 + offset_y;
+        if x < 0 || y < 0 || x as u32 >= canvas_width || y as u32 >= canvas_height {
+            unsafe {
+                *lengths.unsafe_index_mut(idx) = 0;
+            }
+            return;
+        }
 
-        let [r, g, b, a] = [
-            color[(4 * idx) + 0],
-            color[(4 * idx) + 1],
-            color[(4 * idx) + 2],
-            color[(4 * idx) + 3],
+        let pixel = pixel as usize;
+        let [c0, g, c2, a] = [
+            color[(4 * pixel) + 0],
+            color[(4 * pixel) + 1],
+            color[(4 * pixel) + 2],
+            color[(4 * pixel) + 3],
         ];
+        if (a as u32) < threshold {
+            unsafe {
+                *lengths.unsafe_index_mut(idx) = 0;
+            }
+            return;
+        }
+        let (r, b) = if bgra == 0 { (c0, c2) } else { (c2, c0) };
 
+        // LINE_LENGTH, the host constants are not part of the device crate
+        let start = idx * 24;
+        let mut at = start;
         unsafe {
-            *command_buffer.unsafe_index_mut(color_idx + 0) = digit_lookup[(r >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 1) = digit_lookup[(r & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 2) = digit_lookup[(g >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 3) = digit_lookup[(g & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 4) = digit_lookup[(b >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 5) = digit_lookup[(b & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 6) = digit_lookup[(a >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 7) = digit_lookup[(a & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 0) = b'P';
+            *commands.unsafe_index_mut(at + 1) = b'X';
+            *commands.unsafe_index_mut(at + 2) = b' ';
         }
-    }
+        at += 3;
 
-    #[kernel]
-    pub fn fill_bgra(
-        #[global] color: Slice<u8>,
-        #[global] command_buffer: UnsafeSlice<u8>,
-        #[global] color_idx: Slice<u32>,
-        #[global] digit_lookup: Slice<u8>,
-    ) {
-        use krnl_core::buffer::UnsafeIndex;
-
-        let idx = kernel.global_id as usize;
-        let color_idx = color_idx[idx] as usize;
+        // both coordinates in decimal, each followed by a space
+        let mut coordinate = 0;
+        while coordinate < 2 {
+            let value = if coordinate == 0 { x as u32 } else { y as u32 };
+            let mut digits = 1;
+            let mut rest = value / 10;
+            while rest > 0 {
+                digits += 1;
+                rest /= 10;
+            }
 
-        let [b, g, r, a] = [
-            color[(4 * idx) + 0],
-            color[(4 * idx) + 1],
-            color[(4 * idx) + 2],
-            color[(4 * idx) + 3],
-        ];
+            let mut rest = value;
+            let mut digit = digits;
+            while digit > 0 {
+                digit -= 1;
+                unsafe {
+                    *commands.unsafe_index_mut(at + digit) = b'0' + (rest % 10) as u8;
+                }
+                rest /= 10;
+            }
+            unsafe {
+                *commands.unsafe_index_mut(at + digits) = b' ';
+            }
+            at += digits + 1;
+            coordinate += 1;
+        }
 
         unsafe {
-            *command_buffer.unsafe_index_mut(color_idx + 0) = digit_lookup[(r >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 1) = digit_lookup[(r & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 2) = digit_lookup[(g >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 3) = digit_lookup[(g & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 4) = digit_lookup[(b >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 5) = digit_lookup[(b & 0xf) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 6) = digit_lookup[(a >> 4) as usize];
-            *command_buffer.unsafe_index_mut(color_idx + 7) = digit_lookup[(a & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 0) = digit_lookup[(r >> 4) as usize];
+            *commands.unsafe_index_mut(at + 1) = digit_lookup[(r & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 2) = digit_lookup[(g >> 4) as usize];
+            *commands.unsafe_index_mut(at + 3) = digit_lookup[(g & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 4) = digit_lookup[(b >> 4) as usize];
+            *commands.unsafe_index_mut(at + 5) = digit_lookup[(b & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 6) = digit_lookup[(a >> 4) as usize];
+            *commands.unsafe_index_mut(at + 7) = digit_lookup[(a & 0xf) as usize];
+            *commands.unsafe_index_mut(at + 8) = b'\n';
+            *lengths.unsafe_index_mut(idx) = (at + 9 - start) as u32;
         }
     }
 }
@@ -108,11 +148,14 @@ pub enum GpuProcessorError {
 #[derive(Debug)]
 pub struct GpuProcessor {
     device: Device,
-    template: Buffer<u8>,
-    color_idx: Buffer<u32>,
+    size: (u16, u16),
+    canvas_size: (u16, u16),
+    /// Indices of the pixels in draw order, without skipped ones, uploaded once for all offsets
+    draw_order: Buffer<u32>,
+    pixels: usize,
     digit_lookup: Buffer<u8>,
-    rgba_kernel: DebugShield<kernels::fill_rbga::Kernel>,
-    bgra_kernel: DebugShield<kernels::fill_bgra::Kernel>,
+    kernel: DebugShield<kernels::fill::Kernel>,
+    alpha: AlphaPolicy,
 }
 
 impl GpuProcessor {
@@ -137,73 +180,81 @@ impl GpuProcessor {
     pub fn new(
         device_index: usize,
         size: (u16, u16),
-        offset: (u16, u16),
         canvas_size: (u16, u16),
         draw_strategy: DrawStrategy,
+        alpha: AlphaPolicy,
+        skipped: Option<&[bool]>,
     ) -> Result<Self, GpuProcessorError> {
         let device = Device::builder()
             .index(device_index)
             .build()
             .map_err(GpuProcessorError::Setup)?;
 
-        // out of canvas pixel will be written to index 0, later we will ignore the first bytes
-        let mut command_buffer_template: Vec<u8> = vec![0u8; COMMAND_BUFFER_COLOR_LENGTH];
-        let mut color_idx: Vec<u32> = vec![0; size.0 as usize * size.1 as usize];
-
-        let draw_order = draw_strategy.draw_order(size);
-        for &(x, y) in &draw_order {
-            if x + offset.0 >= canvas_size.0 || y + offset.1 >= canvas_size.1 {
-                continue;
-            }
-
-            command_buffer_template
-                .append(&mut format!("PX {} {} ", x + offset.0, y + offset.1).into_bytes());
-            color_idx[(y as usize * size.0 as usize) + x as usize] =
-                command_buffer_template.len() as u32;
-            command_buffer_template.append(&mut vec![b'X'; COMMAND_BUFFER_COLOR_LENGTH]);
-            command_buffer_template.push(b'\n');
+        let mut draw_order = draw_strategy.draw_order(size);
+        if let Some(skipped) = skipped {
+            draw_order.retain(|&(x, y)| !skipped[(y as usize * size.0 as usize) + x as usize]);
         }
+        let pixels = draw_order.len();
+        let draw_order = Buffer::from_vec(
+            draw_order
+                .into_iter()
+                .map(|(x, y)| y as u32 * size.0 as u32 + x as u32)
+                .collect(),
+        )
+        .into_device(device.clone())
+        .map_err(GpuProcessorError::Upload)?;
 
-        let template = Buffer::from_vec(command_buffer_template)
-            .into_device(device.clone())
-            .map_err(GpuProcessorError::Upload)?;
-        let color_idx = Buffer::from_vec(color_idx)
-            .into_device(device.clone())
-            .map_err(GpuProcessorError::Upload)?;
         let digit_lookup = Buffer::from_vec(digit_lookup())
             .into_device(device.clone())
             .map_err(GpuProcessorError::Upload)?;
 
-        let rgba_kernel = kernels::fill_rbga::builder()
+        let kernel = kernels::fill::builder()
             .and_then(|b| b.build(device.clone()))
             .map_err(GpuProcessorError::Setup)?
-            .with_global_threads(draw_order.len() as u32)
-            .into();
-
-        let bgra_kernel = kernels::fill_bgra::builder()
-            .and_then(|b| b.build(device.clone()))
-            .map_err(GpuProcessorError::Setup)?
-            .with_global_threads(draw_order.len() as u32)
+            .with_global_threads(pixels as u32)
             .into();
 
         Ok(Self {
             device,
-            template,
-            color_idx,
+            size,
+            canvas_size,
+            draw_order,
+            pixels,
             digit_lookup,
-            rgba_kernel,
-            bgra_kernel,
+            kernel,
+            alpha,
         })
     }
 }
 
 impl FrameProcessor for GpuProcessor {
-    fn process(&self, frame: &Frame) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>> {
+    fn process(
+        &self,
+        frame: &Frame,
+        offset: (i32, i32),
+    ) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>> {
+        if self.pixels == 0 {
+            return Ok(Box::new([]));
+        }
+
         let buffer = match frame {
             Frame::Rgba(buffer) => buffer.as_ref(),
             Frame::Bgra(buffer) => buffer.as_ref(),
         };
 
+        // commands have room for the alpha digits of every pixel, opaque ones get ff
+        let opaque: Box<[[u8; 4]]>;
+        let buffer = match self.alpha {
+            AlphaPolicy::Opaque => {
+                opaque = buffer
+                    .iter()
+                    .map(|&[c0, c1, c2, _]| [c0, c1, c2, 255])
+                    .collect();
+                opaque.as_ref()
+            }
+            _ => buffer,
+        };
+
         let buffer = unsafe {
             &*slice_from_raw_parts(
                 buffer.as_ptr() as *const u8,
@@ -215,38 +266,47 @@ impl FrameProcessor for GpuProcessor {
             .into_device(self.device.clone())
             .map_err(GpuProcessorError::Upload)?;
 
-        let mut command_buffer = unsafe {
-            let mut buffer = Buffer::uninit(self.device.clone(), self.template.len())
-                .map_err(GpuProcessorError::Alloc)?;
-            buffer
-                .copy_from_slice(&self.template.as_slice())
-                .map_err(GpuProcessorError::Copy)?;
-            buffer
+        // every line is written by the kernel, up to its length
+        let (mut commands, mut lengths) = unsafe {
+            (
+                Buffer::uninit(self.device.clone(), self.pixels * LINE_LENGTH)
+                    .map_err(GpuProcessorError::Alloc)?,
+                Buffer::uninit(self.device.clone(), self.pixels)
+                    .map_err(GpuProcessorError::Alloc)?,
+            )
         };
 
-        match frame {
-            Frame::Rgba(_) => self.rgba_kernel.get().dispatch(
+        // beyond a canvas width or height off the canvas pixels stay off it, the kernel adds in i32
+        let limit = 1 << 16;
+        self.kernel
+            .get()
+            .dispatch(
+                offset.0.clamp(-limit, limit),
+                offset.1.clamp(-limit, limit),
+                self.canvas_size.0 as u32,
+                self.canvas_size.1 as u32,
+                self.size.0 as u32,
+                matches!(frame, Frame::Bgra(_)) as u32,
+                match self.alpha {
+                    AlphaPolicy::Skip { threshold } => threshold as u32,
+                    _ => 0,
+                },
                 buffer.as_slice(),
-                command_buffer.as_slice_mut(),
-                self.color_idx.as_slice(),
+                self.draw_order.as_slice(),
                 self.digit_lookup.as_slice(),
-            ),
-            Frame::Bgra(_) => self.bgra_kernel.get().dispatch(
-                buffer.as_slice(),
-                command_buffer.as_slice_mut(),
-                self.color_idx.as_slice(),
-                self.digit_lookup.as_slice(),
-            ),
-        }
-        .map_err(GpuProcessorError::Dispatch)?;
+                commands.as_slice_mut(),
+                lengths.as_slice_mut(),
+            )
+            .map_err(GpuProcessorError::Dispatch)?;
 
-        command_buffer
-            .slice(COMMAND_BUFFER_COLOR_LENGTH..)
-            .unwrap()
-            .into_vec()
-            .map(|v| v.into_boxed_slice())
-            .map_err(GpuProcessorError::Download)
-            .map_err(Box::from)
+        let commands: Vec<u8> = commands.into_vec().map_err(GpuProcessorError::Download)?;
+        let lengths: Vec<u32> = lengths.into_vec().map_err(GpuProcessorError::Download)?;
+        Ok(commands
+            .chunks_exact(LINE_LENGTH)
+            .zip(lengths)
+            .flat_map(|(line, length)| &line[..length as usize])
+            .copied()
+            .collect())
     }
 }
 
@@ -6,13 +6,23 @@ use crate::frame_source::Frame;
 pub mod gpu_processor;
 pub mod rayon_processor;
 
-pub trait FrameProcessor: Debug {
-    fn process(&self, frame: &Frame) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>>;
+pub trait FrameProcessor: Debug + Send {
+    /// Commands for the pixels of `frame` with its top left corner at `offset`,
+    /// pixels outside of the canvas are left out
+    fn process(
+        &self,
+        frame: &Frame,
+        offset: (i32, i32),
+    ) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>>;
 }
 
 impl<F: FrameProcessor + ?Sized> FrameProcessor for Box<F> {
     #[inline]
-    fn process(&self, frame: &Frame) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>> {
-        (**self).process(frame)
+    fn process(
+        &self,
+        frame: &Frame,
+        offset: (i32, i32),
+    ) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync>> {
+        (**self).process(frame, offset)
     }
 }
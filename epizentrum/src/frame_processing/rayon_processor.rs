@@ -2,6 +2,7 @@ use std::error::Error;
 
 use rayon::prelude::*;
 
+use crate::alpha_policy::AlphaPolicy;
 use crate::draw_strategy::DrawStrategy;
 use crate::frame_processing::FrameProcessor;
 use crate::frame_source::Frame;
@@ -10,67 +11,86 @@ use crate::frame_source::Frame;
 pub struct RayonProcessor {
     size: (u16, u16),
     draw_order: Box<[(u16, u16)]>,
-    offset: (u16, u16),
     canvas_size: (u16, u16),
+    alpha: AlphaPolicy,
 }
 
 impl RayonProcessor {
     pub fn new(
         size: (u16, u16),
-        offset: (u16, u16),
         canvas_size: (u16, u16),
         draw_strategy: DrawStrategy,
+        alpha: AlphaPolicy,
+        skipped: Option<&[bool]>,
     ) -> Self {
+        let mut draw_order = draw_strategy.draw_order(size);
+        if let Some(skipped) = skipped {
+            draw_order.retain(|&(x, y)| !skipped[(y as usize * size.0 as usize) + x as usize]);
+        }
+
         Self {
-            draw_order: draw_strategy.draw_order(size).into(),
+            draw_order: draw_order.into(),
             size,
-            offset,
             canvas_size,
+            alpha,
         }
     }
+
+    /// Canvas position of a pixel, if it is on the canvas
+    #[inline]
+    fn position(&self, (x, y): (u16, u16), offset: (i32, i32)) -> Option<(u16, u16)> {
+        // widened, offsets near the i32 limits must not overflow
+        let xx = u16::try_from(x as i64 + offset.0 as i64).ok()?;
+        let yy = u16::try_from(y as i64 + offset.1 as i64).ok()?;
+        (xx < self.canvas_size.0 && yy < self.canvas_size.1).then_some((xx, yy))
+    }
+
+    fn command(&self, (xx, yy): (u16, u16), [r, g, b, a]: [u8; 4]) -> Option<Vec<u8>> {
+        if self.alpha.skips(a) {
+            return None;
+        }
+
+        let a = match self.alpha {
+            AlphaPolicy::Opaque => 255,
+            _ => a,
+        };
+        Some(
+            match a {
+                255 if r == g && g == b => format!("PX {xx} {yy} {r:02x}\n"),
+                255 => format!("PX {xx} {yy} {r:02x}{g:02x}{b:02x}\n"),
+                a => format!("PX {xx} {yy} {r:02x}{g:02x}{b:02x}{a:02x}\n"),
+            }
+            .into_bytes(),
+        )
+    }
 }
 
 impl FrameProcessor for RayonProcessor {
-    fn process(&self, frame: &Frame) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync + 'static>> {
+    fn process(
+        &self,
+        frame: &Frame,
+        offset: (i32, i32),
+    ) -> Result<Box<[u8]>, Box<dyn Error + Send + Sync + 'static>> {
         Ok(match frame {
             Frame::Rgba(buffer) => self
                 .draw_order
                 .into_par_iter()
-                .filter_map(|(x, y)| {
-                    if *x >= self.canvas_size.0 || *y >= self.canvas_size.1 {
-                        return None;
-                    }
-
-                    let xx = x + self.offset.0;
-                    let yy = y + self.offset.1;
+                .filter_map(|&(x, y)| {
+                    let position = self.position((x, y), offset)?;
 
-                    Some(
-                        match buffer[(*y as usize * self.size.0 as usize) + *x as usize] {
-                            [r, g, b, 255] if r == g && g == b => format!("PX {xx} {yy} {r:02x}\n"),
-                            [r, g, b, 255] => format!("PX {xx} {yy} {r:02x}{g:02x}{b:02x}\n"),
-                            [r, g, b, a] => format!("PX {xx} {yy} {r:02x}{g:02x}{b:02x}{a:02x}\n"),
-                        }
-                        .into_bytes(),
-                    )
+                    let [r, g, b, a] = buffer[(y as usize * self.size.0 as usize) + x as usize];
+                    self.command(position, [r, g, b, a])
                 })
                 .flatten()
                 .collect(),
             Frame::Bgra(buffer) => self
                 .draw_order
                 .into_par_iter()
-                .filter_map(|(x, y)| {
-                    if *x >= self.canvas_size.0 || *y >= self.canvas_size.1 {
-                        return None;
-                    }
+                .filter_map(|&(x, y)| {
+                    let position = self.position((x, y), offset)?;
 
-                    Some(
-                        match buffer[(*y as usize * self.size.0 as usize) + *x as usize] {
-                            [b, g, r, 255] if r == g && g == b => format!("PX {x} {y} {r:02x}"),
-                            [b, g, r, 255] => format!("PX {x} {y} {r:02x}{g:02x}{b:02x}"),
-                            [b, g, r, a] => format!("PX {x} {y} {r:02x}{g:02x}{b:02x}{a:02x}"),
-                        }
-                        .into_bytes(),
-                    )
+                    let [b, g, r, a] = buffer[(y as usize * self.size.0 as usize) + x as usize];
+                    self.command(position, [r, g, b, a])
                 })
                 .flatten()
                 .collect(),
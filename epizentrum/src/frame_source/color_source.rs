@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+/// Luma weights of the hue rotation and saturation matrices in SVG filters
+const LUMA: [f32; 3] = [0.213, 0.715, 0.072];
+
+#[derive(Debug, Error)]
+pub enum ColorSourceError {
+    #[error("invalid {0}: {1}")]
+    Invalid(&'static str, f32),
+}
+
+/// Makes pixels close to a color fully transparent
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChromaKey {
+    pub color: [u8; 3],
+    /// Largest RGB distance to the color that is keyed out, 0 to 1
+    pub tolerance: f32,
+}
+
+/// Color changes applied in the order of the fields, the default changes nothing
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorAdjustment {
+    /// Decided on the unchanged colors
+    pub chroma_key: Option<ChromaKey>,
+    /// Hue rotation in degrees
+    pub hue: f32,
+    /// 0 is gray, 1 unchanged
+    pub saturation: f32,
+    pub grayscale: bool,
+    /// Added to every channel, -1 to 1
+    pub brightness: f32,
+    /// Stretches channels around mid gray, 1 unchanged
+    pub contrast: f32,
+    /// Exponent applied to normalized channels, 1 unchanged
+    pub gamma: f32,
+    pub invert: bool,
+}
+
+impl Default for ColorAdjustment {
+    fn default() -> Self {
+        Self {
+            chroma_key: None,
+            hue: 0.0,
+            saturation: 1.0,
+            grayscale: false,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            invert: false,
+        }
+    }
+}
+
+impl ColorAdjustment {
+    fn validate(&self) -> Result<(), ColorSourceError> {
+        let checks = [
+            ("hue", self.hue, self.hue.is_finite()),
+            (
+                "saturation",
+                self.saturation,
+                self.saturation.is_finite() && self.saturation >= 0.0,
+            ),
+            (
+                "brightness",
+                self.brightness,
+                (-1.0..=1.0).contains(&self.brightness),
+            ),
+            (
+                "contrast",
+                self.contrast,
+                self.contrast.is_finite() && self.contrast >= 0.0,
+            ),
+            (
+                "gamma",
+                self.gamma,
+                self.gamma.is_finite() && self.gamma > 0.0,
+            ),
+        ];
+        let key = self.chroma_key.map(|key| {
+            (
+                "chroma key tolerance",
+                key.tolerance,
+                (0.0..=1.0).contains(&key.tolerance),
+            )
+        });
+
+        match checks.into_iter().chain(key).find(|(_, _, valid)| !valid) {
+            Some((name, value, _)) => Err(ColorSourceError::Invalid(name, value)),
+            None => Ok(()),
+        }
+    }
+
+    /// Hue rotation and saturation in one matrix, keeping the luma of every color
+    fn matrix(&self) -> Option<[[f32; 3]; 3]> {
+        let saturation = if self.grayscale { 0.0 } else { self.saturation };
+        if self.hue % 360.0 == 0.0 && saturation == 1.0 {
+            return None;
+        }
+
+        let (sin, cos) = self.hue.to_radians().sin_cos();
+        let [lr, lg, lb] = LUMA;
+        let hue = [
+            [
+                lr + cos * (1.0 - lr) - sin * lr,
+                lg - cos * lg - sin * lg,
+                lb - cos * lb + sin * (1.0 - lb),
+            ],
+            [
+                lr - cos * lr + sin * 0.143,
+                lg + cos * (1.0 - lg) + sin * 0.140,
+                lb - cos * lb - sin * 0.283,
+            ],
+            [
+                lr - cos * lr - sin * (1.0 - lr),
+                lg - cos * lg + sin * lg,
+                lb + cos * (1.0 - lb) + sin * lb,
+            ],
+        ];
+
+        // mix every row with the luma weights
+        Some(hue.map(|row| {
+            let mut out = [0.0; 3];
+            for (i, out) in out.iter_mut().enumerate() {
+                *out = row[i] * saturation + LUMA[i] * (1.0 - saturation);
+            }
+            out
+        }))
+    }
+
+    /// Brightness, contrast, gamma and inversion per channel value
+    fn table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (value, out) in table.iter_mut().enumerate() {
+            let mut v = value as f32 / 255.0 + self.brightness;
+            v = (v - 0.5) * self.contrast + 0.5;
+            v = v.clamp(0.0, 1.0).powf(1.0 / self.gamma);
+            if self.invert {
+                v = 1.0 - v;
+            }
+            *out = (v * 255.0).round() as u8;
+        }
+        table
+    }
+}
+
+/// An adjustment prepared for applying it to many pixels
+#[derive(Debug)]
+struct Prepared {
+    chroma_key: Option<ChromaKey>,
+    matrix: Option<[[f32; 3]; 3]>,
+    table: [u8; 256],
+}
+
+impl Prepared {
+    /// `rgb` are the indices of the red, green and blue channel
+    fn apply(&self, pixels: &[[u8; 4]], rgb: [usize; 3]) -> Box<[[u8; 4]]> {
+        let key = self.chroma_key.map(|key| {
+            let max = (3.0 * 255.0f32 * 255.0).sqrt() * key.tolerance;
+            (key.color.map(|c| c as f32), max * max)
+        });
+
+        pixels
+            .par_iter()
+            .map(|pixel| {
+                let color = rgb.map(|i| pixel[i] as f32);
+                let keyed = key.is_some_and(|(key, max)| {
+                    let distance: f32 = (0..3).map(|i| (color[i] - key[i]).powi(2)).sum();
+                    distance <= max
+                });
+
+                let color = match self.matrix {
+                    None => rgb.map(|i| pixel[i]),
+                    Some(matrix) => matrix.map(|row| {
+                        let v: f32 = (0..3).map(|i| row[i] * color[i]).sum();
+                        v.round().clamp(0.0, 255.0) as u8
+                    }),
+                };
+
+                let mut out = [0, 0, 0, if keyed { 0 } else { pixel[3] }];
+                for (c, i) in rgb.into_iter().enumerate() {
+                    out[i] = self.table[color[c] as usize];
+                }
+                out
+            })
+            .collect()
+    }
+}
+
+/// Adjusts the colors of every frame of another source
+#[derive(Debug)]
+pub struct ColorSource<Src: FrameSource> {
+    source: Src,
+    adjustment: Prepared,
+    /// Start and end of the adjusted source frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl<Src: FrameSource> ColorSource<Src> {
+    pub fn new(source: Src, adjustment: ColorAdjustment) -> Result<Self, ColorSourceError> {
+        adjustment.validate()?;
+
+        Ok(Self {
+            source,
+            adjustment: Prepared {
+                chroma_key: adjustment.chroma_key,
+                matrix: adjustment.matrix(),
+                table: adjustment.table(),
+            },
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+}
+
+impl<Src: FrameSource> FrameSource for ColorSource<Src> {
+    fn size(&self) -> (u16, u16) {
+        self.source.size()
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let timing = self.source.frame(delta);
+
+        let window = timing.window(delta);
+        if self.window != Some(window) {
+            let adjustment = &self.adjustment;
+            self.frame = match timing.frame {
+                Frame::Rgba(pixels) => Frame::Rgba(adjustment.apply(pixels, [0, 1, 2])),
+                Frame::Bgra(pixels) => Frame::Bgra(adjustment.apply(pixels, [2, 1, 0])),
+            };
+            self.window = Some(window);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: timing.frame_time,
+            time_left: timing.time_left,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::frame_source::expression_source::expression::Program;
+use crate::frame_source::{Frame, FrameSource, Timing, STILL_TIME};
+
+pub mod expression;
+
+#[derive(Debug, Error)]
+pub enum ExpressionSourceError {
+    #[error("invalid size: {0}x{1}")]
+    Size(u16, u16),
+    #[error("invalid frame rate: {0}")]
+    FrameRate(f32),
+}
+
+/// Computes every pixel of every frame with a [Program]
+///
+/// `t` is the start of the frame in seconds, programs that do not use it render a still frame.
+/// Animations do not repeat, they start over after [STILL_TIME].
+#[derive(Debug)]
+pub struct ExpressionSource {
+    size: (u16, u16),
+    program: Program,
+    frame_time: Duration,
+    frames: u32,
+    index: Option<u32>,
+    frame: Frame,
+}
+
+impl ExpressionSource {
+    pub fn new(
+        size: (u16, u16),
+        program: Program,
+        frame_rate: f32,
+    ) -> Result<Self, ExpressionSourceError> {
+        if size.0 == 0 || size.1 == 0 {
+            return Err(ExpressionSourceError::Size(size.0, size.1));
+        }
+
+        let (frame_time, frames) = match program.uses_time() {
+            false => (STILL_TIME, 1),
+            true => {
+                if !(frame_rate.is_finite() && frame_rate > 0.0) {
+                    return Err(ExpressionSourceError::FrameRate(frame_rate));
+                }
+
+                let frame_time = Duration::from_secs_f64(1.0 / frame_rate as f64);
+                let frames = (STILL_TIME.as_nanos() / frame_time.as_nanos())
+                    .clamp(1, u32::MAX as u128) as u32;
+                (frame_time, frames)
+            }
+        };
+
+        let pixels = size.0 as usize * size.1 as usize;
+        Ok(Self {
+            size,
+            program,
+            frame_time,
+            frames,
+            index: None,
+            frame: Frame::Rgba(vec![[0; 4]; pixels].into_boxed_slice()),
+        })
+    }
+
+    /// Whether frames change over time
+    pub fn animated(&self) -> bool {
+        self.frames > 1
+    }
+
+    fn render(&mut self, index: u32) {
+        let Frame::Rgba(frame) = &mut self.frame else {
+            unreachable!()
+        };
+
+        let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+        let t = (self.frame_time * index).as_secs_f32();
+        let program = &self.program;
+        frame.par_chunks_mut(w).enumerate().for_each_init(
+            || program.machine(),
+            |machine, (y, row)| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let inputs = [x as f32, y as f32, t, w as f32, h as f32];
+                    *pixel = program.pixel(machine, inputs);
+                }
+            },
+        );
+    }
+}
+
+impl FrameSource for ExpressionSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.frame_time.saturating_mul(self.frames)
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        let index = ((delta.as_nanos() / self.frame_time.as_nanos()) as u32).min(self.frames - 1);
+
+        if self.index != Some(index) {
+            self.render(index);
+            self.index = Some(index);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: self.frame_time,
+            time_left: (self.frame_time * (index + 1)).saturating_sub(delta),
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! A small formula language compiled to stack machine code
+//!
+//! Programs are assignments separated by `;`, e.g. `v = sin(x / 10 + t); r = v * 127 + 128`.
+//! All values are floats, comparisons and logic yield 1 or 0.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use thiserror::Error;
+
+/// Read-only inputs, in register order
+const INPUTS: [&str; 5] = ["x", "y", "t", "w", "h"];
+/// Channels of the pixel, in register order after the inputs
+const OUTPUTS: [&str; 4] = ["r", "g", "b", "a"];
+/// Channels of pixels that are not assigned
+const DEFAULTS: [f32; 4] = [0.0, 0.0, 0.0, 255.0];
+
+const T: usize = 2;
+const R: usize = INPUTS.len();
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExpressionError {
+    #[error("unexpected {found} at {position}, expected {expected}")]
+    Syntax {
+        position: usize,
+        found: String,
+        expected: &'static str,
+    },
+    #[error("unknown variable: {0}")]
+    UnknownVariable(String),
+    #[error("unknown function: {0}")]
+    UnknownFunction(String),
+    #[error("{function} takes {expected} arguments, not {found}")]
+    Arguments {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{0} can not be assigned")]
+    ReadOnly(String),
+    #[error("nothing is assigned")]
+    Empty,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Op {
+    Const(f32),
+    Load(usize),
+    Store(usize),
+    Unary(fn(f32) -> f32),
+    Binary(fn(f32, f32) -> f32),
+    Ternary(fn(f32, f32, f32) -> f32),
+}
+
+/// A compiled formula that computes the color of a pixel
+#[derive(Debug, Clone)]
+pub struct Program {
+    ops: Box<[Op]>,
+    registers: usize,
+    stack: usize,
+}
+
+/// Registers and stack to run a [Program] with, reused from pixel to pixel
+#[derive(Debug)]
+pub(crate) struct Machine {
+    registers: Box<[f32]>,
+    stack: Vec<f32>,
+}
+
+impl Program {
+    pub fn compile(source: &str) -> Result<Self, ExpressionError> {
+        let mut compiler = Compiler {
+            tokens: tokenize(source)?,
+            next: 0,
+            ops: vec![],
+            depth: 0,
+            stack: 0,
+            names: INPUTS
+                .into_iter()
+                .chain(OUTPUTS)
+                .map(String::from)
+                .collect(),
+        };
+        compiler.program()?;
+
+        if !compiler.ops.iter().any(|op| matches!(op, Op::Store(_))) {
+            return Err(ExpressionError::Empty);
+        }
+        Ok(Self {
+            ops: compiler.ops.into_boxed_slice(),
+            registers: compiler.names.len(),
+            stack: compiler.stack,
+        })
+    }
+
+    /// Whether the result changes over time
+    pub fn uses_time(&self) -> bool {
+        self.ops.iter().any(|op| matches!(op, Op::Load(T)))
+    }
+
+    pub(crate) fn machine(&self) -> Machine {
+        Machine {
+            registers: vec![0.0; self.registers].into_boxed_slice(),
+            stack: Vec::with_capacity(self.stack),
+        }
+    }
+
+    /// Color of the pixel with the inputs `x`, `y`, `t`, `w` and `h`
+    pub(crate) fn pixel(&self, machine: &mut Machine, inputs: [f32; 5]) -> [u8; 4] {
+        let (registers, stack) = (&mut machine.registers, &mut machine.stack);
+        registers[..R].copy_from_slice(&inputs);
+        registers[R..R + OUTPUTS.len()].copy_from_slice(&DEFAULTS);
+
+        for op in self.ops.iter() {
+            match *op {
+                Op::Const(value) => stack.push(value),
+                Op::Load(register) => stack.push(registers[register]),
+                Op::Store(register) => registers[register] = stack.pop().unwrap(),
+                Op::Unary(f) => {
+                    let a = stack.last_mut().unwrap();
+                    *a = f(*a);
+                }
+                Op::Binary(f) => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.last_mut().unwrap();
+                    *a = f(*a, b);
+                }
+                Op::Ternary(f) => {
+                    let c = stack.pop().unwrap();
+                    let b = stack.pop().unwrap();
+                    let a = stack.last_mut().unwrap();
+                    *a = f(*a, b, c);
+                }
+            }
+        }
+
+        // NaN ends up as 0
+        let channel = |register: usize| registers[register].round().clamp(0.0, 255.0) as u8;
+        [channel(R), channel(R + 1), channel(R + 2), channel(R + 3)]
+    }
+}
+
+impl FromStr for Program {
+    type Err = ExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::compile(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Name(name) => f.write_str(name),
+            Token::Symbol(symbol) => write!(f, "\"{symbol}\""),
+            Token::End => f.write_str("end"),
+        }
+    }
+}
+
+/// Two character symbols come first, so they are not split up
+const SYMBOLS: [&str; 22] = [
+    "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "^", "(", ")", ",", ";", "=", "<",
+    ">", "!", "?", ":",
+];
+
+/// Tokens and their byte positions, ending with [Token::End]
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = vec![];
+    let mut position = 0;
+    while position < source.len() {
+        let rest = &source[position..];
+        let c = rest.chars().next().unwrap();
+
+        if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = f32::from_str(&rest[..length]).map_err(|_| ExpressionError::Syntax {
+                position,
+                found: format!("\"{}\"", &rest[..length]),
+                expected: "a number",
+            })?;
+            tokens.push((position, Token::Number(number)));
+            position += length;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push((position, Token::Name(rest[..length].to_string())));
+            position += length;
+        } else if c.is_whitespace() {
+            position += c.len_utf8();
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push((position, Token::Symbol(*symbol)));
+            position += symbol.len();
+        } else {
+            return Err(ExpressionError::Syntax {
+                position,
+                found: format!("\"{c}\""),
+                expected: "a number, name or operator",
+            });
+        }
+    }
+
+    tokens.push((source.len(), Token::End));
+    Ok(tokens)
+}
+
+enum Function {
+    Unary(fn(f32) -> f32),
+    Binary(fn(f32, f32) -> f32),
+    Ternary(fn(f32, f32, f32) -> f32),
+}
+
+fn function(name: &str) -> Option<Function> {
+    use Function::*;
+
+    Some(match name {
+        "sin" => Unary(f32::sin),
+        "cos" => Unary(f32::cos),
+        "tan" => Unary(f32::tan),
+        "asin" => Unary(f32::asin),
+        "acos" => Unary(f32::acos),
+        "atan" => Unary(f32::atan),
+        "sqrt" => Unary(f32::sqrt),
+        "abs" => Unary(f32::abs),
+        "floor" => Unary(f32::floor),
+        "ceil" => Unary(f32::ceil),
+        "round" => Unary(f32::round),
+        "fract" => Unary(|v| v - v.floor()),
+        "exp" => Unary(f32::exp),
+        "log" => Unary(f32::ln),
+        "sign" => Unary(|v| truth(v > 0.0) - truth(v < 0.0)),
+        "atan2" => Binary(f32::atan2),
+        "min" => Binary(f32::min),
+        "max" => Binary(f32::max),
+        "pow" => Binary(f32::powf),
+        "hypot" => Binary(f32::hypot),
+        "mod" => Binary(f32::rem_euclid),
+        "step" => Binary(|edge, v| truth(v >= edge)),
+        "clamp" => Ternary(|v, low, high| v.max(low).min(high)),
+        "mix" => Ternary(|a, b, t| a + (b - a) * t),
+        "smoothstep" => Ternary(|from, to, v| {
+            let t = ((v - from) / (to - from)).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        }),
+        _ => return None,
+    })
+}
+
+fn truth(value: bool) -> f32 {
+    value as u8 as f32
+}
+
+/// Recursive descent parser that emits code in postfix order
+struct Compiler {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    ops: Vec<Op>,
+    /// Values on the stack at the current op and the most at any op
+    depth: usize,
+    stack: usize,
+    /// Name of every register
+    names: Vec<String>,
+}
+
+impl Compiler {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].1.clone();
+        self.next = (self.next + 1).min(self.tokens.len() - 1);
+        token
+    }
+
+    /// Consumes `symbol` if it comes next
+    fn accept(&mut self, symbol: &'static str) -> bool {
+        let accepted = *self.peek() == Token::Symbol(symbol);
+        if accepted {
+            self.advance();
+        }
+        accepted
+    }
+
+    fn unexpected(&self, expected: &'static str) -> ExpressionError {
+        let (position, token) = &self.tokens[self.next];
+        ExpressionError::Syntax {
+            position: *position,
+            found: token.to_string(),
+            expected,
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ExpressionError> {
+        match self.accept(symbol) {
+            true => Ok(()),
+            false => Err(self.unexpected(symbol)),
+        }
+    }
+
+    /// Appends an op, operations on constants are computed right away
+    fn emit(&mut self, op: Op) {
+        let constants = self
+            .ops
+            .iter()
+            .rev()
+            .take_while(|op| matches!(op, Op::Const(_)))
+            .count();
+        let folded = match (op, &self.ops[self.ops.len() - constants.min(3)..]) {
+            (Op::Unary(f), [.., Op::Const(a)]) => Some((1, f(*a))),
+            (Op::Binary(f), [.., Op::Const(a), Op::Const(b)]) => Some((2, f(*a, *b))),
+            (Op::Ternary(f), [Op::Const(a), Op::Const(b), Op::Const(c)]) => {
+                Some((3, f(*a, *b, *c)))
+            }
+            _ => None,
+        };
+
+        match (op, folded) {
+            (_, Some((operands, value))) => {
+                self.ops.truncate(self.ops.len() - operands);
+                self.ops.push(Op::Const(value));
+            }
+            (op, None) => self.ops.push(op),
+        }
+
+        self.depth = match op {
+            Op::Const(_) | Op::Load(_) => self.depth + 1,
+            Op::Unary(_) => self.depth,
+            Op::Store(_) | Op::Binary(_) => self.depth - 1,
+            Op::Ternary(_) => self.depth - 2,
+        };
+        self.stack = self.stack.max(self.depth);
+    }
+
+    fn program(&mut self) -> Result<(), ExpressionError> {
+        loop {
+            while self.accept(";") {}
+            if *self.peek() == Token::End {
+                return Ok(());
+            }
+
+            self.assignment()?;
+            if !(self.accept(";") || *self.peek() == Token::End) {
+                return Err(self.unexpected(";"));
+            }
+        }
+    }
+
+    fn assignment(&mut self) -> Result<(), ExpressionError> {
+        let Token::Name(name) = self.peek().clone() else {
+            return Err(self.unexpected("an assignment"));
+        };
+        self.advance();
+        self.expect("=")?;
+        self.expression()?;
+
+        if INPUTS.contains(&name.as_str()) || constant(&name).is_some() {
+            return Err(ExpressionError::ReadOnly(name));
+        }
+        let register = match self.names.iter().position(|known| *known == name) {
+            Some(register) => register,
+            None => {
+                self.names.push(name);
+                self.names.len() - 1
+            }
+        };
+        self.emit(Op::Store(register));
+        Ok(())
+    }
+
+    fn expression(&mut self) -> Result<(), ExpressionError> {
+        self.or()?;
+        if self.accept("?") {
+            self.expression()?;
+            self.expect(":")?;
+            self.expression()?;
+            self.emit(Op::Ternary(|c, a, b| if c != 0.0 { a } else { b }));
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<(), ExpressionError> {
+        self.and()?;
+        while self.accept("||") {
+            self.and()?;
+            self.emit(Op::Binary(|a, b| truth(a != 0.0 || b != 0.0)));
+        }
+        Ok(())
+    }
+
+    fn and(&mut self) -> Result<(), ExpressionError> {
+        self.comparison()?;
+        while self.accept("&&") {
+            self.comparison()?;
+            self.emit(Op::Binary(|a, b| truth(a != 0.0 && b != 0.0)));
+        }
+        Ok(())
+    }
+
+    fn comparison(&mut self) -> Result<(), ExpressionError> {
+        self.sum()?;
+        let f: fn(f32, f32) -> f32 = match self.peek() {
+            Token::Symbol("<") => |a, b| truth(a < b),
+            Token::Symbol(">") => |a, b| truth(a > b),
+            Token::Symbol("<=") => |a, b| truth(a <= b),
+            Token::Symbol(">=") => |a, b| truth(a >= b),
+            Token::Symbol("==") => |a, b| truth(a == b),
+            Token::Symbol("!=") => |a, b| truth(a != b),
+            _ => return Ok(()),
+        };
+        self.advance();
+        self.sum()?;
+        self.emit(Op::Binary(f));
+        Ok(())
+    }
+
+    fn sum(&mut self) -> Result<(), ExpressionError> {
+        self.product()?;
+        loop {
+            let f: fn(f32, f32) -> f32 = match self.peek() {
+                Token::Symbol("+") => |a, b| a + b,
+                Token::Symbol("-") => |a, b| a - b,
+                _ => return Ok(()),
+            };
+            self.advance();
+            self.product()?;
+            self.emit(Op::Binary(f));
+        }
+    }
+
+    fn product(&mut self) -> Result<(), ExpressionError> {
+        self.unary()?;
+        loop {
+            let f: fn(f32, f32) -> f32 = match self.peek() {
+                Token::Symbol("*") => |a, b| a * b,
+                Token::Symbol("/") => |a, b| a / b,
+                // the sign follows the divisor, patterns repeat across negative coordinates
+                Token::Symbol("%") => f32::rem_euclid,
+                _ => return Ok(()),
+            };
+            self.advance();
+            self.unary()?;
+            self.emit(Op::Binary(f));
+        }
+    }
+
+    fn unary(&mut self) -> Result<(), ExpressionError> {
+        if self.accept("-") {
+            self.unary()?;
+            self.emit(Op::Unary(|a| -a));
+        } else if self.accept("!") {
+            self.unary()?;
+            self.emit(Op::Unary(|a| truth(a == 0.0)));
+        } else {
+            self.power()?;
+        }
+        Ok(())
+    }
+
+    /// Right associative and binds tighter than a leading minus, `-2^2` is -4
+    fn power(&mut self) -> Result<(), ExpressionError> {
+        self.atom()?;
+        if self.accept("^") {
+            self.unary()?;
+            self.emit(Op::Binary(f32::powf));
+        }
+        Ok(())
+    }
+
+    fn atom(&mut self) -> Result<(), ExpressionError> {
+        let token = self.peek().clone();
+        if !matches!(
+            token,
+            Token::Number(_) | Token::Name(_) | Token::Symbol("(")
+        ) {
+            return Err(self.unexpected("a number, name or ("));
+        }
+        self.advance();
+
+        match token {
+            Token::Number(number) => self.emit(Op::Const(number)),
+            Token::Name(name) if self.accept("(") => self.call(name)?,
+            Token::Name(name) => match constant(&name) {
+                Some(value) => self.emit(Op::Const(value)),
+                None => match self.names.iter().position(|known| *known == name) {
+                    Some(register) => self.emit(Op::Load(register)),
+                    None => return Err(ExpressionError::UnknownVariable(name)),
+                },
+            },
+            _ => {
+                self.expression()?;
+                self.expect(")")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Arguments and call of a function, after its opening parenthesis
+    fn call(&mut self, name: String) -> Result<(), ExpressionError> {
+        let mut arguments = 0;
+        if !self.accept(")") {
+            loop {
+                self.expression()?;
+                arguments += 1;
+                if self.accept(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let (expected, op) = match function(&name) {
+            None => return Err(ExpressionError::UnknownFunction(name)),
+            Some(Function::Unary(f)) => (1, Op::Unary(f)),
+            Some(Function::Binary(f)) => (2, Op::Binary(f)),
+            Some(Function::Ternary(f)) => (3, Op::Ternary(f)),
+        };
+        if arguments != expected {
+            return Err(ExpressionError::Arguments {
+                function: name,
+                expected,
+                found: arguments,
+            });
+        }
+        self.emit(op);
+        Ok(())
+    }
+}
+
+fn constant(name: &str) -> Option<f32> {
+    match name {
+        "pi" => Some(std::f32::consts::PI),
+        "tau" => Some(std::f32::consts::TAU),
+        _ => None,
+    }
+}
@@ -0,0 +1,315 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::frame_source::text_source::bitmap_font;
+use crate::frame_source::{Frame, FrameSource, Timing, STILL_TIME};
+
+/// SMPTE color bars at 75% intensity
+const BARS: [[u8; 4]; 7] = [
+    [191, 191, 191, 255],
+    [191, 191, 0, 255],
+    [0, 191, 191, 255],
+    [0, 191, 0, 255],
+    [191, 0, 191, 255],
+    [191, 0, 0, 255],
+    [0, 0, 191, 255],
+];
+
+/// Bars below the color bars, mirroring them with black in between
+const CASTELLATIONS: [[u8; 4]; 7] = [
+    [0, 0, 191, 255],
+    [0, 0, 0, 255],
+    [191, 0, 191, 255],
+    [0, 0, 0, 255],
+    [0, 191, 191, 255],
+    [0, 0, 0, 255],
+    [191, 191, 191, 255],
+];
+
+#[derive(Debug, Error)]
+pub enum GeneratorSourceError {
+    #[error("invalid size: {0}x{1}")]
+    Size(u16, u16),
+    #[error("invalid animation speed: {0}")]
+    Speed(f32),
+    #[error("invalid frame rate: {0}")]
+    FrameRate(f32),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Pattern {
+    Fill([u8; 4]),
+    /// Gradient along a direction in degrees, 0 runs from left to right
+    LinearGradient {
+        from: [u8; 4],
+        to: [u8; 4],
+        angle: f32,
+    },
+    /// Gradient from the center to the corners
+    RadialGradient {
+        inner: [u8; 4],
+        outer: [u8; 4],
+    },
+    Checkerboard {
+        cell: u16,
+        colors: [[u8; 4]; 2],
+    },
+    /// SMPTE style color bars and a gray ramp under a grid labeled with coordinates
+    TestPattern {
+        grid: u16,
+    },
+    Plasma,
+    /// Random colors, the same seed produces the same frames
+    Noise {
+        seed: u64,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Animation {
+    /// Animation cycles per second
+    pub speed: f32,
+    pub frame_rate: f32,
+}
+
+/// Generates synthetic frames, animations loop after one cycle
+#[derive(Debug)]
+pub struct GeneratorSource {
+    size: (u16, u16),
+    pattern: Pattern,
+    frame_time: Duration,
+    frames: u32,
+    index: Option<u32>,
+    frame: Frame,
+}
+
+impl GeneratorSource {
+    /// A still frame without `animation`
+    pub fn new(
+        size: (u16, u16),
+        pattern: Pattern,
+        animation: Option<Animation>,
+    ) -> Result<Self, GeneratorSourceError> {
+        if size.0 == 0 || size.1 == 0 {
+            return Err(GeneratorSourceError::Size(size.0, size.1));
+        }
+
+        let (frame_time, frames) = match (pattern, animation) {
+            (Pattern::Fill(_), _) | (_, None) => (STILL_TIME, 1),
+            (_, Some(Animation { speed, frame_rate })) => {
+                if !(frame_rate.is_finite() && frame_rate > 0.0) {
+                    return Err(GeneratorSourceError::FrameRate(frame_rate));
+                }
+                if !(speed.is_finite() && speed > 0.0) {
+                    return Err(GeneratorSourceError::Speed(speed));
+                }
+
+                let frames = (frame_rate / speed).round().clamp(1.0, u32::MAX as f32) as u32;
+                (Duration::from_secs_f64(1.0 / frame_rate as f64), frames)
+            }
+        };
+
+        let pixels = size.0 as usize * size.1 as usize;
+        Ok(Self {
+            size,
+            pattern,
+            frame_time,
+            frames,
+            index: None,
+            frame: Frame::Rgba(vec![[0; 4]; pixels].into_boxed_slice()),
+        })
+    }
+
+    /// Whether frames change over time
+    pub fn animated(&self) -> bool {
+        self.frames > 1
+    }
+
+    fn render(&mut self, index: u32) {
+        let Frame::Rgba(frame) = &mut self.frame else {
+            unreachable!()
+        };
+
+        let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+        let phase = index as f32 / self.frames as f32;
+        let pattern = self.pattern;
+        frame.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = pattern_pixel(&pattern, (x, y), (w, h), phase, index);
+            }
+        });
+
+        if let Pattern::TestPattern { grid } = pattern {
+            label_grid(frame, (w, h), grid.max(1) as usize);
+            if self.frames > 1 {
+                // a sweeping column makes tearing and latency visible
+                let sweep = (phase * w as f32) as usize;
+                for row in frame.chunks_exact_mut(w) {
+                    let [r, g, b, a] = row[sweep];
+                    row[sweep] = [255 - r, 255 - g, 255 - b, a];
+                }
+            }
+        }
+    }
+}
+
+fn pattern_pixel(
+    pattern: &Pattern,
+    (x, y): (usize, usize),
+    (w, h): (usize, usize),
+    phase: f32,
+    index: u32,
+) -> [u8; 4] {
+    match *pattern {
+        Pattern::Fill(color) => color,
+        Pattern::LinearGradient { from, to, angle } => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            // project onto the direction, scaled so the corners are at 0 and 1
+            let extent = (w as f32 * cos).abs() + (h as f32 * sin).abs();
+            let origin = (w as f32 * cos.min(0.0)) + (h as f32 * sin.min(0.0));
+            let t = ((x as f32 + 0.5) * cos + (y as f32 + 0.5) * sin - origin) / extent;
+            lerp(from, to, shift(t, phase))
+        }
+        Pattern::RadialGradient { inner, outer } => {
+            let (dx, dy) = (
+                x as f32 + 0.5 - w as f32 / 2.0,
+                y as f32 + 0.5 - h as f32 / 2.0,
+            );
+            let radius = (w as f32).hypot(h as f32) / 2.0;
+            lerp(inner, outer, shift(dx.hypot(dy) / radius, phase))
+        }
+        Pattern::Checkerboard { cell, colors } => {
+            let cell = cell.max(1) as usize;
+            // move diagonally by two cells per cycle
+            let offset = (phase * 2.0 * cell as f32) as usize;
+            colors[((x + offset) / cell + (y + offset) / cell) % 2]
+        }
+        Pattern::TestPattern { .. } => {
+            let bar = x * BARS.len() / w;
+            if y < h * 2 / 3 {
+                BARS[bar]
+            } else if y < h * 3 / 4 {
+                CASTELLATIONS[bar]
+            } else {
+                let gray = (x * 256 / w) as u8;
+                [gray, gray, gray, 255]
+            }
+        }
+        Pattern::Plasma => {
+            let scale = TAU / w.min(h) as f32;
+            let (u, v) = (x as f32 * scale, y as f32 * scale);
+            let t = phase * TAU;
+            // whole multiples of the phase keep the animation seamless
+            let value = (u * 2.0 + t).sin()
+                + (v * 3.0 - t).sin()
+                + ((u + v) * 1.5 + 2.0 * t).sin()
+                + ((u - 3.0).hypot(v - 2.0) * 2.0 - t).sin();
+            let channel = |shift: f32| {
+                (((value * std::f32::consts::FRAC_PI_2 + shift).sin() + 1.0) * 127.5) as u8
+            };
+            [
+                channel(0.0),
+                channel(TAU / 3.0),
+                channel(2.0 * TAU / 3.0),
+                255,
+            ]
+        }
+        Pattern::Noise { seed } => {
+            let pixel = (y * w + x) as u64;
+            let [r, g, b, ..] = splitmix64(seed ^ ((index as u64) << 40) ^ pixel).to_le_bytes();
+            [r, g, b, 255]
+        }
+    }
+}
+
+/// Moves a gradient position through a mirrored repetition, `t` is unchanged at phase 0
+fn shift(t: f32, phase: f32) -> f32 {
+    let x = (t.clamp(0.0, 1.0) / 2.0 + phase).fract();
+    1.0 - (2.0 * x - 1.0).abs()
+}
+
+fn lerp(from: [u8; 4], to: [u8; 4], t: f32) -> [u8; 4] {
+    let mut out = [0; 4];
+    for (out, (a, b)) in out.iter_mut().zip(from.into_iter().zip(to)) {
+        *out = (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    }
+    out
+}
+
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Draws grid lines and writes the coordinates next to every crossing
+fn label_grid(frame: &mut [[u8; 4]], (w, h): (usize, usize), grid: usize) {
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    for y in (0..h).step_by(grid) {
+        frame[y * w..(y + 1) * w].fill(WHITE);
+    }
+    for x in (0..w).step_by(grid) {
+        for y in 0..h {
+            frame[y * w + x] = WHITE;
+        }
+    }
+
+    for (x, y) in (0..h)
+        .step_by(grid)
+        .flat_map(|y| (0..w).step_by(grid).map(move |x| (x, y)))
+    {
+        let label = format!("{x},{y}");
+        let (lx, ly) = (x + 2, y + 2);
+        let width = label.len() * (bitmap_font::WIDTH + 1) + 1;
+
+        for (py, px) in (ly..ly + bitmap_font::HEIGHT + 2)
+            .flat_map(|py| (lx..lx + width).map(move |px| (py, px)))
+            .filter(|&(py, px)| px < w && py < h)
+        {
+            frame[py * w + px] = BLACK;
+        }
+
+        for (i, c) in label.chars().enumerate() {
+            for (gx, bits) in bitmap_font::glyph(c).into_iter().enumerate() {
+                for gy in (0..bitmap_font::HEIGHT).filter(|gy| bits & (1 << gy) != 0) {
+                    let (px, py) = (lx + 1 + i * (bitmap_font::WIDTH + 1) + gx, ly + 1 + gy);
+                    if px < w && py < h {
+                        frame[py * w + px] = WHITE;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FrameSource for GeneratorSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.frame_time.saturating_mul(self.frames)
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        let index = ((delta.as_nanos() / self.frame_time.as_nanos()) as u32).min(self.frames - 1);
+
+        if self.index != Some(index) {
+            self.render(index);
+            self.index = Some(index);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: self.frame_time,
+            time_left: (self.frame_time * (index + 1)).saturating_sub(delta),
+        }
+    }
+}
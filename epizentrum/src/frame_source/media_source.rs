@@ -1,15 +1,23 @@
 use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::ptr::slice_from_raw_parts_mut;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError};
+use std::sync::Arc;
 use std::time::Duration;
 
 use image::{AnimationDecoder, Delay, Frame, Frames, ImageError, ImageFormat, ImageResult};
 use rayon::iter::Either;
 use thiserror::Error;
+use tracing::warn;
 
 use crate::frame_source;
 use crate::frame_source::{FrameSource, Timing};
 
+mod container;
+pub mod remote;
+
 #[derive(Debug, Error)]
 pub enum MediaSourceError {
     #[error("io error: {}", 0)]
@@ -22,6 +30,8 @@ pub enum MediaSourceError {
     UnsupportedFormat(ImageFormat),
     #[error("unable to determine size")]
     UnknownSize,
+    #[error("media decoder stopped")]
+    DecoderStopped,
 }
 
 #[derive(Debug)]
@@ -33,77 +43,26 @@ pub struct MediaSource {
 
 impl MediaSource {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, MediaSourceError> {
-        let path = path.as_ref();
-
-        let format = {
-            match image::io::Reader::open(path)?
-                .with_guessed_format()?
-                .format()
-            {
-                None => return Err(MediaSourceError::Format),
-                Some(format) => format,
-            }
-        };
+        Self::decode(&Encoded::File(File::open(path)?))
+    }
 
-        let frames = match format {
-            ImageFormat::Png => {
-                let png_decoder = image::codecs::png::PngDecoder::new(File::open(path)?)?;
-                if png_decoder.is_apng() {
-                    Either::Left(png_decoder.apng().into_frames())
-                } else {
-                    Either::Right(Frame::from_parts(
-                        image::open(path)?.into_rgba8(),
-                        0,
-                        0,
-                        Delay::from_numer_denom_ms(u32::MAX, 1),
-                    ))
-                }
-            }
-            ImageFormat::Gif => {
-                Either::Left(image::codecs::gif::GifDecoder::new(File::open(path)?)?.into_frames())
-            }
-            ImageFormat::WebP => Either::Left(
-                image::codecs::webp::WebPDecoder::new(File::open(path)?)?.into_frames(),
-            ),
-            ImageFormat::Jpeg
-            | ImageFormat::Pnm
-            | ImageFormat::Tiff
-            | ImageFormat::Tga
-            | ImageFormat::Dds
-            | ImageFormat::Bmp
-            | ImageFormat::Ico
-            | ImageFormat::Hdr
-            | ImageFormat::OpenExr
-            | ImageFormat::Farbfeld
-            | ImageFormat::Avif
-            | ImageFormat::Qoi => Either::Right(Frame::from_parts(
-                image::open(path)?.into_rgba8(),
-                0,
-                0,
-                Delay::from_numer_denom_ms(u32::MAX, 1),
-            )),
-            format => return Err(MediaSourceError::UnsupportedFormat(format)),
-        };
+    /// Decodes media that was already read, e.g. downloaded
+    pub fn from_memory(data: Arc<[u8]>) -> Result<Self, MediaSourceError> {
+        Self::decode(&Encoded::Memory(data))
+    }
 
+    fn decode(encoded: &Encoded) -> Result<Self, MediaSourceError> {
         let mut size = None;
-        let frames = match frames {
-            Either::Left(frames) => Frames::new(Box::new(frames)),
-            Either::Right(frame) => Frames::new(Box::new([Ok(frame)].into_iter())),
-        }
-        .map(|frame| {
-            frame.map(|f| {
-                size = Some((f.buffer().width() as u16, f.buffer().height() as u16));
-                let delay = f.delay().into();
-
-                let buffer = f.into_buffer().into_raw().into_boxed_slice();
-                let len = buffer.len();
-                let ptr = Box::into_raw(buffer) as *mut [u8; 4];
-                let buffer = unsafe { Box::from_raw(slice_from_raw_parts_mut(ptr, len / 4)) };
-                (frame_source::Frame::Rgba(buffer), delay)
+        let frames = open(encoded)?
+            .map(|frame| {
+                frame.map(|f| {
+                    let (frame_size, frame, delay) = convert(f);
+                    size = Some(frame_size);
+                    (frame, delay)
+                })
             })
-        })
-        .collect::<ImageResult<Vec<_>>>()?
-        .into_boxed_slice();
+            .collect::<ImageResult<Vec<_>>>()?
+            .into_boxed_slice();
 
         let time = frames.iter().map(|(_, d)| d).sum();
 
@@ -114,6 +73,124 @@ impl MediaSource {
     }
 }
 
+trait MediaRead: BufRead + Seek {}
+
+impl<R: BufRead + Seek> MediaRead for R {}
+
+/// Encoded media, either a file or downloaded bytes
+#[derive(Debug)]
+enum Encoded {
+    File(File),
+    Memory(Arc<[u8]>),
+}
+
+impl Encoded {
+    /// A new reader at the start of the media
+    fn reader(&self) -> std::io::Result<Box<dyn MediaRead>> {
+        Ok(match self {
+            Encoded::File(file) => {
+                let mut file = file.try_clone()?;
+                file.rewind()?;
+                Box::new(BufReader::new(file))
+            }
+            Encoded::Memory(data) => Box::new(Cursor::new(data.clone())),
+        })
+    }
+}
+
+fn format(encoded: &Encoded) -> Result<ImageFormat, MediaSourceError> {
+    image::io::Reader::new(encoded.reader()?)
+        .with_guessed_format()?
+        .format()
+        .ok_or(MediaSourceError::Format)
+}
+
+/// Decodes all frames of encoded media from its start
+fn open(encoded: &Encoded) -> Result<Frames<'static>, MediaSourceError> {
+    let reader = || encoded.reader();
+    let format = format(encoded)?;
+
+    let frames = match format {
+        ImageFormat::Png => {
+            let png_decoder = image::codecs::png::PngDecoder::new(reader()?)?;
+            if png_decoder.is_apng() {
+                Either::Left(png_decoder.apng().into_frames())
+            } else {
+                Either::Right(Frame::from_parts(
+                    image::io::Reader::with_format(reader()?, format)
+                        .decode()?
+                        .into_rgba8(),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(u32::MAX, 1),
+                ))
+            }
+        }
+        ImageFormat::Gif => {
+            Either::Left(image::codecs::gif::GifDecoder::new(reader()?)?.into_frames())
+        }
+        ImageFormat::WebP => {
+            let webp_decoder = image::codecs::webp::WebPDecoder::new(reader()?)?;
+            // still images have no frames
+            if webp_decoder.has_animation() {
+                Either::Left(webp_decoder.into_frames())
+            } else {
+                Either::Right(Frame::from_parts(
+                    image::io::Reader::with_format(reader()?, format)
+                        .decode()?
+                        .into_rgba8(),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(u32::MAX, 1),
+                ))
+            }
+        }
+        ImageFormat::Jpeg
+        | ImageFormat::Pnm
+        | ImageFormat::Tiff
+        | ImageFormat::Tga
+        | ImageFormat::Dds
+        | ImageFormat::Bmp
+        | ImageFormat::Ico
+        | ImageFormat::Hdr
+        | ImageFormat::OpenExr
+        | ImageFormat::Farbfeld
+        | ImageFormat::Avif
+        | ImageFormat::Qoi => Either::Right(Frame::from_parts(
+            image::io::Reader::with_format(reader()?, format)
+                .decode()?
+                .into_rgba8(),
+            0,
+            0,
+            Delay::from_numer_denom_ms(u32::MAX, 1),
+        )),
+        format => return Err(MediaSourceError::UnsupportedFormat(format)),
+    };
+
+    Ok(match frames {
+        Either::Left(frames) => Frames::new(Box::new(frames)),
+        Either::Right(frame) => Frames::new(Box::new([Ok(frame)].into_iter())),
+    })
+}
+
+fn describe(frame: &Frame) -> ((u16, u16), Duration) {
+    let size = (
+        frame.buffer().width() as u16,
+        frame.buffer().height() as u16,
+    );
+    (size, frame.delay().into())
+}
+
+fn convert(frame: Frame) -> ((u16, u16), frame_source::Frame, Duration) {
+    let (size, delay) = describe(&frame);
+
+    let buffer = frame.into_buffer().into_raw().into_boxed_slice();
+    let len = buffer.len();
+    let ptr = Box::into_raw(buffer) as *mut [u8; 4];
+    let buffer = unsafe { Box::from_raw(slice_from_raw_parts_mut(ptr, len / 4)) };
+    (size, frame_source::Frame::Rgba(buffer), delay)
+}
+
 impl FrameSource for MediaSource {
     fn size(&self) -> (u16, u16) {
         self.size
@@ -123,13 +200,13 @@ impl FrameSource for MediaSource {
         self.time
     }
 
-    fn frame(&self, delta: Duration) -> Timing<&frame_source::Frame> {
+    fn frame(&mut self, delta: Duration) -> Timing<&frame_source::Frame> {
         let delta = Duration::from_nanos((delta.as_nanos() % self.time.as_nanos()) as u64);
         let mut accu = Duration::ZERO;
         for (frame, frame_time) in self.frames.iter() {
             accu += *frame_time;
 
-            if accu >= delta {
+            if accu > delta {
                 return Timing {
                     frame,
                     frame_time: *frame_time,
@@ -141,3 +218,166 @@ impl FrameSource for MediaSource {
         unreachable!()
     }
 }
+
+/// How long a frame is shown in place of one the decoder has not caught up with yet
+const DECODER_RETRY: Duration = Duration::from_millis(5);
+
+/// Decodes animations while they are shown instead of up front
+///
+/// A decoder thread stays at most `look_ahead` frames ahead of the shown frame, shown frames are
+/// dropped. Frame delays are read from the container up front, without decoding any pixels.
+/// Frames are never waited for, while the decoder is behind the last decoded frame is shown.
+#[derive(Debug)]
+pub struct StreamingMediaSource {
+    size: (u16, u16),
+    /// end of every frame relative to the start of the animation
+    ends: Box<[Duration]>,
+    frames: Receiver<(usize, frame_source::Frame)>,
+    current: (usize, frame_source::Frame),
+}
+
+impl StreamingMediaSource {
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        look_ahead: NonZeroUsize,
+    ) -> Result<Self, MediaSourceError> {
+        let path = path.as_ref();
+        let encoded = Encoded::File(File::open(path)?);
+        Self::decode(encoded, path.display().to_string(), look_ahead)
+    }
+
+    /// Decodes media that was already read, e.g. downloaded, while it is shown
+    pub fn from_memory(
+        data: Arc<[u8]>,
+        look_ahead: NonZeroUsize,
+    ) -> Result<Self, MediaSourceError> {
+        Self::decode(
+            Encoded::Memory(data),
+            "media in memory".to_string(),
+            look_ahead,
+        )
+    }
+
+    fn decode(
+        encoded: Encoded,
+        name: String,
+        look_ahead: NonZeroUsize,
+    ) -> Result<Self, MediaSourceError> {
+        let format = format(&encoded)?;
+        let (size, delays) = match container::animation(&mut encoded.reader()?, format)? {
+            Some(animation) => animation,
+            None => {
+                let (width, height) =
+                    image::io::Reader::with_format(encoded.reader()?, format).into_dimensions()?;
+                (
+                    (width as u16, height as u16),
+                    vec![Delay::from_numer_denom_ms(u32::MAX, 1).into()],
+                )
+            }
+        };
+        if delays.is_empty() {
+            return Err(MediaSourceError::UnknownSize);
+        }
+
+        let ends = delays
+            .into_iter()
+            .scan(Duration::ZERO, |time, delay| {
+                *time += delay;
+                Some(*time)
+            })
+            .collect::<Box<[_]>>();
+
+        let (tx, frames) = sync_channel(look_ahead.get());
+        let repeat = ends.len() > 1;
+        std::thread::Builder::new()
+            .name("media decoder".to_string())
+            .spawn(move || decode(encoded, name, tx, repeat))?;
+
+        let current = frames
+            .recv()
+            .map_err(|_| MediaSourceError::DecoderStopped)?;
+
+        Ok(Self {
+            size,
+            ends,
+            frames,
+            current,
+        })
+    }
+}
+
+/// Sends all frames of `encoded` until the [StreamingMediaSource] is dropped
+fn decode(
+    encoded: Encoded,
+    name: String,
+    frames: SyncSender<(usize, frame_source::Frame)>,
+    repeat: bool,
+) {
+    loop {
+        let decoded = match open(&encoded) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                warn!("unable to open {name}: {e}");
+                return;
+            }
+        };
+
+        for (i, frame) in decoded.enumerate() {
+            let frame = match frame {
+                Ok(frame) => convert(frame).1,
+                Err(e) => {
+                    warn!("unable to decode {name}: {e}");
+                    return;
+                }
+            };
+
+            if frames.send((i, frame)).is_err() {
+                return;
+            }
+        }
+
+        if !repeat {
+            return;
+        }
+    }
+}
+
+impl FrameSource for StreamingMediaSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.ends[self.ends.len() - 1]
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&frame_source::Frame> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        let i = self.ends.partition_point(|end| *end <= delta);
+
+        // frames arrive in order, skipping ahead or wrapping around drops the frames in between
+        while self.current.0 != i {
+            match self.frames.try_recv() {
+                Ok(frame) => self.current = frame,
+                // the decoder is behind, show the last frame until shortly after
+                Err(TryRecvError::Empty) => {
+                    let end = (delta + DECODER_RETRY).min(self.ends[i]);
+                    return Timing {
+                        frame: &self.current.1,
+                        frame_time: end - delta,
+                        time_left: end - delta,
+                    };
+                }
+                // the decoder failed, keep showing the last frame
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        let start = i.checked_sub(1).map_or(Duration::ZERO, |i| self.ends[i]);
+        Timing {
+            frame: &self.current.1,
+            frame_time: self.ends[i] - start,
+            time_left: self.ends[i] - delta,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use image::{Delay, ImageFormat};
+
+/// Size and frame delays of an animation, read from its container without decoding any pixels
+///
+/// `None` if `format` is not animated. Delays are the same the `image` decoders report.
+pub(super) fn animation<R: Read + Seek>(
+    reader: &mut R,
+    format: ImageFormat,
+) -> std::io::Result<Option<((u16, u16), Vec<Duration>)>> {
+    match format {
+        ImageFormat::Gif => gif(reader).map(Some),
+        ImageFormat::Png => apng(reader),
+        ImageFormat::WebP => webp(reader),
+        _ => Ok(None),
+    }
+}
+
+fn gif<R: Read + Seek>(reader: &mut R) -> std::io::Result<((u16, u16), Vec<Duration>)> {
+    let header = read::<_, 13>(reader)?;
+    if &header[..3] != b"GIF" {
+        return Err(invalid("missing gif signature"));
+    }
+    let size = (
+        u16::from_le_bytes([header[6], header[7]]),
+        u16::from_le_bytes([header[8], header[9]]),
+    );
+    skip_color_table(reader, header[10])?;
+
+    let mut delays = vec![];
+    // set by a graphic control extension for the next image only
+    let mut delay = 0;
+    loop {
+        match read::<_, 1>(reader)?[0] {
+            // extension
+            0x21 => {
+                let label = read::<_, 1>(reader)?[0];
+                let len = read::<_, 1>(reader)?[0];
+                let mut block = vec![0; len as usize];
+                reader.read_exact(&mut block)?;
+                if label == 0xf9 && block.len() >= 4 {
+                    delay = u16::from_le_bytes([block[1], block[2]]);
+                }
+                if len != 0 {
+                    skip_sub_blocks(reader)?;
+                }
+            }
+            // image descriptor, followed by the lzw code size and the image data
+            0x2c => {
+                let descriptor = read::<_, 10>(reader)?;
+                skip_color_table(reader, descriptor[8])?;
+                skip_sub_blocks(reader)?;
+
+                delays.push(Delay::from_numer_denom_ms(delay as u32 * 10, 1).into());
+                delay = 0;
+            }
+            // trailer
+            0x3b => return Ok((size, delays)),
+            _ => return Err(invalid("unknown gif block")),
+        }
+    }
+}
+
+fn skip_color_table<R: Seek>(reader: &mut R, flags: u8) -> std::io::Result<()> {
+    if flags & 0x80 != 0 {
+        skip(reader, 3 << ((flags & 0x07) + 1))?;
+    }
+    Ok(())
+}
+
+fn skip_sub_blocks<R: Read + Seek>(reader: &mut R) -> std::io::Result<()> {
+    loop {
+        match read::<_, 1>(reader)?[0] {
+            0 => return Ok(()),
+            len => skip(reader, len as i64)?,
+        }
+    }
+}
+
+/// `None` for plain pngs, the animation control chunk has to come before the image data
+fn apng<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<((u16, u16), Vec<Duration>)>> {
+    if read::<_, 8>(reader)? != *b"\x89PNG\r\n\x1a\n" {
+        return Err(invalid("missing png signature"));
+    }
+
+    let mut size = (0, 0);
+    let mut frames = None;
+    let mut delays = vec![];
+    loop {
+        let header = read::<_, 8>(reader)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as i64;
+        match &header[4..] {
+            b"IHDR" => {
+                let ihdr = read::<_, 8>(reader)?;
+                size = (
+                    u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]) as u16,
+                    u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]) as u16,
+                );
+                skip(reader, len - 8)?;
+            }
+            b"acTL" => {
+                let actl = read::<_, 4>(reader)?;
+                frames = Some(u32::from_be_bytes(actl) as usize);
+                skip(reader, len - 4)?;
+            }
+            b"fcTL" => {
+                let fctl = read::<_, 24>(reader)?;
+                // seconds as a fraction, a denominator of 0 means 1/100
+                let numerator = u16::from_be_bytes([fctl[20], fctl[21]]) as u32;
+                let denominator = match u16::from_be_bytes([fctl[22], fctl[23]]) {
+                    0 => 100,
+                    denominator => denominator as u32,
+                };
+                delays.push(Delay::from_numer_denom_ms(numerator * 1000, denominator).into());
+                skip(reader, len - 24)?;
+            }
+            b"IDAT" if frames.is_none() => return Ok(None),
+            b"IEND" => break,
+            _ => {
+                skip(reader, len)?;
+            }
+        }
+        // crc
+        skip(reader, 4)?;
+    }
+
+    delays.truncate(frames.unwrap_or_default());
+    Ok(Some((size, delays)))
+}
+
+/// `None` for still webp images, they are not decoded as an animation
+fn webp<R: Read + Seek>(reader: &mut R) -> std::io::Result<Option<((u16, u16), Vec<Duration>)>> {
+    let header = read::<_, 12>(reader)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Err(invalid("missing webp signature"));
+    }
+
+    let mut size = (0, 0);
+    let mut delays = vec![];
+    loop {
+        let header = match read::<_, 8>(reader) {
+            Ok(header) => header,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(Some((size, delays))),
+            Err(e) => return Err(e),
+        };
+        // chunks are padded to an even length
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as i64;
+        let len = len + (len & 1);
+        match &header[..4] {
+            b"VP8X" => {
+                let vp8x = read::<_, 10>(reader)?;
+                if vp8x[0] & 0x02 == 0 {
+                    return Ok(None);
+                }
+                size = (
+                    u24(&vp8x[4..7]).wrapping_add(1) as u16,
+                    u24(&vp8x[7..10]).wrapping_add(1) as u16,
+                );
+                skip(reader, len - 10)?;
+            }
+            b"ANMF" => {
+                let anmf = read::<_, 16>(reader)?;
+                delays.push(Delay::from_numer_denom_ms(u24(&anmf[12..15]), 1).into());
+                skip(reader, len - 16)?;
+            }
+            // a simple file format image, the extended format starts with VP8X
+            b"VP8 " | b"VP8L" => return Ok(None),
+            _ => {
+                skip(reader, len)?;
+            }
+        }
+    }
+}
+
+/// Skips the rest of a chunk, whose length can be smaller than its fixed fields in broken files
+fn skip<R: Seek>(reader: &mut R, len: i64) -> std::io::Result<()> {
+    if len < 0 {
+        return Err(invalid("truncated chunk"));
+    }
+    reader.seek(SeekFrom::Current(len))?;
+    Ok(())
+}
+
+fn u24(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0])
+}
+
+fn read<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
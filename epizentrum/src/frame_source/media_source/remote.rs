@@ -0,0 +1,286 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::draw_strategy::DrawStrategy;
+use crate::reload::Trigger;
+
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("http error: {0}")]
+    Transport(Box<ureq::Transport>),
+    #[error("unexpected http status: {0}")]
+    Status(u16),
+    #[error("download larger than {0} bytes")]
+    TooLarge(u64),
+}
+
+impl From<ureq::Error> for DownloadError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(status, _) => DownloadError::Status(status),
+            ureq::Error::Transport(transport) => DownloadError::Transport(Box::new(transport)),
+        }
+    }
+}
+
+/// Whether a media path is an `http://` or `https://` URL instead of a file
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Splits a media object into its URL and the rest, `None` if it is not a URL
+///
+/// URLs may contain ':' and '=', so the rest is split off from the end: options, then an offset
+/// of two integers with an optional draw strategy. The rest starts with ':' or is empty.
+pub fn split_url(s: &str) -> Option<(&str, &str)> {
+    let (scheme, _) = s.split_once("://")?;
+    if !matches!(scheme, "http" | "https") {
+        return None;
+    }
+    let start = scheme.len() + 3;
+
+    // options follow everything else, their names are lowercase words unlike e.g. a query
+    let mut end = s.len();
+    while let Some(colon) = s[start..end].rfind(':').map(|colon| start + colon) {
+        let option = s[colon + 1..end].split_once('=');
+        if !option.is_some_and(|(name, _)| {
+            !name.is_empty() && name.bytes().all(|b| b.is_ascii_lowercase())
+        }) {
+            break;
+        }
+        end = colon;
+    }
+
+    // a port is a single integer, an offset needs two
+    let offset = |x: &str, y: &str| u16::from_str(x).is_ok() && u16::from_str(y).is_ok();
+    let fields = match s[start..end].rsplitn(4, ':').collect::<Vec<_>>().as_slice() {
+        [strategy, y, x, _] if DrawStrategy::from_str(strategy).is_ok() && offset(x, y) => 3,
+        [y, x, _, ..] if offset(x, y) => 2,
+        _ => 0,
+    };
+    for _ in 0..fields {
+        end = s[..end].rfind(':').expect("field after a ':'");
+    }
+
+    Some(s.split_at(end))
+}
+
+/// `$XDG_CACHE_HOME/tsunami` or `~/.cache/tsunami`
+pub fn default_cache() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|cache| !cache.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("tsunami"))
+}
+
+/// Downloads media with a size limit and timeout
+///
+/// Downloads with an ETag are kept in the cache directory, the server is asked
+/// whether they changed and unchanged ones are read from disk instead.
+#[derive(Debug, Clone)]
+pub struct Download {
+    /// Largest accepted body in bytes
+    pub max_size: u64,
+    /// Limit for a whole request including its body
+    pub timeout: Duration,
+    /// Nothing is cached without a directory
+    pub cache: Option<PathBuf>,
+}
+
+impl Default for Download {
+    fn default() -> Self {
+        Self {
+            max_size: 64 << 20,
+            timeout: Duration::from_secs(10),
+            cache: default_cache(),
+        }
+    }
+}
+
+impl Download {
+    pub fn fetch(&self, url: &str) -> Result<Arc<[u8]>, DownloadError> {
+        let cached = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.join(format!("{:016x}", fnv1a(url.as_bytes()))));
+        let etag = cached
+            .as_ref()
+            .and_then(|cached| fs::read_to_string(cached.with_extension("etag")).ok());
+
+        if let (Some(cached), Some(etag)) = (&cached, etag) {
+            match self.get(url, Some(&etag))? {
+                None => match fs::read(cached) {
+                    Ok(data) => {
+                        debug!("{url} is unchanged, using the cached copy");
+                        return Ok(data.into());
+                    }
+                    // the cached copy is gone, ask for the whole body again
+                    Err(e) => warn!("unable to read the cached copy of {url}: {e}"),
+                },
+                Some(body) => {
+                    self.store(cached, &body);
+                    return Ok(body.data.into());
+                }
+            }
+        }
+
+        match self.get(url, None)? {
+            None => Err(DownloadError::Status(304)),
+            Some(body) => {
+                if let Some(cached) = &cached {
+                    self.store(cached, &body);
+                }
+                Ok(body.data.into())
+            }
+        }
+    }
+
+    /// Body of `url`, nothing if it still matches `etag`
+    fn get(&self, url: &str, etag: Option<&str>) -> Result<Option<Body>, DownloadError> {
+        let agent = ureq::AgentBuilder::new().timeout(self.timeout).build();
+        let mut request = agent.get(url);
+        if let Some(etag) = etag {
+            request = request.set("If-None-Match", etag);
+        }
+
+        let response = request.call()?;
+        match response.status() {
+            200 => {}
+            304 if etag.is_some() => return Ok(None),
+            status => return Err(DownloadError::Status(status)),
+        }
+
+        // refuse early if the server tells the length, the limit applies while reading either way
+        if let Some(length) = response
+            .header("Content-Length")
+            .and_then(|length| length.parse::<u64>().ok())
+        {
+            if length > self.max_size {
+                return Err(DownloadError::TooLarge(self.max_size));
+            }
+        }
+
+        let etag = response.header("ETag").map(str::to_owned);
+        let mut data = vec![];
+        response
+            .into_reader()
+            .take(self.max_size + 1)
+            .read_to_end(&mut data)?;
+        match data.len() as u64 > self.max_size {
+            true => Err(DownloadError::TooLarge(self.max_size)),
+            false => Ok(Some(Body { data, etag })),
+        }
+    }
+
+    /// Keeps a download for later requests, failing to do so only costs a download
+    fn store(&self, cached: &Path, body: &Body) {
+        let stored = (|| -> std::io::Result<()> {
+            // without the etag the cached copy is never used
+            let etag_path = cached.with_extension("etag");
+            match fs::remove_file(&etag_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            let Some(etag) = &body.etag else {
+                return Ok(());
+            };
+
+            if let Some(directory) = cached.parent() {
+                fs::create_dir_all(directory)?;
+            }
+            // written elsewhere and renamed, concurrent readers never see half a file
+            let temporary = cached.with_extension("tmp");
+            fs::write(&temporary, &body.data)?;
+            fs::rename(&temporary, cached)?;
+            fs::write(&temporary, etag)?;
+            fs::rename(&temporary, etag_path)
+        })();
+
+        if let Err(e) = stored {
+            warn!("unable to cache {}: {e}", cached.display());
+        }
+    }
+}
+
+struct Body {
+    data: Vec<u8>,
+    etag: Option<String>,
+}
+
+/// FNV-1a, stable across builds unlike the hasher of the standard library
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Downloads URLs again at an interval and fires when one of them changed
+///
+/// The first download only remembers the content, it happens right away.
+#[derive(Debug)]
+pub struct Refresh {
+    download: Download,
+    interval: Duration,
+    next: Instant,
+    /// URL and hash of its last downloaded content
+    urls: Vec<(String, Option<u64>)>,
+}
+
+impl Refresh {
+    pub fn new(download: Download, url: &str, interval: Duration) -> Self {
+        Self {
+            download,
+            interval,
+            next: Instant::now(),
+            urls: vec![(url.to_owned(), None)],
+        }
+    }
+
+    /// Downloads another URL as well
+    pub fn add(&mut self, url: &str) {
+        self.urls.push((url.to_owned(), None));
+    }
+}
+
+impl Trigger for Refresh {
+    fn fired(&mut self) -> std::io::Result<bool> {
+        let now = Instant::now();
+        if now < self.next {
+            return Ok(false);
+        }
+        self.next = now + self.interval;
+
+        let mut fired = false;
+        for (url, version) in self.urls.iter_mut() {
+            match self.download.fetch(url) {
+                Ok(data) => {
+                    let hash = fnv1a(&data);
+                    fired |= version.is_some_and(|version| version != hash);
+                    *version = Some(hash);
+                }
+                // unreachable servers keep the previous version
+                Err(e) => warn!("unable to refresh {url}: {e}"),
+            }
+        }
+        Ok(fired)
+    }
+
+    fn describe(&self) -> String {
+        self.urls
+            .iter()
+            .map(|(url, _)| url.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
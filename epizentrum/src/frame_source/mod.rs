@@ -1,7 +1,49 @@
 use std::fmt::Debug;
 use std::time::Duration;
 
+pub mod color_source;
+pub mod expression_source;
+pub mod generator_source;
 pub mod media_source;
+pub mod playlist_source;
+pub mod quantized_source;
+pub mod scaled_source;
+pub mod scene_source;
+pub mod stream_source;
+pub mod svg_source;
+pub mod text_source;
+pub mod tiled_source;
+pub mod transformed_source;
+#[cfg(feature = "video")]
+pub mod video_source;
+
+/// Frame time of sources that never change, as long as a still image
+pub(crate) const STILL_TIME: Duration = Duration::from_millis(u32::MAX as u64);
+
+/// Least common multiple of the cycle times of animations, at most [STILL_TIME]
+///
+/// Still cycle times are left out, without any animation the result is [STILL_TIME].
+pub(crate) fn common_cycle_time(cycle_times: impl IntoIterator<Item = Duration>) -> Duration {
+    fn gcd(a: u128, b: u128) -> u128 {
+        match b {
+            0 => a,
+            b => gcd(b, a % b),
+        }
+    }
+
+    let cycle_time = cycle_times
+        .into_iter()
+        .map(|cycle_time| cycle_time.as_nanos())
+        .filter(|nanos| *nanos > 0 && *nanos < STILL_TIME.as_nanos())
+        .fold(1, |lcm, nanos| {
+            (lcm / gcd(lcm, nanos) * nanos).min(STILL_TIME.as_nanos())
+        });
+
+    match cycle_time {
+        1 => STILL_TIME,
+        nanos => Duration::from_nanos(nanos as u64),
+    }
+}
 
 #[derive(Debug)]
 pub struct Timing<F: Debug> {
@@ -10,19 +52,37 @@ pub struct Timing<F: Debug> {
     pub time_left: Duration,
 }
 
+impl<F: Debug> Timing<F> {
+    /// Start and end of the frame relative to `delta`, the same for every request within one frame
+    pub(crate) fn window(&self, delta: Duration) -> (Duration, Duration) {
+        let end = delta + self.time_left;
+        (end.saturating_sub(self.frame_time), end)
+    }
+}
+
 #[derive(Debug)]
 pub enum Frame {
     Rgba(Box<[[u8; 4]]>),
     Bgra(Box<[[u8; 4]]>),
 }
 
-pub trait FrameSource: Debug {
+impl Frame {
+    /// Builds a frame with the same channel order from the pixels of this one
+    pub(crate) fn map(&self, f: impl FnOnce(&[[u8; 4]]) -> Box<[[u8; 4]]>) -> Frame {
+        match self {
+            Frame::Rgba(pixels) => Frame::Rgba(f(pixels)),
+            Frame::Bgra(pixels) => Frame::Bgra(f(pixels)),
+        }
+    }
+}
+
+pub trait FrameSource: Debug + Send {
     fn size(&self) -> (u16, u16);
 
     fn cycle_time(&self) -> Duration;
 
     /// Returns a frame, its full frame time and and how long the frame should be displayed relativ to delta
-    fn frame(&self, delta: Duration) -> Timing<&Frame>;
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame>;
 }
 
 impl<F: FrameSource + ?Sized> FrameSource for Box<F> {
@@ -37,7 +97,7 @@ impl<F: FrameSource + ?Sized> FrameSource for Box<F> {
     }
 
     #[inline]
-    fn frame(&self, delta: Duration) -> Timing<&Frame> {
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
         (**self).frame(delta)
     }
 }
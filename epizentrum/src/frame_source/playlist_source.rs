@@ -0,0 +1,343 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rand::prelude::SliceRandom;
+use rand::thread_rng;
+use thiserror::Error;
+
+use crate::frame_source::media_source::remote;
+use crate::frame_source::{Frame, FrameSource, Timing, STILL_TIME};
+
+/// How long still images are shown without an explicit duration
+pub const STILL_DURATION: Duration = Duration::from_secs(10);
+
+/// Frame time of transitions
+const TRANSITION_STEP: Duration = Duration::from_millis(40);
+
+#[derive(Debug, Error)]
+pub enum PlaylistSourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("empty playlist")]
+    Empty,
+    #[error("invalid duration of playlist entry {0}: {1:?}")]
+    Duration(usize, Duration),
+}
+
+/// Whether a path is read as a playlist: a directory or an M3U file
+pub fn is_playlist(path: &Path) -> bool {
+    if remote::is_url(path) {
+        return false;
+    }
+
+    path.is_dir()
+        || path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension.to_lowercase().as_str(), "m3u" | "m3u8"))
+}
+
+/// Files of a directory sorted by name or the entries of an M3U playlist with their durations
+///
+/// Hidden files are left out, relative playlist entries are relative to the playlist
+/// and URLs are kept as they are.
+pub fn read_playlist(path: &Path) -> Result<Vec<(PathBuf, Option<Duration>)>, PlaylistSourceError> {
+    if path.is_dir() {
+        let mut files = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.retain(|file| {
+            file.is_file()
+                && !file
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        });
+        files.sort();
+        return Ok(files.into_iter().map(|file| (file, None)).collect());
+    }
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut duration = None;
+    let mut entries = vec![];
+    for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // negative or missing lengths mean unknown
+            duration = info
+                .split(',')
+                .next()
+                .and_then(|seconds| seconds.trim().parse::<f64>().ok())
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+        } else if !(line.is_empty() || line.starts_with('#')) {
+            let entry = match remote::is_url(Path::new(line)) {
+                true => PathBuf::from(line),
+                false => directory.join(line),
+            };
+            entries.push((entry, duration.take()));
+        }
+    }
+
+    Ok(entries)
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Transition {
+    /// Entries change at once
+    #[default]
+    Cut,
+    /// Blends from one entry to the next
+    Crossfade(Duration),
+    /// The next entry is revealed from left to right
+    Wipe(Duration),
+}
+
+impl Transition {
+    fn duration(&self) -> Duration {
+        match *self {
+            Transition::Cut => Duration::ZERO,
+            Transition::Crossfade(duration) | Transition::Wipe(duration) => duration,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PlaylistEntry {
+    pub source: Box<dyn FrameSource>,
+    /// Defaults to one cycle of animations and [STILL_DURATION] for still images
+    pub duration: Option<Duration>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PlaylistOptions {
+    pub transition: Transition,
+    /// Play the entries in random order
+    pub shuffle: bool,
+    /// Start over after the last entry instead of staying on it
+    pub looping: bool,
+}
+
+impl Default for PlaylistOptions {
+    fn default() -> Self {
+        Self {
+            transition: Transition::Cut,
+            shuffle: false,
+            looping: true,
+        }
+    }
+}
+
+/// Shows other sources one after another, centered on the size of the largest one
+#[derive(Debug)]
+pub struct PlaylistSource {
+    size: (u16, u16),
+    entries: Vec<(Box<dyn FrameSource>, Duration)>,
+    /// Start of every entry
+    starts: Box<[Duration]>,
+    total: Duration,
+    options: PlaylistOptions,
+    /// Start and end of the shown frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl PlaylistSource {
+    pub fn new(
+        entries: Vec<PlaylistEntry>,
+        options: PlaylistOptions,
+    ) -> Result<Self, PlaylistSourceError> {
+        let mut entries = entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let duration = match entry.duration {
+                    Some(duration) => duration,
+                    None if entry.source.cycle_time() >= STILL_TIME => STILL_DURATION,
+                    None => entry.source.cycle_time(),
+                };
+                match duration.is_zero() {
+                    true => Err(PlaylistSourceError::Duration(i, duration)),
+                    false => Ok((entry.source, duration)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if entries.is_empty() {
+            return Err(PlaylistSourceError::Empty);
+        }
+        if options.shuffle {
+            entries.shuffle(&mut thread_rng());
+        }
+
+        let size = entries.iter().fold((0, 0), |(w, h), (source, _)| {
+            (w.max(source.size().0), h.max(source.size().1))
+        });
+        let mut total = Duration::ZERO;
+        let starts = entries
+            .iter()
+            .map(|(_, duration)| {
+                let start = total;
+                total += *duration;
+                start
+            })
+            .collect();
+
+        Ok(Self {
+            size,
+            entries,
+            starts,
+            total,
+            options,
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+
+    /// Draws the frame of entry `index` at `delta` centered into `pixels`
+    ///
+    /// Returns the time since the frame of the entry started and the time it is shown further.
+    fn draw(
+        &mut self,
+        index: usize,
+        delta: Duration,
+        pixels: &mut [[u8; 4]],
+    ) -> (Duration, Duration) {
+        let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+        let (source, _) = &mut self.entries[index];
+        let (ew, eh) = (source.size().0 as usize, source.size().1 as usize);
+        let (x, y) = ((w - ew) / 2, (h - eh) / 2);
+
+        let timing = source.frame(delta);
+        let (frame, bgra) = match timing.frame {
+            Frame::Rgba(frame) => (frame, false),
+            Frame::Bgra(frame) => (frame, true),
+        };
+        for (row, pixels) in frame
+            .chunks(ew.max(1))
+            .zip(pixels[y * w..].chunks_mut(w.max(1)))
+        {
+            for (pixel, out) in row.iter().zip(&mut pixels[x..]) {
+                *out = match bgra {
+                    true => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                    false => *pixel,
+                };
+            }
+        }
+
+        (
+            timing.frame_time.saturating_sub(timing.time_left),
+            timing.time_left,
+        )
+    }
+}
+
+impl FrameSource for PlaylistSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        match self.options.looping {
+            true => self.total,
+            false => STILL_TIME,
+        }
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        if let Some((start, end)) = self.window {
+            if start <= delta && delta < end {
+                return Timing {
+                    frame: &self.frame,
+                    frame_time: end - start,
+                    time_left: end - delta,
+                };
+            }
+        }
+
+        let position = match self.options.looping {
+            true => Duration::from_nanos((delta.as_nanos() % self.total.as_nanos()) as u64),
+            false => delta,
+        };
+        let index = self.starts.partition_point(|start| *start <= position) - 1;
+        let local = position - self.starts[index];
+        let last = index + 1 == self.entries.len();
+        let left = match last && !self.options.looping {
+            true => STILL_TIME,
+            false => self.entries[index].1 - local,
+        };
+
+        let pixel_count = self.size.0 as usize * self.size.1 as usize;
+        let mut pixels = vec![[0; 4]; pixel_count].into_boxed_slice();
+        let (mut elapsed, mut time_left) = self.draw(index, local, &mut pixels);
+        elapsed = elapsed.min(local);
+        time_left = time_left.min(left);
+
+        let previous = match (index, self.options.looping) {
+            (0, true) if self.entries.len() > 1 => Some(self.entries.len() - 1),
+            (0, _) => None,
+            (index, _) => Some(index - 1),
+        };
+        if let Some(previous) = previous {
+            let length = self.options.transition.duration();
+            let length = length
+                .min(self.entries[index].1)
+                .min(self.entries[previous].1);
+
+            if local < length {
+                // the previous entry plays on during the transition
+                let mut from = vec![[0; 4]; pixel_count];
+                let (previous_elapsed, previous_left) =
+                    self.draw(previous, self.entries[previous].1 + local, &mut from);
+
+                let step = (local.as_nanos() / TRANSITION_STEP.as_nanos()) as u32;
+                let step_start = TRANSITION_STEP * step;
+                let step_end = (TRANSITION_STEP * (step + 1)).min(length);
+                elapsed = elapsed.min(previous_elapsed).min(local - step_start);
+                time_left = time_left.min(previous_left).min(step_end - local);
+
+                // the whole step shows the same mix, however it is requested
+                let progress = step_start.as_secs_f32() / length.as_secs_f32();
+                blend(
+                    &mut pixels,
+                    &from,
+                    self.size.0 as usize,
+                    self.options.transition,
+                    progress,
+                );
+            }
+        }
+
+        self.frame = Frame::Rgba(pixels);
+        let timing = Timing {
+            frame: &self.frame,
+            frame_time: elapsed.saturating_add(time_left),
+            time_left,
+        };
+        self.window = Some(timing.window(delta));
+
+        timing
+    }
+}
+
+/// Mixes the frame of the previous entry into `to`, `progress` goes from 0 to 1
+fn blend(
+    to: &mut [[u8; 4]],
+    from: &[[u8; 4]],
+    width: usize,
+    transition: Transition,
+    progress: f32,
+) {
+    match transition {
+        Transition::Cut => {}
+        Transition::Crossfade(_) => {
+            for (to, from) in to.iter_mut().zip(from) {
+                for (to, from) in to.iter_mut().zip(from) {
+                    *to = (*from as f32 + (*to as f32 - *from as f32) * progress).round() as u8;
+                }
+            }
+        }
+        Transition::Wipe(_) => {
+            let revealed = (width as f32 * progress).round() as usize;
+            for (to, from) in to.chunks_mut(width.max(1)).zip(from.chunks(width.max(1))) {
+                to[revealed..].copy_from_slice(&from[revealed..]);
+            }
+        }
+    }
+}
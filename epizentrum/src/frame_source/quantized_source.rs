@@ -0,0 +1,295 @@
+use std::path::Path;
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+/// Built-in palettes by name
+const PRESETS: [(&str, &[u32]); 4] = [
+    ("bw", &[0x000000, 0xffffff]),
+    ("gray4", &[0x000000, 0x555555, 0xaaaaaa, 0xffffff]),
+    (
+        "cga",
+        &[
+            0x000000, 0x0000aa, 0x00aa00, 0x00aaaa, 0xaa0000, 0xaa00aa, 0xaa5500, 0xaaaaaa,
+            0x555555, 0x5555ff, 0x55ff55, 0x55ffff, 0xff5555, 0xff55ff, 0xffff55, 0xffffff,
+        ],
+    ),
+    (
+        "pico8",
+        &[
+            0x000000, 0x1d2b53, 0x7e2553, 0x008751, 0xab5236, 0x5f574f, 0xc2c3c7, 0xfff1e8,
+            0xff004d, 0xffa300, 0xffec27, 0x00e436, 0x29adff, 0x83769c, 0xff77a8, 0xffccaa,
+        ],
+    ),
+];
+
+/// Side length of the ordered dithering matrix
+const BAYER_SIZE: usize = 8;
+
+#[derive(Debug, Error)]
+pub enum QuantizedSourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid palette line {0}: \"{1}\"")]
+    Line(usize, String),
+    #[error("empty palette")]
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Box<[[u8; 3]]>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Self, QuantizedSourceError> {
+        match colors.is_empty() {
+            true => Err(QuantizedSourceError::Empty),
+            false => Ok(Self {
+                colors: colors.into_boxed_slice(),
+            }),
+        }
+    }
+
+    /// A built-in palette: bw, gray4, cga or pico8
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, colors)| Self {
+                colors: colors
+                    .iter()
+                    .map(|rgb| [(rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8])
+                    .collect(),
+            })
+    }
+
+    /// Loads a GIMP palette (GPL) or a list of hex colors
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, QuantizedSourceError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a GIMP palette (GPL) or a list of hex colors (RRGGBB, optionally with #, ; starts comments)
+    pub fn parse(s: &str) -> Result<Self, QuantizedSourceError> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty())
+            .peekable();
+        let gpl = matches!(lines.peek(), Some((_, "GIMP Palette")));
+
+        let colors = lines
+            .skip(gpl as usize)
+            .filter(|(_, line)| match gpl {
+                true => {
+                    !(line.starts_with('#')
+                        || line.starts_with("Name:")
+                        || line.starts_with("Columns:"))
+                }
+                false => !line.starts_with(';'),
+            })
+            .map(|(i, line)| {
+                let color = match gpl {
+                    true => parse_gpl(line),
+                    false => parse_hex(line),
+                };
+                color.ok_or_else(|| QuantizedSourceError::Line(i, line.into()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Self::new(colors)
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    fn nearest(&self, color: [f32; 3]) -> [u8; 3] {
+        *self
+            .colors
+            .iter()
+            .min_by(|a, b| distance(color, **a).total_cmp(&distance(color, **b)))
+            .expect("palettes are not empty")
+    }
+
+    /// Per channel noise amplitude of ordered dithering, about the gap between neighboring colors
+    fn spread(&self) -> f32 {
+        let nearest = self.colors.iter().filter_map(|a| {
+            self.colors
+                .iter()
+                .filter(|b| *b != a)
+                .map(|b| distance(a.map(|c| c as f32), *b))
+                .min_by(f32::total_cmp)
+        });
+        let (sum, count) = nearest.fold((0.0, 0), |(sum, count), d| (sum + d.sqrt(), count + 1));
+
+        match count {
+            0 => 0.0,
+            count => sum / count as f32 / 3f32.sqrt(),
+        }
+    }
+}
+
+/// `r g b [name]` with decimal channels
+fn parse_gpl(line: &str) -> Option<[u8; 3]> {
+    let mut channels = line.split_whitespace().map(|c| c.parse::<u8>().ok());
+    Some([channels.next()??, channels.next()??, channels.next()??])
+}
+
+fn parse_hex(line: &str) -> Option<[u8; 3]> {
+    let hex = line.strip_prefix('#').unwrap_or(line);
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Squared RGB distance
+fn distance(a: [f32; 3], b: [u8; 3]) -> f32 {
+    (0..3).map(|i| (a[i] - b[i] as f32).powi(2)).sum()
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Dithering {
+    /// Nearest palette color
+    #[default]
+    None,
+    /// Diffuses the error to the following pixels, smooth but flickers in animations
+    FloydSteinberg,
+    /// Adds a fixed threshold pattern, stable across frames
+    Bayer,
+}
+
+/// Threshold of the ordered dithering matrix at `(x, y)`, -0.5 to 0.5
+fn bayer(x: usize, y: usize) -> f32 {
+    let mut value = 0;
+    for bit in 0..BAYER_SIZE.trailing_zeros() {
+        value = (value << 2) | (((x ^ y) >> bit) & 1) << 1 | ((y >> bit) & 1);
+    }
+    (value as f32 + 0.5) / (BAYER_SIZE * BAYER_SIZE) as f32 - 0.5
+}
+
+#[derive(Debug)]
+struct Quantizer {
+    palette: Palette,
+    dithering: Dithering,
+    spread: f32,
+}
+
+impl Quantizer {
+    /// `rgb` are the indices of the red, green and blue channel, alpha is kept
+    fn apply(&self, pixels: &[[u8; 4]], width: usize, rgb: [usize; 3]) -> Box<[[u8; 4]]> {
+        let mut out: Box<[[u8; 4]]> = pixels.into();
+        let set = |pixel: &mut [u8; 4], color: [u8; 3]| {
+            for (c, i) in rgb.into_iter().enumerate() {
+                pixel[i] = color[c];
+            }
+        };
+
+        match self.dithering {
+            Dithering::None | Dithering::Bayer => {
+                out.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        let offset = match self.dithering {
+                            Dithering::Bayer => self.spread * bayer(x % BAYER_SIZE, y % BAYER_SIZE),
+                            _ => 0.0,
+                        };
+                        let color = rgb.map(|i| pixel[i] as f32 + offset);
+                        set(pixel, self.palette.nearest(color));
+                    }
+                })
+            }
+            Dithering::FloydSteinberg => {
+                // errors of the current and the next row, with a pixel of margin on both sides
+                let mut errors = vec![[[0.0f32; 3]; 2]; width + 2];
+                for row in out.chunks_mut(width) {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        let error = errors[x + 1][0];
+                        let color = rgb.map(|i| pixel[i] as f32);
+                        let color = [0, 1, 2].map(|c| (color[c] + error[c]).clamp(0.0, 255.0));
+                        let nearest = self.palette.nearest(color);
+                        set(pixel, nearest);
+
+                        // transparent pixels are not seen, their error would only bleed into others
+                        if pixel[3] == 0 {
+                            continue;
+                        }
+                        for c in 0..3 {
+                            let error = color[c] - nearest[c] as f32;
+                            errors[x + 2][0][c] += error * 7.0 / 16.0;
+                            errors[x][1][c] += error * 3.0 / 16.0;
+                            errors[x + 1][1][c] += error * 5.0 / 16.0;
+                            errors[x + 2][1][c] += error * 1.0 / 16.0;
+                        }
+                    }
+                    for error in errors.iter_mut() {
+                        *error = [error[1], [0.0; 3]];
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+/// Maps the colors of every frame of another source to a palette
+#[derive(Debug)]
+pub struct QuantizedSource<Src: FrameSource> {
+    source: Src,
+    quantizer: Quantizer,
+    /// Start and end of the quantized source frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl<Src: FrameSource> QuantizedSource<Src> {
+    pub fn new(source: Src, palette: Palette, dithering: Dithering) -> Self {
+        Self {
+            source,
+            quantizer: Quantizer {
+                spread: palette.spread(),
+                palette,
+                dithering,
+            },
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        }
+    }
+}
+
+impl<Src: FrameSource> FrameSource for QuantizedSource<Src> {
+    fn size(&self) -> (u16, u16) {
+        self.source.size()
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let width = (self.source.size().0 as usize).max(1);
+        let timing = self.source.frame(delta);
+
+        let window = timing.window(delta);
+        if self.window != Some(window) {
+            let quantizer = &self.quantizer;
+            self.frame = match timing.frame {
+                Frame::Rgba(pixels) => Frame::Rgba(quantizer.apply(pixels, width, [0, 1, 2])),
+                Frame::Bgra(pixels) => Frame::Bgra(quantizer.apply(pixels, width, [2, 1, 0])),
+            };
+            self.window = Some(window);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: timing.frame_time,
+            time_left: timing.time_left,
+        }
+    }
+}
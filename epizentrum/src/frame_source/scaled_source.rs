@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+pub use image::imageops::FilterType;
+use image::{imageops, ImageBuffer, Rgba};
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+#[derive(Debug, Error)]
+pub enum ScaledSourceError {
+    #[error("invalid scaled size: {0}x{1}")]
+    Size(u64, u64),
+    #[error("invalid scale: {0}%")]
+    Percent(f32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Scale {
+    Size(u16, u16),
+    Percent(f32),
+    /// Largest size inside the area that keeps the aspect ratio
+    Contain,
+    /// Smallest size covering the area that keeps the aspect ratio, cropped to the area
+    Cover,
+    /// Exactly the area
+    Stretch,
+}
+
+impl Scale {
+    /// Size to scale `source` to and the size after cropping it centered
+    pub(crate) fn sizes(&self, source: (u16, u16), area: (u16, u16)) -> ((u64, u64), (u64, u64)) {
+        let (sw, sh) = (source.0 as f64, source.1 as f64);
+        let (aw, ah) = (area.0 as f64, area.1 as f64);
+        let by = |factor: f64| {
+            (
+                ((sw * factor).round() as u64).max(1),
+                ((sh * factor).round() as u64).max(1),
+            )
+        };
+
+        let scaled = match *self {
+            Scale::Size(w, h) => (w as u64, h as u64),
+            Scale::Percent(percent) => by(percent as f64 / 100.0),
+            Scale::Contain => by((aw / sw).min(ah / sh)),
+            Scale::Cover => by((aw / sw).max(ah / sh)),
+            Scale::Stretch => (area.0 as u64, area.1 as u64),
+        };
+
+        // rounding may let contained sizes exceed the area by a pixel
+        let size = match self {
+            Scale::Contain | Scale::Cover => {
+                (scaled.0.min(area.0 as u64), scaled.1.min(area.1 as u64))
+            }
+            _ => scaled,
+        };
+
+        (scaled, size)
+    }
+}
+
+/// Resizes every frame of another source
+#[derive(Debug)]
+pub struct ScaledSource<Src: FrameSource> {
+    source: Src,
+    scaled: (u32, u32),
+    size: (u16, u16),
+    filter: FilterType,
+    /// Start and end of the scaled source frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl<Src: FrameSource> ScaledSource<Src> {
+    /// Fit modes are relative to `area`
+    pub fn new(
+        source: Src,
+        scale: Scale,
+        area: (u16, u16),
+        filter: FilterType,
+    ) -> Result<Self, ScaledSourceError> {
+        if let Scale::Percent(percent) = scale {
+            if !(percent.is_finite() && percent > 0.0) {
+                return Err(ScaledSourceError::Percent(percent));
+            }
+        }
+
+        let (scaled, size) = scale.sizes(source.size(), area);
+        let size = match (u16::try_from(size.0), u16::try_from(size.1)) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(ScaledSourceError::Size(size.0, size.1)),
+        };
+        let scaled = match (u32::try_from(scaled.0), u32::try_from(scaled.1)) {
+            (Ok(w), Ok(h)) => (w, h),
+            _ => return Err(ScaledSourceError::Size(scaled.0, scaled.1)),
+        };
+
+        Ok(Self {
+            source,
+            scaled,
+            size,
+            filter,
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+}
+
+fn scale(
+    pixels: &[[u8; 4]],
+    source: (u16, u16),
+    scaled: (u32, u32),
+    size: (u16, u16),
+    filter: FilterType,
+) -> Box<[[u8; 4]]> {
+    // BGRA frames are scaled the same way, every channel is filtered on its own
+    let image = ImageBuffer::<Rgba<u8>, _>::from_raw(
+        source.0 as u32,
+        source.1 as u32,
+        bytemuck::cast_slice::<_, u8>(pixels),
+    )
+    .expect("frame does not match its size");
+
+    let resized = imageops::resize(&image, scaled.0, scaled.1, filter);
+    let (x, y) = (
+        (scaled.0 - size.0 as u32) / 2,
+        (scaled.1 - size.1 as u32) / 2,
+    );
+    let cropped = imageops::crop_imm(&resized, x, y, size.0 as u32, size.1 as u32).to_image();
+
+    bytemuck::cast_slice::<u8, [u8; 4]>(cropped.as_raw()).into()
+}
+
+impl<Src: FrameSource> FrameSource for ScaledSource<Src> {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let source_size = self.source.size();
+        let timing = self.source.frame(delta);
+
+        let window = timing.window(delta);
+        if self.window != Some(window) {
+            let (scaled, size, filter) = (self.scaled, self.size, self.filter);
+            self.frame = timing
+                .frame
+                .map(|pixels| scale(pixels, source_size, scaled, size, filter));
+            self.window = Some(window);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: timing.frame_time,
+            time_left: timing.time_left,
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::alpha_policy::AlphaPolicy;
+use crate::frame_source::{common_cycle_time, Frame, FrameSource, Timing, STILL_TIME};
+use crate::motion_path::MotionPath;
+
+#[derive(Debug, Error)]
+pub enum SceneSourceError {
+    #[error("no layer of the scene is visible on the {0}x{1} canvas")]
+    Invisible(u16, u16),
+}
+
+#[derive(Debug)]
+pub struct Layer {
+    pub source: Box<dyn FrameSource>,
+    /// Position on the canvas
+    pub path: MotionPath,
+    /// Applied to the pixels of this layer before blending:
+    /// skipped pixels do not cover lower layers, opaque layers cover them completely
+    pub alpha: AlphaPolicy,
+}
+
+/// Alpha-blends layers into one frame, later layers are drawn on top
+///
+/// The frame covers the visible part of all layers on the canvas,
+/// see [SceneSource::offset] for its position.
+#[derive(Debug)]
+pub struct SceneSource {
+    layers: Vec<Layer>,
+    offset: (u16, u16),
+    size: (u16, u16),
+    cycle_time: Duration,
+    /// Start and end of the blended frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl SceneSource {
+    pub fn new(layers: Vec<Layer>, canvas_size: (u16, u16)) -> Result<Self, SceneSourceError> {
+        // the area a layer covers on the canvas along its whole path
+        let visible = |layer: &Layer| {
+            let (w, h) = layer.source.size();
+            let (min, max) = layer.path.bounds((w, h));
+            let start = (min.0.max(0), min.1.max(0));
+            let end = (
+                (max.0 + w as i32).min(canvas_size.0 as i32),
+                (max.1 + h as i32).min(canvas_size.1 as i32),
+            );
+            (start.0 < end.0 && start.1 < end.1).then_some((start, end))
+        };
+
+        let layers = layers
+            .into_iter()
+            .filter(|layer| visible(layer).is_some())
+            .collect::<Vec<_>>();
+        let (start, end) = layers
+            .iter()
+            .filter_map(visible)
+            .reduce(|(start, end), (s, e)| {
+                (
+                    (start.0.min(s.0), start.1.min(s.1)),
+                    (end.0.max(e.0), end.1.max(e.1)),
+                )
+            })
+            .ok_or(SceneSourceError::Invisible(canvas_size.0, canvas_size.1))?;
+
+        // a path that never starts over would jump back whenever the scene wraps around
+        let endless = layers
+            .iter()
+            .any(|layer| layer.path.endless(layer.source.size()));
+        let cycle_time = match endless {
+            true => STILL_TIME,
+            false => common_cycle_time(layers.iter().flat_map(|layer| {
+                [
+                    layer.source.cycle_time(),
+                    layer.path.cycle_time(layer.source.size()),
+                ]
+            })),
+        };
+        Ok(Self {
+            cycle_time,
+            layers,
+            offset: (start.0 as u16, start.1 as u16),
+            size: ((end.0 - start.0) as u16, (end.1 - start.1) as u16),
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+
+    /// Position of the blended frame on the canvas
+    pub fn offset(&self) -> (u16, u16) {
+        self.offset
+    }
+}
+
+impl FrameSource for SceneSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.cycle_time
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        if let Some((start, end)) = self.window {
+            if start <= delta && delta < end {
+                return Timing {
+                    frame: &self.frame,
+                    frame_time: end - start,
+                    time_left: end - delta,
+                };
+            }
+        }
+
+        let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+        let mut pixels = vec![[0; 4]; w * h].into_boxed_slice();
+
+        // time since the first layer changed and until the next one changes
+        let (mut elapsed, mut time_left) = (Duration::MAX, Duration::MAX);
+        for layer in &mut self.layers {
+            let (lw, lh) = (
+                layer.source.size().0 as usize,
+                layer.source.size().1 as usize,
+            );
+            let alpha = layer.alpha;
+
+            let position = layer.path.position(delta, (lw as u16, lh as u16));
+            if position.frame_time < STILL_TIME {
+                elapsed = elapsed.min(position.frame_time.saturating_sub(position.time_left));
+                time_left = time_left.min(position.time_left);
+            }
+            let timing = layer.source.frame(delta);
+            elapsed = elapsed.min(timing.frame_time.saturating_sub(timing.time_left));
+            time_left = time_left.min(timing.time_left);
+
+            // layer pixels left of and above the scene are cut off
+            let x = position.frame.0 - self.offset.0 as i32;
+            let y = position.frame.1 - self.offset.1 as i32;
+            let (skip_x, skip_y) = ((-x).max(0) as usize, (-y).max(0) as usize);
+            let (x, y) = (x.max(0) as usize, y.max(0) as usize);
+            let columns = lw.saturating_sub(skip_x).min(w.saturating_sub(x));
+            let rows = match columns {
+                0 => 0..0,
+                _ => skip_y..lh.min(h.saturating_sub(y) + skip_y),
+            };
+
+            let (frame, bgra) = match timing.frame {
+                Frame::Rgba(frame) => (frame, false),
+                Frame::Bgra(frame) => (frame, true),
+            };
+            for row in rows {
+                let from = &frame[row * lw + skip_x..][..columns];
+                let to = &mut pixels[(y + row - skip_y) * w + x..][..columns];
+                for (to, &pixel) in to.iter_mut().zip(from) {
+                    let [r, g, b, a] = match bgra {
+                        true => [pixel[2], pixel[1], pixel[0], pixel[3]],
+                        false => pixel,
+                    };
+                    let a = match alpha {
+                        _ if alpha.skips(a) => continue,
+                        AlphaPolicy::Opaque => 255,
+                        _ => a,
+                    };
+                    *to = over([r, g, b, a], *to);
+                }
+            }
+        }
+
+        self.frame = Frame::Rgba(pixels);
+        self.window = Some((
+            delta.saturating_sub(elapsed),
+            delta.saturating_add(time_left),
+        ));
+
+        Timing {
+            frame: &self.frame,
+            frame_time: elapsed.saturating_add(time_left),
+            time_left,
+        }
+    }
+}
+
+/// Draws the straight alpha color `top` over `bottom`
+fn over(top: [u8; 4], bottom: [u8; 4]) -> [u8; 4] {
+    match (top[3], bottom[3]) {
+        (255, _) | (_, 0) => top,
+        (0, _) => bottom,
+        (ta, ba) => {
+            let (ta, ba) = (ta as u32, ba as u32);
+            // alpha of the bottom color that shows through, scaled by 255
+            let shown = ba * (255 - ta);
+            let alpha = ta * 255 + shown;
+            let channel =
+                |t: u8, b: u8| ((t as u32 * ta * 255 + b as u32 * shown + alpha / 2) / alpha) as u8;
+
+            [
+                channel(top[0], bottom[0]),
+                channel(top[1], bottom[1]),
+                channel(top[2], bottom[2]),
+                ((alpha + 127) / 255) as u8,
+            ]
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+const Y4M_MAGIC: &str = "YUV4MPEG2";
+const MAX_HEADER_LENGTH: u64 = 1024;
+const DEFAULT_Y4M_FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 25);
+
+#[derive(Debug, Error)]
+pub enum StreamSourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid y4m stream: {0}")]
+    Header(String),
+    #[error("unsupported y4m colorspace: {0}")]
+    Colorspace(String),
+    #[error("frame size changed from {0:?} to {1:?}")]
+    SizeChanged((u16, u16), (u16, u16)),
+    #[error("stream ended before the first frame")]
+    Ended,
+}
+
+/// Where frames are read from
+#[derive(Debug, Clone)]
+pub enum StreamInput {
+    Stdin,
+    /// A file or FIFO, FIFOs are reopened when the writer goes away
+    Path(PathBuf),
+    /// Listen on a Unix socket and read from one producer after another
+    Unix(PathBuf),
+}
+
+/// How frames are encoded
+#[derive(Debug, Copy, Clone)]
+pub enum StreamFormat {
+    Rgba {
+        size: (u16, u16),
+        frame_time: Duration,
+    },
+    Bgra {
+        size: (u16, u16),
+        frame_time: Duration,
+    },
+    /// YUV4MPEG2, size and frame rate are read from the stream header
+    Y4m,
+}
+
+/// Shows the latest complete frame pushed by another program
+///
+/// A reader thread decodes frames as fast as they arrive and replaces any frame that was not
+/// shown yet. Live streams do not repeat, the cycle time is [Duration::MAX].
+#[derive(Debug)]
+pub struct StreamSource {
+    size: (u16, u16),
+    frame_time: Duration,
+    latest: Arc<Mutex<Option<Frame>>>,
+    current: Frame,
+}
+
+impl StreamSource {
+    /// Waits for the first producer and its first frame
+    pub fn new(input: StreamInput, format: StreamFormat) -> Result<Self, StreamSourceError> {
+        let mut producers = Producers::new(input)?;
+        let reader = producers.next()?.ok_or(StreamSourceError::Ended)?;
+        let mut decoder = Decoder::new(reader, format)?;
+        let current = decoder.frame()?.ok_or(StreamSourceError::Ended)?;
+
+        let (size, frame_time) = (decoder.size, decoder.frame_time);
+        let latest = Arc::new(Mutex::new(None));
+        let slot = latest.clone();
+        std::thread::Builder::new()
+            .name("stream reader".to_string())
+            .spawn(move || read(producers, decoder, format, slot))?;
+
+        Ok(Self {
+            size,
+            frame_time,
+            latest,
+            current,
+        })
+    }
+}
+
+/// Publishes frames of all producers until the [StreamSource] is dropped
+fn read(
+    mut producers: Producers,
+    mut decoder: Decoder,
+    format: StreamFormat,
+    latest: Arc<Mutex<Option<Frame>>>,
+) {
+    let size = decoder.size;
+    loop {
+        match decoder.frame() {
+            Ok(Some(frame)) => {
+                if Arc::strong_count(&latest) == 1 {
+                    return;
+                }
+                *latest.lock().unwrap() = Some(frame);
+                continue;
+            }
+            Ok(None) => info!("stream producer finished"),
+            Err(e) => warn!("dropping stream producer: {e}"),
+        }
+
+        decoder = loop {
+            let reader = match producers.next() {
+                Ok(Some(reader)) => reader,
+                Ok(None) => {
+                    info!("stream ended, keeping the last frame");
+                    return;
+                }
+                Err(e) => {
+                    warn!("unable to open next stream producer: {e}");
+                    return;
+                }
+            };
+
+            match Decoder::new(reader, format) {
+                Ok(decoder) if decoder.size == size => break decoder,
+                Ok(decoder) => warn!(
+                    "dropping stream producer: {}",
+                    StreamSourceError::SizeChanged(size, decoder.size)
+                ),
+                Err(e) => warn!("dropping stream producer: {e}"),
+            }
+        };
+    }
+}
+
+impl FrameSource for StreamSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        Duration::MAX
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        if let Some(frame) = self.latest.lock().unwrap().take() {
+            self.current = frame;
+        }
+
+        let into_frame = (delta.as_nanos() % self.frame_time.as_nanos().max(1)) as u64;
+        Timing {
+            frame: &self.current,
+            frame_time: self.frame_time,
+            time_left: self.frame_time - Duration::from_nanos(into_frame),
+        }
+    }
+}
+
+enum Producers {
+    Stdin { opened: bool },
+    Path { path: PathBuf, opened: bool },
+    Unix(UnixListener),
+}
+
+impl Producers {
+    fn new(input: StreamInput) -> std::io::Result<Self> {
+        Ok(match input {
+            StreamInput::Stdin => Self::Stdin { opened: false },
+            StreamInput::Path(path) => Self::Path {
+                path,
+                opened: false,
+            },
+            StreamInput::Unix(path) => {
+                // a socket left behind by an earlier run
+                if std::fs::metadata(&path).is_ok_and(|m| m.file_type().is_socket()) {
+                    std::fs::remove_file(&path)?;
+                }
+                info!("waiting for stream producers on {}", path.display());
+                Self::Unix(UnixListener::bind(&path)?)
+            }
+        })
+    }
+
+    /// Waits for the next producer, `None` if there will be none
+    fn next(&mut self) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        match self {
+            Self::Stdin { opened: true } => Ok(None),
+            Self::Stdin { opened } => {
+                *opened = true;
+                Ok(Some(Box::new(std::io::stdin())))
+            }
+            Self::Path { path, opened } => {
+                if *opened && !std::fs::metadata(&path)?.file_type().is_fifo() {
+                    return Ok(None);
+                }
+                *opened = true;
+                Ok(Some(Box::new(File::open(path)?)))
+            }
+            Self::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok(Some(Box::new(stream)))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Encoding {
+    Rgba,
+    Bgra,
+    Yuv(Colorspace),
+}
+
+/// 8 bit YUV layouts, chroma subsampling as shift of width and height
+#[derive(Debug, Copy, Clone)]
+struct Colorspace {
+    chroma: Option<(u8, u8)>,
+    alpha: bool,
+}
+
+impl Colorspace {
+    fn parse(name: &str) -> Result<Self, StreamSourceError> {
+        let (chroma, alpha) = match name {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => (Some((1, 1)), false),
+            "422" => (Some((1, 0)), false),
+            "444" => (Some((0, 0)), false),
+            "444alpha" => (Some((0, 0)), true),
+            "mono" => (None, false),
+            _ => return Err(StreamSourceError::Colorspace(name.to_string())),
+        };
+
+        Ok(Self { chroma, alpha })
+    }
+}
+
+struct Decoder {
+    reader: BufReader<Box<dyn Read + Send>>,
+    encoding: Encoding,
+    size: (u16, u16),
+    frame_time: Duration,
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(reader: Box<dyn Read + Send>, format: StreamFormat) -> Result<Self, StreamSourceError> {
+        let mut reader = BufReader::new(reader);
+        let (encoding, size, frame_time) = match format {
+            StreamFormat::Rgba { size, frame_time } => (Encoding::Rgba, size, frame_time),
+            StreamFormat::Bgra { size, frame_time } => (Encoding::Bgra, size, frame_time),
+            StreamFormat::Y4m => {
+                let line = header_line(&mut reader)?.ok_or(StreamSourceError::Ended)?;
+                let (colorspace, size, frame_time) = y4m_header(&line)?;
+                (Encoding::Yuv(colorspace), size, frame_time)
+            }
+        };
+
+        Ok(Self {
+            reader,
+            encoding,
+            size,
+            frame_time,
+            buffer: vec![],
+        })
+    }
+
+    /// Reads the next frame, `None` if the producer finished
+    fn frame(&mut self) -> Result<Option<Frame>, StreamSourceError> {
+        let pixels = self.size.0 as usize * self.size.1 as usize;
+        match self.encoding {
+            Encoding::Rgba | Encoding::Bgra => {
+                let mut frame = vec![[0u8; 4]; pixels].into_boxed_slice();
+                if !read_frame(&mut self.reader, bytemuck::cast_slice_mut(&mut frame))? {
+                    return Ok(None);
+                }
+
+                Ok(Some(match self.encoding {
+                    Encoding::Bgra => Frame::Bgra(frame),
+                    _ => Frame::Rgba(frame),
+                }))
+            }
+            Encoding::Yuv(colorspace) => {
+                let Some(line) = header_line(&mut self.reader)? else {
+                    return Ok(None);
+                };
+                if !line.starts_with("FRAME") {
+                    return Err(StreamSourceError::Header(format!(
+                        "expected FRAME, got \"{line}\""
+                    )));
+                }
+
+                let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+                let (cw, ch) = match colorspace.chroma {
+                    Some((sx, sy)) => ((w + (1 << sx) - 1) >> sx, (h + (1 << sy) - 1) >> sy),
+                    None => (0, 0),
+                };
+                let alpha = if colorspace.alpha { pixels } else { 0 };
+
+                self.buffer.resize(pixels + 2 * cw * ch + alpha, 0);
+                if !read_frame(&mut self.reader, &mut self.buffer)? {
+                    return Ok(None);
+                }
+
+                Ok(Some(Frame::Rgba(yuv_to_rgba(
+                    &self.buffer,
+                    self.size,
+                    colorspace,
+                ))))
+            }
+        }
+    }
+}
+
+/// Fills `buffer`, false if the stream ended right before it
+fn read_frame<R: Read>(reader: &mut R, buffer: &mut [u8]) -> std::io::Result<bool> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Reads a header line without the newline, `None` at the end of the stream
+fn header_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, StreamSourceError> {
+    let mut line = vec![];
+    reader
+        .take(MAX_HEADER_LENGTH)
+        .read_until(b'\n', &mut line)?;
+
+    match line.pop() {
+        None => Ok(None),
+        Some(b'\n') => String::from_utf8(line)
+            .map(Some)
+            .map_err(|_| StreamSourceError::Header("header is not utf-8".to_string())),
+        Some(_) => Err(StreamSourceError::Header("incomplete header".to_string())),
+    }
+}
+
+fn y4m_header(line: &str) -> Result<(Colorspace, (u16, u16), Duration), StreamSourceError> {
+    let mut params = line.split(' ');
+    if params.next() != Some(Y4M_MAGIC) {
+        return Err(StreamSourceError::Header(format!(
+            "missing {Y4M_MAGIC} signature"
+        )));
+    }
+
+    let invalid = |param: &str| StreamSourceError::Header(format!("invalid parameter {param}"));
+    let (mut width, mut height) = (None, None);
+    let mut frame_time = DEFAULT_Y4M_FRAME_TIME;
+    let mut colorspace = Colorspace::parse("420jpeg")?;
+    for param in params.filter(|p| !p.is_empty()) {
+        let mut chars = param.chars();
+        let (key, value) = (chars.next(), chars.as_str());
+        match key {
+            Some('W') => width = Some(value.parse::<u16>().map_err(|_| invalid(param))?),
+            Some('H') => height = Some(value.parse::<u16>().map_err(|_| invalid(param))?),
+            Some('F') => {
+                let (num, den) = value.split_once(':').ok_or_else(|| invalid(param))?;
+                let num = num.parse::<u64>().map_err(|_| invalid(param))?;
+                let den = den.parse::<u64>().map_err(|_| invalid(param))?;
+                if let Some(nanos) = den
+                    .checked_mul(1_000_000_000)
+                    .and_then(|d| d.checked_div(num))
+                {
+                    frame_time = Duration::from_nanos(nanos);
+                }
+            }
+            Some('C') => colorspace = Colorspace::parse(value)?,
+            // interlacing, aspect ratio and extensions do not matter here
+            _ => {}
+        }
+    }
+
+    match (width, height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Ok((colorspace, (w, h), frame_time)),
+        _ => Err(StreamSourceError::Header("missing frame size".to_string())),
+    }
+}
+
+/// Converts BT.601 limited range YUV planes
+fn yuv_to_rgba(planes: &[u8], size: (u16, u16), colorspace: Colorspace) -> Box<[[u8; 4]]> {
+    let (w, h) = (size.0 as usize, size.1 as usize);
+    let (y_plane, rest) = planes.split_at(w * h);
+    let (sx, sy) = colorspace.chroma.unwrap_or((0, 0));
+    let cw = (w + (1 << sx) - 1) >> sx;
+    let chroma_len = if colorspace.chroma.is_some() {
+        cw * ((h + (1 << sy) - 1) >> sy)
+    } else {
+        0
+    };
+    let (u_plane, rest) = rest.split_at(chroma_len);
+    let (v_plane, a_plane) = rest.split_at(chroma_len);
+
+    let mut frame = vec![[0u8; 4]; w * h].into_boxed_slice();
+    frame.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let luma = (y_plane[y * w + x] as i32 - 16) * 298;
+            let (u, v) = match colorspace.chroma {
+                Some(_) => {
+                    let i = (y >> sy) * cw + (x >> sx);
+                    (u_plane[i] as i32 - 128, v_plane[i] as i32 - 128)
+                }
+                None => (0, 0),
+            };
+            let alpha = if colorspace.alpha {
+                a_plane[y * w + x]
+            } else {
+                255
+            };
+
+            *pixel = [
+                ((luma + 409 * v + 128) >> 8).clamp(0, 255) as u8,
+                ((luma - 100 * u - 208 * v + 128) >> 8).clamp(0, 255) as u8,
+                ((luma + 516 * u + 128) >> 8).clamp(0, 255) as u8,
+                alpha,
+            ];
+        }
+    });
+
+    frame
+}
@@ -0,0 +1,212 @@
+use std::io::Read;
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{Options, Tree};
+use thiserror::Error;
+
+use crate::flut_op::DebugShield;
+use crate::frame_source::scaled_source::Scale;
+use crate::frame_source::transformed_source::{self, TransformedSourceError};
+use crate::frame_source::{Frame, FrameSource, Timing, STILL_TIME};
+
+#[derive(Debug, Error)]
+pub enum SvgSourceError {
+    #[error("invalid svg: {0}")]
+    Svg(#[from] resvg::usvg::Error),
+    #[error("invalid rendered size: {0}x{1}")]
+    Size(u64, u64),
+    #[error("invalid scale: {0}%")]
+    Percent(f32),
+    #[error("invalid transform: {0}")]
+    Transform(#[from] TransformedSourceError),
+}
+
+/// Whether the start of a file looks like an SVG document, gzip compressed ones included
+///
+/// The root element has to be `svg`, after the XML declaration, comments and the doctype.
+pub fn is_svg(head: &[u8]) -> bool {
+    if !head.starts_with(&[0x1f, 0x8b]) {
+        return has_svg_root(head);
+    }
+
+    // only the start of the file is given, the decompressed part is all that is needed
+    let mut decompressed = vec![];
+    let _ = GzDecoder::new(head).read_to_end(&mut decompressed);
+    has_svg_root(&decompressed)
+}
+
+fn has_svg_root(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    let mut rest = head.trim_start_matches('\u{feff}');
+    loop {
+        rest = rest.trim_start();
+        let end = if rest.starts_with("<?") {
+            rest.find("?>").map(|end| end + 2)
+        } else if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else if rest.starts_with("<!DOCTYPE") {
+            // the internal subset in brackets may contain '>'
+            let close = rest.find('>');
+            match rest.find('[') {
+                Some(open) if close.is_some_and(|close| open < close) => {
+                    rest[open..].find(']').and_then(|end| {
+                        rest[open + end..]
+                            .find('>')
+                            .map(|close| open + end + close + 1)
+                    })
+                }
+                _ => close.map(|close| close + 1),
+            }
+        } else {
+            let Some(element) = rest.strip_prefix('<') else {
+                return false;
+            };
+            let name = element
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default();
+            return name == "svg" || name.ends_with(":svg");
+        };
+
+        match end {
+            Some(end) => rest = &rest[end..],
+            // the prolog goes on after the given start
+            None => return false,
+        }
+    }
+}
+
+/// Renders a vector graphic as a still image
+///
+/// The document is kept, so it can be rendered again at any size without losing sharpness.
+#[derive(Debug)]
+pub struct SvgSource {
+    tree: DebugShield<Tree>,
+    size: (u16, u16),
+    frame: Frame,
+}
+
+impl SvgSource {
+    /// Parses an SVG or SVGZ document and renders it at its own size
+    pub fn new(data: &[u8]) -> Result<Self, SvgSourceError> {
+        let mut options = Options::default();
+        options.fontdb_mut().load_system_fonts();
+        let tree = Tree::from_data(data, &options)?;
+
+        let mut source = Self {
+            tree: tree.into(),
+            size: (0, 0),
+            frame: Frame::Rgba(Box::new([])),
+        };
+        source.rasterize(Scale::Percent(100.0), (0, 0))?;
+        Ok(source)
+    }
+
+    /// Size of the document itself in whole pixels
+    pub fn own_size(&self) -> (u16, u16) {
+        let own = self.tree.get().size();
+        (
+            (own.width().ceil() as u64).clamp(1, u16::MAX as u64) as u16,
+            (own.height().ceil() as u64).clamp(1, u16::MAX as u64) as u16,
+        )
+    }
+
+    /// Renders the document again at the size `scale` gives it, fit modes are relative to `area`
+    pub fn rasterize(&mut self, scale: Scale, area: (u16, u16)) -> Result<(), SvgSourceError> {
+        if let Scale::Percent(percent) = scale {
+            if !(percent.is_finite() && percent > 0.0) {
+                return Err(SvgSourceError::Percent(percent));
+            }
+        }
+
+        let (scaled, size) = scale.sizes(self.own_size(), area);
+        self.render(scaled, size)
+    }
+
+    /// Renders the document for `transforms` that are followed by `scale`
+    ///
+    /// Transforms work on pixels, so the document is rendered at the resolution the transformed
+    /// frame is scaled to and crops are scaled along, scaling afterwards never enlarges it.
+    /// Returns the transforms for the rendered size.
+    pub fn rasterize_transformed(
+        &mut self,
+        scale: Scale,
+        area: (u16, u16),
+        transforms: &[transformed_source::Transform],
+    ) -> Result<Vec<transformed_source::Transform>, SvgSourceError> {
+        let own = self.own_size();
+        let transformed = transforms
+            .iter()
+            .try_fold(own, |size, transform| transform.size(size))?;
+        let (scaled, _) = scale.sizes(transformed, area);
+        let factor = (scaled.0 as f64 / transformed.0 as f64)
+            .max(scaled.1 as f64 / transformed.1 as f64)
+            .max(1.0);
+
+        self.rasterize(Scale::Percent((factor * 100.0) as f32), (0, 0))?;
+        let mut size = self.size;
+        transforms
+            .iter()
+            .map(|transform| {
+                let transform = transform.scaled(factor, size);
+                size = transform.size(size)?;
+                Ok(transform)
+            })
+            .collect()
+    }
+
+    /// Renders the document stretched to `scaled`, cropped centered to `size`
+    fn render(&mut self, scaled: (u64, u64), size: (u64, u64)) -> Result<(), SvgSourceError> {
+        let size = match (u16::try_from(size.0), u16::try_from(size.1)) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(SvgSourceError::Size(size.0, size.1)),
+        };
+        let mut pixmap = Pixmap::new(size.0 as u32, size.1 as u32)
+            .ok_or(SvgSourceError::Size(size.0 as u64, size.1 as u64))?;
+
+        let own = self.tree.get().size();
+        let transform = Transform::from_scale(
+            scaled.0 as f32 / own.width(),
+            scaled.1 as f32 / own.height(),
+        )
+        .post_translate(
+            -(((scaled.0 - size.0 as u64) / 2) as f32),
+            -(((scaled.1 - size.1 as u64) / 2) as f32),
+        );
+        resvg::render(self.tree.get(), transform, &mut pixmap.as_mut());
+
+        // tiny-skia keeps premultiplied alpha, frames are straight
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .map(|pixel| {
+                let pixel = pixel.demultiply();
+                [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+            })
+            .collect();
+
+        self.size = size;
+        self.frame = Frame::Rgba(pixels);
+        Ok(())
+    }
+}
+
+impl FrameSource for SvgSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        STILL_TIME
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        Timing {
+            frame: &self.frame,
+            frame_time: STILL_TIME,
+            time_left: STILL_TIME.saturating_sub(delta),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::path::Path;
+use std::time::Duration;
+
+use ab_glyph::{Font as _, FontArc, InvalidFont, PxScale, ScaleFont};
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing, STILL_TIME};
+
+pub(crate) mod bitmap_font;
+
+#[derive(Debug, Error)]
+pub enum TextSourceError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid font: {0}")]
+    Font(#[from] InvalidFont),
+    #[error("nothing to render")]
+    Empty,
+    #[error("rendered text too large: {0}x{1}")]
+    TooLarge(usize, usize),
+    #[error("invalid marquee speed: {0}")]
+    Speed(f32),
+}
+
+#[derive(Debug, Clone)]
+pub enum Font {
+    /// Built-in 5x7 pixel font, scaled by whole pixels
+    Bitmap,
+    Outline(FontArc),
+}
+
+impl Font {
+    /// Loads a TTF or OTF font
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, TextSourceError> {
+        Ok(Self::Outline(FontArc::try_from_vec(std::fs::read(path)?)?))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TextStyle {
+    /// Line height in pixels
+    pub size: f32,
+    pub foreground: [u8; 4],
+    pub background: [u8; 4],
+    /// Outline color and width in pixels
+    pub outline: Option<([u8; 4], u16)>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum TextMode {
+    Static,
+    /// Scrolls the text from right to left through a window of `width` pixels
+    Marquee {
+        width: u16,
+        speed: f32,
+    },
+}
+
+#[derive(Debug)]
+struct Marquee {
+    /// Time to move by one pixel
+    step: Duration,
+    /// Pixels until the text scrolled through the window once
+    period: u32,
+    /// `step * period`
+    cycle_time: Duration,
+    offset: Option<u32>,
+}
+
+/// Renders text once and shows it still or as a marquee
+#[derive(Debug)]
+pub struct TextSource {
+    size: (u16, u16),
+    text: Box<[[u8; 4]]>,
+    text_width: usize,
+    background: [u8; 4],
+    marquee: Option<Marquee>,
+    frame: Frame,
+}
+
+impl TextSource {
+    pub fn new(
+        text: &str,
+        font: &Font,
+        style: TextStyle,
+        mode: TextMode,
+    ) -> Result<Self, TextSourceError> {
+        let (mask, (w, h)) = match font {
+            Font::Bitmap => bitmap_mask(text, style.size),
+            Font::Outline(font) => outline_mask(text, font, style.size),
+        };
+        if w == 0 || h == 0 {
+            return Err(TextSourceError::Empty);
+        }
+
+        let (outline, border) = match style.outline {
+            Some((color, width)) if width > 0 => (Some(color), width as usize),
+            _ => (None, 0),
+        };
+        let (w, h, mask) = pad(&mask, (w, h), border);
+        let outline_mask = outline.map(|_| dilate(&mask, (w, h), border));
+
+        let text = (0..w * h)
+            .map(|i| {
+                let pixel = match (outline, &outline_mask) {
+                    (Some(color), Some(outline_mask)) => {
+                        blend(style.background, color, outline_mask[i])
+                    }
+                    _ => style.background,
+                };
+                blend(pixel, style.foreground, mask[i])
+            })
+            .collect::<Box<[_]>>();
+
+        let (frame_width, marquee) = match mode {
+            TextMode::Static => (w, None),
+            TextMode::Marquee { width, speed } => {
+                if !(speed.is_finite() && speed > 0.0) {
+                    return Err(TextSourceError::Speed(speed));
+                }
+                let step = Duration::try_from_secs_f64(1.0 / speed as f64)
+                    .ok()
+                    .filter(|step| !step.is_zero())
+                    .ok_or(TextSourceError::Speed(speed))?;
+                let period = u32::try_from(w + width as usize)
+                    .map_err(|_| TextSourceError::TooLarge(w, h))?;
+                let marquee = Marquee {
+                    step,
+                    period,
+                    cycle_time: step
+                        .checked_mul(period)
+                        .ok_or(TextSourceError::Speed(speed))?,
+                    offset: None,
+                };
+                (width as usize, Some(marquee))
+            }
+        };
+        let size = match (u16::try_from(frame_width), u16::try_from(h)) {
+            (Ok(fw), Ok(fh)) if fw > 0 => (fw, fh),
+            _ => return Err(TextSourceError::TooLarge(frame_width, h)),
+        };
+
+        let frame = Frame::Rgba(match marquee {
+            None => text.clone(),
+            Some(_) => vec![style.background; frame_width * h].into_boxed_slice(),
+        });
+
+        Ok(Self {
+            size,
+            text,
+            text_width: w,
+            background: style.background,
+            marquee,
+            frame,
+        })
+    }
+
+    /// Copies the part of the text visible at `offset` into the frame
+    fn scroll(&mut self, offset: u32, period: u32) {
+        let Frame::Rgba(frame) = &mut self.frame else {
+            unreachable!()
+        };
+
+        let width = self.size.0 as usize;
+        for (y, row) in frame.chunks_exact_mut(width).enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                // the text enters at the right edge of the window
+                let column = ((offset as u64 + x as u64) % period as u64) as i64 - width as i64;
+                *pixel = match usize::try_from(column) {
+                    Ok(column) if column < self.text_width => {
+                        self.text[y * self.text_width + column]
+                    }
+                    _ => self.background,
+                };
+            }
+        }
+    }
+}
+
+/// Coverage of the built-in font, one row of glyph cells per line
+fn bitmap_mask(text: &str, size: f32) -> (Vec<f32>, (usize, usize)) {
+    let scale = ((size / (bitmap_font::HEIGHT + 1) as f32).round() as usize).max(1);
+    let (cell_w, cell_h) = (
+        (bitmap_font::WIDTH + 1) * scale,
+        (bitmap_font::HEIGHT + 1) * scale,
+    );
+
+    let lines = text.lines().collect::<Vec<_>>();
+    let columns = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    // no spacing after the last column and below the last line
+    let w = (columns * cell_w).saturating_sub(scale);
+    let h = (lines.len() * cell_h).saturating_sub(scale);
+
+    let mut mask = vec![0.0; w * h];
+    for (line, text) in lines.iter().enumerate() {
+        for (column, c) in text.chars().enumerate() {
+            for (gx, bits) in bitmap_font::glyph(c).into_iter().enumerate() {
+                for gy in (0..bitmap_font::HEIGHT).filter(|gy| bits & (1 << gy) != 0) {
+                    for (sx, sy) in (0..scale).flat_map(|sx| (0..scale).map(move |sy| (sx, sy))) {
+                        let x = column * cell_w + gx * scale + sx;
+                        let y = line * cell_h + gy * scale + sy;
+                        mask[y * w + x] = 1.0;
+                    }
+                }
+            }
+        }
+    }
+
+    (mask, (w, h))
+}
+
+/// Anti-aliased coverage of a TTF or OTF font
+fn outline_mask(text: &str, font: &FontArc, size: f32) -> (Vec<f32>, (usize, usize)) {
+    let font = font.as_scaled(PxScale::from(size));
+    let line_height = font.ascent() - font.descent() + font.line_gap();
+
+    let mut glyphs = vec![];
+    let mut width = 0f32;
+    let lines = text.lines().collect::<Vec<_>>();
+    for (line, text) in lines.iter().enumerate() {
+        let baseline = line as f32 * line_height + font.ascent();
+        let mut x = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                x += font.kern(previous, id);
+            }
+            glyphs.push(id.with_scale_and_position(font.scale(), ab_glyph::point(x, baseline)));
+            x += font.h_advance(id);
+            previous = Some(id);
+        }
+        width = width.max(x);
+    }
+
+    let (w, h) = (
+        width.ceil() as usize,
+        (lines.len() as f32 * line_height - font.line_gap()).ceil() as usize,
+    );
+    let mut mask = vec![0.0f32; w * h];
+    for glyph in glyphs {
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let x = bounds.min.x as i64 + gx as i64;
+            let y = bounds.min.y as i64 + gy as i64;
+            if (0..w as i64).contains(&x) && (0..h as i64).contains(&y) {
+                let i = y as usize * w + x as usize;
+                mask[i] = (mask[i] + coverage).min(1.0);
+            }
+        });
+    }
+
+    (mask, (w, h))
+}
+
+/// Adds `border` empty pixels on every side
+fn pad(mask: &[f32], (w, h): (usize, usize), border: usize) -> (usize, usize, Vec<f32>) {
+    let (pw, ph) = (w + 2 * border, h + 2 * border);
+    let mut padded = vec![0.0; pw * ph];
+    for y in 0..h {
+        let start = (y + border) * pw + border;
+        padded[start..start + w].copy_from_slice(&mask[y * w..(y + 1) * w]);
+    }
+
+    (pw, ph, padded)
+}
+
+/// Grows the coverage by a disc of `radius` pixels
+fn dilate(mask: &[f32], (w, h): (usize, usize), radius: usize) -> Vec<f32> {
+    let r = radius as i64;
+    let disc = (-r..=r)
+        .flat_map(|dy| (-r..=r).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| dx * dx + dy * dy <= r * r)
+        .collect::<Vec<_>>();
+
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = ((i % w) as i64, (i / w) as i64);
+            disc.iter()
+                .filter_map(|(dx, dy)| {
+                    let (sx, sy) = (x + dx, y + dy);
+                    ((0..w as i64).contains(&sx) && (0..h as i64).contains(&sy))
+                        .then(|| mask[sy as usize * w + sx as usize])
+                })
+                .fold(0.0, f32::max)
+        })
+        .collect()
+}
+
+fn blend(below: [u8; 4], above: [u8; 4], coverage: f32) -> [u8; 4] {
+    let mut out = [0; 4];
+    for (out, (b, a)) in out.iter_mut().zip(below.into_iter().zip(above)) {
+        *out = (b as f32 + (a as f32 - b as f32) * coverage).round() as u8;
+    }
+    out
+}
+
+impl FrameSource for TextSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        match &self.marquee {
+            None => STILL_TIME,
+            Some(marquee) => marquee.cycle_time,
+        }
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let Some(marquee) = &self.marquee else {
+            return Timing {
+                frame: &self.frame,
+                frame_time: STILL_TIME,
+                time_left: STILL_TIME.saturating_sub(delta),
+            };
+        };
+
+        let (step, period) = (marquee.step, marquee.period);
+        let offset = (delta.as_nanos() / step.as_nanos() % period as u128) as u32;
+        if marquee.offset != Some(offset) {
+            self.scroll(offset, period);
+            if let Some(marquee) = &mut self.marquee {
+                marquee.offset = Some(offset);
+            }
+        }
+
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        Timing {
+            frame: &self.frame,
+            frame_time: step,
+            time_left: (step * (offset + 1)).saturating_sub(delta),
+        }
+    }
+}
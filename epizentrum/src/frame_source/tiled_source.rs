@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+/// Color of the spacing between tiles
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+#[derive(Debug, Error)]
+pub enum TiledSourceError {
+    #[error("invalid tiled area: {0}x{1}")]
+    Size(u16, u16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tiling {
+    /// Area covered with tiles, tiles at the right and bottom edge are cut off
+    pub size: (u16, u16),
+    /// Transparent gap between neighboring tiles
+    pub spacing: (u16, u16),
+    /// How far every tile runs behind the previous one, row by row
+    pub phase: Duration,
+}
+
+/// Repeats the frames of another source across an area
+///
+/// All tiles share the frames of the source. Tiles with a phase offset request
+/// the source once per distinct time, sorted to keep streaming sources moving forward.
+#[derive(Debug)]
+pub struct TiledSource<Src: FrameSource> {
+    source: Src,
+    tiling: Tiling,
+    /// Top left corner of every tile
+    tiles: Box<[(usize, usize)]>,
+    /// Start and end of the tiled frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl<Src: FrameSource> TiledSource<Src> {
+    pub fn new(source: Src, tiling: Tiling) -> Result<Self, TiledSourceError> {
+        let (w, h) = tiling.size;
+        if w == 0 || h == 0 {
+            return Err(TiledSourceError::Size(w, h));
+        }
+
+        let (tw, th) = source.size();
+        let step = (
+            (tw as usize + tiling.spacing.0 as usize).max(1),
+            (th as usize + tiling.spacing.1 as usize).max(1),
+        );
+        let tiles = (0..h as usize)
+            .step_by(step.1)
+            .flat_map(|y| (0..w as usize).step_by(step.0).map(move |x| (x, y)))
+            .collect();
+
+        Ok(Self {
+            source,
+            tiling,
+            tiles,
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+
+    /// Offset of a tile into the source animation
+    fn phase(&self, tile: usize) -> Duration {
+        let cycle_time = self.source.cycle_time().as_nanos().max(1);
+        let phase = (self.tiling.phase.as_nanos() * tile as u128) % cycle_time;
+        Duration::from_nanos(phase as u64)
+    }
+}
+
+impl<Src: FrameSource> FrameSource for TiledSource<Src> {
+    fn size(&self) -> (u16, u16) {
+        self.tiling.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        if let Some((start, end)) = self.window {
+            if start <= delta && delta < end {
+                return Timing {
+                    frame: &self.frame,
+                    frame_time: end - start,
+                    time_left: end - delta,
+                };
+            }
+        }
+
+        let (w, h) = (self.tiling.size.0 as usize, self.tiling.size.1 as usize);
+        let (tw, th) = (self.source.size().0 as usize, self.source.size().1 as usize);
+        let mut pixels = vec![TRANSPARENT; w * h].into_boxed_slice();
+        let mut bgra = false;
+
+        let mut tiles = (0..self.tiles.len())
+            .map(|tile| (self.phase(tile), tile))
+            .collect::<Vec<_>>();
+        tiles.sort_unstable();
+
+        // time since the first tile changed and until the next one changes
+        let (mut elapsed, mut time_left) = (Duration::MAX, Duration::MAX);
+        for tiles in tiles.chunk_by(|a, b| a.0 == b.0) {
+            let timing = self.source.frame(delta + tiles[0].0);
+            elapsed = elapsed.min(timing.frame_time.saturating_sub(timing.time_left));
+            time_left = time_left.min(timing.time_left);
+
+            let frame = match timing.frame {
+                Frame::Rgba(frame) => frame,
+                Frame::Bgra(frame) => {
+                    bgra = true;
+                    frame
+                }
+            };
+            for &(_, tile) in tiles {
+                let (x, y) = self.tiles[tile];
+                let cw = tw.min(w - x);
+                for row in 0..th.min(h - y) {
+                    let to = (y + row) * w + x;
+                    pixels[to..to + cw].copy_from_slice(&frame[row * tw..row * tw + cw]);
+                }
+            }
+        }
+
+        self.frame = match bgra {
+            true => Frame::Bgra(pixels),
+            false => Frame::Rgba(pixels),
+        };
+        let timing = Timing {
+            frame: &self.frame,
+            frame_time: elapsed + time_left,
+            time_left,
+        };
+        self.window = Some(timing.window(delta));
+
+        timing
+    }
+}
@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+/// Color of pixels without a source pixel after arbitrary rotations
+const TRANSPARENT: [u8; 4] = [0, 0, 0, 0];
+
+#[derive(Debug, Error)]
+pub enum TransformedSourceError {
+    #[error("crop {x},{y} {w}x{h} is outside of the {}x{} frame", .size.0, .size.1)]
+    Crop {
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+        size: (u16, u16),
+    },
+    #[error("invalid rotation: {0}°")]
+    Angle(f32),
+    #[error("transformed frame too large: {0}x{1}")]
+    TooLarge(usize, usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transform {
+    Crop {
+        x: u16,
+        y: u16,
+        w: u16,
+        h: u16,
+    },
+    /// Clockwise quarter turns
+    Rotate(u8),
+    /// Clockwise rotation in degrees around the center, the frame grows to fit the corners
+    RotateBy(f32),
+    /// Mirror left and right
+    FlipHorizontal,
+    /// Mirror top and bottom
+    FlipVertical,
+}
+
+impl Transform {
+    /// Clockwise rotation by `degrees`, multiples of 90 are exact
+    pub fn rotation(degrees: f32) -> Result<Self, TransformedSourceError> {
+        if !degrees.is_finite() {
+            return Err(TransformedSourceError::Angle(degrees));
+        }
+
+        let degrees = degrees.rem_euclid(360.0);
+        Ok(if degrees % 90.0 == 0.0 {
+            Transform::Rotate((degrees / 90.0) as u8)
+        } else {
+            Transform::RotateBy(degrees)
+        })
+    }
+
+    /// Size of the frame this transform turns a frame of `(w, h)` into
+    pub(crate) fn size(&self, (w, h): (u16, u16)) -> Result<(u16, u16), TransformedSourceError> {
+        match *self {
+            Transform::Crop { x, y, w: cw, h: ch } => {
+                let fits = cw > 0
+                    && ch > 0
+                    && x as u32 + cw as u32 <= w as u32
+                    && y as u32 + ch as u32 <= h as u32;
+                match fits {
+                    true => Ok((cw, ch)),
+                    false => Err(TransformedSourceError::Crop {
+                        x,
+                        y,
+                        w: cw,
+                        h: ch,
+                        size: (w, h),
+                    }),
+                }
+            }
+            Transform::Rotate(turns) if turns % 2 == 1 => Ok((h, w)),
+            Transform::RotateBy(degrees) => {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                let (w, h) = (w as f32, h as f32);
+                // drop the rounding noise of the trigonometry before rounding up
+                let rw = ((w * cos.abs() + h * sin.abs()) - 1e-3).ceil() as usize;
+                let rh = ((w * sin.abs() + h * cos.abs()) - 1e-3).ceil() as usize;
+                match (u16::try_from(rw), u16::try_from(rh)) {
+                    (Ok(rw), Ok(rh)) => Ok((rw, rh)),
+                    _ => Err(TransformedSourceError::TooLarge(rw, rh)),
+                }
+            }
+            _ => Ok((w, h)),
+        }
+    }
+
+    /// The same transform of a frame `factor` times as large, crops stay within `size`
+    pub(crate) fn scaled(&self, factor: f64, size: (u16, u16)) -> Self {
+        match *self {
+            Transform::Crop { x, y, w, h } => {
+                let scale = |v: u16| (v as f64 * factor).round().min(u16::MAX as f64) as u16;
+                let (x, y) = (scale(x).min(size.0 - 1), scale(y).min(size.1 - 1));
+                Transform::Crop {
+                    x,
+                    y,
+                    w: scale(w).clamp(1, size.0 - x),
+                    h: scale(h).clamp(1, size.1 - y),
+                }
+            }
+            transform => transform,
+        }
+    }
+
+    fn apply(&self, pixels: &[[u8; 4]], from: (u16, u16), to: (u16, u16)) -> Box<[[u8; 4]]> {
+        let (sw, sh) = (from.0 as usize, from.1 as usize);
+        let (w, h) = (to.0 as usize, to.1 as usize);
+        let mut out = vec![TRANSPARENT; w * h].into_boxed_slice();
+
+        out.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let (sx, sy) = match *self {
+                    Transform::Crop { x: cx, y: cy, .. } => (x + cx as usize, y + cy as usize),
+                    Transform::Rotate(turns) => match turns % 4 {
+                        1 => (y, sh - 1 - x),
+                        2 => (sw - 1 - x, sh - 1 - y),
+                        3 => (sw - 1 - y, x),
+                        _ => (x, y),
+                    },
+                    Transform::RotateBy(degrees) => {
+                        *pixel = sample_rotated(pixels, from, to, (x, y), degrees);
+                        continue;
+                    }
+                    Transform::FlipHorizontal => (sw - 1 - x, y),
+                    Transform::FlipVertical => (x, sh - 1 - y),
+                };
+                *pixel = pixels[sy * sw + sx];
+            }
+        });
+
+        out
+    }
+}
+
+/// Bilinear sample of the source pixel that rotates onto `(x, y)`
+fn sample_rotated(
+    pixels: &[[u8; 4]],
+    (sw, sh): (u16, u16),
+    (w, h): (u16, u16),
+    (x, y): (usize, usize),
+    degrees: f32,
+) -> [u8; 4] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (dx, dy) = (
+        x as f32 + 0.5 - w as f32 / 2.0,
+        y as f32 + 0.5 - h as f32 / 2.0,
+    );
+    // rotate back counterclockwise, pixel centers are at .5
+    let sx = dx * cos + dy * sin + sw as f32 / 2.0 - 0.5;
+    let sy = -dx * sin + dy * cos + sh as f32 / 2.0 - 0.5;
+
+    let (x0, y0) = (sx.floor(), sy.floor());
+    let (fx, fy) = (sx - x0, sy - y0);
+    let at = |x: f32, y: f32| {
+        if x < 0.0 || y < 0.0 || x >= sw as f32 || y >= sh as f32 {
+            TRANSPARENT
+        } else {
+            pixels[y as usize * sw as usize + x as usize]
+        }
+    };
+    let corners = [
+        (at(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (at(x0 + 1.0, y0), fx * (1.0 - fy)),
+        (at(x0, y0 + 1.0), (1.0 - fx) * fy),
+        (at(x0 + 1.0, y0 + 1.0), fx * fy),
+    ];
+
+    let mut out = [0; 4];
+    for (i, channel) in out.iter_mut().enumerate() {
+        let value: f32 = corners.iter().map(|(p, weight)| p[i] as f32 * weight).sum();
+        *channel = value.round() as u8;
+    }
+    out
+}
+
+/// Applies crops, rotations and flips in order to every frame of another source
+#[derive(Debug)]
+pub struct TransformedSource<Src: FrameSource> {
+    source: Src,
+    /// Transforms with the size of the frame they produce
+    transforms: Vec<(Transform, (u16, u16))>,
+    /// Start and end of the transformed source frame
+    window: Option<(Duration, Duration)>,
+    frame: Frame,
+}
+
+impl<Src: FrameSource> TransformedSource<Src> {
+    pub fn new(source: Src, transforms: &[Transform]) -> Result<Self, TransformedSourceError> {
+        let mut size = source.size();
+        let transforms = transforms
+            .iter()
+            .map(|transform| {
+                size = transform.size(size)?;
+                Ok((*transform, size))
+            })
+            .collect::<Result<_, TransformedSourceError>>()?;
+
+        Ok(Self {
+            source,
+            transforms,
+            window: None,
+            frame: Frame::Rgba(Box::new([])),
+        })
+    }
+}
+
+impl<Src: FrameSource> FrameSource for TransformedSource<Src> {
+    fn size(&self) -> (u16, u16) {
+        self.transforms
+            .last()
+            .map_or(self.source.size(), |(_, size)| *size)
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let source_size = self.source.size();
+        let timing = self.source.frame(delta);
+
+        let window = timing.window(delta);
+        if self.window != Some(window) {
+            let transforms = &self.transforms;
+            self.frame = timing.frame.map(|pixels| {
+                let mut size = source_size;
+                let mut frame = None;
+                for (transform, to) in transforms {
+                    frame = Some(transform.apply(frame.as_deref().unwrap_or(pixels), size, *to));
+                    size = *to;
+                }
+                frame.unwrap_or_else(|| pixels.into())
+            });
+            self.window = Some(window);
+        }
+
+        Timing {
+            frame: &self.frame,
+            frame_time: timing.frame_time,
+            time_left: timing.time_left,
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::path::Path;
+use std::time::Duration;
+
+use ffmpeg::error::EAGAIN;
+use ffmpeg::format::Pixel;
+use ffmpeg::media::Type;
+use ffmpeg::software::scaling;
+use ffmpeg::util::frame::video::Video;
+use ffmpeg::{decoder, format, Packet, Rational};
+use ffmpeg_next as ffmpeg;
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::flut_op::DebugShield;
+use crate::frame_source::{Frame, FrameSource, Timing};
+
+/// Jumps further ahead than this seek instead of decoding every frame in between
+const SEEK_THRESHOLD: Duration = Duration::from_secs(1);
+const DEFAULT_FRAME_TIME: Duration = Duration::from_millis(40);
+
+#[derive(Debug, Error)]
+pub enum VideoSourceError {
+    #[error("ffmpeg error: {0}")]
+    Ffmpeg(#[from] ffmpeg::Error),
+    #[error("no video stream")]
+    NoVideoStream,
+    #[error("no decodable frame")]
+    NoFrame,
+    #[error("unable to determine duration")]
+    UnknownDuration,
+    #[error("video too large: {0}x{1}")]
+    Size(u32, u32),
+}
+
+/// Decodes a video file with libav on demand
+///
+/// Only the shown frame and the one after it are kept in memory. Frames are timed by
+/// their presentation timestamps, wrapping around or jumping ahead seeks the input.
+#[derive(Debug)]
+pub struct VideoSource {
+    size: (u16, u16),
+    duration: Duration,
+    frame_time: Duration,
+
+    input: DebugShield<format::context::Input>,
+    stream_index: usize,
+    time_base: Rational,
+    start_time: i64,
+    decoder: DebugShield<decoder::Video>,
+    scaler: DebugShield<scaling::Context>,
+
+    /// frame, start and end of the shown frame
+    current: (Frame, Duration, Duration),
+    next: Option<(Frame, Duration)>,
+    last_start: Duration,
+}
+
+impl VideoSource {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, VideoSourceError> {
+        ffmpeg::init()?;
+
+        let input = format::input(&path.as_ref())?;
+        let stream = input
+            .streams()
+            .best(Type::Video)
+            .ok_or(VideoSourceError::NoVideoStream)?;
+
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+        let size = match (
+            u16::try_from(decoder.width()),
+            u16::try_from(decoder.height()),
+        ) {
+            (Ok(w), Ok(h)) if w > 0 && h > 0 => (w, h),
+            _ => return Err(VideoSourceError::Size(decoder.width(), decoder.height())),
+        };
+
+        let stream_index = stream.index();
+        let time_base = stream.time_base();
+        let start_time = match stream.start_time() {
+            ffmpeg::ffi::AV_NOPTS_VALUE => 0,
+            start_time => start_time,
+        };
+        let duration = match (stream.duration(), input.duration()) {
+            (d, _) if d > 0 => rescale(d, time_base),
+            (_, d) if d > 0 => Duration::from_micros(d as u64),
+            _ => return Err(VideoSourceError::UnknownDuration),
+        };
+        let frame_time = match f64::from(stream.avg_frame_rate()) {
+            fps if fps.is_finite() && fps > 0.0 => Duration::from_secs_f64(1.0 / fps),
+            _ => DEFAULT_FRAME_TIME,
+        };
+
+        let scaler = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGBA,
+            size.0 as u32,
+            size.1 as u32,
+            scaling::Flags::BILINEAR,
+        )?;
+
+        let mut source = Self {
+            size,
+            duration,
+            frame_time,
+            stream_index,
+            time_base,
+            start_time,
+            input: input.into(),
+            decoder: decoder.into(),
+            scaler: scaler.into(),
+            current: (Frame::Rgba(Box::new([])), Duration::ZERO, Duration::ZERO),
+            next: None,
+            last_start: Duration::ZERO,
+        };
+
+        let (frame, _) = source.decode().ok_or(VideoSourceError::NoFrame)?;
+        let end = source.peek_end();
+        source.current = (frame, Duration::ZERO, end);
+
+        Ok(source)
+    }
+
+    /// Makes `current` the frame shown at `delta`
+    fn advance(&mut self, delta: Duration) {
+        let (_, start, end) = self.current;
+        if delta < start || delta >= end + SEEK_THRESHOLD {
+            self.seek(delta);
+        }
+
+        while delta >= self.current.2 {
+            let Some((frame, start)) = self.next.take().or_else(|| self.decode()) else {
+                // end of stream, the last frame stays until the cycle ends
+                self.current.2 = self.duration.max(delta + Duration::from_nanos(1));
+                return;
+            };
+
+            // the first frame after seeking may start a little after delta
+            let start = start.min(delta);
+            let end = self.peek_end().max(start + Duration::from_nanos(1));
+            self.current = (frame, start, end);
+        }
+    }
+
+    /// Start of the next frame or the end of the video
+    fn peek_end(&mut self) -> Duration {
+        self.next = self.decode();
+        match &self.next {
+            Some((_, start)) => *start,
+            None => self.duration.max(self.last_start + self.frame_time),
+        }
+    }
+
+    fn seek(&mut self, delta: Duration) {
+        let start = rescale(self.start_time, self.time_base).as_micros() as i64;
+        let target = start + delta.as_micros() as i64;
+        debug!("seeking to {delta:?}");
+
+        if let Err(e) = self.input.get_mut().seek(target, ..target) {
+            warn!("unable to seek to {delta:?}: {e}");
+        }
+        self.decoder.get_mut().flush();
+        self.next = None;
+
+        // decode from here on
+        self.current.1 = Duration::ZERO;
+        self.current.2 = Duration::ZERO;
+    }
+
+    /// Decodes the next frame and its start time, `None` at the end of the stream
+    fn decode(&mut self) -> Option<(Frame, Duration)> {
+        let mut decoded = Video::empty();
+        loop {
+            match self.decoder.get_mut().receive_frame(&mut decoded) {
+                Ok(()) => break,
+                Err(ffmpeg::Error::Other { errno: EAGAIN }) => self.feed(),
+                Err(ffmpeg::Error::Eof) => return None,
+                Err(e) => {
+                    warn!("unable to decode video frame: {e}");
+                    return None;
+                }
+            }
+        }
+
+        let start = match decoded.timestamp() {
+            Some(ts) => rescale(ts - self.start_time, self.time_base),
+            None => self.last_start + self.frame_time,
+        };
+        self.last_start = start;
+
+        Some((self.convert(&decoded), start))
+    }
+
+    /// Sends the next packet of the video stream to the decoder
+    fn feed(&mut self) {
+        let mut packet = Packet::empty();
+        loop {
+            match packet.read(self.input.get_mut()) {
+                Ok(()) if packet.stream() != self.stream_index => continue,
+                Ok(()) => {
+                    if let Err(e) = self.decoder.get_mut().send_packet(&packet) {
+                        debug!("skipping video packet: {e}");
+                        continue;
+                    }
+                }
+                Err(ffmpeg::Error::Eof) => {
+                    let _ = self.decoder.get_mut().send_eof();
+                }
+                Err(e) => {
+                    warn!("unable to read video packet: {e}");
+                    let _ = self.decoder.get_mut().send_eof();
+                }
+            }
+
+            return;
+        }
+    }
+
+    fn convert(&mut self, decoded: &Video) -> Frame {
+        let mut rgba = Video::empty();
+        if let Err(ffmpeg::Error::InputChanged) = self.scaler.get_mut().run(decoded, &mut rgba) {
+            // resolution or pixel format changed mid stream
+            match scaling::Context::get(
+                decoded.format(),
+                decoded.width(),
+                decoded.height(),
+                Pixel::RGBA,
+                self.size.0 as u32,
+                self.size.1 as u32,
+                scaling::Flags::BILINEAR,
+            ) {
+                Ok(scaler) => {
+                    self.scaler = scaler.into();
+                    let _ = self.scaler.get_mut().run(decoded, &mut rgba);
+                }
+                Err(e) => warn!("unable to convert video frame: {e}"),
+            }
+        }
+
+        let (w, h) = (self.size.0 as usize, self.size.1 as usize);
+        if rgba.width() as usize != w || rgba.height() as usize != h {
+            return Frame::Rgba(vec![[0, 0, 0, 0]; w * h].into_boxed_slice());
+        }
+
+        let stride = rgba.stride(0);
+        let data = rgba.data(0);
+        Frame::Rgba(
+            (0..h)
+                .flat_map(|y| data[y * stride..y * stride + w * 4].chunks_exact(4))
+                .map(|p| [p[0], p[1], p[2], p[3]])
+                .collect(),
+        )
+    }
+}
+
+fn rescale(ts: i64, time_base: Rational) -> Duration {
+    let nanos = ts as i128 * time_base.numerator() as i128 * 1_000_000_000
+        / time_base.denominator().max(1) as i128;
+    Duration::from_nanos(nanos.clamp(0, u64::MAX as i128) as u64)
+}
+
+impl FrameSource for VideoSource {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.duration
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.duration.as_nanos()) as u64);
+        self.advance(delta);
+
+        let (frame, start, end) = &self.current;
+        Timing {
+            frame,
+            frame_time: *end - *start,
+            time_left: *end - delta,
+        }
+    }
+}
@@ -4,29 +4,73 @@ extern crate core;
 use std::cmp::Ordering;
 use std::error::Error;
 use std::fmt::Debug;
-use std::ops::Add;
-use std::rc::Rc;
+use std::ops::{Add, Deref, Range};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use memmap2::Mmap;
 pub use rummelplatz;
 use rummelplatz::io_uring::squeue::PushError;
 use thiserror::Error;
 
 use crate::frame_processing::FrameProcessor;
-use crate::frame_source::{FrameSource, Timing};
+use crate::frame_source::{common_cycle_time, FrameSource, Timing, STILL_TIME};
+use crate::motion_path::MotionPath;
 
 mod breadth_flatten;
 pub mod flut_op;
 
+pub mod alpha_policy;
+pub mod assignment;
 pub mod draw_strategy;
 pub mod frame_processing;
 pub mod frame_source;
+pub mod motion_path;
+pub mod precompiled;
+pub mod reload;
+
+/// Commands written to a connection in one go, cheap to share between connections
+#[derive(Debug, Clone)]
+pub enum CommandBuffer {
+    Owned(Arc<[u8]>),
+    /// A range of a memory mapped file, borrowed instead of copied
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl Deref for CommandBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            CommandBuffer::Owned(buffer) => buffer,
+            CommandBuffer::Mapped(mmap, range) => &mmap[range.clone()],
+        }
+    }
+}
+
+impl From<Arc<[u8]>> for CommandBuffer {
+    fn from(buffer: Arc<[u8]>) -> Self {
+        CommandBuffer::Owned(buffer)
+    }
+}
 
-pub trait CommandBufferSource: Debug {
+impl From<Box<[u8]>> for CommandBuffer {
+    fn from(buffer: Box<[u8]>) -> Self {
+        CommandBuffer::Owned(buffer.into())
+    }
+}
+
+impl From<&[u8]> for CommandBuffer {
+    fn from(buffer: &[u8]) -> Self {
+        CommandBuffer::Owned(buffer.into())
+    }
+}
+
+pub trait CommandBufferSource: Debug + Send {
     fn command_buffer(
         &mut self,
         delta: Duration,
-    ) -> Result<Timing<Rc<[u8]>>, Box<dyn Error + Send + Sync>>;
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>>;
     fn cycle_time(&self) -> Duration;
 }
 
@@ -34,6 +78,8 @@ pub trait CommandBufferSource: Debug {
 pub struct CompositeBufferSource<Src: FrameSource, Proc: FrameProcessor> {
     pub source: Src,
     pub processor: Proc,
+    /// Where the frames are drawn on the canvas
+    pub path: MotionPath,
 }
 
 impl<Src: FrameSource, Proc: FrameProcessor> CommandBufferSource
@@ -42,28 +88,43 @@ impl<Src: FrameSource, Proc: FrameProcessor> CommandBufferSource
     fn command_buffer(
         &mut self,
         delta: Duration,
-    ) -> Result<Timing<Rc<[u8]>>, Box<dyn Error + Send + Sync>> {
-        let Timing {
-            frame,
-            frame_time,
-            time_left,
-        } = self.source.frame(delta);
-
-        self.processor.process(frame).map(|frame| Timing {
-            frame: frame.into(),
-            frame_time,
-            time_left,
-        })
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        let size = self.source.size();
+        let position = self.path.position(delta, size);
+        let timing = self.source.frame(delta);
+
+        // the command buffer changes with the frame and with the position
+        let (mut start, mut end) = timing.window(delta);
+        if position.frame_time < STILL_TIME {
+            let (position_start, position_end) = position.window(delta);
+            start = start.max(position_start);
+            end = end.min(position_end);
+        }
+
+        self.processor
+            .process(timing.frame, position.frame)
+            .map(|frame| Timing {
+                frame: frame.into(),
+                frame_time: end - start,
+                time_left: end - delta,
+            })
     }
 
     fn cycle_time(&self) -> Duration {
-        self.source.cycle_time()
+        let size = self.source.size();
+        let cycle_time = self.path.cycle_time(size);
+        match (self.path.moves(size), cycle_time < STILL_TIME) {
+            (false, _) => self.source.cycle_time(),
+            (true, true) => common_cycle_time([self.source.cycle_time(), cycle_time]),
+            // the path never starts over, wrapping around would make it jump
+            (true, false) => STILL_TIME,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct ComputeOnceCache<Src: CommandBufferSource> {
-    cache: Vec<((Duration, Duration), Rc<[u8]>)>,
+    cache: Vec<((Duration, Duration), CommandBuffer)>,
     src: Src,
 }
 
@@ -80,7 +141,7 @@ impl<Src: CommandBufferSource> CommandBufferSource for ComputeOnceCache<Src> {
     fn command_buffer(
         &mut self,
         delta: Duration,
-    ) -> Result<Timing<Rc<[u8]>>, Box<dyn Error + Send + Sync>> {
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
         let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
 
         match self.cache.binary_search_by(|&((start, end), _)| {
@@ -128,7 +189,7 @@ impl<Src: CommandBufferSource> CommandBufferSource for ComputeOnceCache<Src> {
 
 #[derive(Debug)]
 pub struct SingleFrameCache<Src: CommandBufferSource> {
-    cache: Option<(Instant, Duration, Rc<[u8]>)>,
+    cache: Option<(Instant, Duration, CommandBuffer)>,
     src: Src,
 }
 
@@ -145,7 +206,7 @@ impl<Src: CommandBufferSource> CommandBufferSource for SingleFrameCache<Src> {
     fn command_buffer(
         &mut self,
         delta: Duration,
-    ) -> Result<Timing<Rc<[u8]>>, Box<dyn Error + Send + Sync>> {
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
         let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
         let now = Instant::now();
 
@@ -0,0 +1,294 @@
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::frame_source::{common_cycle_time, Timing, STILL_TIME};
+
+/// Time between two positions of a moving object
+pub const MOTION_STEP: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Error)]
+pub enum MotionPathError {
+    #[error("keyframes need to start at 0 and be in order")]
+    Keyframes,
+    #[error("invalid period: {0:?}")]
+    Period(Duration),
+    #[error("invalid speed: {0},{1}")]
+    Speed(f32, f32),
+}
+
+/// How a move between two keyframes speeds up and slows down
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    In,
+    Out,
+    InOut,
+}
+
+impl Easing {
+    /// Maps the progress of a move from 0 to 1 with a cubic curve
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::In => t * t * t,
+            Easing::Out => 1.0 - (1.0 - t).powi(3),
+            Easing::InOut if t < 0.5 => 4.0 * t * t * t,
+            Easing::InOut => 1.0 - (2.0 - 2.0 * t).powi(3) / 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe {
+    pub time: Duration,
+    /// Top left corner of the object
+    pub position: (i32, i32),
+}
+
+/// Position of an object on the canvas over time
+///
+/// Positions may lie partly or fully outside of the canvas, processors clip them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MotionPath {
+    /// Stays at one top left corner
+    Fixed(i32, i32),
+    /// Moves between keyframes and starts over after the last one
+    Keyframes {
+        keyframes: Vec<Keyframe>,
+        easing: Easing,
+    },
+    /// Moves in straight lines and bounces off the edges of an area, in pixels per second
+    Bounce {
+        area: (i32, i32, u16, u16),
+        speed: (f32, f32),
+    },
+    /// Moves the center of the object along a Lissajous figure, one cycle takes `period`
+    ///
+    /// Equal amplitudes and frequencies of 1 make a circle.
+    Lissajous {
+        center: (f32, f32),
+        amplitude: (f32, f32),
+        frequency: (u32, u32),
+        period: Duration,
+    },
+}
+
+impl Default for MotionPath {
+    fn default() -> Self {
+        MotionPath::Fixed(0, 0)
+    }
+}
+
+impl MotionPath {
+    /// Checks keyframes, periods and speeds
+    pub fn validate(&self) -> Result<(), MotionPathError> {
+        match self {
+            MotionPath::Fixed(..) => Ok(()),
+            MotionPath::Keyframes { keyframes, .. } => {
+                match keyframes.first().map(|keyframe| keyframe.time) {
+                    Some(Duration::ZERO)
+                        if keyframes.windows(2).all(|pair| pair[0].time < pair[1].time) =>
+                    {
+                        Ok(())
+                    }
+                    _ => Err(MotionPathError::Keyframes),
+                }
+            }
+            MotionPath::Bounce { speed, .. } => match speed.0.is_finite() && speed.1.is_finite() {
+                true => Ok(()),
+                false => Err(MotionPathError::Speed(speed.0, speed.1)),
+            },
+            MotionPath::Lissajous { period, .. } => match period.is_zero() {
+                true => Err(MotionPathError::Period(*period)),
+                false => Ok(()),
+            },
+        }
+    }
+
+    /// Time until an object of `size` is back at its first position
+    ///
+    /// [STILL_TIME] for objects that do not move, but also for bouncing objects whose axes take
+    /// too long to line up again, see [MotionPath::moves].
+    pub fn cycle_time(&self, size: (u16, u16)) -> Duration {
+        match self {
+            MotionPath::Fixed(..) => STILL_TIME,
+            MotionPath::Keyframes { keyframes, .. } => match keyframes.last() {
+                Some(last) if !last.time.is_zero() => last.time,
+                _ => STILL_TIME,
+            },
+            MotionPath::Bounce { area, speed } => common_cycle_time([
+                bounce_period(area.2, size.0, speed.0),
+                bounce_period(area.3, size.1, speed.1),
+            ]),
+            MotionPath::Lissajous { period, .. } => *period,
+        }
+    }
+
+    /// Whether an object of `size` changes its position
+    pub fn moves(&self, size: (u16, u16)) -> bool {
+        match self {
+            MotionPath::Bounce { area, speed } => {
+                bounce_period(area.2, size.0, speed.0) < STILL_TIME
+                    || bounce_period(area.3, size.1, speed.1) < STILL_TIME
+            }
+            _ => self.cycle_time(size) < STILL_TIME,
+        }
+    }
+
+    /// Whether an object of `size` moves without ever starting over
+    pub fn endless(&self, size: (u16, u16)) -> bool {
+        self.moves(size) && self.cycle_time(size) >= STILL_TIME
+    }
+
+    /// Top left corner of an object of `size` at `delta`
+    ///
+    /// Moving objects keep their position for [MOTION_STEP].
+    pub fn position(&self, delta: Duration, size: (u16, u16)) -> Timing<(i32, i32)> {
+        if !self.moves(size) {
+            return Timing {
+                frame: self.at(Duration::ZERO, size),
+                frame_time: STILL_TIME,
+                time_left: STILL_TIME,
+            };
+        }
+
+        // bouncing axes repeat on their own even without a common cycle
+        let cycle_time = self.cycle_time(size);
+        let local = match cycle_time < STILL_TIME {
+            true => Duration::from_nanos((delta.as_nanos() % cycle_time.as_nanos()) as u64),
+            false => delta,
+        };
+        let step = (local.as_nanos() / MOTION_STEP.as_nanos()) as u32;
+        let start = MOTION_STEP * step;
+        let end = (start + MOTION_STEP).min(cycle_time);
+
+        Timing {
+            frame: self.at(start, size),
+            frame_time: end - start,
+            time_left: end - local,
+        }
+    }
+
+    /// Smallest and largest top left corner an object of `size` takes
+    pub fn bounds(&self, size: (u16, u16)) -> ((i32, i32), (i32, i32)) {
+        match self {
+            MotionPath::Fixed(x, y) => ((*x, *y), (*x, *y)),
+            MotionPath::Keyframes { keyframes, .. } => keyframes.iter().fold(
+                ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN)),
+                |(min, max),
+                 Keyframe {
+                     position: (x, y), ..
+                 }| {
+                    (
+                        (min.0.min(*x), min.1.min(*y)),
+                        (max.0.max(*x), max.1.max(*y)),
+                    )
+                },
+            ),
+            MotionPath::Bounce { area, .. } => (
+                (area.0, area.1),
+                (
+                    area.0 + area.2.saturating_sub(size.0) as i32,
+                    area.1 + area.3.saturating_sub(size.1) as i32,
+                ),
+            ),
+            MotionPath::Lissajous {
+                center, amplitude, ..
+            } => {
+                let corner = |center: f32, amplitude: f32, size: u16| {
+                    (
+                        (center - amplitude.abs() - size as f32 / 2.0).floor() as i32,
+                        (center + amplitude.abs() - size as f32 / 2.0).ceil() as i32,
+                    )
+                };
+                let (x, y) = (
+                    corner(center.0, amplitude.0, size.0),
+                    corner(center.1, amplitude.1, size.1),
+                );
+                ((x.0, y.0), (x.1, y.1))
+            }
+        }
+    }
+
+    /// Exact position at `delta` within the first cycle, bounces take any `delta`
+    fn at(&self, delta: Duration, size: (u16, u16)) -> (i32, i32) {
+        match self {
+            MotionPath::Fixed(x, y) => (*x, *y),
+            MotionPath::Keyframes { keyframes, easing } => {
+                let next = keyframes.partition_point(|keyframe| keyframe.time <= delta);
+                match (keyframes.get(next.wrapping_sub(1)), keyframes.get(next)) {
+                    (Some(from), Some(to)) => {
+                        let t =
+                            (delta - from.time).as_secs_f32() / (to.time - from.time).as_secs_f32();
+                        let t = easing.apply(t);
+                        let lerp = |a: i32, b: i32| (a as f32 + (b - a) as f32 * t).round() as i32;
+                        (
+                            lerp(from.position.0, to.position.0),
+                            lerp(from.position.1, to.position.1),
+                        )
+                    }
+                    (Some(keyframe), None) | (None, Some(keyframe)) => keyframe.position,
+                    (None, None) => (0, 0),
+                }
+            }
+            MotionPath::Bounce { area, speed } => {
+                let axis = |start: i32, length: u16, size: u16, speed: f32| {
+                    let range = length.saturating_sub(size) as f32;
+                    let period = bounce_period(length, size, speed);
+                    if period >= STILL_TIME {
+                        return start;
+                    }
+
+                    // triangle wave going back and forth once per period
+                    let t = (delta.as_nanos() % period.as_nanos()) as f32
+                        / period.as_nanos() as f32
+                        * 2.0;
+                    let travelled = match t < 1.0 {
+                        true => t * range,
+                        false => (2.0 - t) * range,
+                    };
+                    match speed < 0.0 {
+                        true => start + (range - travelled).round() as i32,
+                        false => start + travelled.round() as i32,
+                    }
+                };
+                (
+                    axis(area.0, area.2, size.0, speed.0),
+                    axis(area.1, area.3, size.1, speed.1),
+                )
+            }
+            MotionPath::Lissajous {
+                center,
+                amplitude,
+                frequency,
+                period,
+            } => {
+                let angle = delta.as_secs_f32() / period.as_secs_f32() * TAU;
+                let x = center.0 + amplitude.0 * (angle * frequency.0 as f32).cos();
+                let y = center.1 + amplitude.1 * (angle * frequency.1 as f32).sin();
+                (
+                    (x - size.0 as f32 / 2.0).round() as i32,
+                    (y - size.1 as f32 / 2.0).round() as i32,
+                )
+            }
+        }
+    }
+}
+
+/// Time to move through an area of `length` and back, [STILL_TIME] without movement
+fn bounce_period(length: u16, size: u16, speed: f32) -> Duration {
+    let range = length.saturating_sub(size) as f32;
+    match range > 0.0 && speed != 0.0 {
+        true => {
+            let nanos = (2.0 * range as f64 / speed.abs() as f64 * 1e9).round();
+            match nanos >= 1.0 && nanos < STILL_TIME.as_nanos() as f64 {
+                true => Duration::from_nanos(nanos as u64),
+                false => STILL_TIME,
+            }
+        }
+        false => STILL_TIME,
+    }
+}
@@ -0,0 +1,298 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+use std::num::NonZeroU64;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use memmap2::Mmap;
+use thiserror::Error;
+use tracing::info;
+
+use crate::assignment::{AssignmentPolicy, SourceWeights};
+use crate::frame_source::{Timing, STILL_TIME};
+use crate::{CommandBuffer, CommandBufferSource};
+
+// File layout (little endian):
+//
+// header: magic [u8; 8] | version u32 | canvas width u16 | canvas height u16
+//         | source count u32 | index offset u64
+// data:   command buffers of all frames of all sources
+// index:  per source: cycle time ns u64 | pixels u64 | weight u64 | policy u8 | frame count u32
+//         | per frame: start ns u64 | end ns u64 | offset u64 | length u64
+//
+// Policies of single sources are 0 for none, then round-robin, dedicated, weighted and pixels.
+const MAGIC: &[u8; 8] = b"TSUNAMI\0";
+const VERSION: u32 = 2;
+const HEADER_LENGTH: usize = 28;
+
+type FrameIndex = Box<[((Duration, Duration), Range<usize>)]>;
+
+#[derive(Debug, Error)]
+pub enum PrecompiledError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unable to render frame: {0}")]
+    Render(Box<dyn Error + Send + Sync>),
+    #[error("source {0} changes but never repeats")]
+    Endless(usize),
+    #[error("not a precompiled show")]
+    Magic,
+    #[error("unsupported version: {0}")]
+    Version(u32),
+    #[error("corrupted precompiled show")]
+    Corrupted,
+}
+
+/// Renders every frame of `sources` and writes them into a precompiled show
+pub fn compile<W: Write + Seek>(
+    output: &mut W,
+    canvas_size: (u16, u16),
+    sources: &mut [(Box<dyn CommandBufferSource>, SourceWeights)],
+) -> Result<(), PrecompiledError> {
+    output.write_all(MAGIC)?;
+    output.write_all(&VERSION.to_le_bytes())?;
+    output.write_all(&canvas_size.0.to_le_bytes())?;
+    output.write_all(&canvas_size.1.to_le_bytes())?;
+    output.write_all(&(sources.len() as u32).to_le_bytes())?;
+    output.write_all(&0u64.to_le_bytes())?;
+
+    let mut offset = HEADER_LENGTH as u64;
+    let mut index = vec![];
+    for (i, (source, weights)) in sources.iter_mut().enumerate() {
+        let cycle_time = source.cycle_time();
+        index.extend_from_slice(&(cycle_time.as_nanos() as u64).to_le_bytes());
+        index.extend_from_slice(&weights.pixels.get().to_le_bytes());
+        index.extend_from_slice(&weights.weight.get().to_le_bytes());
+        index.push(match weights.policy {
+            None => 0,
+            Some(AssignmentPolicy::RoundRobin) => 1,
+            Some(AssignmentPolicy::Dedicated) => 2,
+            Some(AssignmentPolicy::Weighted) => 3,
+            Some(AssignmentPolicy::Pixels) => 4,
+        });
+
+        let mut frames = vec![];
+        let mut delta = Duration::ZERO;
+        loop {
+            let Timing {
+                frame,
+                frame_time,
+                time_left,
+            } = source
+                .command_buffer(delta)
+                .map_err(PrecompiledError::Render)?;
+
+            let end = delta + time_left;
+            let start = end.saturating_sub(frame_time);
+            output.write_all(&frame)?;
+            frames.push((start, end, offset, frame.len() as u64));
+            offset += frame.len() as u64;
+
+            if end >= cycle_time {
+                break;
+            }
+            if cycle_time >= STILL_TIME {
+                return Err(PrecompiledError::Endless(i));
+            }
+            delta = end + Duration::from_nanos(1);
+        }
+
+        info!(
+            "compiled source {i}: {} frames, {} bytes",
+            frames.len(),
+            frames.iter().map(|(_, _, _, len)| len).sum::<u64>()
+        );
+
+        index.extend_from_slice(&(frames.len() as u32).to_le_bytes());
+        for (start, end, offset, len) in frames {
+            index.extend_from_slice(&(start.as_nanos() as u64).to_le_bytes());
+            index.extend_from_slice(&(end.as_nanos() as u64).to_le_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&len.to_le_bytes());
+        }
+    }
+
+    output.write_all(&index)?;
+    output.seek(SeekFrom::Start(HEADER_LENGTH as u64 - 8))?;
+    output.write_all(&offset.to_le_bytes())?;
+    output.seek(SeekFrom::End(0))?;
+
+    Ok(())
+}
+
+/// A memory mapped precompiled show
+#[derive(Debug)]
+pub struct PrecompiledShow {
+    canvas_size: (u16, u16),
+    sources: Vec<(PrecompiledSource, SourceWeights)>,
+}
+
+impl PrecompiledShow {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PrecompiledError> {
+        let file = File::open(path)?;
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+
+        let mut reader = Reader {
+            buffer: &mmap,
+            position: 0,
+        };
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(PrecompiledError::Magic);
+        }
+        match reader.u32()? {
+            VERSION => {}
+            version => return Err(PrecompiledError::Version(version)),
+        }
+        let canvas_size = (reader.u16()?, reader.u16()?);
+        let source_count = reader.u32()?;
+        let index_offset = reader.u64()? as usize;
+
+        let mut reader = Reader {
+            buffer: &mmap,
+            position: index_offset,
+        };
+        let mut sources = vec![];
+        for _ in 0..source_count {
+            let cycle_time = Duration::from_nanos(reader.u64()?);
+            let weights = SourceWeights {
+                pixels: NonZeroU64::new(reader.u64()?).ok_or(PrecompiledError::Corrupted)?,
+                weight: NonZeroU64::new(reader.u64()?).ok_or(PrecompiledError::Corrupted)?,
+                policy: match reader.u8()? {
+                    0 => None,
+                    1 => Some(AssignmentPolicy::RoundRobin),
+                    2 => Some(AssignmentPolicy::Dedicated),
+                    3 => Some(AssignmentPolicy::Weighted),
+                    4 => Some(AssignmentPolicy::Pixels),
+                    _ => return Err(PrecompiledError::Corrupted),
+                },
+            };
+
+            let frame_count = reader.u32()? as usize;
+            if frame_count == 0 || cycle_time.is_zero() {
+                return Err(PrecompiledError::Corrupted);
+            }
+
+            let frames = (0..frame_count)
+                .map(|_| {
+                    let start = Duration::from_nanos(reader.u64()?);
+                    let end = Duration::from_nanos(reader.u64()?);
+                    let offset = reader.u64()? as usize;
+                    let len = reader.u64()? as usize;
+
+                    match offset.checked_add(len) {
+                        Some(data_end) if data_end <= index_offset && start <= end => {
+                            Ok(((start, end), offset..data_end))
+                        }
+                        _ => Err(PrecompiledError::Corrupted),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_boxed_slice();
+
+            sources.push((
+                PrecompiledSource {
+                    mmap: mmap.clone(),
+                    cycle_time,
+                    frames,
+                },
+                weights,
+            ));
+        }
+
+        Ok(Self {
+            canvas_size,
+            sources,
+        })
+    }
+
+    pub fn canvas_size(&self) -> (u16, u16) {
+        self.canvas_size
+    }
+
+    pub fn into_sources(self) -> Vec<(PrecompiledSource, SourceWeights)> {
+        self.sources
+    }
+}
+
+/// Streams the command buffers of a precompiled show
+///
+/// Command buffers are borrowed from the mapping, frames are neither decoded nor processed.
+#[derive(Debug)]
+pub struct PrecompiledSource {
+    mmap: Arc<Mmap>,
+    cycle_time: Duration,
+    frames: FrameIndex,
+}
+
+impl CommandBufferSource for PrecompiledSource {
+    fn command_buffer(
+        &mut self,
+        delta: Duration,
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time.as_nanos()) as u64);
+
+        let i = match self.frames.binary_search_by(|((start, end), _)| {
+            if delta < *start {
+                Ordering::Greater
+            } else if delta >= *end {
+                Ordering::Less
+            } else {
+                Ordering::Equal
+            }
+        }) {
+            Ok(i) => i,
+            Err(i) => i.min(self.frames.len() - 1),
+        };
+        let ((start, end), range) = &self.frames[i];
+
+        Ok(Timing {
+            frame: CommandBuffer::Mapped(self.mmap.clone(), range.clone()),
+            frame_time: *end - *start,
+            time_left: end.saturating_sub(delta),
+        })
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.cycle_time
+    }
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PrecompiledError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .ok_or(PrecompiledError::Corrupted)?;
+        let bytes = self
+            .buffer
+            .get(self.position..end)
+            .ok_or(PrecompiledError::Corrupted)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, PrecompiledError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, PrecompiledError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, PrecompiledError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, PrecompiledError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
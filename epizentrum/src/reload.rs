@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::ffi::{CString, OsStr, OsString};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::frame_source::Timing;
+use crate::{CommandBuffer, CommandBufferSource};
+
+/// How often the trigger is asked for changes
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// Time without further changes before a file is reloaded, editors often write in several steps
+const SETTLE_TIME: Duration = Duration::from_millis(250);
+
+const EVENT_HEADER_LENGTH: usize = std::mem::size_of::<libc::inotify_event>();
+
+/// Builds a fresh command buffer source from the current state of the watched file
+pub type Reload =
+    Box<dyn FnMut() -> Result<Box<dyn CommandBufferSource>, Box<dyn Error + Send + Sync>> + Send>;
+
+/// Tells the reload thread when to rebuild a source
+pub trait Trigger: Send {
+    /// Whether the source should be rebuilt since the last call, never blocks for long
+    fn fired(&mut self) -> std::io::Result<bool>;
+
+    /// What is watched, for log messages
+    fn describe(&self) -> String;
+}
+
+impl Trigger for Vec<Box<dyn Trigger>> {
+    fn fired(&mut self) -> std::io::Result<bool> {
+        // every trigger is asked, so none of them keeps a change for later
+        let mut fired = false;
+        for trigger in self.iter_mut() {
+            fired |= trigger.fired()?;
+        }
+        Ok(fired)
+    }
+
+    fn describe(&self) -> String {
+        self.iter()
+            .map(|trigger| trigger.describe())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Watches files with inotify
+///
+/// The directories of the files are watched, so files replaced by renaming
+/// another file over them (as many editors save) are noticed as well.
+/// Whole directories can be watched too, e.g. for directory playlists.
+#[derive(Debug)]
+pub struct FileWatcher {
+    inotify: OwnedFd,
+    /// Watch descriptor of the directory and name of every file
+    files: Vec<(i32, OsString)>,
+    /// Watch descriptor and path of every directory whose files are all watched
+    directories: Vec<(i32, OsString)>,
+}
+
+impl FileWatcher {
+    /// Watches a file, or every file of a directory
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let inotify = match unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) } {
+            -1 => return Err(std::io::Error::last_os_error()),
+            fd => unsafe { OwnedFd::from_raw_fd(fd) },
+        };
+
+        let mut watcher = Self {
+            inotify,
+            files: vec![],
+            directories: vec![],
+        };
+        match path.as_ref().is_dir() {
+            true => watcher.add_directory(path)?,
+            false => watcher.add(path)?,
+        }
+        Ok(watcher)
+    }
+
+    /// Watches another file
+    pub fn add<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::InvalidInput))?
+            .to_owned();
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let descriptor = self.watch(
+            directory,
+            libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE,
+        )?;
+        self.files.push((descriptor, name));
+        Ok(())
+    }
+
+    /// Watches every file of a directory, including files added, removed or renamed later
+    ///
+    /// Hidden files are left out, editors keep their swap files there.
+    pub fn add_directory<P: AsRef<Path>>(&mut self, path: P) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let mask = libc::IN_CLOSE_WRITE
+            | libc::IN_MOVED_TO
+            | libc::IN_MOVED_FROM
+            | libc::IN_CREATE
+            | libc::IN_DELETE;
+        let descriptor = self.watch(path, mask)?;
+        self.directories
+            .push((descriptor, path.as_os_str().to_owned()));
+        Ok(())
+    }
+
+    fn watch(&self, directory: &Path, mask: u32) -> std::io::Result<i32> {
+        let directory = CString::new(directory.as_os_str().as_bytes())
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+
+        // directories watched before keep their watch descriptor and the events they were watched for
+        let mask = mask | libc::IN_MASK_ADD;
+        match unsafe { libc::inotify_add_watch(self.inotify.as_raw_fd(), directory.as_ptr(), mask) }
+        {
+            -1 => Err(std::io::Error::last_os_error()),
+            descriptor => Ok(descriptor),
+        }
+    }
+
+    /// Names of the watched files and paths of the watched directories
+    pub fn names(&self) -> Vec<&OsStr> {
+        self.files
+            .iter()
+            .chain(&self.directories)
+            .map(|(_, name)| name.as_os_str())
+            .collect()
+    }
+
+    /// Whether a file was written or replaced since the last call, never blocks
+    pub fn changed(&mut self) -> std::io::Result<bool> {
+        #[repr(align(8))]
+        struct Events([u8; 4096]);
+
+        let mut events = Events([0; 4096]);
+        let mut changed = false;
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.inotify.as_raw_fd(),
+                    events.0.as_mut_ptr() as *mut libc::c_void,
+                    events.0.len(),
+                )
+            };
+            let read = match read {
+                -1 => match std::io::Error::last_os_error() {
+                    e if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(changed),
+                    e => return Err(e),
+                },
+                read => read as usize,
+            };
+
+            let mut offset = 0;
+            while offset + EVENT_HEADER_LENGTH <= read {
+                let event = unsafe {
+                    std::ptr::read_unaligned(
+                        events.0.as_ptr().add(offset) as *const libc::inotify_event
+                    )
+                };
+                let name = &events.0[offset + EVENT_HEADER_LENGTH..]
+                    [..(event.len as usize).min(read - offset - EVENT_HEADER_LENGTH)];
+                let name = name.split(|b| *b == 0).next().unwrap_or_default();
+
+                let hidden = name.starts_with(b".");
+                let name = OsString::from_vec(name.to_vec());
+                changed |= self
+                    .files
+                    .iter()
+                    .any(|file| file.0 == event.wd && file.1 == name)
+                    || (!hidden && self.directories.iter().any(|(wd, _)| *wd == event.wd));
+                offset += EVENT_HEADER_LENGTH + event.len as usize;
+            }
+        }
+    }
+}
+
+impl Trigger for FileWatcher {
+    fn fired(&mut self) -> std::io::Result<bool> {
+        self.changed()
+    }
+
+    fn describe(&self) -> String {
+        self.names()
+            .iter()
+            .map(|name| name.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Rebuilds a command buffer source whenever its [Trigger] fires, e.g. one of its files changes
+///
+/// Changes are watched and sources rebuilt on a background thread, the new source
+/// replaces the previous one between two command buffers once it is ready.
+/// If rebuilding fails the previous source stays in use.
+#[derive(Debug)]
+pub struct ReloadingSource {
+    source: Box<dyn CommandBufferSource>,
+    /// The latest rebuilt source that is not in use yet
+    reloaded: Arc<Mutex<Option<Box<dyn CommandBufferSource>>>>,
+}
+
+impl ReloadingSource {
+    pub fn new<T: Trigger + 'static>(
+        source: Box<dyn CommandBufferSource>,
+        reload: Reload,
+        trigger: T,
+    ) -> std::io::Result<Self> {
+        let reloaded = Arc::new(Mutex::new(None));
+        let weak = Arc::downgrade(&reloaded);
+        std::thread::Builder::new()
+            .name("reload".to_string())
+            .spawn(move || watch(reload, trigger, weak))?;
+
+        Ok(Self { source, reloaded })
+    }
+}
+
+/// Rebuilds the source after changes until the [ReloadingSource] is dropped
+fn watch<T: Trigger>(
+    mut reload: Reload,
+    mut trigger: T,
+    reloaded: Weak<Mutex<Option<Box<dyn CommandBufferSource>>>>,
+) {
+    // time of the last unhandled change
+    let mut changed = None;
+    loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        if reloaded.strong_count() == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        match trigger.fired() {
+            Ok(true) => changed = Some(now),
+            Ok(false) => {}
+            Err(e) => warn!("unable to watch {}: {e}", trigger.describe()),
+        }
+
+        if !matches!(changed, Some(changed) if now - changed >= SETTLE_TIME) {
+            continue;
+        }
+        changed = None;
+        match reload() {
+            Ok(source) => {
+                let Some(reloaded) = reloaded.upgrade() else {
+                    return;
+                };
+                // a source that was not picked up yet is replaced by the newer one
+                *reloaded.lock().unwrap() = Some(source);
+                info!("reloaded {}", trigger.describe());
+            }
+            Err(e) => warn!(
+                "unable to reload {}, keeping the previous version: {e}",
+                trigger.describe()
+            ),
+        }
+    }
+}
+
+impl CommandBufferSource for ReloadingSource {
+    fn command_buffer(
+        &mut self,
+        delta: Duration,
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        // never waits for the reload thread
+        if let Ok(mut reloaded) = self.reloaded.try_lock() {
+            if let Some(source) = reloaded.take() {
+                self.source = source;
+            }
+        }
+
+        self.source.command_buffer(delta)
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.source.cycle_time()
+    }
+}
@@ -0,0 +1,214 @@
+//! Leaves out, flattens or passes through the alpha of translucent pixels
+
+use std::str::FromStr;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use epizentrum::alpha_policy::{AlphaPolicy, SKIPPED_PIXELS_FRAMES};
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_processing::gpu_processor::GpuProcessor;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_processing::FrameProcessor;
+use epizentrum::frame_source::{Frame, FrameSource};
+use epizentrum::motion_path::MotionPath;
+use epizentrum::CompositeBufferSource;
+use strand::Server;
+
+use crate::common::{spawn_flut, wait_until, Backend, Format, Frames, RED};
+
+mod common;
+
+const SIZE: (u16, u16) = (4, 1);
+const FRAME_TIME: Duration = Duration::from_millis(100);
+const LEFT_TO_RIGHT: DrawStrategy = DrawStrategy::Columns { reversed: false };
+
+/// Two frames of four pixels, the first pixel is transparent in both, the second only in the first frame
+fn fading(format: Format) -> Frames {
+    let frame = |pixels: [[u8; 4]; 4]| match format {
+        Format::Rgba => Frame::Rgba(pixels.into()),
+        Format::Bgra => Frame::Bgra(pixels.map(|[r, g, b, a]| [b, g, r, a]).into()),
+    };
+
+    let frames = vec![
+        frame([
+            [1, 2, 3, 0],
+            [4, 5, 6, 0],
+            [7, 8, 9, 128],
+            [10, 10, 10, 255],
+        ]),
+        frame([
+            [1, 2, 3, 0],
+            [4, 5, 6, 255],
+            [7, 8, 9, 128],
+            [10, 10, 10, 255],
+        ]),
+    ];
+    Frames::new(SIZE, frames, FRAME_TIME)
+}
+
+/// Commands for the frame `source` shows at `delta`
+fn commands(processor: &dyn FrameProcessor, source: &mut Frames, delta: Duration) -> String {
+    let frame = source.frame(delta).frame;
+    String::from_utf8(processor.process(frame, (0, 0)).unwrap().into()).unwrap()
+}
+
+#[test]
+fn skipped_in_every_frame() {
+    let mut source = fading(Format::Rgba);
+
+    let skipped = AlphaPolicy::default().skipped_pixels(&mut source);
+    assert_eq!(skipped.as_ref(), [true, false, false, false]);
+
+    let skipped = AlphaPolicy::Skip { threshold: 200 }.skipped_pixels(&mut source);
+    assert_eq!(skipped.as_ref(), [true, false, true, false]);
+
+    for policy in [AlphaPolicy::Opaque, AlphaPolicy::PassThrough] {
+        assert!(!policy.skipped_pixels(&mut source).contains(&true));
+    }
+}
+
+#[test]
+fn long_cycles_skip_nothing() {
+    let transparent =
+        |frames: usize| Frames::solid((2, 1), &vec![[0, 0, 0, 0]; frames], FRAME_TIME);
+
+    let mut source = transparent(SKIPPED_PIXELS_FRAMES);
+    let skipped = AlphaPolicy::default().skipped_pixels(&mut source);
+    assert_eq!(skipped.as_ref(), [true, true]);
+    assert_eq!(
+        source.requests.load(Ordering::Relaxed),
+        SKIPPED_PIXELS_FRAMES
+    );
+
+    // frames after the limit are not requested, their pixels could be visible
+    let mut source = transparent(SKIPPED_PIXELS_FRAMES + 1);
+    let skipped = AlphaPolicy::default().skipped_pixels(&mut source);
+    assert_eq!(skipped.as_ref(), [false, false]);
+    assert_eq!(
+        source.requests.load(Ordering::Relaxed),
+        SKIPPED_PIXELS_FRAMES
+    );
+}
+
+#[test]
+fn rayon_policies() {
+    for format in [Format::Rgba, Format::Bgra] {
+        let mut source = fading(format);
+        let processor = |policy| RayonProcessor::new(SIZE, SIZE, LEFT_TO_RIGHT, policy, None);
+
+        let skip = processor(AlphaPolicy::default());
+        assert_eq!(
+            commands(&skip, &mut source, Duration::ZERO),
+            "PX 2 0 07080980\nPX 3 0 0a\n"
+        );
+        assert_eq!(
+            commands(&skip, &mut source, FRAME_TIME),
+            "PX 1 0 040506\nPX 2 0 07080980\nPX 3 0 0a\n"
+        );
+
+        let opaque = processor(AlphaPolicy::Opaque);
+        assert_eq!(
+            commands(&opaque, &mut source, Duration::ZERO),
+            "PX 0 0 010203\nPX 1 0 040506\nPX 2 0 070809\nPX 3 0 0a\n"
+        );
+
+        let pass = processor(AlphaPolicy::PassThrough);
+        assert_eq!(
+            commands(&pass, &mut source, Duration::ZERO),
+            "PX 0 0 01020300\nPX 1 0 04050600\nPX 2 0 07080980\nPX 3 0 0a\n"
+        );
+    }
+}
+
+#[test]
+fn skipped_pixels_leave_the_draw_order() {
+    let mut source = fading(Format::Rgba);
+    let policy = AlphaPolicy::Skip { threshold: 200 };
+    let skipped = policy.skipped_pixels(&mut source);
+
+    let rayon = RayonProcessor::new(SIZE, SIZE, LEFT_TO_RIGHT, policy, Some(&skipped));
+    assert_eq!(
+        commands(&rayon, &mut source, FRAME_TIME),
+        "PX 1 0 040506\nPX 3 0 0a\n"
+    );
+
+    // skipped on machines without a GPU
+    if let Ok(gpu) = GpuProcessor::new(0, SIZE, SIZE, LEFT_TO_RIGHT, policy, Some(&skipped)) {
+        assert_eq!(
+            commands(&gpu, &mut source, FRAME_TIME),
+            "PX 1 0 040506ff\nPX 3 0 0a0a0aff\n"
+        );
+        // pixels skipped in some frames are left out per frame
+        assert_eq!(
+            commands(&gpu, &mut source, Duration::ZERO),
+            "PX 3 0 0a0a0aff\n"
+        );
+    }
+
+    // without skipped pixels, e.g. for streams, every pixel is checked per frame
+    if let Ok(gpu) = GpuProcessor::new(0, SIZE, SIZE, LEFT_TO_RIGHT, policy, None) {
+        assert_eq!(
+            commands(&gpu, &mut source, Duration::ZERO),
+            "PX 3 0 0a0a0aff\n"
+        );
+    }
+}
+
+#[test]
+fn transparent_frames_keep_connections() {
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+    let timeout = Duration::from_secs(10);
+
+    for backend in Backend::ALL.into_iter().filter(Backend::available) {
+        // nothing to draw at all, then nothing until the second frame
+        for (colors, drawn) in [(vec![CLEAR], false), (vec![CLEAR, RED], true)] {
+            let server = Server::bind("127.0.0.1:0", SIZE)
+                .and_then(Server::spawn)
+                .unwrap();
+            // an empty write looks like a closed connection, without reconnects the flut exits
+            let flut = spawn_flut(backend, server.local_addr(), 2, None, Some(0), move || {
+                Box::new(CompositeBufferSource {
+                    source: Frames::solid(SIZE, &colors, FRAME_TIME * 3),
+                    processor: RayonProcessor::new(
+                        SIZE,
+                        SIZE,
+                        LEFT_TO_RIGHT,
+                        AlphaPolicy::default(),
+                        None,
+                    ),
+                    path: MotionPath::default(),
+                })
+            });
+
+            let red = || server.canvas().get(3, 0) == Some(RED);
+            match drawn {
+                true => assert!(wait_until(timeout, red), "{backend:?}"),
+                false => {
+                    thread::sleep(FRAME_TIME * 5);
+                    assert_eq!(server.stats().bytes.load(Ordering::Relaxed), 0);
+                }
+            }
+            assert!(!flut.is_finished(), "{backend:?}");
+            let stats = server.stats();
+            assert_eq!(stats.total_connections.load(Ordering::Relaxed), 2);
+        }
+    }
+}
+
+#[test]
+fn parse() {
+    for (s, policy) in [
+        ("skip", AlphaPolicy::Skip { threshold: 1 }),
+        ("skip,128", AlphaPolicy::Skip { threshold: 128 }),
+        ("opaque", AlphaPolicy::Opaque),
+        ("pass", AlphaPolicy::PassThrough),
+    ] {
+        assert_eq!(AlphaPolicy::from_str(s).unwrap(), policy);
+        assert_eq!(policy.to_string(), s);
+    }
+
+    for s in ["", "skip,", "skip,256", "opaque,1", "alpha"] {
+        assert!(AlphaPolicy::from_str(s).is_err(), "{s}");
+    }
+}
@@ -0,0 +1,179 @@
+//! Distributes writes between sources
+
+use std::num::NonZeroU64;
+
+use epizentrum::assignment::{Assignment, AssignmentPolicy, SourceWeights};
+
+fn weighted(weights: &[u64]) -> Assignment {
+    Assignment::new(
+        AssignmentPolicy::Weighted,
+        weights
+            .iter()
+            .map(|&weight| NonZeroU64::new(weight).unwrap())
+            .collect(),
+    )
+}
+
+/// Sources of `n` consecutive writes on one connection
+fn picks(assignment: &mut Assignment, n: usize) -> Vec<usize> {
+    let mut source = assignment.first(0);
+    let mut picks = vec![source];
+    while picks.len() < n {
+        source = assignment.next(source);
+        picks.push(source);
+    }
+    picks
+}
+
+fn counts(picks: &[usize], sources: usize) -> Vec<usize> {
+    let mut counts = vec![0; sources];
+    for &source in picks {
+        counts[source] += 1;
+    }
+    counts
+}
+
+#[test]
+fn round_robin_cycles_through_sources() {
+    let mut assignment = Assignment::round_robin(3);
+    assert_eq!(picks(&mut assignment, 7), [0, 1, 2, 0, 1, 2, 0]);
+    // connections start at different sources
+    assert_eq!(
+        (0..4).map(|id| assignment.first(id)).collect::<Vec<_>>(),
+        [0, 1, 2, 0]
+    );
+}
+
+#[test]
+fn weighted_picks_follow_the_weights() {
+    let mut assignment = weighted(&[3, 1]);
+    let order = picks(&mut assignment, 400);
+    assert_eq!(counts(&order, 2), [300, 100]);
+    // smooth: the heavy source is interleaved instead of picked in one run
+    assert_eq!(order[..8], [0, 0, 1, 0, 0, 0, 1, 0]);
+
+    let mut assignment = weighted(&[5, 3, 2]);
+    assert_eq!(counts(&picks(&mut assignment, 1000), 3), [500, 300, 200]);
+
+    // pixel counts as weights: a full screen background and a 16x16 sprite
+    let mut assignment = weighted(&[1920 * 1080, 16 * 16]);
+    assert_eq!(counts(&picks(&mut assignment, 8101 * 4), 2), [8100 * 4, 4]);
+}
+
+#[test]
+fn equal_and_smallest_weights() {
+    // equal weights alternate like round robin
+    let mut assignment = weighted(&[7, 7, 7]);
+    assert_eq!(picks(&mut assignment, 6), [0, 1, 2, 0, 1, 2]);
+
+    // weights can not be zero, the smallest weight still gets its share
+    let mut assignment = weighted(&[1_000, 1]);
+    assert_eq!(counts(&picks(&mut assignment, 1001 * 3), 2), [3000, 3]);
+}
+
+#[test]
+fn single_source_is_always_picked() {
+    for policy in [
+        AssignmentPolicy::RoundRobin,
+        AssignmentPolicy::Dedicated,
+        AssignmentPolicy::Weighted,
+    ] {
+        let mut assignment = Assignment::new(policy, [NonZeroU64::new(5).unwrap()].into());
+        assert_eq!(assignment.sources(), 1);
+        assert_eq!(
+            (0..3).map(|id| assignment.first(id)).collect::<Vec<_>>(),
+            [0; 3]
+        );
+        assert_eq!(picks(&mut assignment, 10), [0; 10]);
+    }
+}
+
+#[test]
+fn dedicated_connections_are_split_by_weight() {
+    let mut assignment = Assignment::new(
+        AssignmentPolicy::Dedicated,
+        [NonZeroU64::new(3).unwrap(), NonZeroU64::MIN].into(),
+    );
+    let firsts = (0..8).map(|id| assignment.first(id)).collect::<Vec<_>>();
+    assert_eq!(counts(&firsts, 2), [6, 2]);
+
+    // every connection sticks to its source
+    for source in firsts {
+        assert_eq!(assignment.next(source), source);
+    }
+}
+
+#[test]
+fn dedicated_sources_leave_the_rest_shared() {
+    let mut assignment = Assignment::mixed([
+        (AssignmentPolicy::RoundRobin, NonZeroU64::MIN),
+        (AssignmentPolicy::Dedicated, NonZeroU64::new(2).unwrap()),
+        (AssignmentPolicy::RoundRobin, NonZeroU64::MIN),
+    ]);
+    // the dedicated source and the two shared ones as a whole
+    assert_eq!(assignment.groups(), 2);
+
+    // connections are split 2 to 2, the shared sources count with weight 1 each
+    let firsts = (0..8).map(|id| assignment.first(id)).collect::<Vec<_>>();
+    assert_eq!(firsts.iter().filter(|&&source| source == 1).count(), 4);
+
+    assert_eq!(assignment.next(1), 1);
+    // shared round-robin sources skip the dedicated one
+    assert_eq!(assignment.next(0), 2);
+    assert_eq!(assignment.next(2), 0);
+}
+
+#[test]
+fn mixed_shared_sources_are_picked_by_weight() {
+    // round-robin sources count with weight 1 among weighted ones
+    let mut assignment = Assignment::mixed([
+        (AssignmentPolicy::Weighted, NonZeroU64::new(3).unwrap()),
+        (AssignmentPolicy::RoundRobin, NonZeroU64::new(100).unwrap()),
+    ]);
+    assert_eq!(assignment.groups(), 1);
+    assert_eq!(counts(&picks(&mut assignment, 400), 2), [300, 100]);
+}
+
+#[test]
+fn source_policies_override_the_default() {
+    let source = |pixels, weight, policy| SourceWeights {
+        pixels: NonZeroU64::new(pixels).unwrap(),
+        weight: NonZeroU64::new(weight).unwrap(),
+        policy,
+    };
+    let sources = [
+        source(1920 * 1080, 1, None),
+        source(16 * 16, 1, None),
+        source(16 * 16, 1, Some(AssignmentPolicy::Dedicated)),
+    ];
+
+    // pixel counts weigh the shared sources, the sprite keeps a connection of its own
+    let mut assignment = Assignment::from_weights(AssignmentPolicy::Pixels, &sources);
+    assert_eq!(assignment.groups(), 2);
+    let shared = picks(&mut assignment, 8101);
+    assert_eq!(counts(&shared, 3), [8100, 1, 0]);
+    assert_eq!(assignment.next(2), 2);
+
+    // without overrides the default applies to every source
+    let sources = [source(4, 3, None), source(4, 1, None)];
+    let mut assignment = Assignment::from_weights(AssignmentPolicy::Weighted, &sources);
+    assert_eq!(counts(&picks(&mut assignment, 400), 2), [300, 100]);
+    let mut assignment = Assignment::from_weights(AssignmentPolicy::RoundRobin, &sources);
+    assert_eq!(picks(&mut assignment, 4), [0, 1, 0, 1]);
+}
+
+#[test]
+fn candidates_stay_within_the_connection_group() {
+    let assignment = Assignment::mixed([
+        (AssignmentPolicy::Weighted, NonZeroU64::new(1_000).unwrap()),
+        (AssignmentPolicy::Dedicated, NonZeroU64::MIN),
+        (AssignmentPolicy::Weighted, NonZeroU64::MIN),
+        (AssignmentPolicy::RoundRobin, NonZeroU64::MIN),
+    ]);
+
+    // shared sources in round-robin order, starting with the given one
+    assert_eq!(assignment.candidates(0), [0, 2, 3]);
+    assert_eq!(assignment.candidates(2), [2, 3, 0]);
+    assert_eq!(assignment.candidates(3), [3, 0, 2]);
+    assert_eq!(assignment.candidates(1), [1]);
+}
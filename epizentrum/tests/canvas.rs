@@ -0,0 +1,353 @@
+//! Draws test images with [FlutOp] onto an in-process pixelflut server and
+//! compares the resulting canvas pixel for pixel.
+
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::Assignment;
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::flut_op::epoll::EpollLoop;
+use epizentrum::flut_op::FlutOp;
+use epizentrum::frame_processing::gpu_processor::GpuProcessor;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_processing::FrameProcessor;
+use epizentrum::frame_source::media_source::MediaSource;
+use epizentrum::frame_source::FrameSource;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::{
+    tsunami_ring, CommandBufferSource, CompositeBufferSource, ComputeOnceCache, ControlFlowError,
+    SetupError, SingleFrameCache, TeardownError,
+};
+use strand::Server;
+
+use crate::common::{mismatch, pattern, Backend, Format, TestImage};
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const DRAW_STRATEGIES: [DrawStrategy; 5] = [
+    DrawStrategy::Random,
+    DrawStrategy::Rows { reversed: false },
+    DrawStrategy::Rows { reversed: true },
+    DrawStrategy::Columns { reversed: false },
+    DrawStrategy::Columns { reversed: true },
+];
+
+#[derive(Debug, Copy, Clone)]
+enum Processor {
+    Rayon,
+    Gpu,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Caching {
+    None,
+    SingleFrame,
+    ComputeOnce,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Case {
+    processor: Processor,
+    backend: Backend,
+    caching: Caching,
+    draw_strategy: DrawStrategy,
+    format: Format,
+    size: (u16, u16),
+    offset: (u16, u16),
+    canvas_size: (u16, u16),
+}
+
+impl Case {
+    fn new(processor: Processor, backend: Backend) -> Self {
+        Self {
+            processor,
+            backend,
+            caching: Caching::None,
+            draw_strategy: DrawStrategy::Random,
+            format: Format::Rgba,
+            size: (16, 12),
+            offset: (3, 5),
+            canvas_size: (32, 24),
+        }
+    }
+
+    fn processor(&self) -> Option<Box<dyn FrameProcessor>> {
+        match self.processor {
+            Processor::Rayon => Some(Box::new(RayonProcessor::new(
+                self.size,
+                self.canvas_size,
+                self.draw_strategy,
+                AlphaPolicy::default(),
+                None,
+            ))),
+            Processor::Gpu => GpuProcessor::new(
+                0,
+                self.size,
+                self.canvas_size,
+                self.draw_strategy,
+                AlphaPolicy::default(),
+                None,
+            )
+            .ok()
+            .map(|proc| Box::new(proc) as Box<dyn FrameProcessor>),
+        }
+    }
+
+    fn pipeline<Src: FrameSource + 'static>(
+        &self,
+        source: Src,
+        processor: Box<dyn FrameProcessor>,
+    ) -> Box<dyn CommandBufferSource> {
+        let source = CompositeBufferSource {
+            source,
+            processor,
+            path: MotionPath::Fixed(self.offset.0 as i32, self.offset.1 as i32),
+        };
+        match self.caching {
+            Caching::None => Box::new(source),
+            Caching::SingleFrame => Box::new(SingleFrameCache::new(source)),
+            Caching::ComputeOnce => Box::new(ComputeOnceCache::new(source)),
+        }
+    }
+
+    /// Draws a [TestImage]
+    fn run(self) {
+        self.run_with(move || TestImage::new(self.size, self.format))
+    }
+
+    /// Draws `source` until the canvas matches or [TIMEOUT] is reached
+    ///
+    /// Cases whose processor or backend is unavailable on this machine are skipped.
+    fn run_with<Src, F>(self, source: F)
+    where
+        Src: FrameSource + 'static,
+        F: FnOnce() -> Src + Send + 'static,
+    {
+        let server = Server::bind("127.0.0.1:0", self.canvas_size)
+            .and_then(Server::spawn)
+            .expect("unable to start server");
+        let addr = server.local_addr();
+
+        let flut = thread::spawn(move || -> Result<bool, String> {
+            let Some(processor) = self.processor() else {
+                return Ok(false);
+            };
+
+            let flut_op = FlutOp::new(
+                &[addr],
+                None,
+                [self.pipeline(source(), processor)].into(),
+                NonZeroUsize::new(2),
+                None,
+                Some(0),
+                vec![],
+                Instant::now(),
+                Assignment::round_robin(1),
+            );
+
+            match self.backend {
+                Backend::IoUring => {
+                    let Ok(ring) = tsunami_ring::Ring::new_raw_ring(NonZeroU32::new(128).unwrap())
+                    else {
+                        return Ok(false);
+                    };
+                    tsunami_ring::Ring::new(ring, None, flut_op)
+                        .run::<SetupError, ControlFlowError, TeardownError>()
+                        .map_err(|e| e.to_string())?;
+                }
+                Backend::Epoll => EpollLoop::new(flut_op)
+                    .map_err(|e| e.to_string())?
+                    .run()
+                    .map_err(|e| e.to_string())?,
+            }
+
+            Ok(true)
+        });
+
+        let deadline = Instant::now() + TIMEOUT;
+        let mismatch = loop {
+            let mismatch = mismatch(server.canvas(), self.size, self.offset);
+            if mismatch.is_none() || flut.is_finished() || Instant::now() >= deadline {
+                break mismatch;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        let outside = server.stats().outside.load(Ordering::Relaxed);
+
+        // closing all connections makes the FlutOp exit, there are no reconnects
+        drop(server);
+        match flut.join().expect("flut thread panicked") {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("skipping {self:?}: processor or backend unavailable");
+                return;
+            }
+            Err(e) => panic!("{self:?} failed: {e}"),
+        }
+
+        if let Some(((x, y), expected, actual)) = mismatch {
+            panic!("{self:?}: pixel {x} {y} is {actual:02x?}, expected {expected:02x?}");
+        }
+        assert_eq!(
+            outside, 0,
+            "{self:?}: pixels outside of the canvas were sent"
+        );
+    }
+}
+
+fn all_modes(processor: Processor, backend: Backend) {
+    for format in [Format::Rgba, Format::Bgra] {
+        for draw_strategy in DRAW_STRATEGIES {
+            for caching in [Caching::None, Caching::SingleFrame, Caching::ComputeOnce] {
+                Case {
+                    format,
+                    draw_strategy,
+                    caching,
+                    ..Case::new(processor, backend)
+                }
+                .run();
+            }
+        }
+    }
+}
+
+fn clipping(processor: Processor, backend: Backend) {
+    for format in [Format::Rgba, Format::Bgra] {
+        let case = Case {
+            format,
+            ..Case::new(processor, backend)
+        };
+
+        // across the right and bottom edge
+        Case {
+            offset: (24, 20),
+            ..case
+        }
+        .run();
+
+        // larger than the canvas
+        Case {
+            size: (48, 40),
+            offset: (0, 0),
+            ..case
+        }
+        .run();
+
+        // larger than the canvas with offset
+        Case {
+            size: (48, 40),
+            offset: (7, 2),
+            ..case
+        }
+        .run();
+
+        // exactly fills the canvas
+        Case {
+            size: (32, 24),
+            offset: (0, 0),
+            ..case
+        }
+        .run();
+    }
+}
+
+#[test]
+fn offsets_at_the_coordinate_limits() {
+    // positions past the largest coordinate are clipped instead of overflowing or wrapping
+    let (size, canvas_size) = ((4, 4), (u16::MAX, u16::MAX));
+    let draw_strategy = DrawStrategy::Rows { reversed: false };
+    let mut processors: Vec<Box<dyn FrameProcessor>> = vec![Box::new(RayonProcessor::new(
+        size,
+        canvas_size,
+        draw_strategy,
+        AlphaPolicy::default(),
+        None,
+    ))];
+    // skipped on machines without a GPU
+    if let Ok(gpu) = GpuProcessor::new(
+        0,
+        size,
+        canvas_size,
+        draw_strategy,
+        AlphaPolicy::default(),
+        None,
+    ) {
+        processors.push(Box::new(gpu));
+    }
+    let mut image = TestImage::new(size, Format::Rgba);
+    let frame = image.frame(Duration::ZERO).frame;
+
+    let edge = u16::MAX as i32 - 2;
+    for processor in &processors {
+        for (offset, expected) in [
+            ((edge, 0), 8),
+            ((0, edge), 8),
+            ((edge, edge), 4),
+            ((i32::MAX, i32::MAX), 0),
+            ((i32::MIN, 0), 0),
+            ((0, i32::MIN + 1), 0),
+        ] {
+            let commands = processor.process(frame, offset).unwrap();
+            let commands = commands.split(|b| *b == b'\n').count() - 1;
+            assert_eq!(commands, expected, "{processor:?} offset {offset:?}");
+        }
+    }
+}
+
+#[test]
+fn rayon_io_uring() {
+    all_modes(Processor::Rayon, Backend::IoUring);
+}
+
+#[test]
+fn rayon_epoll() {
+    all_modes(Processor::Rayon, Backend::Epoll);
+}
+
+#[test]
+fn gpu_io_uring() {
+    all_modes(Processor::Gpu, Backend::IoUring);
+}
+
+#[test]
+fn gpu_epoll() {
+    all_modes(Processor::Gpu, Backend::Epoll);
+}
+
+#[test]
+fn rayon_clipping() {
+    clipping(Processor::Rayon, Backend::IoUring);
+    clipping(Processor::Rayon, Backend::Epoll);
+}
+
+#[test]
+fn gpu_clipping() {
+    clipping(Processor::Gpu, Backend::IoUring);
+    clipping(Processor::Gpu, Backend::Epoll);
+}
+
+#[test]
+fn media_source() {
+    let case = Case {
+        offset: (9, 4),
+        ..Case::new(Processor::Rayon, Backend::Epoll)
+    };
+
+    let path = std::env::temp_dir().join(format!("tsunami-canvas-{}.png", std::process::id()));
+    image::RgbaImage::from_fn(case.size.0 as u32, case.size.1 as u32, |x, y| {
+        image::Rgba(pattern(x as u16, y as u16))
+    })
+    .save(&path)
+    .expect("unable to write test image");
+
+    let source = MediaSource::new(&path).expect("unable to load test image");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(source.size(), case.size);
+
+    case.run_with(move || source);
+}
@@ -0,0 +1,188 @@
+//! Adjusts colors and keys out a color of every frame
+
+use std::time::Duration;
+
+use epizentrum::frame_source::color_source::{
+    ChromaKey, ColorAdjustment, ColorSource, ColorSourceError,
+};
+use epizentrum::frame_source::generator_source::{GeneratorSource, Pattern};
+
+use crate::common::{pattern, pixels, Format, TestImage};
+
+mod common;
+
+const SIZE: (u16, u16) = (7, 5);
+
+/// Pixels of the frame `source` shows at `delta` in RGBA order
+/// The adjusted [TestImage] next to the original in both channel orders
+fn adjusted(adjustment: ColorAdjustment) -> Vec<([u8; 4], [u8; 4])> {
+    let mut all = vec![];
+    for format in [Format::Rgba, Format::Bgra] {
+        let mut source = ColorSource::new(TestImage::new(SIZE, format), adjustment).unwrap();
+        let original = (0..SIZE.1).flat_map(|y| (0..SIZE.0).map(move |x| pattern(x, y)));
+        all.extend(original.zip(pixels(&mut source, Duration::ZERO)));
+    }
+    all
+}
+
+fn fill(color: [u8; 4], adjustment: ColorAdjustment) -> [u8; 4] {
+    let fill = GeneratorSource::new((1, 1), Pattern::Fill(color), None).unwrap();
+    pixels(
+        &mut ColorSource::new(fill, adjustment).unwrap(),
+        Duration::ZERO,
+    )[0]
+}
+
+#[test]
+fn default_changes_nothing() {
+    for (original, pixel) in adjusted(ColorAdjustment::default()) {
+        assert_eq!(pixel, original);
+    }
+
+    let full_turn = ColorAdjustment {
+        hue: 360.0,
+        ..Default::default()
+    };
+    for (original, pixel) in adjusted(full_turn) {
+        assert_eq!(pixel, original);
+    }
+}
+
+#[test]
+fn invert_and_grayscale() {
+    let invert = ColorAdjustment {
+        invert: true,
+        ..Default::default()
+    };
+    for ([r, g, b, a], pixel) in adjusted(invert) {
+        assert_eq!(pixel, [255 - r, 255 - g, 255 - b, a]);
+    }
+
+    let grayscale = ColorAdjustment {
+        grayscale: true,
+        ..Default::default()
+    };
+    for ([r, g, b, _], [gr, gg, gb, _]) in adjusted(grayscale) {
+        assert!(gr == gg && gg == gb);
+        let luma = 0.213 * r as f32 + 0.715 * g as f32 + 0.072 * b as f32;
+        assert!((gr as f32 - luma).abs() <= 1.0, "{gr} != {luma}");
+    }
+}
+
+#[test]
+fn channel_curves() {
+    let gray = [64, 64, 64, 255];
+    let adjust = |adjustment| fill(gray, adjustment);
+
+    assert_eq!(
+        adjust(ColorAdjustment {
+            brightness: 1.0,
+            ..Default::default()
+        }),
+        [255, 255, 255, 255]
+    );
+    assert_eq!(
+        adjust(ColorAdjustment {
+            contrast: 0.0,
+            ..Default::default()
+        }),
+        [128, 128, 128, 255]
+    );
+    assert_eq!(
+        adjust(ColorAdjustment {
+            gamma: 2.0,
+            ..Default::default()
+        }),
+        [128, 128, 128, 255]
+    );
+}
+
+#[test]
+fn hue_and_saturation() {
+    let red = [200, 0, 0, 255];
+    let [r, g, b, _] = fill(
+        red,
+        ColorAdjustment {
+            hue: 120.0,
+            ..Default::default()
+        },
+    );
+    assert!(g > r && g > b, "{r} {g} {b}");
+
+    let [r, g, b, _] = fill(
+        red,
+        ColorAdjustment {
+            saturation: 0.5,
+            ..Default::default()
+        },
+    );
+    assert!(r < 200 && g > 0 && g == b, "{r} {g} {b}");
+}
+
+#[test]
+fn chroma_key() {
+    let key = |tolerance| ColorAdjustment {
+        chroma_key: Some(ChromaKey {
+            color: [0, 200, 0],
+            tolerance,
+        }),
+        // decided before the colors change
+        invert: true,
+        ..Default::default()
+    };
+
+    assert_eq!(fill([0, 200, 0, 255], key(0.0))[3], 0);
+    assert_eq!(fill([20, 190, 10, 255], key(0.1))[3], 0);
+    assert_eq!(fill([20, 190, 10, 255], key(0.01))[3], 255);
+
+    // the key is an RGB color in both channel orders
+    for format in [Format::Rgba, Format::Bgra] {
+        let [r, g, b, _] = pattern(1, 0);
+        let adjustment = ColorAdjustment {
+            chroma_key: Some(ChromaKey {
+                color: [r, g, b],
+                tolerance: 0.0,
+            }),
+            ..Default::default()
+        };
+        let mut source = ColorSource::new(TestImage::new(SIZE, format), adjustment).unwrap();
+        let pixels = pixels(&mut source, Duration::ZERO);
+        assert_eq!(pixels[1], [r, g, b, 0], "{format:?}");
+        assert_eq!(pixels[2][3], 255, "{format:?}");
+    }
+}
+
+#[test]
+fn invalid_adjustment() {
+    for (adjustment, name) in [
+        (
+            ColorAdjustment {
+                gamma: 0.0,
+                ..Default::default()
+            },
+            "gamma",
+        ),
+        (
+            ColorAdjustment {
+                brightness: f32::NAN,
+                ..Default::default()
+            },
+            "brightness",
+        ),
+        (
+            ColorAdjustment {
+                chroma_key: Some(ChromaKey {
+                    color: [0; 3],
+                    tolerance: 2.0,
+                }),
+                ..Default::default()
+            },
+            "chroma key tolerance",
+        ),
+    ] {
+        match ColorSource::new(TestImage::new(SIZE, Format::Rgba), adjustment) {
+            Err(ColorSourceError::Invalid(invalid, _)) => assert_eq!(invalid, name),
+            result => panic!("{adjustment:?} was accepted: {result:?}"),
+        }
+    }
+}
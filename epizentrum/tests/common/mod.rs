@@ -0,0 +1,328 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)]
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::Assignment;
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::flut_op::epoll::EpollLoop;
+use epizentrum::flut_op::FlutOp;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::{Frame, FrameSource, Timing};
+use epizentrum::motion_path::MotionPath;
+use epizentrum::{
+    tsunami_ring, CommandBuffer, CommandBufferSource, CompositeBufferSource, ControlFlowError,
+    SetupError, TeardownError,
+};
+use strand::Canvas;
+
+pub const BLACK: [u8; 4] = [0, 0, 0, 255];
+pub const WHITE: [u8; 4] = [255, 255, 255, 255];
+pub const RED: [u8; 4] = [255, 0, 0, 255];
+
+/// Position, expected and actual color of a wrong pixel
+pub type Mismatch = ((u16, u16), [u8; 4], [u8; 4]);
+
+#[derive(Debug, Copy, Clone)]
+pub enum Backend {
+    IoUring,
+    Epoll,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::IoUring, Backend::Epoll];
+
+    pub fn available(&self) -> bool {
+        match self {
+            Backend::IoUring => {
+                tsunami_ring::Ring::new_raw_ring(NonZeroU32::new(128).unwrap()).is_ok()
+            }
+            Backend::Epoll => true,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Format {
+    Rgba,
+    Bgra,
+}
+
+/// Opaque test pattern with distinct colors and some gray pixels
+pub fn pattern(x: u16, y: u16) -> [u8; 4] {
+    match (x + y) % 5 {
+        0 => {
+            let v = (x * 11 + y * 3) as u8;
+            [v, v, v, 255]
+        }
+        _ => [(x * 7) as u8, (y * 13) as u8, ((x ^ y) * 5) as u8, 255],
+    }
+}
+
+/// A still image made of [pattern]
+#[derive(Debug)]
+pub struct TestImage {
+    size: (u16, u16),
+    frame: Frame,
+}
+
+impl TestImage {
+    pub fn new(size: (u16, u16), format: Format) -> Self {
+        let pixels = (0..size.1)
+            .flat_map(|y| (0..size.0).map(move |x| pattern(x, y)))
+            .map(|[r, g, b, a]| match format {
+                Format::Rgba => [r, g, b, a],
+                Format::Bgra => [b, g, r, a],
+            })
+            .collect();
+
+        Self {
+            size,
+            frame: match format {
+                Format::Rgba => Frame::Rgba(pixels),
+                Format::Bgra => Frame::Bgra(pixels),
+            },
+        }
+    }
+}
+
+impl FrameSource for TestImage {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        Timing {
+            frame: &self.frame,
+            frame_time: self.cycle_time(),
+            time_left: self.cycle_time().saturating_sub(delta),
+        }
+    }
+}
+
+/// A still gradient
+#[derive(Debug)]
+pub struct Gradient {
+    size: (u16, u16),
+    frame: Frame,
+}
+
+impl Gradient {
+    pub fn new(size: (u16, u16)) -> Self {
+        let pixels = (0..size.1)
+            .flat_map(|y| (0..size.0).map(move |x| Self::pixel(x, y)))
+            .collect();
+        Self {
+            size,
+            frame: Frame::Rgba(pixels),
+        }
+    }
+
+    pub fn pixel(x: u16, y: u16) -> [u8; 4] {
+        [(x * 7) as u8, (y * 9) as u8, 128, 255]
+    }
+}
+
+impl FrameSource for Gradient {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        Timing {
+            frame: &self.frame,
+            frame_time: self.cycle_time(),
+            time_left: self.cycle_time().saturating_sub(delta),
+        }
+    }
+}
+
+/// A [TestImage] covering a canvas of `size`, drawn by the CPU
+pub fn test_image_source(size: (u16, u16), strategy: DrawStrategy) -> Box<dyn CommandBufferSource> {
+    Box::new(CompositeBufferSource {
+        source: TestImage::new(size, Format::Rgba),
+        processor: RayonProcessor::new(size, size, strategy, AlphaPolicy::default(), None),
+        path: MotionPath::default(),
+    })
+}
+
+/// Pixels of `frame` in RGBA order
+pub fn rgba(frame: &Frame) -> Vec<[u8; 4]> {
+    match frame {
+        Frame::Rgba(pixels) => pixels.to_vec(),
+        Frame::Bgra(pixels) => pixels.iter().map(|[b, g, r, a]| [*r, *g, *b, *a]).collect(),
+    }
+}
+
+/// Pixels of the frame `source` shows at `delta` in RGBA order
+pub fn pixels(source: &mut impl FrameSource, delta: Duration) -> Vec<[u8; 4]> {
+    rgba(source.frame(delta).frame)
+}
+
+/// Shows `frames` one after another, each for `frame_time`, and counts the requested frames
+#[derive(Debug)]
+pub struct Frames {
+    size: (u16, u16),
+    frames: Vec<Frame>,
+    frame_time: Duration,
+    pub requests: Arc<AtomicUsize>,
+}
+
+impl Frames {
+    pub fn new(size: (u16, u16), frames: Vec<Frame>, frame_time: Duration) -> Self {
+        Self {
+            size,
+            frames,
+            frame_time,
+            requests: Default::default(),
+        }
+    }
+
+    /// Frames of one color each
+    pub fn solid(size: (u16, u16), colors: &[[u8; 4]], frame_time: Duration) -> Self {
+        let pixels = size.0 as usize * size.1 as usize;
+        let frames = colors
+            .iter()
+            .map(|color| Frame::Rgba(vec![*color; pixels].into_boxed_slice()))
+            .collect();
+        Self::new(size, frames, frame_time)
+    }
+
+    /// A still image of one color
+    pub fn still(size: (u16, u16), color: [u8; 4]) -> Self {
+        Self::solid(size, &[color], Duration::from_millis(u32::MAX as u64))
+    }
+}
+
+impl FrameSource for Frames {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.frame_time * self.frames.len() as u32
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        let index = (delta.as_nanos() / self.frame_time.as_nanos()) as usize;
+
+        Timing {
+            frame: &self.frames[index],
+            frame_time: self.frame_time,
+            time_left: self.frame_time * (index as u32 + 1) - delta,
+        }
+    }
+}
+
+/// Counts command buffers built by the wrapped source
+#[derive(Debug)]
+pub struct Counting<Src> {
+    pub src: Src,
+    pub built: Arc<AtomicUsize>,
+}
+
+impl<Src: CommandBufferSource> CommandBufferSource for Counting<Src> {
+    fn command_buffer(
+        &mut self,
+        delta: Duration,
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        self.built.fetch_add(1, Ordering::Relaxed);
+        self.src.command_buffer(delta)
+    }
+
+    fn cycle_time(&self) -> Duration {
+        self.src.cycle_time()
+    }
+}
+
+/// First pixel of `canvas` that differs from a [TestImage] of `size` drawn at `offset`
+pub fn mismatch(canvas: &Canvas, size: (u16, u16), offset: (u16, u16)) -> Option<Mismatch> {
+    let expected = |x: u16, y: u16| match (x.checked_sub(offset.0), y.checked_sub(offset.1)) {
+        (Some(x), Some(y)) if x < size.0 && y < size.1 => pattern(x, y),
+        _ => BLACK,
+    };
+
+    let (w, h) = canvas.size();
+    (0..h)
+        .flat_map(|y| (0..w).map(move |x| (x, y)))
+        .find_map(|(x, y)| {
+            let expected = expected(x, y);
+            match canvas.get(x, y) {
+                Some(actual) if actual == expected => None,
+                actual => Some(((x, y), expected, actual.unwrap_or_default())),
+            }
+        })
+}
+
+/// Polls `condition` until it holds or `timeout` is reached
+pub fn wait_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Runs a [FlutOp] with a single source against `target` on its own thread
+pub fn spawn_flut<F>(
+    backend: Backend,
+    target: SocketAddr,
+    connections: usize,
+    reconnect_backoff_limit: Option<Duration>,
+    reconnect_limit: Option<usize>,
+    source: F,
+) -> JoinHandle<Result<(), String>>
+where
+    F: FnOnce() -> Box<dyn CommandBufferSource> + Send + 'static,
+{
+    thread::spawn(move || {
+        let flut_op = FlutOp::new(
+            &[target],
+            None,
+            [source()].into(),
+            NonZeroUsize::new(connections),
+            reconnect_backoff_limit,
+            reconnect_limit,
+            vec![],
+            Instant::now(),
+            Assignment::round_robin(1),
+        );
+
+        match backend {
+            Backend::IoUring => {
+                let ring = tsunami_ring::Ring::new_raw_ring(NonZeroU32::new(128).unwrap())
+                    .map_err(|e| e.to_string())?;
+                tsunami_ring::Ring::new(ring, None, flut_op)
+                    .run::<SetupError, ControlFlowError, TeardownError>()
+                    .map_err(|e| e.to_string())
+            }
+            Backend::Epoll => EpollLoop::new(flut_op)
+                .map_err(|e| e.to_string())?
+                .run()
+                .map_err(|e| e.to_string()),
+        }
+    })
+}
@@ -0,0 +1,218 @@
+//! Drives the epoll backend directly against a local target
+
+use std::error::Error;
+use std::io::Read;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::{Assignment, AssignmentPolicy};
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::flut_op::epoll::EpollLoop;
+use epizentrum::flut_op::FlutOp;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::Timing;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::{CommandBuffer, CommandBufferSource, CompositeBufferSource};
+
+use crate::common::{spawn_flut, wait_until, Backend, Gradient};
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const SIZE: (u16, u16) = (32, 24);
+
+fn source() -> Box<dyn CommandBufferSource> {
+    Box::new(CompositeBufferSource {
+        source: Gradient::new(SIZE),
+        processor: RayonProcessor::new(
+            SIZE,
+            SIZE,
+            DrawStrategy::Rows { reversed: false },
+            AlphaPolicy::default(),
+            None,
+        ),
+        path: MotionPath::default(),
+    })
+}
+
+/// Never has anything to draw, like a fully transparent still image
+#[derive(Debug)]
+struct Nothing;
+
+impl CommandBufferSource for Nothing {
+    fn command_buffer(
+        &mut self,
+        delta: Duration,
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        Ok(Timing {
+            frame: CommandBuffer::from(&[] as &[u8]),
+            frame_time: self.cycle_time(),
+            time_left: self.cycle_time().saturating_sub(delta),
+        })
+    }
+
+    fn cycle_time(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+}
+
+/// Accepts connections and records what they write
+struct Target {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+    /// Currently open connections
+    open: Arc<AtomicUsize>,
+    bytes: Arc<AtomicU64>,
+    /// Start of the first connection
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Target {
+    fn bind(addr: &str) -> Self {
+        let listener = TcpListener::bind(addr).unwrap();
+        let target = Self {
+            addr: listener.local_addr().unwrap(),
+            stopped: Default::default(),
+            connections: Default::default(),
+            open: Default::default(),
+            bytes: Default::default(),
+            written: Default::default(),
+        };
+
+        let (stopped, connections) = (target.stopped.clone(), target.connections.clone());
+        let (open, bytes, written) = (
+            target.open.clone(),
+            target.bytes.clone(),
+            target.written.clone(),
+        );
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                if stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                connections
+                    .lock()
+                    .unwrap()
+                    .push(stream.try_clone().unwrap());
+                open.fetch_add(1, Ordering::Relaxed);
+
+                let (open, bytes, written) = (open.clone(), bytes.clone(), written.clone());
+                thread::spawn(move || {
+                    let mut buffer = [0; 4096];
+                    while let Ok(read @ 1..) = stream.read(&mut buffer) {
+                        bytes.fetch_add(read as u64, Ordering::Relaxed);
+                        let mut written = written.lock().unwrap();
+                        let missing = (1usize << 16).saturating_sub(written.len());
+                        written.extend_from_slice(&buffer[..read.min(missing)]);
+                    }
+                    open.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+
+        target
+    }
+
+    /// Closes the listener and all connections
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect(self.addr);
+        for connection in self.connections.lock().unwrap().drain(..) {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+
+    fn open(&self) -> usize {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Whether the first connection wrote the whole command buffer at least once
+    fn drawn(&self) -> bool {
+        let expected = source().command_buffer(Duration::ZERO).unwrap().frame;
+        let written = self.written.lock().unwrap();
+        written.len() >= expected.len() && written[..expected.len()] == *expected
+    }
+}
+
+#[test]
+fn writes_until_stopped() {
+    let target = Target::bind("127.0.0.1:0");
+    let flut = spawn_flut(Backend::Epoll, target.addr, 2, None, None, source);
+
+    assert!(wait_until(TIMEOUT, || target.drawn()));
+    assert!(wait_until(TIMEOUT, || target.open() == 2));
+
+    // the same frame is written over and over
+    let bytes = target.bytes();
+    assert!(wait_until(TIMEOUT, || target.bytes() > bytes * 2));
+    assert!(!flut.is_finished());
+}
+
+#[test]
+fn reconnects_to_a_restarted_target() {
+    let target = Target::bind("127.0.0.1:0");
+    let flut = spawn_flut(Backend::Epoll, target.addr, 1, None, None, source);
+    assert!(wait_until(TIMEOUT, || target.open() == 1));
+
+    // the first reconnect after 1s is refused, the second after 1s + 2s reaches the new target
+    target.stop();
+    thread::sleep(Duration::from_millis(1500));
+    let restarted = Target::bind(&target.addr.to_string());
+
+    assert!(wait_until(TIMEOUT, || restarted.drawn()));
+    assert!(!flut.is_finished());
+}
+
+#[test]
+fn exits_when_the_reconnect_limit_is_reached() {
+    let target = Target::bind("127.0.0.1:0");
+    let flut = spawn_flut(Backend::Epoll, target.addr, 2, None, Some(1), source);
+    assert!(wait_until(TIMEOUT, || target.open() == 2));
+
+    // one refused reconnect after 1s, then both connections died
+    target.stop();
+    assert!(wait_until(TIMEOUT, || flut.is_finished()));
+    assert_eq!(flut.join().unwrap(), Ok(()));
+}
+
+#[test]
+fn empty_heavy_sources_do_not_starve_the_others() {
+    let target = Target::bind("127.0.0.1:0");
+    let addr = target.addr;
+    // the empty source is picked a thousand times as often as the gradient
+    let flut = thread::spawn(move || {
+        let flut_op = FlutOp::new(
+            &[addr],
+            None,
+            [Box::new(Nothing) as Box<dyn CommandBufferSource>, source()].into(),
+            NonZeroUsize::new(1),
+            None,
+            None,
+            vec![],
+            Instant::now(),
+            Assignment::new(
+                AssignmentPolicy::Weighted,
+                [NonZeroU64::new(1_000).unwrap(), NonZeroU64::MIN].into(),
+            ),
+        );
+        EpollLoop::new(flut_op)
+            .map_err(|e| e.to_string())?
+            .run()
+            .map_err(|e| e.to_string())
+    });
+
+    assert!(wait_until(TIMEOUT, || target.drawn()));
+    assert!(!flut.is_finished());
+}
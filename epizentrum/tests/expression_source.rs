@@ -0,0 +1,151 @@
+//! Compiles per-pixel formulas and evaluates them for every pixel and frame
+
+use std::time::Duration;
+
+use epizentrum::frame_source::expression_source::expression::{ExpressionError, Program};
+use epizentrum::frame_source::expression_source::{ExpressionSource, ExpressionSourceError};
+use epizentrum::frame_source::FrameSource;
+
+use crate::common::pixels;
+
+mod common;
+
+fn source(size: (u16, u16), program: &str, frame_rate: f32) -> ExpressionSource {
+    ExpressionSource::new(size, program.parse().unwrap(), frame_rate).unwrap()
+}
+
+#[test]
+fn channels_follow_the_coordinates() {
+    let mut source = source((4, 3), "r = x * 10; g = y * 20; b = w + h", 30.0);
+    assert!(!source.animated());
+    assert_eq!(source.cycle_time(), Duration::from_millis(u32::MAX as u64));
+
+    let pixels = pixels(&mut source, Duration::ZERO);
+    for (i, pixel) in pixels.into_iter().enumerate() {
+        let (x, y) = (i % 4, i / 4);
+        assert_eq!(pixel, [x as u8 * 10, y as u8 * 20, 7, 255], "at {x}, {y}");
+    }
+}
+
+#[test]
+fn channels_are_rounded_and_clamped() {
+    let mut source = source((1, 1), "r = -20; g = 1000; b = 0 / 0; a = 127.6", 30.0);
+    assert_eq!(pixels(&mut source, Duration::ZERO), [[0, 255, 0, 128]]);
+}
+
+#[test]
+fn operators_bind_like_in_math() {
+    for (formula, expected) in [
+        ("1 + 2 * 3", 7),
+        ("(1 + 2) * 3", 9),
+        ("-2 ^ 2 + 10", 6),
+        ("2 ^ 3 ^ 2 / 256", 2),
+        ("-7 % 5", 3),
+        ("3 > 2 && 1 > 2 || 1 == 1 ? 40 : 50", 40),
+        ("!0 + !5", 1),
+        ("clamp(300, 0, 200) + min(2, 3) + max(2, 3) + abs(-1)", 206),
+        ("floor(pi) + round(tau) + mix(0, 10, 0.5)", 14),
+    ] {
+        let mut source = source((1, 1), &format!("r = {formula}"), 30.0);
+        assert_eq!(
+            pixels(&mut source, Duration::ZERO)[0][0],
+            expected,
+            "{formula}"
+        );
+    }
+}
+
+#[test]
+fn temporaries_carry_over_between_statements() {
+    let mut source = source((2, 1), "v = x * 100; v = v + 5;; r = v; g = v * 2", 30.0);
+    assert_eq!(
+        pixels(&mut source, Duration::ZERO),
+        [[5, 10, 0, 255], [105, 210, 0, 255]]
+    );
+}
+
+#[test]
+fn time_advances_per_frame() {
+    let mut source = source((1, 1), "r = t * 100", 10.0);
+    assert!(source.animated());
+    assert!(source.cycle_time() > Duration::from_secs(3600));
+
+    let timing = source.frame(Duration::from_millis(250));
+    assert_eq!(timing.frame_time, Duration::from_millis(100));
+    assert_eq!(timing.time_left, Duration::from_millis(50));
+
+    // t is the start of the frame
+    for (millis, expected) in [(0, 0), (99, 0), (100, 10), (250, 20), (2000, 200)] {
+        assert_eq!(
+            pixels(&mut source, Duration::from_millis(millis))[0][0],
+            expected,
+            "at {millis}ms"
+        );
+    }
+}
+
+#[test]
+fn invalid_programs_are_refused() {
+    let compile = |source: &str| Program::compile(source).unwrap_err();
+
+    assert_eq!(
+        compile("r = (x + 1"),
+        ExpressionError::Syntax {
+            position: 10,
+            found: "end".to_string(),
+            expected: ")",
+        }
+    );
+    assert!(matches!(
+        compile("r = x $ 2"),
+        ExpressionError::Syntax { position: 6, .. }
+    ));
+    assert!(matches!(
+        compile("r = x g = y"),
+        ExpressionError::Syntax { position: 6, .. }
+    ));
+    assert_eq!(
+        compile("r = z"),
+        ExpressionError::UnknownVariable("z".to_string())
+    );
+    // temporaries can only be read after they were assigned
+    assert_eq!(
+        compile("r = v; v = 1"),
+        ExpressionError::UnknownVariable("v".to_string())
+    );
+    assert_eq!(
+        compile("r = noise(x)"),
+        ExpressionError::UnknownFunction("noise".to_string())
+    );
+    assert_eq!(
+        compile("r = atan2(x)"),
+        ExpressionError::Arguments {
+            function: "atan2".to_string(),
+            expected: 2,
+            found: 1,
+        }
+    );
+    assert_eq!(compile("t = 1"), ExpressionError::ReadOnly("t".to_string()));
+    assert_eq!(
+        compile("pi = 3"),
+        ExpressionError::ReadOnly("pi".to_string())
+    );
+    assert_eq!(compile(" ; "), ExpressionError::Empty);
+}
+
+#[test]
+fn invalid_sizes_and_frame_rates_are_refused() {
+    let still: Program = "r = 255".parse().unwrap();
+    let animated: Program = "r = t".parse().unwrap();
+
+    assert!(matches!(
+        ExpressionSource::new((0, 4), still.clone(), 30.0),
+        Err(ExpressionSourceError::Size(0, 4))
+    ));
+    // the frame rate only matters for animations
+    assert!(ExpressionSource::new((4, 4), still, 0.0).is_ok());
+    assert!(matches!(
+        ExpressionSource::new((4, 4), animated, 0.0),
+        Err(ExpressionSourceError::FrameRate(_))
+    ));
+}
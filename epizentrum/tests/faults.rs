@@ -0,0 +1,452 @@
+//! Injects network faults between [FlutOp] and a pixelflut server and checks
+//! the reconnect, backoff and exit behaviour.
+
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::CommandBufferSource;
+use strand::proxy::{Fault, Proxy, ProxyHandle};
+use strand::{Server, ServerHandle};
+
+use crate::common::{mismatch, spawn_flut, test_image_source, wait_until, Backend};
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Allowed scheduling delay on top of the backoff
+const SLACK: Duration = Duration::from_millis(700);
+
+const SIZE: (u16, u16) = (64, 48);
+
+#[derive(Debug, Copy, Clone)]
+struct Settings {
+    connections: usize,
+    reconnect_backoff_limit: Option<Duration>,
+    reconnect_limit: Option<usize>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            connections: 2,
+            reconnect_backoff_limit: None,
+            reconnect_limit: None,
+        }
+    }
+}
+
+/// tsunami -> proxy -> server
+struct Fixture {
+    server: ServerHandle,
+    proxy: ProxyHandle,
+    flut: JoinHandle<Result<(), String>>,
+    settings: Settings,
+}
+
+impl Fixture {
+    fn start(backend: Backend, settings: Settings, fault: Fault) -> Self {
+        let server = Server::bind("127.0.0.1:0", SIZE)
+            .and_then(Server::spawn)
+            .expect("unable to start server");
+        let proxy = Proxy::bind("127.0.0.1:0", server.local_addr())
+            .and_then(Proxy::spawn)
+            .expect("unable to start proxy");
+        proxy.set_fault(fault);
+
+        let flut = spawn_flut(
+            backend,
+            proxy.local_addr(),
+            settings.connections,
+            settings.reconnect_backoff_limit,
+            settings.reconnect_limit,
+            source,
+        );
+
+        Self {
+            server,
+            proxy,
+            flut,
+            settings,
+        }
+    }
+
+    /// Starts flooding and waits until the canvas is drawn
+    fn drawn(backend: Backend, settings: Settings) -> Self {
+        let fixture = Self::start(backend, settings, Fault::None);
+        assert!(
+            wait_until(TIMEOUT, || fixture.active() == settings.connections
+                && fixture.canvas_drawn()),
+            "{backend:?}: canvas was not drawn"
+        );
+        fixture
+    }
+
+    fn accepted(&self) -> usize {
+        self.proxy.stats().accepted.load(Ordering::Relaxed)
+    }
+
+    fn active(&self) -> usize {
+        self.proxy.stats().active.load(Ordering::Relaxed)
+    }
+
+    fn bytes(&self) -> u64 {
+        self.proxy.stats().bytes.load(Ordering::Relaxed)
+    }
+
+    fn canvas_drawn(&self) -> bool {
+        mismatch(self.server.canvas(), SIZE, (0, 0)).is_none()
+    }
+
+    /// Waits until `n` connections were accepted in total
+    fn wait_for_accepted(&self, n: usize) -> bool {
+        wait_until(TIMEOUT, || self.accepted() >= n)
+    }
+
+    /// When the last `n` connections were accepted, relative to `since`
+    fn last_accepts(&self, n: usize, since: Instant) -> Vec<Duration> {
+        let times = self.proxy.stats().accept_times();
+        times[times.len() - n..]
+            .iter()
+            .map(|t| t.duration_since(since))
+            .collect()
+    }
+
+    /// Asserts that the last `settings.connections` reconnects happened `expected` after `since`
+    fn assert_reconnected_after(&self, since: Instant, expected: Duration) {
+        for reconnect in self.last_accepts(self.settings.connections, since) {
+            assert!(
+                reconnect >= expected.saturating_sub(Duration::from_millis(50))
+                    && reconnect <= expected + SLACK,
+                "reconnected after {reconnect:?}, expected {expected:?}"
+            );
+        }
+    }
+
+    fn exits_within(self, timeout: Duration) -> Result<(), String> {
+        assert!(
+            wait_until(timeout, || self.flut.is_finished()),
+            "flut did not exit within {timeout:?}"
+        );
+        self.flut.join().expect("flut thread panicked")
+    }
+}
+
+fn source() -> Box<dyn CommandBufferSource> {
+    test_image_source(SIZE, DrawStrategy::Random)
+}
+
+/// Runs `test` for every available backend in parallel
+fn each_backend(test: fn(Backend)) {
+    let tests: Vec<_> = Backend::ALL
+        .into_iter()
+        .filter(|backend| {
+            let available = backend.available();
+            if !available {
+                eprintln!("skipping {backend:?}: backend unavailable");
+            }
+            available
+        })
+        .map(|backend| thread::spawn(move || test(backend)))
+        .collect();
+
+    for test in tests {
+        if let Err(panic) = test.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+#[test]
+fn reconnects_after_reset() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(backend, Settings::default());
+
+        let reset = Instant::now();
+        fixture.proxy.reset_all();
+
+        assert!(fixture.wait_for_accepted(4), "{backend:?}: no reconnect");
+        fixture.assert_reconnected_after(reset, Duration::from_secs(1));
+
+        let bytes = fixture.bytes();
+        assert!(
+            wait_until(TIMEOUT, || fixture.bytes() > bytes),
+            "{backend:?}: no data after reconnect"
+        );
+        assert_eq!(fixture.active(), 2);
+        assert!(!fixture.flut.is_finished());
+    });
+}
+
+#[test]
+fn reconnect_backoff_doubles() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(backend, Settings::default());
+
+        // refuse the first attempt after 1s, accept the second one after 1s + 2s
+        let reset = Instant::now();
+        fixture.proxy.refuse(true);
+        fixture.proxy.reset_all();
+        thread::sleep(Duration::from_millis(1500));
+        fixture.proxy.refuse(false);
+
+        assert!(fixture.wait_for_accepted(4), "{backend:?}: no reconnect");
+        fixture.assert_reconnected_after(reset, Duration::from_secs(3));
+    });
+}
+
+#[test]
+fn reconnect_backoff_limit() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(
+            backend,
+            Settings {
+                reconnect_backoff_limit: Some(Duration::from_millis(500)),
+                ..Default::default()
+            },
+        );
+
+        // attempts every 500ms, the third one is accepted
+        let reset = Instant::now();
+        fixture.proxy.refuse(true);
+        fixture.proxy.reset_all();
+        thread::sleep(Duration::from_millis(1200));
+        fixture.proxy.refuse(false);
+
+        assert!(fixture.wait_for_accepted(4), "{backend:?}: no reconnect");
+        fixture.assert_reconnected_after(reset, Duration::from_millis(1500));
+    });
+}
+
+#[test]
+fn reconnect_limit_gives_up() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(
+            backend,
+            Settings {
+                reconnect_limit: Some(2),
+                ..Default::default()
+            },
+        );
+
+        // two failed attempts after 1s and 1s + 2s, then every connection died
+        let reset = Instant::now();
+        fixture.proxy.refuse(true);
+        fixture.proxy.reset_all();
+
+        assert_eq!(
+            fixture.exits_within(TIMEOUT),
+            Ok(()),
+            "{backend:?}: flut failed"
+        );
+        let exited = reset.elapsed();
+        assert!(
+            exited >= Duration::from_millis(2950) && exited <= Duration::from_secs(3) + SLACK,
+            "{backend:?}: exited after {exited:?}, expected 3s"
+        );
+    });
+}
+
+#[test]
+fn reconnect_limit_counts_every_failed_attempt() {
+    // the limit used to be checked the wrong way round, giving up after the first failed attempt
+    each_backend(|backend| {
+        for limit in [1u32, 3] {
+            let fixture = Fixture::drawn(
+                backend,
+                Settings {
+                    reconnect_backoff_limit: Some(Duration::from_millis(200)),
+                    reconnect_limit: Some(limit as usize),
+                    ..Default::default()
+                },
+            );
+
+            // one attempt every 200ms, the connections give up after `limit` of them
+            let reset = Instant::now();
+            fixture.proxy.refuse(true);
+            fixture.proxy.reset_all();
+
+            assert_eq!(fixture.exits_within(TIMEOUT), Ok(()));
+            let exited = reset.elapsed();
+            let expected = Duration::from_millis(200) * limit;
+            assert!(
+                exited >= expected - Duration::from_millis(50) && exited <= expected + SLACK,
+                "{backend:?}: exited after {exited:?} with limit {limit}, expected {expected:?}"
+            );
+        }
+    });
+}
+
+#[test]
+fn reconnect_limit_zero_exits() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(
+            backend,
+            Settings {
+                reconnect_limit: Some(0),
+                ..Default::default()
+            },
+        );
+
+        fixture.proxy.reset_all();
+        assert_eq!(fixture.exits_within(SLACK), Ok(()));
+    });
+}
+
+#[test]
+fn reconnect_limit_resets_after_reconnect() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(
+            backend,
+            Settings {
+                reconnect_limit: Some(2),
+                ..Default::default()
+            },
+        );
+
+        // each round uses one of two attempts, a successful reconnect starts over
+        for round in 1..=2 {
+            fixture.proxy.refuse(true);
+            fixture.proxy.reset_all();
+            thread::sleep(Duration::from_millis(1500));
+            fixture.proxy.refuse(false);
+
+            assert!(
+                fixture.wait_for_accepted(2 + 2 * round),
+                "{backend:?}: no reconnect in round {round}"
+            );
+            assert!(wait_until(TIMEOUT, || fixture.active() == 2));
+
+            // a reset before the connect completed would count as a failed reconnect
+            thread::sleep(Duration::from_millis(200));
+        }
+        assert!(!fixture.flut.is_finished());
+    });
+}
+
+#[test]
+fn exits_when_all_connections_died() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(
+            backend,
+            Settings {
+                connections: 3,
+                reconnect_limit: Some(0),
+                ..Default::default()
+            },
+        );
+
+        for remaining in [2, 1] {
+            assert!(fixture.proxy.reset_one());
+            assert!(wait_until(TIMEOUT, || fixture.active() == remaining));
+
+            // the remaining connections keep flooding
+            thread::sleep(Duration::from_millis(200));
+            assert!(!fixture.flut.is_finished(), "{backend:?}: exited early");
+            let bytes = fixture.bytes();
+            assert!(wait_until(TIMEOUT, || fixture.bytes() > bytes));
+        }
+
+        assert!(fixture.proxy.reset_one());
+        assert_eq!(fixture.accepted(), 3);
+        assert_eq!(fixture.exits_within(SLACK), Ok(()));
+    });
+}
+
+#[test]
+fn stalls_and_slow_reads() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(backend, Settings::default());
+
+        // full send buffers make writes partial
+        for fault in [
+            Fault::Stall,
+            Fault::SlowRead {
+                chunk: 997,
+                interval: Duration::from_millis(1),
+            },
+            Fault::None,
+        ] {
+            fixture.proxy.set_fault(fault);
+            thread::sleep(Duration::from_millis(300));
+        }
+
+        let bytes = fixture.bytes();
+        assert!(wait_until(TIMEOUT, || fixture.bytes() > bytes));
+        assert!(fixture.canvas_drawn());
+        assert_eq!(fixture.accepted(), 2, "{backend:?}: connections died");
+        assert_eq!(fixture.server.stats().invalid.load(Ordering::Relaxed), 0);
+        assert_eq!(fixture.server.stats().outside.load(Ordering::Relaxed), 0);
+    });
+}
+
+#[test]
+fn truncated_connections() {
+    each_backend(|backend| {
+        // cut every connection in the middle of a command
+        let fixture = Fixture::start(
+            backend,
+            Settings::default(),
+            Fault::Truncate { after: 1001 },
+        );
+        assert!(fixture.wait_for_accepted(2));
+        assert!(wait_until(TIMEOUT, || fixture.active() == 0));
+        fixture.proxy.set_fault(Fault::None);
+
+        assert!(fixture.wait_for_accepted(4), "{backend:?}: no reconnect");
+        assert!(
+            wait_until(TIMEOUT, || fixture.canvas_drawn()),
+            "{backend:?}: canvas was not drawn after reconnect"
+        );
+        assert_eq!(fixture.server.stats().invalid.load(Ordering::Relaxed), 0);
+        assert_eq!(fixture.server.stats().outside.load(Ordering::Relaxed), 0);
+    });
+}
+
+#[test]
+fn reset_on_accept() {
+    each_backend(|backend| {
+        let fixture = Fixture::drawn(backend, Settings::default());
+
+        // reconnects succeed but the connections are reset right away
+        fixture.proxy.set_fault(Fault::Reset);
+        fixture.proxy.reset_all();
+        assert!(fixture.wait_for_accepted(4));
+        fixture.proxy.set_fault(Fault::None);
+
+        assert!(fixture.wait_for_accepted(6), "{backend:?}: no reconnect");
+        assert!(wait_until(TIMEOUT, || fixture.active() == 2));
+        assert!(!fixture.flut.is_finished());
+    });
+}
+
+#[test]
+fn server_restart() {
+    each_backend(|backend| {
+        let Fixture {
+            mut server,
+            proxy,
+            flut,
+            ..
+        } = Fixture::drawn(backend, Settings::default());
+
+        // the proxy resets connections while the server is down
+        let addr = server.local_addr();
+        server.shutdown();
+        drop(server);
+        thread::sleep(Duration::from_millis(1500));
+
+        let server = Server::bind(addr, SIZE)
+            .and_then(Server::spawn)
+            .expect("unable to restart server");
+        assert!(
+            wait_until(TIMEOUT, || mismatch(server.canvas(), SIZE, (0, 0))
+                .is_none()),
+            "{backend:?}: canvas was not drawn after restart"
+        );
+        assert!(proxy.stats().accepted.load(Ordering::Relaxed) > 2);
+        assert!(!flut.is_finished());
+    });
+}
@@ -0,0 +1,143 @@
+//! Generates still and animated synthetic frames
+
+use std::time::Duration;
+
+use epizentrum::frame_source::generator_source::{
+    Animation, GeneratorSource, GeneratorSourceError, Pattern,
+};
+use epizentrum::frame_source::FrameSource;
+
+use crate::common::{pixels, BLACK, WHITE};
+
+mod common;
+
+const ANIMATION: Animation = Animation {
+    speed: 1.0,
+    frame_rate: 10.0,
+};
+
+#[test]
+fn fill_is_still() {
+    let color = [1, 2, 3, 4];
+    // fills ignore the animation
+    let mut source = GeneratorSource::new((3, 2), Pattern::Fill(color), Some(ANIMATION)).unwrap();
+    assert_eq!(source.cycle_time(), Duration::from_millis(u32::MAX as u64));
+    assert!(!source.animated());
+    assert!(pixels(&mut source, Duration::from_secs(5))
+        .iter()
+        .all(|p| *p == color));
+}
+
+#[test]
+fn linear_gradient_direction() {
+    let gradient = |angle| Pattern::LinearGradient {
+        from: BLACK,
+        to: WHITE,
+        angle,
+    };
+
+    let mut horizontal = GeneratorSource::new((256, 4), gradient(0.0), None).unwrap();
+    let pixels_h = pixels(&mut horizontal, Duration::ZERO);
+    assert_eq!(pixels_h[0], BLACK);
+    assert_eq!(pixels_h[255], WHITE);
+    assert!(pixels_h[..256].windows(2).all(|p| p[0][0] <= p[1][0]));
+    assert_eq!(pixels_h[..256], pixels_h[256..512]);
+
+    // 90 degrees runs from top to bottom
+    let mut vertical = GeneratorSource::new((4, 256), gradient(90.0), None).unwrap();
+    let pixels_v = pixels(&mut vertical, Duration::ZERO);
+    assert!(pixels_v[..4].iter().all(|p| *p == BLACK));
+    assert!(pixels_v[255 * 4..].iter().all(|p| *p == WHITE));
+}
+
+#[test]
+fn animations_loop() {
+    for pattern in [
+        Pattern::RadialGradient {
+            inner: WHITE,
+            outer: BLACK,
+        },
+        Pattern::Checkerboard {
+            cell: 8,
+            colors: [WHITE, BLACK],
+        },
+        Pattern::TestPattern { grid: 16 },
+        Pattern::Plasma,
+        Pattern::Noise { seed: 7 },
+    ] {
+        let mut source = GeneratorSource::new((32, 24), pattern, Some(ANIMATION)).unwrap();
+        assert_eq!(source.cycle_time(), Duration::from_secs(1), "{pattern:?}");
+        assert!(source.animated(), "{pattern:?}");
+
+        let timing = source.frame(Duration::from_millis(250));
+        assert_eq!(timing.frame_time, Duration::from_millis(100));
+        assert_eq!(timing.time_left, Duration::from_millis(50));
+
+        let first = pixels(&mut source, Duration::ZERO);
+        let second = pixels(&mut source, Duration::from_millis(100));
+        assert!(first != second, "{pattern:?} does not move");
+        assert!(
+            first == pixels(&mut source, Duration::from_millis(1020)),
+            "{pattern:?} does not loop"
+        );
+    }
+}
+
+#[test]
+fn seeded_noise() {
+    let noise = |seed| {
+        let mut source =
+            GeneratorSource::new((16, 16), Pattern::Noise { seed }, Some(ANIMATION)).unwrap();
+        pixels(&mut source, Duration::from_millis(300))
+    };
+
+    assert!(noise(1) == noise(1));
+    assert!(noise(1) != noise(2));
+}
+
+#[test]
+fn test_pattern_grid() {
+    let mut source =
+        GeneratorSource::new((140, 90), Pattern::TestPattern { grid: 64 }, None).unwrap();
+    let pixels = pixels(&mut source, Duration::ZERO);
+    let pixel = |x: usize, y: usize| pixels[y * 140 + x];
+
+    // grid lines over the color bars
+    assert_eq!(pixel(64, 30), WHITE);
+    assert_eq!(pixel(30, 64), WHITE);
+    assert_eq!(pixel(40, 30), [0, 191, 191, 255]);
+
+    // labels on black boxes next to the crossings
+    assert_eq!(pixel(66, 66), BLACK);
+    assert!((67..67 + 5 * 6).any(|x| pixel(x, 69) == WHITE));
+}
+
+#[test]
+fn invalid_parameters() {
+    assert!(matches!(
+        GeneratorSource::new((0, 4), Pattern::Plasma, None),
+        Err(GeneratorSourceError::Size(0, 4))
+    ));
+    assert!(matches!(
+        GeneratorSource::new(
+            (4, 4),
+            Pattern::Plasma,
+            Some(Animation {
+                speed: -1.0,
+                ..ANIMATION
+            })
+        ),
+        Err(GeneratorSourceError::Speed(_))
+    ));
+    assert!(matches!(
+        GeneratorSource::new(
+            (4, 4),
+            Pattern::Plasma,
+            Some(Animation {
+                frame_rate: f32::NAN,
+                ..ANIMATION
+            })
+        ),
+        Err(GeneratorSourceError::FrameRate(_))
+    ));
+}
@@ -0,0 +1,209 @@
+//! Compares lazily decoded and cached animations with fully decoded ones
+
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::media_source::{MediaSource, StreamingMediaSource};
+use epizentrum::frame_source::FrameSource;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::{CommandBufferSource, CompositeBufferSource, ComputeOnceCache};
+use image::codecs::gif::GifEncoder;
+use image::{Delay, RgbaImage};
+
+use crate::common::{pattern, rgba, Counting};
+
+mod common;
+
+const SIZE: (u16, u16) = (16, 12);
+const DELAYS: [u32; 5] = [20, 40, 10, 60, 30];
+
+/// Writes a gif whose frames are [pattern] shifted by their index
+fn animation(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tsunami-{name}-{}.gif", std::process::id()));
+
+    let frames = DELAYS.iter().enumerate().map(|(i, delay)| {
+        let buffer = RgbaImage::from_fn(SIZE.0 as u32, SIZE.1 as u32, |x, y| {
+            image::Rgba(pattern(x as u16 + i as u16, y as u16))
+        });
+        image::Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(*delay, 1))
+    });
+
+    let mut encoder = GifEncoder::new(std::fs::File::create(&path).unwrap());
+    encoder
+        .encode_frames(frames)
+        .expect("unable to write test animation");
+
+    path
+}
+
+#[test]
+fn streaming_matches_eager() {
+    let path = animation("streaming");
+    let mut eager = MediaSource::new(&path).expect("unable to load test animation");
+    let mut streaming = StreamingMediaSource::new(&path, NonZeroUsize::new(2).unwrap())
+        .expect("unable to load test animation");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(streaming.size(), eager.size());
+    assert_eq!(streaming.cycle_time(), eager.cycle_time());
+    assert_eq!(
+        eager.cycle_time(),
+        Duration::from_millis(DELAYS.iter().sum::<u32>() as u64)
+    );
+
+    // in order, skipping frames, on frame boundaries and across several cycles
+    let deltas = (0..400)
+        .step_by(5)
+        .chain([70, 75, 0, 1, 159, 160, 161, 20, 130, 10, 480, 485, 1000])
+        .map(Duration::from_millis);
+    for delta in deltas {
+        let expected = eager.frame(delta);
+        let (frame, frame_time, time_left) = (
+            rgba(expected.frame),
+            expected.frame_time,
+            expected.time_left,
+        );
+
+        // the decoder is not waited for, the last decoded frame is shown until it caught up
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while streaming.frame(delta).frame_time != frame_time {
+            assert!(Instant::now() < deadline, "decoder behind at {delta:?}");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let actual = streaming.frame(delta);
+        assert_eq!(actual.frame_time, frame_time, "frame time at {delta:?}");
+        assert_eq!(actual.time_left, time_left, "time left at {delta:?}");
+        assert!(rgba(actual.frame) == frame, "frame at {delta:?}");
+    }
+}
+
+#[test]
+fn frames_end_before_the_next_one_starts() {
+    let path = animation("boundaries");
+    let mut source = MediaSource::new(&path).expect("unable to load test animation");
+    std::fs::remove_file(&path).unwrap();
+
+    // the first frame is shown from 0ms to 20ms, the second one from 20ms to 60ms
+    let first = rgba(source.frame(Duration::ZERO).frame);
+    let timing = source.frame(Duration::from_millis(20));
+    assert_eq!(timing.frame_time, Duration::from_millis(40));
+    assert_eq!(timing.time_left, Duration::from_millis(40));
+    assert!(rgba(timing.frame) != first);
+
+    let timing = source.frame(Duration::from_millis(160));
+    assert_eq!(timing.time_left, Duration::from_millis(20));
+    assert!(rgba(timing.frame) == first);
+}
+
+#[test]
+fn streaming_does_not_wait_for_the_decoder() {
+    let path = animation("behind");
+    let mut streaming = StreamingMediaSource::new(&path, NonZeroUsize::new(1).unwrap())
+        .expect("unable to load test animation");
+    std::fs::remove_file(&path).unwrap();
+
+    // the last frame is from 130ms to 160ms, a frame shown in its place ends within that frame
+    for delta in [140, 150, 155] {
+        let timing = streaming.frame(Duration::from_millis(delta));
+        let end = Duration::from_millis(delta) + timing.time_left;
+        assert!(timing.time_left <= timing.frame_time, "at {delta}ms");
+        assert!(end <= Duration::from_millis(160), "at {delta}ms");
+        assert!(
+            timing.frame_time == Duration::from_millis(30)
+                || timing.time_left <= Duration::from_millis(5),
+            "at {delta}ms"
+        );
+    }
+}
+
+#[test]
+fn streaming_still_image() {
+    let path = std::env::temp_dir().join(format!("tsunami-still-{}.png", std::process::id()));
+    RgbaImage::from_fn(SIZE.0 as u32, SIZE.1 as u32, |x, y| {
+        image::Rgba(pattern(x as u16, y as u16))
+    })
+    .save(&path)
+    .unwrap();
+
+    let mut source = StreamingMediaSource::new(&path, NonZeroUsize::new(1).unwrap())
+        .expect("unable to load test image");
+    std::fs::remove_file(&path).unwrap();
+
+    for delta in [0, 1, 1000, 3600 * 1000] {
+        let frame = source.frame(Duration::from_millis(delta)).frame;
+        assert_eq!(rgba(frame)[1], pattern(1, 0));
+    }
+}
+
+#[test]
+fn still_webp() {
+    // a single transparent pixel in the simple lossless format
+    let webp = b"RIFF\x1a\0\0\0WEBPVP8L\x0d\0\0\0\x2f\0\0\0\x10\x07\x10\x11\x11\x88\x88\xfe\x07\0";
+    let path = std::env::temp_dir().join(format!("tsunami-still-{}.webp", std::process::id()));
+    std::fs::write(&path, webp).unwrap();
+
+    let mut eager = MediaSource::new(&path).expect("unable to load test image");
+    let mut streaming = StreamingMediaSource::new(&path, NonZeroUsize::new(1).unwrap())
+        .expect("unable to load test image");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(streaming.size(), (1, 1));
+    assert_eq!(streaming.cycle_time(), eager.cycle_time());
+    for delta in [0, 1000, 3600 * 1000].map(Duration::from_millis) {
+        assert_eq!(rgba(streaming.frame(delta).frame), [[0, 0, 0, 0]]);
+        assert_eq!(rgba(eager.frame(delta).frame), [[0, 0, 0, 0]]);
+    }
+}
+
+#[test]
+fn compute_once_cache_animation() {
+    let path = animation("cache");
+    let pipeline = || {
+        let source = MediaSource::new(&path).expect("unable to load test animation");
+        let processor = RayonProcessor::new(
+            SIZE,
+            SIZE,
+            DrawStrategy::Rows { reversed: false },
+            AlphaPolicy::default(),
+            None,
+        );
+        CompositeBufferSource {
+            source,
+            processor: Box::new(processor),
+            path: MotionPath::default(),
+        }
+    };
+    let built = Arc::new(AtomicUsize::new(0));
+    let mut uncached = pipeline();
+    let mut cached = ComputeOnceCache::new(Counting {
+        src: pipeline(),
+        built: built.clone(),
+    });
+    std::fs::remove_file(&path).unwrap();
+
+    let deltas = [
+        150, 5, 65, 25, 100, 0, 155, 45, 70, 135, 61, 21, 121, 300, 190,
+    ];
+    for delta in deltas.into_iter().chain(deltas) {
+        let delta = Duration::from_millis(delta);
+        let expected = uncached.command_buffer(delta).unwrap();
+        let actual = cached.command_buffer(delta).unwrap();
+
+        assert!(
+            *actual.frame == *expected.frame,
+            "command buffer at {delta:?}"
+        );
+        assert_eq!(actual.frame_time, expected.frame_time);
+        assert_eq!(actual.time_left, expected.time_left);
+    }
+
+    // every frame is built exactly once
+    assert_eq!(built.load(Ordering::Relaxed), DELAYS.len());
+}
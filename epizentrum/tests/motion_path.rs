@@ -0,0 +1,276 @@
+//! Objects moving over the canvas
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_processing::gpu_processor::GpuProcessor;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_processing::FrameProcessor;
+use epizentrum::frame_source::FrameSource;
+use epizentrum::motion_path::{Easing, Keyframe, MotionPath, MotionPathError, MOTION_STEP};
+use epizentrum::{CommandBufferSource, CompositeBufferSource};
+use strand::Server;
+
+use crate::common::{mismatch, spawn_flut, wait_until, Backend, Format, TestImage};
+
+mod common;
+
+const SIZE: (u16, u16) = (2, 2);
+const LEFT_TO_RIGHT: DrawStrategy = DrawStrategy::Rows { reversed: false };
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn keyframes(easing: Easing) -> MotionPath {
+    MotionPath::Keyframes {
+        keyframes: vec![
+            Keyframe {
+                time: Duration::ZERO,
+                position: (-10, 0),
+            },
+            Keyframe {
+                time: ms(1000),
+                position: (10, 20),
+            },
+            Keyframe {
+                time: ms(1500),
+                position: (10, 20),
+            },
+        ],
+        easing,
+    }
+}
+
+#[test]
+fn keyframes_with_easing() {
+    let linear = keyframes(Easing::Linear);
+    linear.validate().unwrap();
+    assert_eq!(linear.cycle_time(SIZE), ms(1500));
+    assert_eq!(linear.bounds(SIZE), ((-10, 0), (10, 20)));
+
+    assert_eq!(linear.position(ms(500), SIZE).frame, (0, 10));
+    assert_eq!(linear.position(ms(1200), SIZE).frame, (10, 20));
+    // starts over after the last keyframe
+    assert_eq!(linear.position(ms(1750), SIZE).frame, (-5, 5));
+
+    // positions are kept for one step, the step decides the position
+    let timing = linear.position(ms(505), SIZE);
+    assert_eq!(timing.frame, (0, 10));
+    assert_eq!(timing.frame_time, MOTION_STEP);
+    assert_eq!(timing.time_left, MOTION_STEP - ms(5));
+
+    let ease_in = keyframes(Easing::In);
+    assert_eq!(ease_in.position(ms(500), SIZE).frame, (-8, 3));
+    let ease_in_out = keyframes(Easing::InOut);
+    assert_eq!(ease_in_out.position(ms(500), SIZE).frame, (0, 10));
+    assert_eq!(ease_in_out.position(ms(200), SIZE).frame, (-9, 1));
+
+    let unordered = MotionPath::Keyframes {
+        keyframes: vec![Keyframe {
+            time: ms(10),
+            position: (0, 0),
+        }],
+        easing: Easing::Linear,
+    };
+    assert!(matches!(
+        unordered.validate(),
+        Err(MotionPathError::Keyframes)
+    ));
+}
+
+#[test]
+fn bounces_inside_the_area() {
+    let bounce = MotionPath::Bounce {
+        area: (0, 0, 10, 4),
+        speed: (80.0, -20.0),
+    };
+    // 8 pixels to the right and back at 80 px/s, 2 pixels up and back at 20 px/s
+    assert_eq!(bounce.cycle_time(SIZE), ms(200));
+    assert!(!bounce.endless(SIZE));
+    assert_eq!(bounce.bounds(SIZE), ((0, 0), (8, 2)));
+
+    assert_eq!(bounce.position(Duration::ZERO, SIZE).frame, (0, 2));
+    assert_eq!(bounce.position(ms(100), SIZE).frame, (8, 0));
+    assert_eq!(bounce.position(ms(140), SIZE).frame, (5, 1));
+    assert_eq!(bounce.position(ms(200), SIZE).frame, (0, 2));
+
+    // objects larger than the area stay in place
+    let still = MotionPath::Bounce {
+        area: (3, 4, 1, 1),
+        speed: (80.0, 20.0),
+    };
+    assert_eq!(still.position(ms(30), SIZE).frame, (3, 4));
+    assert!(still.position(ms(30), SIZE).frame_time > Duration::from_secs(3600));
+    assert!(!still.endless(SIZE));
+}
+
+#[test]
+fn bounces_without_a_common_cycle() {
+    // 1180px at 60 px/s take 39.33s back and forth, 620px at 40 px/s take 31s,
+    // the axes line up again only after far longer than a still image is shown
+    let bounce = MotionPath::Bounce {
+        area: (0, 0, 1280, 720),
+        speed: (60.0, 40.0),
+    };
+    let size = (100, 100);
+    assert!(bounce.moves(size));
+    assert!(bounce.endless(size));
+    assert!(bounce.cycle_time(size) > Duration::from_secs(3600 * 24));
+
+    let position = |s: u64| bounce.position(Duration::from_secs(s), size);
+    assert_eq!(position(0).frame, (0, 0));
+    assert_eq!(position(10).frame, (600, 400));
+    assert_eq!(position(31).frame, (500, 0));
+    assert_eq!(position(50).frame, (640, 480));
+    assert_eq!(position(0).frame_time, MOTION_STEP);
+    assert_eq!(position(3600).time_left, MOTION_STEP);
+
+    // wrapping around at the cycle of the image would make the object jump
+    let source = CompositeBufferSource {
+        source: TestImage::new(size, Format::Rgba),
+        processor: RayonProcessor::new(
+            size,
+            (1280, 720),
+            LEFT_TO_RIGHT,
+            AlphaPolicy::default(),
+            None,
+        ),
+        path: bounce,
+    };
+    assert!(source.cycle_time() > Duration::from_secs(3600 * 24));
+}
+
+#[test]
+fn circles_around_the_center() {
+    let circle = MotionPath::Lissajous {
+        center: (10.0, 10.0),
+        amplitude: (5.0, 5.0),
+        frequency: (1, 1),
+        period: ms(1000),
+    };
+    assert_eq!(circle.cycle_time(SIZE), ms(1000));
+    assert_eq!(circle.bounds(SIZE), ((4, 4), (14, 14)));
+
+    // the center of the object is on the circle
+    assert_eq!(circle.position(Duration::ZERO, SIZE).frame, (14, 9));
+    assert_eq!(circle.position(ms(250), SIZE).frame, (9, 14));
+    assert_eq!(circle.position(ms(500), SIZE).frame, (4, 9));
+}
+
+/// Commands of a 2x2 [TestImage] at `offset` on a 2x2 canvas
+fn commands(processor: &dyn FrameProcessor, offset: (i32, i32)) -> String {
+    let mut image = TestImage::new(SIZE, Format::Rgba);
+    let frame = image.frame(Duration::ZERO).frame;
+    String::from_utf8(processor.process(frame, offset).unwrap().into()).unwrap()
+}
+
+#[test]
+fn processors_clip_moving_objects() {
+    let rayon = RayonProcessor::new(SIZE, SIZE, LEFT_TO_RIGHT, AlphaPolicy::default(), None);
+    let at_origin = commands(&rayon, (0, 0));
+    assert_eq!(at_origin.lines().count(), 4);
+
+    // only the bottom right pixel is left on the canvas, at its top left corner
+    let last = at_origin
+        .lines()
+        .last()
+        .unwrap()
+        .replacen("PX 1 1", "PX 0 0", 1);
+    assert_eq!(commands(&rayon, (-1, -1)), format!("{last}\n"));
+    let first = at_origin
+        .lines()
+        .next()
+        .unwrap()
+        .replacen("PX 0 0", "PX 1 1", 1);
+    assert_eq!(commands(&rayon, (1, 1)), format!("{first}\n"));
+    assert_eq!(commands(&rayon, (2, 0)), "");
+    assert_eq!(commands(&rayon, (-5, 1)), "");
+
+    // skipped on machines without a GPU
+    if let Ok(gpu) = GpuProcessor::new(0, SIZE, SIZE, LEFT_TO_RIGHT, AlphaPolicy::default(), None) {
+        assert_eq!(commands(&gpu, (0, 0)).lines().count(), 4);
+        assert!(commands(&gpu, (-1, -1)).starts_with("PX 0 0 "));
+        assert_eq!(commands(&gpu, (-1, -1)).lines().count(), 1);
+        assert_eq!(commands(&gpu, (2, 2)), "");
+    }
+}
+
+#[test]
+fn moving_command_buffers() {
+    let mut source = CompositeBufferSource {
+        source: TestImage::new(SIZE, Format::Rgba),
+        processor: RayonProcessor::new(SIZE, SIZE, LEFT_TO_RIGHT, AlphaPolicy::default(), None),
+        path: MotionPath::Keyframes {
+            keyframes: vec![
+                Keyframe {
+                    time: Duration::ZERO,
+                    position: (0, 0),
+                },
+                Keyframe {
+                    time: MOTION_STEP * 2,
+                    position: (-2, 0),
+                },
+            ],
+            easing: Easing::Linear,
+        },
+    };
+    // the still image is shown for an hour, long enough for the object to move many times
+    assert_eq!(source.cycle_time(), Duration::from_secs(3600));
+
+    let first = source.command_buffer(ms(5)).unwrap();
+    assert_eq!(first.frame_time, MOTION_STEP);
+    assert_eq!(first.time_left, MOTION_STEP - ms(5));
+    assert_eq!(first.frame.split(|b| *b == b'\n').count() - 1, 4);
+
+    // one column left of the canvas
+    let second = source.command_buffer(MOTION_STEP).unwrap();
+    assert_eq!(second.frame.split(|b| *b == b'\n').count() - 1, 2);
+}
+
+#[test]
+fn off_canvas_objects_keep_connections() {
+    let keyframe = |ms: u64, x| Keyframe {
+        time: Duration::from_millis(ms),
+        position: (x, 0),
+    };
+    // off the canvas, then on it from 220ms on
+    let path = MotionPath::Keyframes {
+        keyframes: vec![
+            keyframe(0, -2),
+            keyframe(200, -2),
+            keyframe(220, 0),
+            keyframe(600, 0),
+        ],
+        easing: Easing::Linear,
+    };
+
+    for backend in Backend::ALL.into_iter().filter(Backend::available) {
+        let server = Server::bind("127.0.0.1:0", SIZE)
+            .and_then(Server::spawn)
+            .unwrap();
+        let path = path.clone();
+        // an empty write looks like a closed connection, without reconnects the flut exits
+        let flut = spawn_flut(backend, server.local_addr(), 2, None, Some(0), move || {
+            Box::new(CompositeBufferSource {
+                source: TestImage::new(SIZE, Format::Rgba),
+                processor: RayonProcessor::new(
+                    SIZE,
+                    SIZE,
+                    LEFT_TO_RIGHT,
+                    AlphaPolicy::default(),
+                    None,
+                ),
+                path,
+            })
+        });
+
+        let drawn = || mismatch(server.canvas(), SIZE, (0, 0)).is_none();
+        assert!(wait_until(Duration::from_secs(10), drawn), "{backend:?}");
+        assert!(!flut.is_finished(), "{backend:?}");
+        let stats = server.stats();
+        assert_eq!(stats.total_connections.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,144 @@
+//! Shows sources one after another with transitions
+
+use std::time::Duration;
+
+use epizentrum::frame_source::playlist_source::{
+    read_playlist, PlaylistEntry, PlaylistOptions, PlaylistSource, PlaylistSourceError, Transition,
+    STILL_DURATION,
+};
+use epizentrum::frame_source::FrameSource;
+
+use crate::common::{pattern, pixels, Format, Frames, TestImage};
+
+mod common;
+
+const SECOND: Duration = Duration::from_secs(1);
+
+/// A still image of one color
+fn entry(size: (u16, u16), value: u8, duration: Option<Duration>) -> PlaylistEntry {
+    PlaylistEntry {
+        source: Box::new(Frames::still(size, [value, value, value, 255])),
+        duration,
+    }
+}
+
+#[test]
+fn entries_follow_their_durations() {
+    let entries = vec![
+        entry((1, 1), 10, Some(SECOND)),
+        entry((1, 1), 20, Some(SECOND * 2)),
+        entry((1, 1), 30, None),
+    ];
+    let mut source = PlaylistSource::new(entries, PlaylistOptions::default()).unwrap();
+    assert_eq!(source.cycle_time(), SECOND * 3 + STILL_DURATION);
+
+    let value = |source: &mut PlaylistSource, delta| pixels(source, delta)[0][0];
+    assert_eq!(value(&mut source, Duration::ZERO), 10);
+    assert_eq!(value(&mut source, SECOND * 3 / 2), 20);
+    assert_eq!(value(&mut source, SECOND * 3), 30);
+    // loops around to the first entry
+    assert_eq!(value(&mut source, SECOND * 3 + STILL_DURATION), 10);
+
+    let timing = source.frame(SECOND * 2);
+    assert_eq!(timing.time_left, SECOND);
+    assert_eq!(timing.frame_time, SECOND * 2);
+}
+
+#[test]
+fn smaller_entries_are_centered() {
+    let entries = vec![
+        PlaylistEntry {
+            source: Box::new(TestImage::new((3, 3), Format::Bgra)),
+            duration: Some(SECOND),
+        },
+        entry((1, 1), 50, Some(SECOND)),
+    ];
+    let options = PlaylistOptions {
+        looping: false,
+        ..PlaylistOptions::default()
+    };
+    let mut source = PlaylistSource::new(entries, options).unwrap();
+    assert_eq!(source.size(), (3, 3));
+
+    let expected = (0..3)
+        .flat_map(|y| (0..3).map(move |x| pattern(x, y)))
+        .collect::<Vec<_>>();
+    assert_eq!(pixels(&mut source, Duration::ZERO), expected);
+
+    // the last entry stays once the playlist is over
+    let mut expected = vec![[0, 0, 0, 0]; 9];
+    expected[4] = [50, 50, 50, 255];
+    assert_eq!(pixels(&mut source, SECOND * 60), expected);
+}
+
+#[test]
+fn transitions_mix_neighboring_entries() {
+    let entries = || {
+        vec![
+            entry((4, 1), 0, Some(SECOND)),
+            entry((4, 1), 200, Some(SECOND)),
+        ]
+    };
+    let length = Duration::from_millis(160);
+
+    let options = PlaylistOptions {
+        transition: Transition::Crossfade(length),
+        ..PlaylistOptions::default()
+    };
+    let mut source = PlaylistSource::new(entries(), options).unwrap();
+    // halfway through the transition
+    assert_eq!(
+        pixels(&mut source, SECOND + length / 2),
+        [[100, 100, 100, 255]; 4]
+    );
+    let timing = source.frame(SECOND + length / 2 + Duration::from_millis(10));
+    assert_eq!(timing.frame_time, Duration::from_millis(40));
+    // looping fades from the last entry into the first one
+    assert_eq!(pixels(&mut source, length / 2), [[100, 100, 100, 255]; 4]);
+    assert_eq!(pixels(&mut source, length), [[0, 0, 0, 255]; 4]);
+
+    let options = PlaylistOptions {
+        transition: Transition::Wipe(length),
+        ..PlaylistOptions::default()
+    };
+    let mut source = PlaylistSource::new(entries(), options).unwrap();
+    assert_eq!(
+        pixels(&mut source, SECOND + length / 2),
+        [
+            [200, 200, 200, 255],
+            [200, 200, 200, 255],
+            [0, 0, 0, 255],
+            [0, 0, 0, 255]
+        ]
+    );
+}
+
+#[test]
+fn reads_m3u_playlists() {
+    let directory = std::env::temp_dir().join(format!("tsunami-playlist-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("show.m3u");
+    std::fs::write(
+        &path,
+        "#EXTM3U\n#EXTINF:2.5,First\nfirst.png\n\n# comment\n/absolute/second.gif\n#EXTINF:-1,Third\nthird.png\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        read_playlist(&path).unwrap(),
+        [
+            (
+                directory.join("first.png"),
+                Some(Duration::from_millis(2500))
+            ),
+            ("/absolute/second.gif".into(), None),
+            (directory.join("third.png"), None),
+        ]
+    );
+    std::fs::remove_dir_all(directory).unwrap();
+
+    assert!(matches!(
+        PlaylistSource::new(vec![], PlaylistOptions::default()),
+        Err(PlaylistSourceError::Empty)
+    ));
+}
@@ -0,0 +1,169 @@
+//! Compiles animations into a precompiled show and plays them back
+
+use std::fs::File;
+use std::num::NonZeroU64;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::{AssignmentPolicy, SourceWeights};
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::{Frame, FrameSource, Timing};
+use epizentrum::motion_path::MotionPath;
+use epizentrum::precompiled::{self, PrecompiledError, PrecompiledShow};
+use epizentrum::{CommandBuffer, CommandBufferSource, CompositeBufferSource};
+
+const CANVAS: (u16, u16) = (8, 6);
+const FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// Three solid frames of different colors
+#[derive(Debug)]
+struct Steps {
+    frames: [Frame; 3],
+}
+
+impl Steps {
+    fn new() -> Self {
+        Steps {
+            frames: [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+                .map(|color| Frame::Rgba(vec![color; 4].into_boxed_slice())),
+        }
+    }
+}
+
+impl FrameSource for Steps {
+    fn size(&self) -> (u16, u16) {
+        (2, 2)
+    }
+
+    fn cycle_time(&self) -> Duration {
+        FRAME_TIME * 3
+    }
+
+    fn frame(&mut self, delta: Duration) -> Timing<&Frame> {
+        let delta = Duration::from_nanos((delta.as_nanos() % self.cycle_time().as_nanos()) as u64);
+        let index = (delta.as_nanos() / FRAME_TIME.as_nanos()) as usize;
+        Timing {
+            frame: &self.frames[index],
+            frame_time: FRAME_TIME,
+            time_left: FRAME_TIME * (index as u32 + 1) - delta,
+        }
+    }
+}
+
+fn source(offset: (i32, i32)) -> Box<dyn CommandBufferSource> {
+    Box::new(CompositeBufferSource {
+        source: Steps::new(),
+        processor: RayonProcessor::new(
+            (2, 2),
+            CANVAS,
+            DrawStrategy::Rows { reversed: false },
+            AlphaPolicy::default(),
+            None,
+        ),
+        path: MotionPath::Fixed(offset.0, offset.1),
+    })
+}
+
+fn weights(weight: u64) -> SourceWeights {
+    SourceWeights {
+        pixels: NonZeroU64::new(4).unwrap(),
+        weight: NonZeroU64::new(weight).unwrap(),
+        policy: None,
+    }
+}
+
+fn path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tsunami-precompiled-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn plays_back_what_was_compiled() {
+    let path = path("roundtrip");
+    let dedicated = SourceWeights {
+        policy: Some(AssignmentPolicy::Dedicated),
+        ..weights(3)
+    };
+    let mut sources = vec![(source((0, 0)), weights(1)), (source((5, 3)), dedicated)];
+    precompiled::compile(&mut File::create(&path).unwrap(), CANVAS, &mut sources).unwrap();
+
+    let show = PrecompiledShow::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(show.canvas_size(), CANVAS);
+
+    let played = show.into_sources();
+    assert_eq!(played.len(), 2);
+    for ((mut played, played_weights), (original, original_weights)) in
+        played.into_iter().zip(&mut sources)
+    {
+        assert_eq!(played_weights.weight, original_weights.weight);
+        assert_eq!(played_weights.policy, original_weights.policy);
+        assert_eq!(played.cycle_time(), FRAME_TIME * 3);
+
+        // every frame, including a wrapped second cycle and frame boundaries
+        for ms in [0, 50, 99, 100, 250, 299, 300, 420, 1234] {
+            let delta = Duration::from_millis(ms);
+            let expected = original.command_buffer(delta).unwrap();
+            let actual = played.command_buffer(delta).unwrap();
+            assert_eq!(*actual.frame, *expected.frame, "at {ms}ms");
+            assert_eq!(actual.frame_time, expected.frame_time, "at {ms}ms");
+            assert_eq!(actual.time_left, expected.time_left, "at {ms}ms");
+            // borrowed from the mapping instead of copied
+            assert!(matches!(actual.frame, CommandBuffer::Mapped(..)));
+        }
+    }
+}
+
+#[test]
+fn rejects_other_files() {
+    let path = path("invalid");
+    std::fs::write(&path, b"PX 0 0 ff0000\n").unwrap();
+    assert!(matches!(
+        PrecompiledShow::open(&path),
+        Err(PrecompiledError::Magic)
+    ));
+
+    // shows from before source policies were stored
+    std::fs::write(&path, b"TSUNAMI\0\x01\0\0\0").unwrap();
+    assert!(matches!(
+        PrecompiledShow::open(&path),
+        Err(PrecompiledError::Version(1))
+    ));
+
+    // a truncated index
+    let mut sources = vec![(source((0, 0)), weights(1))];
+    precompiled::compile(&mut File::create(&path).unwrap(), CANVAS, &mut sources).unwrap();
+    let mut show = std::fs::read(&path).unwrap();
+    show.truncate(show.len() - 8);
+    std::fs::write(&path, show).unwrap();
+    assert!(matches!(
+        PrecompiledShow::open(&path),
+        Err(PrecompiledError::Corrupted)
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn rejects_paths_that_never_repeat() {
+    // the bouncing axes line up again only after years
+    let source = CompositeBufferSource {
+        source: Steps::new(),
+        processor: RayonProcessor::new(
+            (2, 2),
+            (1280, 720),
+            DrawStrategy::Rows { reversed: false },
+            AlphaPolicy::default(),
+            None,
+        ),
+        path: MotionPath::Bounce {
+            area: (0, 0, 1280, 720),
+            speed: (70.0, 40.0),
+        },
+    };
+    let mut sources: Vec<(Box<dyn CommandBufferSource>, _)> = vec![(Box::new(source), weights(1))];
+    assert!(matches!(
+        precompiled::compile(&mut std::io::Cursor::new(vec![]), CANVAS, &mut sources),
+        Err(PrecompiledError::Endless(0))
+    ));
+}
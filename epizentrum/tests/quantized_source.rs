@@ -0,0 +1,116 @@
+//! Maps every frame to a palette with and without dithering
+
+use std::time::Duration;
+
+use epizentrum::frame_source::generator_source::{GeneratorSource, Pattern};
+use epizentrum::frame_source::quantized_source::{
+    Dithering, Palette, QuantizedSource, QuantizedSourceError,
+};
+
+use crate::common::{pattern, pixels, Format, TestImage};
+
+mod common;
+
+const SIZE: (u16, u16) = (32, 16);
+const GRAY: [u8; 4] = [128, 128, 128, 255];
+
+/// Share of white pixels of a gray fill dithered to black and white
+fn white_share(dithering: Dithering) -> f32 {
+    let fill = GeneratorSource::new(SIZE, Pattern::Fill(GRAY), None).unwrap();
+    let palette = Palette::preset("bw").unwrap();
+    let pixels = pixels(
+        &mut QuantizedSource::new(fill, palette, dithering),
+        Duration::ZERO,
+    );
+
+    assert!(pixels
+        .iter()
+        .all(|p| *p == [0, 0, 0, 255] || *p == [255, 255, 255, 255]));
+    pixels.iter().filter(|p| p[0] == 255).count() as f32 / pixels.len() as f32
+}
+
+#[test]
+fn parse_palettes() {
+    let gpl =
+        "GIMP Palette\nName: Test\nColumns: 2\n# comment\n  0   0   0\tBlack\n255 128 1 Orange\n";
+    assert_eq!(
+        Palette::parse(gpl).unwrap().colors(),
+        [[0, 0, 0], [255, 128, 1]]
+    );
+
+    let hex = "; paint.net style comment\n#000000\n\nff8001\n";
+    assert_eq!(
+        Palette::parse(hex).unwrap().colors(),
+        [[0, 0, 0], [255, 128, 1]]
+    );
+
+    assert!(matches!(
+        Palette::parse("000000\nnope\n"),
+        Err(QuantizedSourceError::Line(2, line)) if line == "nope"
+    ));
+    assert!(matches!(
+        Palette::parse("GIMP Palette\n0 0 256\n"),
+        Err(QuantizedSourceError::Line(2, _))
+    ));
+    assert!(matches!(
+        Palette::parse("; nothing\n"),
+        Err(QuantizedSourceError::Empty)
+    ));
+
+    for preset in ["bw", "gray4", "cga", "pico8"] {
+        assert!(Palette::preset(preset).is_some(), "{preset}");
+    }
+    assert!(Palette::preset("nope").is_none());
+}
+
+#[test]
+fn nearest_color_keeps_alpha() {
+    let palette = Palette::new(vec![[0, 0, 0], [255, 0, 0], [0, 0, 255]]).unwrap();
+    for format in [Format::Rgba, Format::Bgra] {
+        let mut source = QuantizedSource::new(
+            TestImage::new(SIZE, format),
+            palette.clone(),
+            Dithering::None,
+        );
+        let original = (0..SIZE.1).flat_map(|y| (0..SIZE.0).map(move |x| pattern(x, y)));
+
+        for (original, pixel) in original.zip(pixels(&mut source, Duration::ZERO)) {
+            let nearest = palette
+                .colors()
+                .iter()
+                .min_by_key(|c| {
+                    (0..3)
+                        .map(|i| (c[i] as i32 - original[i] as i32).pow(2))
+                        .sum::<i32>()
+                })
+                .unwrap();
+            assert_eq!(pixel[..3], nearest[..], "{original:?}");
+            assert_eq!(pixel[3], original[3]);
+        }
+    }
+}
+
+#[test]
+fn dithering_mixes_colors() {
+    assert_eq!(white_share(Dithering::None), 1.0);
+
+    for dithering in [Dithering::FloydSteinberg, Dithering::Bayer] {
+        let share = white_share(dithering);
+        assert!((0.4..=0.6).contains(&share), "{dithering:?}: {share}");
+    }
+}
+
+#[test]
+fn bayer_is_a_stable_pattern() {
+    let fill = GeneratorSource::new(SIZE, Pattern::Fill(GRAY), None).unwrap();
+    let palette = Palette::preset("bw").unwrap();
+    let pixels = pixels(
+        &mut QuantizedSource::new(fill, palette, Dithering::Bayer),
+        Duration::ZERO,
+    );
+
+    // the 8x8 matrix repeats, neighbors differ on a flat 50% gray
+    let at = |x: usize, y: usize| pixels[y * SIZE.0 as usize + x];
+    assert_eq!(at(1, 2), at(9, 10));
+    assert_ne!(at(0, 0), at(1, 0));
+}
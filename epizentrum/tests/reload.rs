@@ -0,0 +1,175 @@
+//! Rebuilds command buffer sources when their files change
+
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::media_source::MediaSource;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::reload::{FileWatcher, Reload, ReloadingSource};
+use epizentrum::{CommandBuffer, CommandBufferSource, CompositeBufferSource};
+use image::{Rgba, RgbaImage};
+
+const SIZE: (u16, u16) = (2, 1);
+/// Longer than the check interval and the settle time of reloads
+const WAIT: Duration = Duration::from_millis(800);
+
+fn directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("tsunami-reload-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn still(path: &Path, color: [u8; 4]) {
+    RgbaImage::from_pixel(SIZE.0 as u32, SIZE.1 as u32, Rgba(color))
+        .save(path)
+        .unwrap();
+}
+
+/// Reloads `path`, taking at least `delay` for every reload
+fn reloading(path: &Path, delay: Duration) -> ReloadingSource {
+    let watcher = FileWatcher::new(path).unwrap();
+    let path = path.to_owned();
+    let mut reload: Reload = Box::new(move || {
+        thread::sleep(delay);
+        let processor = RayonProcessor::new(
+            SIZE,
+            SIZE,
+            DrawStrategy::Columns { reversed: false },
+            AlphaPolicy::default(),
+            None,
+        );
+        Ok(Box::new(CompositeBufferSource {
+            source: MediaSource::new(&path)?,
+            processor,
+            path: MotionPath::default(),
+        }))
+    });
+
+    let source = reload().unwrap();
+    ReloadingSource::new(source, reload, watcher).unwrap()
+}
+
+/// Command buffer after the source had time to notice, settle and reload changes
+fn settled(source: &mut ReloadingSource) -> CommandBuffer {
+    thread::sleep(WAIT);
+    source.command_buffer(Duration::ZERO).unwrap().frame
+}
+
+#[test]
+fn reloads_written_and_replaced_files() {
+    let directory = directory("changes");
+    let path = directory.join("still.png");
+    still(&path, [255, 0, 0, 255]);
+
+    let mut source = reloading(&path, Duration::ZERO);
+    assert_eq!(&*settled(&mut source), b"PX 0 0 ff0000\nPX 1 0 ff0000\n");
+
+    // written in place
+    still(&path, [0, 255, 0, 255]);
+    assert_eq!(&*settled(&mut source), b"PX 0 0 00ff00\nPX 1 0 00ff00\n");
+
+    // written elsewhere and renamed over the file, the way many editors save
+    let temporary = directory.join("still.tmp.png");
+    still(&temporary, [0, 0, 255, 255]);
+    std::fs::rename(&temporary, &path).unwrap();
+    assert_eq!(&*settled(&mut source), b"PX 0 0 0000ff\nPX 1 0 0000ff\n");
+
+    // other files in the directory are ignored
+    still(&directory.join("other.png"), [1, 1, 1, 255]);
+    assert_eq!(&*settled(&mut source), b"PX 0 0 0000ff\nPX 1 0 0000ff\n");
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn broken_files_keep_the_previous_version() {
+    let directory = directory("broken");
+    let path = directory.join("still.png");
+    still(&path, [255, 255, 255, 255]);
+
+    let mut source = reloading(&path, Duration::ZERO);
+    std::fs::write(&path, b"not a png").unwrap();
+    assert_eq!(&*settled(&mut source), b"PX 0 0 ff\nPX 1 0 ff\n");
+
+    // fixing the file picks it up again
+    still(&path, [0, 0, 0, 255]);
+    assert_eq!(&*settled(&mut source), b"PX 0 0 00\nPX 1 0 00\n");
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn slow_reloads_keep_the_previous_version_in_use() {
+    let directory = directory("slow");
+    let path = directory.join("still.png");
+    still(&path, [255, 255, 255, 255]);
+
+    let mut source = reloading(&path, Duration::from_secs(1));
+    still(&path, [0, 0, 0, 255]);
+
+    // command buffers of the previous version are ready while the reload takes its time
+    let start = Instant::now();
+    loop {
+        let requested = Instant::now();
+        let buffer = source.command_buffer(Duration::ZERO).unwrap().frame;
+        assert!(requested.elapsed() < Duration::from_millis(100));
+        if &*buffer == b"PX 0 0 00\nPX 1 0 00\n" {
+            break;
+        }
+        assert_eq!(&*buffer, b"PX 0 0 ff\nPX 1 0 ff\n");
+        assert!(start.elapsed() < Duration::from_secs(5), "never reloaded");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(start.elapsed() >= Duration::from_secs(1));
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn watches_several_files() {
+    let directory = directory("several");
+    let (first, second) = (directory.join("first.png"), directory.join("second.png"));
+    let mut watcher = FileWatcher::new(&first).unwrap();
+    watcher.add(&second).unwrap();
+    assert!(!watcher.changed().unwrap());
+
+    still(&second, [0, 0, 0, 255]);
+    assert!(watcher.changed().unwrap());
+    still(&directory.join("third.png"), [0, 0, 0, 255]);
+    assert!(!watcher.changed().unwrap());
+    still(&first, [0, 0, 0, 255]);
+    assert!(watcher.changed().unwrap());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn watches_directories() {
+    let directory = directory("playlist");
+    still(&directory.join("first.png"), [0, 0, 0, 255]);
+    let mut watcher = FileWatcher::new(&directory).unwrap();
+    assert!(!watcher.changed().unwrap());
+
+    still(&directory.join("second.png"), [0, 0, 0, 255]);
+    assert!(watcher.changed().unwrap());
+    still(&directory.join("first.png"), [255, 255, 255, 255]);
+    assert!(watcher.changed().unwrap());
+    std::fs::remove_file(directory.join("second.png")).unwrap();
+    assert!(watcher.changed().unwrap());
+
+    // editors keep their swap files next to the slides
+    std::fs::write(directory.join(".first.png.swp"), b"swap").unwrap();
+    assert!(!watcher.changed().unwrap());
+
+    // files watched in the same directory do not narrow the events
+    watcher.add(directory.join("first.png")).unwrap();
+    std::fs::remove_file(directory.join("first.png")).unwrap();
+    assert!(watcher.changed().unwrap());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
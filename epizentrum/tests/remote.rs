@@ -0,0 +1,271 @@
+//! Downloads media from a local HTTP server
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use epizentrum::frame_source::media_source::remote::{split_url, Download, DownloadError, Refresh};
+use epizentrum::frame_source::media_source::MediaSource;
+use epizentrum::frame_source::FrameSource;
+use epizentrum::reload::Trigger;
+use image::{ImageFormat, RgbaImage};
+
+use crate::common::{pattern, rgba};
+
+mod common;
+
+const SIZE: (u16, u16) = (6, 4);
+
+#[derive(Debug, Default)]
+struct Served {
+    body: Vec<u8>,
+    etag: Option<String>,
+    /// Send a Content-Length header, otherwise the body ends with the connection
+    length: bool,
+    /// Accept connections but never answer
+    silent: bool,
+    /// If-None-Match header of every request
+    requests: Vec<Option<String>>,
+}
+
+/// Serves one response per connection, returns the URL of the served file
+fn serve(served: Arc<Mutex<Served>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                return;
+            };
+
+            let mut if_none_match = None;
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("if-none-match") {
+                        if_none_match = Some(value.trim().to_string());
+                    }
+                }
+            }
+
+            let mut served = served.lock().unwrap();
+            served.requests.push(if_none_match.clone());
+            if served.silent {
+                // keep the connection open until the client gives up
+                drop(served);
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+
+            let mut response = match (&served.etag, &if_none_match) {
+                (Some(etag), Some(if_none_match)) if etag == if_none_match => {
+                    format!("HTTP/1.1 304 Not Modified\r\nETag: {etag}\r\n").into_bytes()
+                }
+                _ => {
+                    let mut response = String::from("HTTP/1.1 200 OK\r\n");
+                    if let Some(etag) = &served.etag {
+                        response += &format!("ETag: {etag}\r\n");
+                    }
+                    if served.length {
+                        response += &format!("Content-Length: {}\r\n", served.body.len());
+                    }
+                    response.into_bytes()
+                }
+            };
+            response.extend_from_slice(b"Connection: close\r\n\r\n");
+            if response.starts_with(b"HTTP/1.1 200") {
+                response.extend_from_slice(&served.body);
+            }
+            let _ = stream.write_all(&response);
+        }
+    });
+
+    url
+}
+
+/// PNG made of [pattern], shifted by `shift`
+fn png(shift: u16) -> Vec<u8> {
+    let image = RgbaImage::from_fn(SIZE.0 as u32, SIZE.1 as u32, |x, y| {
+        image::Rgba(pattern(x as u16 + shift, y as u16))
+    });
+    let mut png = std::io::Cursor::new(vec![]);
+    image.write_to(&mut png, ImageFormat::Png).unwrap();
+    png.into_inner()
+}
+
+fn cache(name: &str) -> PathBuf {
+    let cache = std::env::temp_dir().join(format!("tsunami-remote-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&cache);
+    cache
+}
+
+fn download(cache: Option<PathBuf>) -> Download {
+    Download {
+        max_size: 1 << 20,
+        timeout: Duration::from_secs(2),
+        cache,
+    }
+}
+
+#[test]
+fn downloads_and_decodes_from_memory() {
+    let served = Arc::new(Mutex::new(Served {
+        body: png(0),
+        length: true,
+        ..Served::default()
+    }));
+    let url = serve(served);
+
+    let data = download(None).fetch(&url).unwrap();
+    let mut source = MediaSource::from_memory(data).unwrap();
+    assert_eq!(source.size(), SIZE);
+
+    let expected = (0..SIZE.1)
+        .flat_map(|y| (0..SIZE.0).map(move |x| pattern(x, y)))
+        .collect::<Vec<_>>();
+    assert!(rgba(source.frame(Duration::ZERO).frame) == expected);
+}
+
+#[test]
+fn unchanged_downloads_come_from_the_cache() {
+    let cache = cache("etag");
+    let served = Arc::new(Mutex::new(Served {
+        body: png(0),
+        etag: Some("\"v1\"".to_string()),
+        length: true,
+        ..Served::default()
+    }));
+    let url = serve(served.clone());
+    let download = download(Some(cache.clone()));
+
+    let first = download.fetch(&url).unwrap();
+    let second = download.fetch(&url).unwrap();
+    assert_eq!(first, second);
+
+    // a new version replaces the cached one
+    {
+        let mut served = served.lock().unwrap();
+        served.body = png(1);
+        served.etag = Some("\"v2\"".to_string());
+    }
+    let third = download.fetch(&url).unwrap();
+    assert_eq!(&*third, png(1).as_slice());
+    assert_eq!(&*download.fetch(&url).unwrap(), png(1).as_slice());
+
+    assert_eq!(
+        served.lock().unwrap().requests,
+        [
+            None,
+            Some("\"v1\"".to_string()),
+            Some("\"v1\"".to_string()),
+            Some("\"v2\"".to_string())
+        ]
+    );
+    std::fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn large_downloads_are_refused() {
+    for length in [true, false] {
+        let served = Arc::new(Mutex::new(Served {
+            body: vec![0; 4096],
+            length,
+            ..Served::default()
+        }));
+        let url = serve(served);
+
+        let download = Download {
+            max_size: 1024,
+            ..download(None)
+        };
+        assert!(
+            matches!(download.fetch(&url), Err(DownloadError::TooLarge(1024))),
+            "with content length: {length}"
+        );
+    }
+}
+
+#[test]
+fn silent_servers_time_out() {
+    let served = Arc::new(Mutex::new(Served {
+        silent: true,
+        ..Served::default()
+    }));
+    let url = serve(served);
+
+    let download = Download {
+        timeout: Duration::from_millis(200),
+        ..download(None)
+    };
+    let start = Instant::now();
+    assert!(download.fetch(&url).is_err());
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[test]
+fn refresh_fires_on_changes() {
+    let cache = cache("refresh");
+    let served = Arc::new(Mutex::new(Served {
+        body: png(0),
+        etag: Some("\"v1\"".to_string()),
+        length: true,
+        ..Served::default()
+    }));
+    let url = serve(served.clone());
+    let interval = Duration::from_millis(100);
+    let mut refresh = Refresh::new(download(Some(cache.clone())), &url, interval);
+
+    // the first download only remembers the content
+    assert!(!refresh.fired().unwrap());
+    // nothing is downloaded before the interval passed
+    assert!(!refresh.fired().unwrap());
+    assert_eq!(served.lock().unwrap().requests.len(), 1);
+
+    thread::sleep(interval);
+    assert!(!refresh.fired().unwrap());
+
+    {
+        let mut served = served.lock().unwrap();
+        served.body = png(1);
+        served.etag = Some("\"v2\"".to_string());
+    }
+    thread::sleep(interval);
+    assert!(refresh.fired().unwrap());
+    thread::sleep(interval);
+    assert!(!refresh.fired().unwrap());
+
+    std::fs::remove_dir_all(cache).unwrap();
+}
+
+#[test]
+fn urls_end_before_offset_and_options() {
+    for (media, url, rest) in [
+        ("http://host/a.png", "http://host/a.png", ""),
+        ("http://host:8080", "http://host:8080", ""),
+        ("http://host:8080:10:20", "http://host:8080", ":10:20"),
+        ("http://host:10:20:up", "http://host", ":10:20:up"),
+        (
+            "https://host:8080/a.png?v=2:1:2:left:scale=2x:z=1",
+            "https://host:8080/a.png?v=2",
+            ":1:2:left:scale=2x:z=1",
+        ),
+        (
+            "http://host/a:b.png:scale=50%",
+            "http://host/a:b.png",
+            ":scale=50%",
+        ),
+    ] {
+        assert_eq!(split_url(media), Some((url, rest)), "{media}");
+    }
+
+    assert_eq!(split_url("logo.png:10:20"), None);
+    assert_eq!(split_url("ftp://host/a.png"), None);
+}
@@ -0,0 +1,134 @@
+//! Scales every frame of a source to a size, a percentage or to fit an area
+
+use std::time::Duration;
+
+use epizentrum::frame_source::generator_source::{Animation, GeneratorSource, Pattern};
+use epizentrum::frame_source::scaled_source::{FilterType, Scale, ScaledSource, ScaledSourceError};
+use epizentrum::frame_source::FrameSource;
+
+use crate::common::{pixels, BLACK, WHITE};
+
+mod common;
+
+fn checkerboard(size: (u16, u16), cell: u16) -> GeneratorSource {
+    let pattern = Pattern::Checkerboard {
+        cell,
+        colors: [WHITE, BLACK],
+    };
+    GeneratorSource::new(size, pattern, None).unwrap()
+}
+
+fn scaled_size(source: (u16, u16), scale: Scale) -> (u16, u16) {
+    ScaledSource::new(
+        checkerboard(source, 1),
+        scale,
+        (100, 100),
+        FilterType::Nearest,
+    )
+    .unwrap()
+    .size()
+}
+
+#[test]
+fn sizes() {
+    assert_eq!(scaled_size((40, 20), Scale::Size(7, 9)), (7, 9));
+    assert_eq!(scaled_size((40, 20), Scale::Percent(50.0)), (20, 10));
+    assert_eq!(scaled_size((40, 20), Scale::Contain), (100, 50));
+    assert_eq!(scaled_size((20, 40), Scale::Contain), (50, 100));
+    assert_eq!(scaled_size((40, 20), Scale::Cover), (100, 100));
+    assert_eq!(scaled_size((40, 20), Scale::Stretch), (100, 100));
+}
+
+#[test]
+fn nearest_keeps_pixels_sharp() {
+    let mut source = ScaledSource::new(
+        checkerboard((4, 4), 2),
+        Scale::Percent(200.0),
+        (100, 100),
+        FilterType::Nearest,
+    )
+    .unwrap();
+
+    let expected = pixels(&mut checkerboard((8, 8), 4), Duration::ZERO);
+    assert_eq!(pixels(&mut source, Duration::ZERO), expected);
+}
+
+#[test]
+fn cover_crops_centered() {
+    // 4x2 cells of 10 pixels covering 20x20 are scaled to 40x20, the middle 20 columns remain
+    let mut source = ScaledSource::new(
+        checkerboard((40, 20), 10),
+        Scale::Cover,
+        (20, 20),
+        FilterType::Nearest,
+    )
+    .unwrap();
+    let pixels = pixels(&mut source, Duration::ZERO);
+
+    assert_eq!(pixels[0], BLACK);
+    assert_eq!(pixels[9], BLACK);
+    assert_eq!(pixels[10], WHITE);
+    assert_eq!(pixels[10 * 20], WHITE);
+}
+
+#[test]
+fn smooth_filter_blends() {
+    let mut source = ScaledSource::new(
+        checkerboard((8, 8), 1),
+        Scale::Size(3, 3),
+        (100, 100),
+        FilterType::Lanczos3,
+    )
+    .unwrap();
+
+    let pixels = pixels(&mut source, Duration::ZERO);
+    assert!(pixels.iter().all(|p| *p != WHITE && *p != BLACK));
+}
+
+#[test]
+fn scales_every_frame() {
+    let animation = Some(Animation {
+        speed: 1.0,
+        frame_rate: 4.0,
+    });
+    let noise = || GeneratorSource::new((3, 2), Pattern::Noise { seed: 1 }, animation).unwrap();
+    let mut source = ScaledSource::new(
+        noise(),
+        Scale::Percent(200.0),
+        (100, 100),
+        FilterType::Nearest,
+    )
+    .unwrap();
+    let mut reference = noise();
+    assert_eq!(source.cycle_time(), reference.cycle_time());
+
+    for delta in [0, 100, 300, 600, 1100].map(Duration::from_millis) {
+        let original = pixels(&mut reference, delta);
+        let scaled = pixels(&mut source, delta);
+        for (i, pixel) in scaled.iter().enumerate() {
+            let (x, y) = (i % 6, i / 6);
+            assert_eq!(*pixel, original[y / 2 * 3 + x / 2], "{delta:?} at {x},{y}");
+        }
+
+        let timing = source.frame(delta);
+        assert_eq!(timing.time_left, reference.frame(delta).time_left);
+    }
+}
+
+#[test]
+fn invalid_scale() {
+    let scale =
+        |scale| ScaledSource::new(checkerboard((4, 4), 1), scale, (0, 10), FilterType::Nearest);
+    assert!(matches!(
+        scale(Scale::Percent(-5.0)),
+        Err(ScaledSourceError::Percent(_))
+    ));
+    assert!(matches!(
+        scale(Scale::Size(0, 4)),
+        Err(ScaledSourceError::Size(0, 4))
+    ));
+    assert!(matches!(
+        scale(Scale::Stretch),
+        Err(ScaledSourceError::Size(0, 10))
+    ));
+}
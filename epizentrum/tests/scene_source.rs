@@ -0,0 +1,171 @@
+//! Alpha-blends several sources into one scene
+
+use std::time::Duration;
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::frame_source::scene_source::{Layer, SceneSource, SceneSourceError};
+use epizentrum::frame_source::FrameSource;
+use epizentrum::motion_path::{Easing, Keyframe, MotionPath, MOTION_STEP};
+
+use crate::common::{pattern, pixels, Format, Frames, TestImage, RED};
+
+mod common;
+
+/// Blinks between two colors, every color is shown for `frame_time`
+fn blink(
+    size: (u16, u16),
+    colors: [[u8; 4]; 2],
+    frame_time: Duration,
+    (x, y): (i32, i32),
+) -> Layer {
+    Layer {
+        source: Box::new(Frames::solid(size, &colors, frame_time)),
+        path: MotionPath::Fixed(x, y),
+        alpha: AlphaPolicy::default(),
+    }
+}
+
+const HALF_BLUE: [u8; 4] = [0, 0, 255, 128];
+const SECOND: Duration = Duration::from_secs(1);
+
+#[test]
+fn later_layers_are_blended_on_top() {
+    let layers = vec![
+        blink((2, 1), [RED; 2], SECOND, (3, 2)),
+        blink((2, 2), [HALF_BLUE; 2], SECOND, (4, 2)),
+    ];
+    let mut source = SceneSource::new(layers, (10, 10)).unwrap();
+    assert_eq!(source.offset(), (3, 2));
+    assert_eq!(source.size(), (3, 2));
+
+    assert_eq!(
+        pixels(&mut source, Duration::ZERO),
+        [
+            RED,
+            [127, 0, 128, 255],
+            HALF_BLUE,
+            [0, 0, 0, 0],
+            HALF_BLUE,
+            HALF_BLUE
+        ]
+    );
+}
+
+#[test]
+fn alpha_policies_decide_coverage() {
+    let scene = |alpha| {
+        let mut top = blink((1, 1), [HALF_BLUE; 2], SECOND, (0, 0));
+        top.alpha = alpha;
+        let layers = vec![
+            Layer {
+                source: Box::new(TestImage::new((2, 1), Format::Bgra)),
+                path: MotionPath::default(),
+                alpha: AlphaPolicy::default(),
+            },
+            top,
+        ];
+        SceneSource::new(layers, (2, 1)).unwrap()
+    };
+
+    // half transparent pixels are below the threshold and leave the image visible
+    let mut source = scene(AlphaPolicy::Skip { threshold: 200 });
+    assert_eq!(
+        pixels(&mut source, Duration::ZERO),
+        [pattern(0, 0), pattern(1, 0)]
+    );
+
+    let mut source = scene(AlphaPolicy::Opaque);
+    assert_eq!(
+        pixels(&mut source, Duration::ZERO),
+        [[0, 0, 255, 255], pattern(1, 0)]
+    );
+}
+
+#[test]
+fn animations_share_one_cycle() {
+    let layers = vec![
+        blink((1, 1), [RED, [0, 0, 0, 0]], SECOND * 3 / 2, (0, 0)),
+        blink((1, 1), [[0, 0, 0, 0], HALF_BLUE], SECOND, (0, 0)),
+    ];
+    let mut source = SceneSource::new(layers, (1, 1)).unwrap();
+    // both layers start over together after 6 seconds
+    assert_eq!(source.cycle_time(), SECOND * 6);
+
+    assert_eq!(pixels(&mut source, Duration::ZERO), [RED]);
+    assert_eq!(pixels(&mut source, SECOND * 5 / 4), [[127, 0, 128, 255]]);
+    let timing = source.frame(SECOND * 5 / 4);
+    // the top layer changed after one second, the bottom one changes after one and a half
+    assert_eq!(timing.time_left, SECOND / 4);
+    assert_eq!(timing.frame_time, SECOND / 2);
+    assert_eq!(pixels(&mut source, SECOND * 7 / 4), [HALF_BLUE]);
+}
+
+#[test]
+fn layers_are_clipped_to_the_canvas() {
+    let layers = vec![
+        blink((4, 4), [RED; 2], SECOND, (2, 2)),
+        blink((1, 1), [HALF_BLUE; 2], SECOND, (9, 0)),
+    ];
+    let mut source = SceneSource::new(layers, (4, 3)).unwrap();
+    assert_eq!(source.offset(), (2, 2));
+    assert_eq!(source.size(), (2, 1));
+    assert_eq!(pixels(&mut source, Duration::ZERO), [RED; 2]);
+
+    let layers = vec![blink((1, 1), [RED; 2], SECOND, (4, 0))];
+    assert!(matches!(
+        SceneSource::new(layers, (4, 3)),
+        Err(SceneSourceError::Invisible(4, 3))
+    ));
+}
+
+#[test]
+fn moving_layers_are_cut_at_the_edges() {
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    let mut sprite = blink((2, 1), [BLUE; 2], SECOND, (0, 0));
+    sprite.path = MotionPath::Keyframes {
+        keyframes: vec![
+            Keyframe {
+                time: Duration::ZERO,
+                position: (-1, 0),
+            },
+            Keyframe {
+                time: MOTION_STEP * 3,
+                position: (2, 0),
+            },
+        ],
+        easing: Easing::Linear,
+    };
+    let layers = vec![blink((3, 1), [RED; 2], SECOND, (0, 0)), sprite];
+    let mut source = SceneSource::new(layers, (3, 1)).unwrap();
+    assert_eq!(source.offset(), (0, 0));
+    assert_eq!(source.size(), (3, 1));
+
+    assert_eq!(pixels(&mut source, Duration::ZERO), [BLUE, RED, RED]);
+    let timing = source.frame(Duration::ZERO);
+    assert_eq!(timing.frame_time, MOTION_STEP);
+    assert_eq!(pixels(&mut source, MOTION_STEP), [BLUE, BLUE, RED]);
+    assert_eq!(pixels(&mut source, MOTION_STEP * 2), [RED, BLUE, BLUE]);
+}
+
+#[test]
+fn bouncing_layers_without_a_common_cycle_never_wrap() {
+    let mut sprite = blink((100, 100), [HALF_BLUE; 2], SECOND, (0, 0));
+    sprite.path = MotionPath::Bounce {
+        area: (0, 0, 1280, 720),
+        speed: (60.0, 40.0),
+    };
+    // an animation that repeats every 2s does not make the scene repeat with it
+    let layers = vec![blink((1, 1), [RED, HALF_BLUE], SECOND, (0, 0)), sprite];
+    let source = SceneSource::new(layers, (1280, 720)).unwrap();
+    assert!(source.cycle_time() > Duration::from_secs(3600 * 24));
+
+    // with a common cycle the scene repeats with the bounce and the animation
+    let mut sprite = blink((2, 2), [HALF_BLUE; 2], SECOND, (0, 0));
+    sprite.path = MotionPath::Bounce {
+        area: (0, 0, 4, 2),
+        speed: (100.0, 0.0),
+    };
+    let layers = vec![blink((1, 1), [RED, HALF_BLUE], SECOND, (0, 0)), sprite];
+    let source = SceneSource::new(layers, (4, 2)).unwrap();
+    assert_eq!(source.cycle_time(), SECOND * 2);
+}
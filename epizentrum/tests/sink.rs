@@ -0,0 +1,201 @@
+//! Writes command buffers to an output instead of network targets
+
+use std::error::Error;
+use std::io::{ErrorKind, Write};
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::time::{Duration, Instant};
+
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::{Assignment, AssignmentPolicy};
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::flut_op::sink::SinkLoop;
+use epizentrum::flut_op::FlutOp;
+use epizentrum::frame_processing::rayon_processor::RayonProcessor;
+use epizentrum::frame_source::Timing;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::{CommandBuffer, CommandBufferSource, CompositeBufferSource};
+
+use crate::common::Gradient;
+
+mod common;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Always the same commands
+#[derive(Debug)]
+struct Fixed(&'static str);
+
+impl CommandBufferSource for Fixed {
+    fn command_buffer(
+        &mut self,
+        _delta: Duration,
+    ) -> Result<Timing<CommandBuffer>, Box<dyn Error + Send + Sync>> {
+        Ok(Timing {
+            frame: self.0.as_bytes().into(),
+            frame_time: self.cycle_time(),
+            time_left: self.cycle_time(),
+        })
+    }
+
+    fn cycle_time(&self) -> Duration {
+        Duration::from_secs(3600)
+    }
+}
+
+const SIZE: (u16, u16) = (32, 24);
+
+/// Accepts `capacity` bytes, then reports a closed pipe
+struct Closing {
+    capacity: usize,
+    written: Vec<u8>,
+}
+
+impl Write for Closing {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.capacity - self.written.len());
+        if n == 0 {
+            return Err(ErrorKind::BrokenPipe.into());
+        }
+        self.written.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn sink<W: Write>(
+    sources: Vec<Box<dyn CommandBufferSource>>,
+    assignment: Assignment,
+    output: W,
+    cycles: Option<usize>,
+    duration: Option<Duration>,
+) -> SinkLoop<W> {
+    let flut_op = FlutOp::new(
+        &[],
+        None,
+        sources.into_boxed_slice(),
+        None,
+        None,
+        None,
+        vec![],
+        Instant::now(),
+        assignment,
+    );
+    SinkLoop::new(
+        flut_op,
+        output,
+        cycles.and_then(NonZeroUsize::new),
+        duration,
+    )
+}
+
+const RED: &str = "PX 0 0 ff0000\n";
+const GREEN: &str = "PX 1 0 00ff00\n";
+
+fn fixed() -> Vec<Box<dyn CommandBufferSource>> {
+    vec![Box::new(Fixed(RED)), Box::new(Fixed(GREEN))]
+}
+
+#[test]
+fn writes_cycles_in_scheduling_order() {
+    let mut output = vec![];
+    sink(
+        fixed(),
+        Assignment::round_robin(2),
+        &mut output,
+        Some(2),
+        None,
+    )
+    .run()
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        [RED, GREEN].repeat(2).concat()
+    );
+
+    // a cycle is one write per source, the weighted order decides which
+    let assignment = Assignment::new(
+        AssignmentPolicy::Weighted,
+        [NonZeroU64::new(3).unwrap(), NonZeroU64::MIN].into(),
+    );
+    let mut output = vec![];
+    sink(fixed(), assignment, &mut output, Some(2), None)
+        .run()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        [RED, RED, GREEN, RED].concat()
+    );
+}
+
+#[test]
+fn output_draws_the_canvas() {
+    let source = CompositeBufferSource {
+        source: Gradient::new(SIZE),
+        processor: RayonProcessor::new(
+            SIZE,
+            SIZE,
+            DrawStrategy::Random,
+            AlphaPolicy::default(),
+            None,
+        ),
+        path: MotionPath::default(),
+    };
+    let mut output = vec![];
+    sink(
+        vec![Box::new(source)],
+        Assignment::round_robin(1),
+        &mut output,
+        Some(1),
+        None,
+    )
+    .run()
+    .unwrap();
+
+    // every pixel exactly once, as a server would parse it
+    let mut canvas = vec![None; SIZE.0 as usize * SIZE.1 as usize];
+    for line in String::from_utf8(output).unwrap().lines() {
+        let [px, x, y, color] = line.split(' ').collect::<Vec<_>>()[..] else {
+            panic!("invalid command: {line:?}");
+        };
+        assert_eq!(px, "PX");
+        let (x, y) = (x.parse::<u16>().unwrap(), y.parse::<u16>().unwrap());
+        let pixel = &mut canvas[y as usize * SIZE.0 as usize + x as usize];
+        assert!(pixel.is_none(), "{x} {y} drawn twice");
+        *pixel = Some(color.to_string());
+    }
+    for (i, pixel) in canvas.into_iter().enumerate() {
+        let (x, y) = (i as u16 % SIZE.0, i as u16 / SIZE.0);
+        let [r, g, b, _] = Gradient::pixel(x, y);
+        assert_eq!(pixel, Some(format!("{r:02x}{g:02x}{b:02x}")), "{x} {y}");
+    }
+}
+
+#[test]
+fn stops_after_a_duration_or_a_closed_output() {
+    let mut output = vec![];
+    let started = Instant::now();
+    sink(
+        fixed(),
+        Assignment::round_robin(2),
+        &mut output,
+        None,
+        Some(Duration::from_millis(50)),
+    )
+    .run()
+    .unwrap();
+    assert!(started.elapsed() < TIMEOUT);
+    assert!(output.starts_with([RED, GREEN].concat().as_bytes()));
+
+    // closing the reading end of a pipe is no error
+    let mut output = Closing {
+        capacity: RED.len() * 3,
+        written: vec![],
+    };
+    sink(fixed(), Assignment::round_robin(2), &mut output, None, None)
+        .run()
+        .unwrap();
+    assert_eq!(output.written, [RED, GREEN, RED].concat().as_bytes());
+}
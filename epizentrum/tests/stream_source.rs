@@ -0,0 +1,128 @@
+//! Reads raw and YUV4MPEG2 frames pushed through files and Unix sockets
+
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use epizentrum::frame_source::stream_source::{
+    StreamFormat, StreamInput, StreamSource, StreamSourceError,
+};
+use epizentrum::frame_source::{Frame, FrameSource};
+
+use crate::common::wait_until;
+
+mod common;
+
+const SIZE: (u16, u16) = (4, 2);
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tsunami-{name}-{}", std::process::id()))
+}
+
+fn first_pixel(source: &mut StreamSource) -> [u8; 4] {
+    match source.frame(Duration::ZERO).frame {
+        Frame::Rgba(pixels) | Frame::Bgra(pixels) => pixels[0],
+    }
+}
+
+/// One 4:4:4 frame filled with a single YUV sample
+fn y4m_frame(yuv: [u8; 3]) -> Vec<u8> {
+    let pixels = SIZE.0 as usize * SIZE.1 as usize;
+    let mut frame = b"FRAME\n".to_vec();
+    for sample in yuv {
+        frame.extend(std::iter::repeat_n(sample, pixels));
+    }
+    frame
+}
+
+#[test]
+fn raw_file_shows_latest_frame() {
+    let path = temp_path("raw");
+    let pixels = SIZE.0 as usize * SIZE.1 as usize;
+    let mut data = vec![];
+    for color in [[1, 2, 3, 255], [4, 5, 6, 255], [7, 8, 9, 255]] {
+        data.extend(color.iter().cycle().take(pixels * 4));
+    }
+    std::fs::write(&path, data).unwrap();
+
+    let format = StreamFormat::Rgba {
+        size: SIZE,
+        frame_time: Duration::from_millis(40),
+    };
+    let mut source =
+        StreamSource::new(StreamInput::Path(path.clone()), format).expect("unable to open stream");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(source.size(), SIZE);
+    assert!(wait_until(TIMEOUT, || first_pixel(&mut source) == [7, 8, 9, 255]));
+
+    let timing = source.frame(Duration::from_millis(50));
+    assert_eq!(timing.frame_time, Duration::from_millis(40));
+    assert_eq!(timing.time_left, Duration::from_millis(30));
+}
+
+#[test]
+fn y4m_unix_socket_producers() {
+    let path = temp_path("y4m.sock");
+    let header = format!("YUV4MPEG2 W{} H{} F25:1 Ip A1:1 C444\n", SIZE.0, SIZE.1);
+
+    let producer = {
+        let (path, header) = (path.clone(), header.clone());
+        std::thread::spawn(move || {
+            let mut stream = loop {
+                match UnixStream::connect(&path) {
+                    Ok(stream) => break stream,
+                    Err(_) => std::thread::sleep(Duration::from_millis(5)),
+                }
+            };
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(&y4m_frame([235, 128, 128])).unwrap();
+        })
+    };
+
+    let mut source =
+        StreamSource::new(StreamInput::Unix(path.clone()), StreamFormat::Y4m).expect("no stream");
+    producer.join().unwrap();
+
+    assert_eq!(source.size(), SIZE);
+    assert_eq!(
+        source.frame(Duration::ZERO).frame_time,
+        Duration::from_millis(40)
+    );
+    assert_eq!(first_pixel(&mut source), [255, 255, 255, 255]);
+
+    // the next producer takes over, frames of another size are dropped
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream
+        .write_all(format!("YUV4MPEG2 W{} H{} C444\n", SIZE.0 + 1, SIZE.1).as_bytes())
+        .unwrap();
+    drop(stream);
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream.write_all(header.as_bytes()).unwrap();
+    stream.write_all(&y4m_frame([16, 128, 128])).unwrap();
+    stream.write_all(&y4m_frame([81, 90, 240])).unwrap();
+
+    assert!(wait_until(TIMEOUT, || first_pixel(&mut source) == [255, 0, 0, 255]));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn y4m_invalid_header() {
+    let path = temp_path("invalid.y4m");
+    for (header, colorspace) in [
+        ("YUV4MPEG2 W4 H2 C420p10\n", true),
+        ("YUV4MPEG2 W4\n", false),
+        ("RIFF W4 H2\n", false),
+    ] {
+        std::fs::write(&path, header).unwrap();
+        match StreamSource::new(StreamInput::Path(path.clone()), StreamFormat::Y4m) {
+            Err(StreamSourceError::Colorspace(_)) if colorspace => {}
+            Err(StreamSourceError::Header(_)) if !colorspace => {}
+            result => panic!("{header:?} was accepted: {result:?}"),
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,189 @@
+//! Renders vector graphics at their own and at scaled sizes
+
+use std::io::Write;
+use std::time::Duration;
+
+use epizentrum::frame_source::scaled_source::Scale;
+use epizentrum::frame_source::svg_source::{is_svg, SvgSource, SvgSourceError};
+use epizentrum::frame_source::transformed_source::{Transform, TransformedSource};
+use epizentrum::frame_source::FrameSource;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::common::pixels;
+
+mod common;
+
+/// Opaque red on the left half, half transparent blue on the right quarter, nothing in between
+const SVG: &str = r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="8" height="4" viewBox="0 0 8 4">
+  <rect x="0" y="0" width="4" height="4" fill="#ff0000"/>
+  <rect x="6" y="0" width="2" height="4" fill="#0000ff" fill-opacity="0.5"/>
+</svg>"##;
+
+/// Alpha and color of translucent pixels, colors of transparent ones do not matter
+fn assert_pixel(actual: [u8; 4], expected: [u8; 4], at: (u16, u16)) {
+    assert!(
+        actual[3].abs_diff(expected[3]) <= 1,
+        "alpha {actual:?} at {at:?}"
+    );
+    if expected[3] > 0 {
+        for channel in 0..3 {
+            assert!(
+                actual[channel].abs_diff(expected[channel]) <= 2,
+                "color {actual:?} at {at:?}"
+            );
+        }
+    }
+}
+
+/// Expected color of [SVG] at `x` when it is `width` wide
+fn expected(x: u16, width: u16) -> [u8; 4] {
+    match x * 8 / width {
+        0..=3 => [255, 0, 0, 255],
+        4 | 5 => [0, 0, 0, 0],
+        _ => [0, 0, 255, 128],
+    }
+}
+
+fn assert_rendered(source: &mut SvgSource, size: (u16, u16)) {
+    assert_eq!(source.size(), size);
+    let pixels = pixels(source, Duration::ZERO);
+    assert_eq!(pixels.len(), size.0 as usize * size.1 as usize);
+    for (i, pixel) in pixels.into_iter().enumerate() {
+        let (x, y) = ((i % size.0 as usize) as u16, (i / size.0 as usize) as u16);
+        assert_pixel(pixel, expected(x, size.0), (x, y));
+    }
+}
+
+#[test]
+fn renders_at_its_own_size_with_straight_alpha() {
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+    assert_eq!(source.own_size(), (8, 4));
+    assert_rendered(&mut source, (8, 4));
+}
+
+#[test]
+fn renders_sharp_at_scaled_sizes() {
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+
+    // edges stay on pixel boundaries instead of being blurred by a filter
+    source.rasterize(Scale::Percent(1000.0), (0, 0)).unwrap();
+    assert_rendered(&mut source, (80, 40));
+
+    source.rasterize(Scale::Contain, (400, 400)).unwrap();
+    assert_rendered(&mut source, (400, 200));
+
+    // rendering again from the document, not from the previous pixels
+    source.rasterize(Scale::Size(16, 2), (0, 0)).unwrap();
+    assert_rendered(&mut source, (16, 2));
+}
+
+#[test]
+fn covered_areas_are_cropped_centered() {
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+    source.rasterize(Scale::Cover, (8, 8)).unwrap();
+    assert_eq!(source.size(), (8, 8));
+
+    // 16x8 cropped to the middle 8 columns: red, transparent, blue
+    let pixels = pixels(&mut source, Duration::ZERO);
+    for x in 0..8u16 {
+        assert_pixel(pixels[x as usize], expected(x + 4, 16), (x, 0));
+    }
+}
+
+#[test]
+fn renders_sharp_before_transforms() {
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+
+    // the right half is scaled to 40x40, so it is rendered 10 times as large and cropped along
+    let transforms = [Transform::Crop {
+        x: 4,
+        y: 0,
+        w: 4,
+        h: 4,
+    }];
+    let transforms = source
+        .rasterize_transformed(Scale::Size(40, 40), (0, 0), &transforms)
+        .unwrap();
+    assert_eq!(source.size(), (80, 40));
+    assert_eq!(
+        transforms,
+        [Transform::Crop {
+            x: 40,
+            y: 0,
+            w: 40,
+            h: 40,
+        }]
+    );
+
+    let mut cropped = TransformedSource::new(source, &transforms).unwrap();
+    assert_eq!(cropped.size(), (40, 40));
+    for (x, pixel) in pixels(&mut cropped, Duration::ZERO)[..40]
+        .iter()
+        .enumerate()
+    {
+        assert_pixel(*pixel, expected(x as u16 + 40, 80), (x as u16, 0));
+    }
+
+    // shrinking renders at the own size
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+    let transforms = [Transform::Rotate(1)];
+    let rotated = source
+        .rasterize_transformed(Scale::Size(2, 4), (0, 0), &transforms)
+        .unwrap();
+    assert_eq!(source.size(), (8, 4));
+    assert_eq!(rotated, transforms);
+}
+
+#[test]
+fn invalid_documents_and_scales_are_refused() {
+    assert!(matches!(
+        SvgSource::new(b"<svg"),
+        Err(SvgSourceError::Svg(_))
+    ));
+
+    let mut source = SvgSource::new(SVG.as_bytes()).unwrap();
+    assert!(matches!(
+        source.rasterize(Scale::Percent(0.0), (0, 0)),
+        Err(SvgSourceError::Percent(_))
+    ));
+    assert!(matches!(
+        source.rasterize(Scale::Contain, (0, 10)),
+        Err(SvgSourceError::Size(..))
+    ));
+    // a failed rasterization keeps the previous size
+    assert_rendered(&mut source, (8, 4));
+}
+
+#[test]
+fn recognizes_svg_documents() {
+    assert!(is_svg(SVG.as_bytes()));
+    assert!(is_svg(
+        b"\xef\xbb\xbf  <svg xmlns=\"http://www.w3.org/2000/svg\"/>"
+    ));
+    let prolog = br##"<?xml version="1.0"?>
+<!-- made by hand -->
+<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" [ <!ENTITY red "#ff0000"> ]>
+<svg:svg xmlns:svg="http://www.w3.org/2000/svg">"##;
+    assert!(is_svg(prolog));
+    assert!(!is_svg(b"\x89PNG\r\n\x1a\n"));
+    assert!(!is_svg(b"GIF89a"));
+
+    // other XML documents
+    assert!(!is_svg(b"<?xml version=\"1.0\"?>\n<rss version=\"2.0\">"));
+    assert!(!is_svg(b"<!-- not closed before the end"));
+    assert!(!is_svg(b"<svgx/>"));
+
+    // compressed documents are recognized from their start
+    let mut svgz = GzEncoder::new(vec![], Compression::default());
+    svgz.write_all(SVG.as_bytes()).unwrap();
+    let svgz = svgz.finish().unwrap();
+    assert!(is_svg(&svgz[..svgz.len() / 2]));
+    assert!(SvgSource::new(&svgz).is_ok());
+
+    let mut gzip = GzEncoder::new(vec![], Compression::default());
+    gzip.write_all(b"<?xml version=\"1.0\"?><rss/>").unwrap();
+    assert!(!is_svg(&gzip.finish().unwrap()));
+    assert!(!is_svg(&[0x1f, 0x8b, 0x08]));
+}
@@ -0,0 +1,151 @@
+//! Renders text with the bitmap and outline fonts, still and as a marquee
+
+use std::path::Path;
+use std::time::Duration;
+
+use epizentrum::frame_source::text_source::{
+    Font, TextMode, TextSource, TextSourceError, TextStyle,
+};
+use epizentrum::frame_source::FrameSource;
+
+use crate::common::{pixels, BLACK, RED, WHITE};
+
+mod common;
+
+const FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+
+fn style(size: f32) -> TextStyle {
+    TextStyle {
+        size,
+        foreground: WHITE,
+        background: BLACK,
+        outline: None,
+    }
+}
+
+#[test]
+fn bitmap_lines() {
+    // 16px lines scale the 6x8 glyph cells by 2, without spacing after the last cell
+    let mut source =
+        TextSource::new("AB\nC", &Font::Bitmap, style(16.0), TextMode::Static).unwrap();
+    assert_eq!(source.size(), (2 * 12 - 2, 2 * 16 - 2));
+    assert_eq!(source.cycle_time(), Duration::from_millis(u32::MAX as u64));
+
+    let pixels = pixels(&mut source, Duration::ZERO);
+    assert!(pixels.iter().all(|p| *p == WHITE || *p == BLACK));
+    assert!(pixels.contains(&WHITE));
+
+    // the second line is shorter
+    let width = source.size().0 as usize;
+    let second_line = &pixels[16 * width..];
+    assert!(second_line
+        .chunks_exact(width)
+        .all(|row| row[12..].iter().all(|p| *p == BLACK)));
+}
+
+#[test]
+fn bitmap_outline() {
+    let plain = TextSource::new("x", &Font::Bitmap, style(8.0), TextMode::Static).unwrap();
+    let mut outlined = TextSource::new(
+        "x",
+        &Font::Bitmap,
+        TextStyle {
+            outline: Some((RED, 2)),
+            ..style(8.0)
+        },
+        TextMode::Static,
+    )
+    .unwrap();
+
+    let (w, h) = plain.size();
+    assert_eq!(outlined.size(), (w + 4, h + 4));
+
+    let pixels = pixels(&mut outlined, Duration::ZERO);
+    assert!(pixels.contains(&RED));
+    assert!(pixels.contains(&WHITE));
+    assert_eq!(pixels[0], BLACK);
+}
+
+#[test]
+fn marquee_scrolls() {
+    let mut source = TextSource::new(
+        "I",
+        &Font::Bitmap,
+        style(8.0),
+        TextMode::Marquee {
+            width: 10,
+            speed: 100.0,
+        },
+    )
+    .unwrap();
+    let step = Duration::from_millis(10);
+    assert_eq!(source.size(), (10, 7));
+    assert_eq!(source.cycle_time(), step * (5 + 10));
+
+    // the text starts outside the window
+    let timing = source.frame(Duration::from_millis(5));
+    assert_eq!(timing.frame_time, step);
+    assert_eq!(timing.time_left, Duration::from_millis(5));
+    assert!(pixels(&mut source, Duration::ZERO)
+        .iter()
+        .all(|p| *p == BLACK));
+
+    // after one window width the text reaches the left edge
+    let text = TextSource::new("I", &Font::Bitmap, style(8.0), TextMode::Static)
+        .map(|mut s| pixels(&mut s, Duration::ZERO))
+        .unwrap();
+    let window = pixels(&mut source, step * 10);
+    for (row, text_row) in window.chunks_exact(10).zip(text.chunks_exact(5)) {
+        assert_eq!(&row[..5], text_row);
+        assert!(row[5..].iter().all(|p| *p == BLACK));
+    }
+
+    // and scrolls through again after a cycle
+    assert_eq!(pixels(&mut source, step * 25), window);
+}
+
+#[test]
+fn outline_font() {
+    if !Path::new(FONT).exists() {
+        eprintln!("{FONT} is missing, skipping");
+        return;
+    }
+
+    let font = Font::open(FONT).unwrap();
+    let mut source = TextSource::new("Hi", &font, style(24.0), TextMode::Static).unwrap();
+    let (w, h) = source.size();
+    assert!((20..=32).contains(&h), "unexpected line height {h}");
+    assert!(w > 10);
+
+    // anti-aliased edges blend foreground and background
+    let pixels = pixels(&mut source, Duration::ZERO);
+    assert!(pixels.contains(&WHITE));
+    assert!(pixels.iter().any(|p| *p != WHITE && *p != BLACK));
+}
+
+#[test]
+fn invalid_input() {
+    assert!(matches!(
+        TextSource::new("", &Font::Bitmap, style(8.0), TextMode::Static),
+        Err(TextSourceError::Empty)
+    ));
+    // too slow to fit a Duration, too fast for a nanosecond or a cycle that overflows
+    for speed in [0.0, 1e-20, 1e30, 1e-19] {
+        assert!(
+            matches!(
+                TextSource::new(
+                    "x",
+                    &Font::Bitmap,
+                    style(8.0),
+                    TextMode::Marquee { width: 10, speed }
+                ),
+                Err(TextSourceError::Speed(_))
+            ),
+            "{speed}"
+        );
+    }
+    assert!(matches!(
+        Font::open("/nonexistent.ttf"),
+        Err(TextSourceError::Io(_))
+    ));
+}
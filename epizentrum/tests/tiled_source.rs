@@ -0,0 +1,92 @@
+//! Repeats the frames of one source across an area
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use epizentrum::frame_source::tiled_source::{TiledSource, TiledSourceError, Tiling};
+use epizentrum::frame_source::{Frame, FrameSource};
+
+use crate::common::{pattern, pixels, Format, Frames, TestImage};
+
+mod common;
+
+const FRAME_TIME: Duration = Duration::from_millis(100);
+
+/// One pixel counting up to 3 every [FRAME_TIME]
+fn counter() -> Frames {
+    let frames = [0, 1, 2, 3].map(|i| Frame::Rgba(Box::new([[i, i, i, 255]])));
+    Frames::new((1, 1), frames.into(), FRAME_TIME)
+}
+
+#[test]
+fn tiles_with_spacing() {
+    let tile = (7, 5);
+    let tiling = Tiling {
+        size: (20, 12),
+        spacing: (2, 1),
+        phase: Duration::ZERO,
+    };
+
+    for format in [Format::Rgba, Format::Bgra] {
+        let mut source = TiledSource::new(TestImage::new(tile, format), tiling).unwrap();
+        assert_eq!(source.size(), (20, 12));
+
+        let pixels = pixels(&mut source, Duration::ZERO);
+        for y in 0..12u16 {
+            for x in 0..20u16 {
+                // tiles start every 9 pixels horizontally and every 6 vertically
+                let (tx, ty) = (x % 9, y % 6);
+                let expected = match tx < tile.0 && ty < tile.1 {
+                    true => pattern(tx, ty),
+                    false => [0, 0, 0, 0],
+                };
+                assert_eq!(pixels[(y * 20 + x) as usize], expected, "{x},{y}");
+            }
+        }
+    }
+}
+
+#[test]
+fn phase_offsets_share_the_source() {
+    let tiling = Tiling {
+        size: (3, 2),
+        spacing: (0, 0),
+        phase: FRAME_TIME,
+    };
+    let counter = counter();
+    let requests = counter.requests.clone();
+    let mut source = TiledSource::new(counter, tiling).unwrap();
+    assert_eq!(source.cycle_time(), FRAME_TIME * 4);
+
+    let values = |pixels: Vec<[u8; 4]>| pixels.iter().map(|p| p[0]).collect::<Vec<_>>();
+    assert_eq!(
+        values(pixels(&mut source, Duration::ZERO)),
+        [0, 1, 2, 3, 0, 1]
+    );
+    // four distinct phases, the last two tiles wrap around to the first ones
+    assert_eq!(requests.load(Ordering::Relaxed), 4);
+
+    let timing = source.frame(FRAME_TIME / 4);
+    assert_eq!(timing.time_left, FRAME_TIME * 3 / 4);
+    assert_eq!(timing.frame_time, FRAME_TIME);
+    // still within the frame, the source is not asked again
+    assert_eq!(requests.load(Ordering::Relaxed), 4);
+
+    assert_eq!(
+        values(pixels(&mut source, FRAME_TIME * 5 / 2)),
+        [2, 3, 0, 1, 2, 3]
+    );
+}
+
+#[test]
+fn empty_area() {
+    let tiling = Tiling {
+        size: (0, 4),
+        spacing: (0, 0),
+        phase: Duration::ZERO,
+    };
+    assert!(matches!(
+        TiledSource::new(counter(), tiling),
+        Err(TiledSourceError::Size(0, 4))
+    ));
+}
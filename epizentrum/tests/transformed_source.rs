@@ -0,0 +1,147 @@
+//! Crops, rotates and flips the frames of a source
+
+use std::time::Duration;
+
+use epizentrum::frame_source::transformed_source::{
+    Transform, TransformedSource, TransformedSourceError,
+};
+use epizentrum::frame_source::{Frame, FrameSource};
+
+use crate::common::{pattern, Format, TestImage};
+
+mod common;
+
+const SIZE: (u16, u16) = (5, 3);
+
+fn transformed(transforms: &[Transform]) -> TransformedSource<TestImage> {
+    TransformedSource::new(TestImage::new(SIZE, Format::Rgba), transforms).unwrap()
+}
+
+/// Checks every pixel against the source pixel `at` maps it to
+fn assert_maps(transforms: &[Transform], size: (u16, u16), at: impl Fn(u16, u16) -> (u16, u16)) {
+    let mut source = transformed(transforms);
+    assert_eq!(source.size(), size, "{transforms:?}");
+
+    let Frame::Rgba(pixels) = source.frame(Duration::ZERO).frame else {
+        panic!("channel order changed")
+    };
+    for (i, pixel) in pixels.iter().enumerate() {
+        let (x, y) = ((i % size.0 as usize) as u16, (i / size.0 as usize) as u16);
+        let (sx, sy) = at(x, y);
+        assert_eq!(*pixel, pattern(sx, sy), "{transforms:?} at {x},{y}");
+    }
+}
+
+#[test]
+fn quarter_turns_and_flips() {
+    let (w, h) = SIZE;
+    assert_maps(&[Transform::Rotate(1)], (h, w), |x, y| (y, h - 1 - x));
+    assert_maps(&[Transform::Rotate(2)], (w, h), |x, y| {
+        (w - 1 - x, h - 1 - y)
+    });
+    assert_maps(&[Transform::Rotate(3)], (h, w), |x, y| (w - 1 - y, x));
+    assert_maps(&[Transform::FlipHorizontal], (w, h), |x, y| (w - 1 - x, y));
+    assert_maps(&[Transform::FlipVertical], (w, h), |x, y| (x, h - 1 - y));
+
+    // half a turn is flipping both ways
+    assert_maps(
+        &[Transform::FlipHorizontal, Transform::FlipVertical],
+        (w, h),
+        |x, y| (w - 1 - x, h - 1 - y),
+    );
+    assert_maps(&[Transform::Rotate(1); 4], (w, h), |x, y| (x, y));
+}
+
+#[test]
+fn transforms_apply_in_order() {
+    let crop = Transform::Crop {
+        x: 1,
+        y: 1,
+        w: 3,
+        h: 2,
+    };
+    assert_maps(&[crop], (3, 2), |x, y| (x + 1, y + 1));
+    assert_maps(&[crop, Transform::FlipHorizontal], (3, 2), |x, y| {
+        (3 - x, y + 1)
+    });
+    assert_maps(&[Transform::FlipHorizontal, crop], (3, 2), |x, y| {
+        (3 - x, y + 1)
+    });
+    assert_maps(&[crop, Transform::Rotate(1)], (2, 3), |x, y| (y + 1, 2 - x));
+
+    // the rotated frame is 3x5
+    let crop = Transform::Crop {
+        x: 0,
+        y: 1,
+        w: 3,
+        h: 2,
+    };
+    assert_maps(&[Transform::Rotate(1), crop], (3, 2), |x, y| (y + 1, 2 - x));
+}
+
+#[test]
+fn arbitrary_rotation() {
+    let mut quarter = transformed(&[Transform::Rotate(1)]);
+    let mut arbitrary = transformed(&[Transform::RotateBy(90.0)]);
+    assert_eq!(arbitrary.size(), quarter.size());
+
+    let (Frame::Rgba(expected), Frame::Rgba(pixels)) = (
+        quarter.frame(Duration::ZERO).frame,
+        arbitrary.frame(Duration::ZERO).frame,
+    ) else {
+        panic!("channel order changed")
+    };
+    for (expected, pixel) in expected.iter().zip(pixels.iter()) {
+        for (e, p) in expected.iter().zip(pixel) {
+            assert!(e.abs_diff(*p) <= 1, "{expected:?} != {pixel:?}");
+        }
+    }
+
+    // the frame grows to fit the corners, which are left transparent
+    let mut diagonal = TransformedSource::new(
+        TestImage::new((10, 10), Format::Rgba),
+        &[Transform::RotateBy(45.0)],
+    )
+    .unwrap();
+    assert_eq!(diagonal.size(), (15, 15));
+    let Frame::Rgba(pixels) = diagonal.frame(Duration::ZERO).frame else {
+        panic!("channel order changed")
+    };
+    assert_eq!(pixels[0], [0, 0, 0, 0]);
+    assert_eq!(pixels[7 * 15 + 7][3], 255);
+}
+
+#[test]
+fn rotation_angles() {
+    let rotation = |degrees| Transform::rotation(degrees).unwrap();
+    assert_eq!(rotation(0.0), Transform::Rotate(0));
+    assert_eq!(rotation(270.0), Transform::Rotate(3));
+    assert_eq!(rotation(-90.0), Transform::Rotate(3));
+    assert_eq!(rotation(450.0), Transform::Rotate(1));
+    assert_eq!(rotation(30.0), Transform::RotateBy(30.0));
+    assert!(matches!(
+        Transform::rotation(f32::INFINITY),
+        Err(TransformedSourceError::Angle(_))
+    ));
+}
+
+#[test]
+fn crop_outside_frame() {
+    let crop = |x, y, w, h| {
+        TransformedSource::new(
+            TestImage::new(SIZE, Format::Bgra),
+            &[Transform::Rotate(1), Transform::Crop { x, y, w, h }],
+        )
+    };
+
+    // the crop applies to the rotated 3x5 frame
+    assert!(crop(0, 0, 3, 5).is_ok());
+    assert!(matches!(
+        crop(0, 0, 5, 3),
+        Err(TransformedSourceError::Crop { size: (3, 5), .. })
+    ));
+    assert!(matches!(
+        crop(1, 0, 0, 1),
+        Err(TransformedSourceError::Crop { .. })
+    ));
+}
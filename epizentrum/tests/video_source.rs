@@ -0,0 +1,98 @@
+//! Decodes a tiny uncompressed video with libav
+#![cfg(feature = "video")]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use epizentrum::frame_source::video_source::{VideoSource, VideoSourceError};
+use epizentrum::frame_source::{Frame, FrameSource};
+
+const SIZE: (u16, u16) = (8, 6);
+const FRAME_TIME: Duration = Duration::from_millis(100);
+/// Luma of the gray frames, full range
+const LEVELS: [u8; 4] = [0, 85, 170, 255];
+
+/// Writes a YUV4MPEG2 video of gray frames at 10 fps, libav demuxes it without any codec
+fn video(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("tsunami-{name}-{}.y4m", std::process::id()));
+    let (w, h) = (SIZE.0 as usize, SIZE.1 as usize);
+
+    let mut file = std::fs::File::create(&path).unwrap();
+    writeln!(file, "YUV4MPEG2 W{w} H{h} F10:1 Ip A1:1 C420jpeg").unwrap();
+    for level in LEVELS {
+        file.write_all(b"FRAME\n").unwrap();
+        file.write_all(&vec![level; w * h]).unwrap();
+        file.write_all(&vec![128; w * h / 2]).unwrap();
+    }
+
+    path
+}
+
+/// Gray level of a uniformly gray frame
+fn level(frame: &Frame) -> u8 {
+    let Frame::Rgba(pixels) = frame else {
+        panic!("video frames are rgba");
+    };
+    assert_eq!(pixels.len(), SIZE.0 as usize * SIZE.1 as usize);
+    let [r, g, b, a] = pixels[0];
+    assert!(pixels.iter().all(|p| *p == pixels[0]));
+    assert!(r.abs_diff(g) <= 2 && r.abs_diff(b) <= 2, "{:?}", pixels[0]);
+    assert_eq!(a, 255);
+    r
+}
+
+fn assert_level(actual: u8, expected: u8) {
+    assert!(
+        actual.abs_diff(expected) <= 2,
+        "{actual} instead of {expected}"
+    );
+}
+
+#[test]
+fn size_and_cycle_time() {
+    let path = video("video-size");
+    let source = VideoSource::new(&path).expect("unable to load test video");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(source.size(), SIZE);
+    assert_eq!(source.cycle_time(), FRAME_TIME * LEVELS.len() as u32);
+}
+
+#[test]
+fn steps_through_frames() {
+    let path = video("video-steps");
+    let mut source = VideoSource::new(&path).expect("unable to load test video");
+    std::fs::remove_file(&path).unwrap();
+
+    // in order, within frames and at their boundaries
+    for (ms, index) in [(0, 0), (50, 0), (99, 0), (100, 1), (250, 2), (399, 3)] {
+        let timing = source.frame(Duration::from_millis(ms));
+        assert_level(level(timing.frame), LEVELS[index]);
+        assert_eq!(timing.frame_time, FRAME_TIME, "at {ms}ms");
+        assert_eq!(
+            timing.time_left,
+            FRAME_TIME * (index as u32 + 1) - Duration::from_millis(ms),
+            "at {ms}ms"
+        );
+    }
+
+    // wrapping around and jumping back seek
+    for (ms, index) in [(420, 0), (1150, 3), (310, 3), (120, 1), (0, 0)] {
+        let timing = source.frame(Duration::from_millis(ms));
+        assert_level(level(timing.frame), LEVELS[index]);
+    }
+}
+
+#[test]
+fn rejects_files_without_video() {
+    let path = std::env::temp_dir().join(format!("tsunami-video-none-{}.txt", std::process::id()));
+    std::fs::write(&path, b"not a video").unwrap();
+    let result = VideoSource::new(&path);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        result,
+        Err(VideoSourceError::Ffmpeg(_) | VideoSourceError::NoVideoStream)
+    ));
+}
@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use image::RgbaImage;
+
+/// In-memory canvas shared by all connections
+#[derive(Debug)]
+pub struct Canvas {
+    size: (u16, u16),
+    pixels: Box<[AtomicU32]>,
+}
+
+impl Canvas {
+    pub fn new(size: (u16, u16)) -> Self {
+        Self {
+            size,
+            pixels: (0..size.0 as usize * size.1 as usize)
+                .map(|_| AtomicU32::new(u32::from_be_bytes([0, 0, 0, 255])))
+                .collect(),
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    pub fn get(&self, x: u16, y: u16) -> Option<[u8; 4]> {
+        self.index(x, y)
+            .map(|i| self.pixels[i].load(Ordering::Relaxed).to_be_bytes())
+    }
+
+    /// Blends `color` onto the canvas, returns false if the pixel is out of bounds
+    pub fn set(&self, x: u16, y: u16, [r, g, b, a]: [u8; 4]) -> bool {
+        let Some(i) = self.index(x, y) else {
+            return false;
+        };
+
+        let color = match a {
+            255 => [r, g, b, 255],
+            0 => return true,
+            a => {
+                let [dr, dg, db, _] = self.pixels[i].load(Ordering::Relaxed).to_be_bytes();
+                let blend = |s: u8, d: u8| {
+                    ((s as u32 * a as u32 + d as u32 * (255 - a as u32)) / 255) as u8
+                };
+                [blend(r, dr), blend(g, dg), blend(b, db), 255]
+            }
+        };
+
+        self.pixels[i].store(u32::from_be_bytes(color), Ordering::Relaxed);
+        true
+    }
+
+    pub fn snapshot(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.size.0 as u32, self.size.1 as u32, |x, y| {
+            image::Rgba(self.get(x as u16, y as u16).unwrap())
+        })
+    }
+
+    fn index(&self, x: u16, y: u16) -> Option<usize> {
+        if x >= self.size.0 || y >= self.size.1 {
+            return None;
+        }
+
+        Some(y as usize * self.size.0 as usize + x as usize)
+    }
+}
@@ -0,0 +1,143 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::str::from_utf8;
+use std::sync::atomic::Ordering;
+
+use tracing::debug;
+
+use crate::canvas::Canvas;
+use crate::Stats;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+const PB_LENGTH: usize = 10;
+
+/// Serves one client until it disconnects
+pub(crate) fn handle(mut stream: TcpStream, canvas: &Canvas, stats: &Stats) -> std::io::Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut filled = 0;
+    let mut offset = (0u16, 0u16);
+    let mut response = vec![];
+
+    loop {
+        let n = match stream.read(&mut buffer[filled..]) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        stats.bytes.fetch_add(n as u64, Ordering::Relaxed);
+        filled += n;
+
+        let mut position = 0;
+        loop {
+            let rest = &buffer[position..filled];
+
+            if rest.starts_with(b"PB") {
+                if rest.len() < PB_LENGTH {
+                    break;
+                }
+
+                let x = u16::from_le_bytes([rest[2], rest[3]]);
+                let y = u16::from_le_bytes([rest[4], rest[5]]);
+                let color = [rest[6], rest[7], rest[8], rest[9]];
+                set(canvas, stats, offset, (x, y), color);
+                position += PB_LENGTH;
+                continue;
+            }
+
+            match rest.iter().position(|&b| b == b'\n') {
+                None => break,
+                Some(end) => {
+                    let line = &rest[..end];
+                    position += end + 1;
+                    command(line, canvas, stats, &mut offset, &mut response);
+                }
+            }
+        }
+
+        buffer.copy_within(position..filled, 0);
+        filled -= position;
+        if filled == buffer.len() {
+            debug!("line too long, closing connection");
+            return Ok(());
+        }
+
+        if !response.is_empty() {
+            stream.write_all(&response)?;
+            response.clear();
+        }
+    }
+}
+
+fn command(
+    line: &[u8],
+    canvas: &Canvas,
+    stats: &Stats,
+    offset: &mut (u16, u16),
+    response: &mut Vec<u8>,
+) {
+    let Ok(line) = from_utf8(line) else {
+        stats.invalid.fetch_add(1, Ordering::Relaxed);
+        return;
+    };
+
+    match line.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+        ["SIZE"] => {
+            let (w, h) = canvas.size();
+            response.extend_from_slice(format!("SIZE {w} {h}\n").as_bytes());
+        }
+        ["HELP"] => response.extend_from_slice(
+            b"HELP SIZE | PX <x> <y> [<ww>|<rrggbb>|<rrggbbaa>] | OFFSET <x> <y> | PB<x><y><rgba>\n",
+        ),
+        ["OFFSET", x, y] => match (x.parse(), y.parse()) {
+            (Ok(x), Ok(y)) => *offset = (x, y),
+            _ => invalid(stats, line),
+        },
+        ["PX", x, y] => match (x.parse::<u16>(), y.parse::<u16>()) {
+            (Ok(x), Ok(y)) => {
+                let pixel = x
+                    .checked_add(offset.0)
+                    .zip(y.checked_add(offset.1))
+                    .and_then(|(xx, yy)| canvas.get(xx, yy));
+                if let Some([r, g, b, _]) = pixel {
+                    response.extend_from_slice(format!("PX {x} {y} {r:02x}{g:02x}{b:02x}\n").as_bytes());
+                }
+            }
+            _ => invalid(stats, line),
+        },
+        ["PX", x, y, color] => match (x.parse(), y.parse(), parse_color(color)) {
+            (Ok(x), Ok(y), Some(color)) => set(canvas, stats, *offset, (x, y), color),
+            _ => invalid(stats, line),
+        },
+        _ => invalid(stats, line),
+    }
+}
+
+fn invalid(stats: &Stats, line: &str) {
+    debug!("invalid command: {line:?}");
+    stats.invalid.fetch_add(1, Ordering::Relaxed);
+}
+
+fn set(canvas: &Canvas, stats: &Stats, offset: (u16, u16), (x, y): (u16, u16), color: [u8; 4]) {
+    let drawn = match (x.checked_add(offset.0), y.checked_add(offset.1)) {
+        (Some(x), Some(y)) => canvas.set(x, y, color),
+        _ => false,
+    };
+
+    if drawn {
+        stats.pixels.fetch_add(1, Ordering::Relaxed);
+    } else {
+        stats.outside.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn parse_color(color: &str) -> Option<[u8; 4]> {
+    let value = u32::from_str_radix(color, 16).ok()?;
+
+    match color.len() {
+        2 => Some([value as u8, value as u8, value as u8, 255]),
+        6 => Some([(value >> 16) as u8, (value >> 8) as u8, value as u8, 255]),
+        8 => Some(value.to_be_bytes()),
+        _ => None,
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use image::ImageFormat;
+use tracing::{debug, info, warn};
+
+pub use crate::canvas::Canvas;
+
+pub mod canvas;
+mod connection;
+#[cfg(feature = "test-support")]
+pub mod proxy;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub bytes: AtomicU64,
+    pub pixels: AtomicU64,
+    /// Pixels addressed outside of the canvas
+    pub outside: AtomicU64,
+    /// Lines that are not a valid command
+    pub invalid: AtomicU64,
+    pub connections: AtomicUsize,
+    pub total_connections: AtomicUsize,
+}
+
+/// A minimal Pixelflut server
+///
+/// Supports `SIZE`, `HELP`, `PX` (set and get), `OFFSET` and binary `PB` commands.
+#[derive(Debug)]
+pub struct Server {
+    listener: TcpListener,
+    canvas: Arc<Canvas>,
+    stats: Arc<Stats>,
+    shutdown: Arc<AtomicBool>,
+    clients: Clients,
+}
+
+/// Open connections by id, kept to close them on shutdown
+type Clients = Arc<Mutex<HashMap<u64, TcpStream>>>;
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A, size: (u16, u16)) -> std::io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            canvas: Arc::new(Canvas::new(size)),
+            stats: Default::default(),
+            shutdown: Default::default(),
+            clients: Default::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn canvas(&self) -> Arc<Canvas> {
+        self.canvas.clone()
+    }
+
+    pub fn stats(&self) -> Arc<Stats> {
+        self.stats.clone()
+    }
+
+    /// Accepts connections until the server is shut down
+    pub fn run(&self) -> std::io::Result<()> {
+        for (id, stream) in (0u64..).zip(self.listener.incoming()) {
+            if self.shutdown.load(Ordering::Relaxed) {
+                break;
+            }
+
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("unable to accept connection: {e}");
+                    continue;
+                }
+            };
+
+            // a client can reset the connection before it is set up
+            let (peer, clone) = match (stream.peer_addr(), stream.try_clone()) {
+                (Ok(peer), Ok(clone)) => (peer, clone),
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("unable to set up connection: {e}");
+                    continue;
+                }
+            };
+            debug!("+ connection {peer}");
+            self.clients.lock().unwrap().insert(id, clone);
+            self.stats.connections.fetch_add(1, Ordering::Relaxed);
+            self.stats.total_connections.fetch_add(1, Ordering::Relaxed);
+
+            let canvas = self.canvas.clone();
+            let stats = self.stats.clone();
+            let clients = self.clients.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = connection::handle(stream, &canvas, &stats) {
+                    debug!("connection {peer} failed: {e}");
+                }
+                debug!("- connection {peer}");
+                clients.lock().unwrap().remove(&id);
+                stats.connections.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Runs the server on a background thread
+    pub fn spawn(self) -> std::io::Result<ServerHandle> {
+        let addr = self.local_addr()?;
+        let canvas = self.canvas();
+        let stats = self.stats();
+        let shutdown = self.shutdown.clone();
+        let clients = self.clients.clone();
+        let thread = std::thread::spawn(move || self.run());
+
+        Ok(ServerHandle {
+            addr,
+            canvas,
+            stats,
+            shutdown,
+            clients,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    canvas: Arc<Canvas>,
+    stats: Arc<Stats>,
+    shutdown: Arc<AtomicBool>,
+    clients: Clients,
+    thread: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Stops accepting connections and closes all open connections
+    pub fn shutdown(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        for (_, client) in self.clients.lock().unwrap().drain() {
+            let _ = client.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Logs throughput every `interval`
+pub fn report(stats: &Stats, interval: Duration) -> ! {
+    let mut last = (Instant::now(), 0, 0);
+    loop {
+        std::thread::sleep(interval);
+
+        let now = Instant::now();
+        let bytes = stats.bytes.load(Ordering::Relaxed);
+        let pixels = stats.pixels.load(Ordering::Relaxed);
+        let seconds = (now - last.0).as_secs_f64();
+
+        info!(
+            "{:.2} Mpx/s, {:.2} MiB/s, {} connections",
+            (pixels - last.2) as f64 / seconds / 1_000_000.0,
+            (bytes - last.1) as f64 / seconds / (1024.0 * 1024.0),
+            stats.connections.load(Ordering::Relaxed),
+        );
+        last = (now, bytes, pixels);
+    }
+}
+
+/// Saves a PNG snapshot of the canvas to `path` every `interval`
+pub fn snapshots(canvas: &Canvas, path: &Path, interval: Duration) -> ! {
+    let tmp = path.with_extension("png.tmp");
+    loop {
+        std::thread::sleep(interval);
+
+        if let Err(e) = canvas
+            .snapshot()
+            .save_with_format(&tmp, ImageFormat::Png)
+            .map_err(|e| e.to_string())
+            .and_then(|_| std::fs::rename(&tmp, path).map_err(|e| e.to_string()))
+        {
+            warn!("unable to save snapshot to {}: {e}", path.display());
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use socket2::SockRef;
+use tracing::{debug, warn};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Fault applied to the client side of every proxied connection
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Forward everything
+    #[default]
+    None,
+    /// Stop reading from clients, their send buffers fill up
+    Stall,
+    /// Read at most `chunk` bytes every `interval`
+    SlowRead { chunk: usize, interval: Duration },
+    /// Forward `after` bytes of every connection, then reset it
+    Truncate { after: usize },
+    /// Reset every connection right after it was accepted
+    Reset,
+}
+
+#[derive(Debug, Default)]
+pub struct ProxyStats {
+    pub accepted: AtomicUsize,
+    pub active: AtomicUsize,
+    pub bytes: AtomicU64,
+    accept_times: Mutex<Vec<Instant>>,
+}
+
+impl ProxyStats {
+    /// When every connection so far was accepted
+    pub fn accept_times(&self) -> Vec<Instant> {
+        self.accept_times.lock().unwrap().clone()
+    }
+}
+
+#[derive(Debug)]
+struct Connection {
+    client: TcpStream,
+    upstream: TcpStream,
+    closed: AtomicBool,
+}
+
+impl Connection {
+    /// Closes both sides, the client sees a reset once all handles are dropped
+    fn reset(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let _ = SockRef::from(&self.client).set_linger(Some(Duration::ZERO));
+        let _ = self.client.shutdown(Shutdown::Both);
+        let _ = self.upstream.shutdown(Shutdown::Both);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    fault: Mutex<Fault>,
+    refuse: AtomicBool,
+    shutdown: AtomicBool,
+    connections: Mutex<Vec<Arc<Connection>>>,
+    stats: ProxyStats,
+}
+
+impl Shared {
+    fn fault(&self) -> Fault {
+        *self.fault.lock().unwrap()
+    }
+}
+
+/// A TCP proxy that injects faults between a client and a server
+#[derive(Debug)]
+pub struct Proxy {
+    listener: TcpListener,
+    upstream: SocketAddr,
+    shared: Arc<Shared>,
+}
+
+impl Proxy {
+    pub fn bind<A: ToSocketAddrs>(addr: A, upstream: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            upstream,
+            shared: Default::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accepts and forwards connections on a background thread
+    pub fn spawn(self) -> std::io::Result<ProxyHandle> {
+        let addr = self.local_addr()?;
+        let shared = self.shared.clone();
+        let thread = std::thread::spawn(move || self.run(addr));
+
+        Ok(ProxyHandle {
+            addr,
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    fn run(self, addr: SocketAddr) {
+        let Self {
+            listener,
+            upstream,
+            shared,
+        } = self;
+
+        let mut listener = Some(listener);
+        while !shared.shutdown.load(Ordering::Relaxed) {
+            // refused connects need a closed port
+            if shared.refuse.load(Ordering::Relaxed) {
+                listener = None;
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+
+            let Some(l) = &listener else {
+                match TcpListener::bind(addr).and_then(|l| {
+                    l.set_nonblocking(true)?;
+                    Ok(l)
+                }) {
+                    Ok(l) => listener = Some(l),
+                    Err(e) => {
+                        warn!("unable to rebind proxy to {addr}: {e}");
+                        std::thread::sleep(POLL_INTERVAL);
+                    }
+                }
+                continue;
+            };
+
+            match l.accept() {
+                Ok((client, peer)) => {
+                    debug!("+ proxy connection {peer}");
+                    if let Err(e) = forward(&shared, upstream, client) {
+                        debug!("unable to proxy connection {peer}: {e}");
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => warn!("unable to accept proxy connection: {e}"),
+            }
+        }
+    }
+}
+
+fn forward(shared: &Arc<Shared>, upstream: SocketAddr, client: TcpStream) -> std::io::Result<()> {
+    // decided before the accept is counted, so waiting for accepts observes the fault
+    let reset = shared.fault() == Fault::Reset;
+    let stats = &shared.stats;
+    stats.accepted.fetch_add(1, Ordering::Relaxed);
+    stats.accept_times.lock().unwrap().push(Instant::now());
+
+    client.set_nonblocking(false)?;
+    let upstream = match TcpStream::connect(upstream) {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            // like a restarting server
+            let _ = SockRef::from(&client).set_linger(Some(Duration::ZERO));
+            return Err(e);
+        }
+    };
+
+    let connection = Arc::new(Connection {
+        client: client.try_clone()?,
+        upstream: upstream.try_clone()?,
+        closed: AtomicBool::new(false),
+    });
+    if reset {
+        connection.reset();
+        return Ok(());
+    }
+
+    stats.active.fetch_add(1, Ordering::Relaxed);
+    shared.connections.lock().unwrap().push(connection.clone());
+
+    let shared = shared.clone();
+    let (c, mut upstream_write) = (connection.clone(), upstream.try_clone()?);
+    let mut client_read = client.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = to_upstream(&shared, &c, &mut client_read, &mut upstream_write);
+        c.reset();
+
+        shared.stats.active.fetch_sub(1, Ordering::Relaxed);
+        shared
+            .connections
+            .lock()
+            .unwrap()
+            .retain(|connection| !Arc::ptr_eq(connection, &c));
+    });
+
+    let (mut upstream_read, mut client_write) = (upstream, client);
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut upstream_read, &mut client_write);
+        connection.reset();
+    });
+
+    Ok(())
+}
+
+fn to_upstream(
+    shared: &Shared,
+    connection: &Connection,
+    client: &mut TcpStream,
+    upstream: &mut TcpStream,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let mut forwarded = 0;
+
+    while !connection.is_closed() {
+        let fault = shared.fault();
+        let len = match fault {
+            Fault::Stall => {
+                std::thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Fault::SlowRead { chunk, .. } => chunk.clamp(1, BUFFER_SIZE),
+            Fault::Truncate { after } => after.saturating_sub(forwarded).min(BUFFER_SIZE),
+            Fault::None | Fault::Reset => BUFFER_SIZE,
+        };
+
+        let n = match len {
+            0 => 0,
+            len => client.read(&mut buffer[..len])?,
+        };
+        if n == 0 {
+            break;
+        }
+
+        upstream.write_all(&buffer[..n])?;
+        forwarded += n;
+        shared.stats.bytes.fetch_add(n as u64, Ordering::Relaxed);
+
+        if let Fault::SlowRead { interval, .. } = fault {
+            std::thread::sleep(interval);
+        }
+    }
+
+    Ok(())
+}
+
+/// Controls a running [Proxy]
+#[derive(Debug)]
+pub struct ProxyHandle {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ProxyHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stats(&self) -> &ProxyStats {
+        &self.shared.stats
+    }
+
+    pub fn set_fault(&self, fault: Fault) {
+        *self.shared.fault.lock().unwrap() = fault;
+    }
+
+    /// Closes the listening port, connects are refused until `refuse(false)`
+    pub fn refuse(&self, refuse: bool) {
+        self.shared.refuse.store(refuse, Ordering::Relaxed);
+    }
+
+    /// Resets the oldest connection, returns false if there is none
+    pub fn reset_one(&self) -> bool {
+        let connections = self.shared.connections.lock().unwrap();
+        match connections.iter().find(|c| !c.is_closed()) {
+            Some(connection) => {
+                connection.reset();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Resets all open connections
+    pub fn reset_all(&self) {
+        for connection in self.shared.connections.lock().unwrap().iter() {
+            connection.reset();
+        }
+    }
+
+    /// Resets all connections and refuses new ones for `downtime`
+    pub fn restart(&self, downtime: Duration) {
+        self.refuse(true);
+        self.reset_all();
+        std::thread::sleep(downtime);
+        self.refuse(false);
+    }
+
+    /// Stops accepting connections and resets all open connections
+    pub fn shutdown(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        self.reset_all();
+    }
+}
+
+impl Drop for ProxyHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
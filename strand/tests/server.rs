@@ -0,0 +1,77 @@
+//! Keeps serving while clients come and go
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Socket, Type};
+use strand::Server;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn size(addr: SocketAddr) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"SIZE\n").unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    line
+}
+
+fn open_fds() -> usize {
+    std::fs::read_dir("/proc/self/fd").unwrap().count()
+}
+
+fn wait_until<F: FnMut() -> bool>(mut condition: F) -> bool {
+    let deadline = Instant::now() + TIMEOUT;
+    while !condition() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    true
+}
+
+#[test]
+fn closed_connections_are_released() {
+    let server = Server::bind("127.0.0.1:0", (4, 3))
+        .and_then(Server::spawn)
+        .unwrap();
+    let connections = || server.stats().connections.load(Ordering::Relaxed);
+    assert_eq!(size(server.local_addr()), "SIZE 4 3\n");
+    assert!(wait_until(|| connections() == 0));
+
+    let before = open_fds();
+    for _ in 0..200 {
+        assert_eq!(size(server.local_addr()), "SIZE 4 3\n");
+    }
+    assert!(wait_until(|| connections() == 0));
+
+    // every connection held a descriptor, closed ones must not keep it,
+    // the slack covers sockets of tests running at the same time
+    let after = open_fds();
+    assert!(
+        after <= before + 50,
+        "{before} descriptors before, {after} after"
+    );
+}
+
+#[test]
+fn survives_clients_resetting_right_away() {
+    let server = Server::bind("127.0.0.1:0", (4, 3))
+        .and_then(Server::spawn)
+        .unwrap();
+
+    for _ in 0..100 {
+        let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+        socket.set_linger(Some(Duration::ZERO)).unwrap();
+        socket.connect(&server.local_addr().into()).unwrap();
+        // closing with a zero linger sends a reset instead of a FIN
+        drop(socket);
+    }
+
+    assert_eq!(size(server.local_addr()), "SIZE 4 3\n");
+    assert!(server.stats().total_connections.load(Ordering::Relaxed) >= 1);
+}
@@ -3,11 +3,21 @@ use std::net::IpAddr;
 use std::num::{NonZeroU16, NonZeroU64, NonZeroUsize};
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::builder::{PossibleValue, Str};
 use clap::{Parser, Subcommand, ValueEnum};
 
+use epizentrum::alpha_policy::AlphaPolicy;
 use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::frame_source::color_source::{ChromaKey, ColorAdjustment};
+use epizentrum::frame_source::expression_source::expression::Program;
+use epizentrum::frame_source::media_source::remote::{self, Download};
+use epizentrum::frame_source::playlist_source::{PlaylistOptions, Transition};
+use epizentrum::frame_source::quantized_source::{Dithering, Palette};
+use epizentrum::frame_source::scaled_source::{FilterType, Scale};
+use epizentrum::frame_source::transformed_source::Transform;
+use epizentrum::motion_path::{Easing, Keyframe, MotionPath};
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
@@ -41,6 +51,29 @@ pub struct Args {
     #[arg(long, default_value_t, env = "TSUNAMI_TIME_OFFSET")]
     pub time_offset: i64,
 
+    /// How connections are assigned to media objects
+    ///
+    /// Applies to all objects without an assign= option of their own. Dedicated objects get
+    /// connections of their own, all others share the remaining connections.
+    #[arg(long, default_value_t, env = "TSUNAMI_ASSIGNMENT")]
+    pub assignment: ConnectionAssignment,
+
+    /// Network backend, Auto falls back to Epoll if io_uring is unavailable
+    #[arg(long, default_value_t, env = "TSUNAMI_BACKEND")]
+    pub backend: Backend,
+
+    /// Write command buffers to a file (or - for stdout) instead of the targets, needs --canvas
+    #[arg(short, long, requires = "canvas_size", env = "TSUNAMI_OUTPUT")]
+    pub output: Option<PathBuf>,
+
+    /// Stop writing the output after this many cycles (one command buffer per media object)
+    #[arg(long, requires = "output", env = "TSUNAMI_CYCLES")]
+    pub cycles: Option<NonZeroUsize>,
+
+    /// Stop writing the output after this many seconds
+    #[arg(long, requires = "output", env = "TSUNAMI_DURATION")]
+    pub duration: Option<NonZeroU64>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -63,8 +96,293 @@ impl FromStr for CanvasSize {
 pub enum Commands {
     /// List all GPUs
     Gpus,
-    /// Flut media files (jpeg, png, apng, gif, ...)
+    /// Flut media files (jpeg, png, apng, gif, ...) and videos if built with the `video` feature
     Media(Media),
+    /// Flut video files (mp4, mkv, webm, ...)
+    #[cfg(feature = "video")]
+    Video(Media),
+    /// Render every frame of media files into a precompiled show
+    Compile(Compile),
+    /// Flut a precompiled show
+    Play(Play),
+    /// Flut frames pushed by another program through stdin, a FIFO or a Unix socket
+    Stream(Stream),
+    /// Flut text rendered with a TTF/OTF or the built-in bitmap font, optionally as a scrolling marquee
+    Text(Text),
+    /// Flut generated fills, gradients, test patterns, plasma, noise or per-pixel formulas
+    Generate(Generate),
+    /// Run a local Pixelflut server for testing
+    Serve(Serve),
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Stream {
+    #[command(flatten)]
+    pub gpu_preference: GpuPreference,
+
+    /// Frame encoding, raw formats need --size
+    #[arg(long, default_value_t)]
+    pub format: StreamFormat,
+
+    /// Frame size of raw formats
+    /// (Example: 640x480)
+    #[arg(long, required_if_eq_any([("format", "Rgba"), ("format", "Bgra")]))]
+    pub size: Option<CanvasSize>,
+
+    /// Frame rate of raw formats
+    #[arg(long, default_value_t = 30.0)]
+    pub fps: f64,
+
+    /// Listen on a Unix socket at the input path and read from one producer after another
+    #[arg(long)]
+    pub unix: bool,
+
+    #[arg(allow_hyphen_values = true, value_parser = clap::value_parser ! (MediaDescription), help = r"Input to read frames from
+
+INPUT:  <- | path>[:<OFFSET>[:<DRAW_STRATEGY>]]  (- reads from stdin)
+OFFSET and DRAW_STRATEGY as for media objects")]
+    pub input: MediaDescription,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Text {
+    #[command(flatten)]
+    pub gpu_preference: GpuPreference,
+
+    #[command(flatten)]
+    pub placement: Placement,
+
+    /// TTF or OTF font, defaults to the built-in bitmap font
+    #[arg(long)]
+    pub font: Option<PathBuf>,
+
+    /// Line height in pixels
+    #[arg(long, default_value_t = 32.0)]
+    pub size: f32,
+
+    /// Text color as RRGGBB or RRGGBBAA
+    #[arg(long, default_value = "ffffff")]
+    pub color: Color,
+
+    /// Background color as RRGGBB or RRGGBBAA
+    #[arg(long, default_value = "000000")]
+    pub background: Color,
+
+    /// Outline color as RRGGBB or RRGGBBAA
+    #[arg(long)]
+    pub outline: Option<Color>,
+
+    /// Outline width in pixels
+    #[arg(long, default_value_t = 1)]
+    pub outline_width: u16,
+
+    /// Scroll the text through a window this many pixels wide
+    #[arg(long, value_name = "WIDTH")]
+    pub marquee: Option<NonZeroU16>,
+
+    /// Marquee speed in pixels per second
+    #[arg(long, default_value_t = 60.0, requires = "marquee")]
+    pub speed: f32,
+
+    /// Text to render, line breaks start new lines
+    #[arg(allow_hyphen_values = true)]
+    pub text: String,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Generate {
+    #[command(flatten)]
+    pub gpu_preference: GpuPreference,
+
+    #[command(flatten)]
+    pub placement: Placement,
+
+    #[arg(long = "caching", default_value_t)]
+    pub caching_strategy: CachingStrategy,
+
+    /// Image size, defaults to the rest of the canvas
+    /// (Example: 320x240)
+    #[arg(long)]
+    pub size: Option<CanvasSize>,
+
+    /// Animation cycles per second, 0 for a still image
+    #[arg(long, default_value_t = 0.1)]
+    pub speed: f32,
+
+    /// Frames per second of animations
+    #[arg(long, default_value_t = 30.0)]
+    pub fps: f32,
+
+    /// Fill color, first gradient and checkerboard color as RRGGBB or RRGGBBAA
+    #[arg(long, default_value = "ffffff")]
+    pub color: Color,
+
+    /// Second gradient and checkerboard color as RRGGBB or RRGGBBAA
+    #[arg(long, default_value = "000000")]
+    pub to: Color,
+
+    /// Direction of linear gradients in degrees, 0 runs from left to right
+    #[arg(long, default_value_t = 0.0)]
+    pub angle: f32,
+
+    /// Checkerboard cell and test pattern grid size in pixels
+    #[arg(long, default_value_t = 64)]
+    pub cell: u16,
+
+    /// Noise seed
+    #[arg(long, default_value_t)]
+    pub seed: u64,
+
+    /// Per-pixel formula instead of a pattern, assigns r, g, b and a (0 to 255) from x, y,
+    /// the time t in seconds and the size w and h. Statements are separated by ';',
+    /// with +-*/%^, comparisons, && || ! and c ? a : b, sin, cos, tan, asin, acos, atan,
+    /// atan2, sqrt, pow, exp, log, abs, sign, floor, ceil, round, fract, mod, min, max,
+    /// clamp, mix, step, smoothstep, hypot, pi and tau
+    /// (Example: 'v = sin(x / 10 + t); r = v * 127 + 128; b = 255 - r')
+    #[arg(long, value_name = "PROGRAM", conflicts_with = "pattern")]
+    pub expr: Option<Program>,
+
+    #[arg(required_unless_present = "expr")]
+    pub pattern: Option<Pattern>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Pattern {
+    /// Solid color
+    Fill,
+    /// Linear gradient
+    Linear,
+    /// Radial gradient from the center
+    Radial,
+    /// Checkerboard
+    Checkerboard,
+    /// SMPTE style color bars with a labeled coordinate grid
+    Test,
+    /// Animated plasma
+    Plasma,
+    /// Seeded random colors
+    Noise,
+}
+
+impl From<&Pattern> for Str {
+    fn from(value: &Pattern) -> Self {
+        match value {
+            Pattern::Fill => Str::from("Fill"),
+            Pattern::Linear => Str::from("Linear"),
+            Pattern::Radial => Str::from("Radial"),
+            Pattern::Checkerboard => Str::from("Checkerboard"),
+            Pattern::Test => Str::from("Test"),
+            Pattern::Plasma => Str::from("Plasma"),
+            Pattern::Noise => Str::from("Noise"),
+        }
+    }
+}
+
+impl ValueEnum for Pattern {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::Fill,
+            Self::Linear,
+            Self::Radial,
+            Self::Checkerboard,
+            Self::Test,
+            Self::Plasma,
+            Self::Noise,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self))
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Fill => f.write_str("Fill"),
+            Pattern::Linear => f.write_str("Linear"),
+            Pattern::Radial => f.write_str("Radial"),
+            Pattern::Checkerboard => f.write_str("Checkerboard"),
+            Pattern::Test => f.write_str("Test"),
+            Pattern::Plasma => f.write_str("Plasma"),
+            Pattern::Noise => f.write_str("Noise"),
+        }
+    }
+}
+
+/// Where a generated image is drawn on the canvas
+#[derive(clap::Args, Debug, Clone)]
+pub struct Placement {
+    /// Horizontal offset on the canvas
+    #[arg(short, default_value_t)]
+    pub x: u16,
+
+    /// Vertical offset on the canvas
+    #[arg(short, default_value_t)]
+    pub y: u16,
+
+    /// Pixel order: random, up, down, left or right
+    #[arg(long, default_value_t = DrawStrategy::Random, value_parser = DrawStrategy::from_str)]
+    pub draw_strategy: DrawStrategy,
+
+    /// Pixels that are not opaque: skip[,<threshold>] (leave out alpha below the threshold, default: 1), opaque or pass
+    #[arg(long, default_value_t, value_parser = AlphaPolicy::from_str)]
+    pub alpha: AlphaPolicy,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Color(pub [u8; 4]);
+
+impl FromStr for Color {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+        match (hex.len(), hex.is_ascii()) {
+            (6, true) => Ok(Self([channel(0)?, channel(2)?, channel(4)?, 0xff])),
+            (8, true) => Ok(Self([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+            _ => Err(eyre::eyre!("invalid color: \"{s}\"")),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Compile {
+    /// Precompiled show to write
+    pub file: PathBuf,
+
+    #[command(flatten)]
+    pub media: Media,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Play {
+    /// Precompiled show to flut
+    pub file: PathBuf,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct Serve {
+    /// Address to listen on
+    #[arg(short, long, default_value = "[::]:1337")]
+    pub listen: String,
+
+    /// Canvas size
+    #[arg(long, default_value = "1280x720")]
+    pub size: CanvasSize,
+
+    /// Save a PNG snapshot of the canvas to this file periodically
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Snapshot interval in seconds
+    #[arg(long, default_value = "10")]
+    pub snapshot_interval: NonZeroU64,
+
+    /// Throughput report interval in seconds
+    #[arg(long, default_value = "5")]
+    pub report_interval: NonZeroU64,
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -166,6 +484,135 @@ impl Display for CachingStrategy {
     }
 }
 
+#[derive(Debug, Copy, Clone, Default)]
+pub enum Backend {
+    #[default]
+    Auto,
+    IoUring,
+    Epoll,
+}
+
+impl From<&Backend> for Str {
+    fn from(value: &Backend) -> Self {
+        match value {
+            Backend::Auto => Str::from("Auto"),
+            Backend::IoUring => Str::from("IoUring"),
+            Backend::Epoll => Str::from("Epoll"),
+        }
+    }
+}
+
+impl ValueEnum for Backend {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Auto, Self::IoUring, Self::Epoll]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self))
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Auto => f.write_str("Auto"),
+            Backend::IoUring => f.write_str("IoUring"),
+            Backend::Epoll => f.write_str("Epoll"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub enum StreamFormat {
+    /// Raw 8 bit RGBA
+    Rgba,
+    /// Raw 8 bit BGRA
+    Bgra,
+    /// YUV4MPEG2 with 8 bit 420, 422, 444 or mono samples
+    #[default]
+    Y4m,
+}
+
+impl From<&StreamFormat> for Str {
+    fn from(value: &StreamFormat) -> Self {
+        match value {
+            StreamFormat::Rgba => Str::from("Rgba"),
+            StreamFormat::Bgra => Str::from("Bgra"),
+            StreamFormat::Y4m => Str::from("Y4m"),
+        }
+    }
+}
+
+impl ValueEnum for StreamFormat {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Self::Rgba, Self::Bgra, Self::Y4m]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self))
+    }
+}
+
+impl Display for StreamFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamFormat::Rgba => f.write_str("Rgba"),
+            StreamFormat::Bgra => f.write_str("Bgra"),
+            StreamFormat::Y4m => f.write_str("Y4m"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub enum ConnectionAssignment {
+    /// Move on to the next media object after every write
+    #[default]
+    RoundRobin,
+    /// Split connections between media objects by weight, every connection sticks to its object
+    Dedicated,
+    /// Pick media objects proportionally to their visible pixel count
+    Pixels,
+    /// Pick media objects proportionally to their weight
+    Weighted,
+}
+
+impl From<&ConnectionAssignment> for Str {
+    fn from(value: &ConnectionAssignment) -> Self {
+        match value {
+            ConnectionAssignment::RoundRobin => Str::from("RoundRobin"),
+            ConnectionAssignment::Dedicated => Str::from("Dedicated"),
+            ConnectionAssignment::Pixels => Str::from("Pixels"),
+            ConnectionAssignment::Weighted => Str::from("Weighted"),
+        }
+    }
+}
+
+impl ValueEnum for ConnectionAssignment {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self::RoundRobin,
+            Self::Dedicated,
+            Self::Pixels,
+            Self::Weighted,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(PossibleValue::new(self))
+    }
+}
+
+impl Display for ConnectionAssignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionAssignment::RoundRobin => f.write_str("RoundRobin"),
+            ConnectionAssignment::Dedicated => f.write_str("Dedicated"),
+            ConnectionAssignment::Pixels => f.write_str("Pixels"),
+            ConnectionAssignment::Weighted => f.write_str("Weighted"),
+        }
+    }
+}
+
 #[derive(clap::Args, Debug, Clone)]
 pub struct Media {
     #[command(flatten)]
@@ -174,16 +621,97 @@ pub struct Media {
     #[arg(long = "caching", default_value_t)]
     pub caching_strategy: CachingStrategy,
 
+    /// Decode animations while fluting and keep at most this many frames ahead instead of decoding all frames up front,
+    /// not with --caching KeepAllLazy
+    #[arg(long, value_name = "FRAMES")]
+    pub look_ahead: Option<NonZeroUsize>,
+
+    /// Reload media files and playlists when they or the files they list change, keeping the connections open
+    #[arg(long)]
+    pub watch: bool,
+
+    /// Largest download of a media object given as URL in MiB
+    #[arg(long, value_name = "MIB", default_value_t = 64)]
+    pub max_download: u64,
+
+    /// Time limit for downloading a media object given as URL in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 10)]
+    pub download_timeout: u64,
+
+    /// Directory downloads are cached in by their ETag
+    /// (default: $XDG_CACHE_HOME/tsunami or ~/.cache/tsunami)
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Alpha-blend all media objects into one scene in z order and only send visible pixels,
+    /// all media objects need the same draw strategy and weights are ignored
+    #[arg(long)]
+    pub composite: bool,
+
     #[arg(num_args = 1.., value_parser = clap::value_parser ! (MediaDescription), help = r"Media objects to flut
     
 MEDIA_OBJECTS: <MEDIA_OBJECT>[ <MEDIA_OBJECT>…]
-MEDIA_OBJECT : <path to file>[:<OFFSET>[:<DRAW_STRATEGY>]]
+MEDIA_OBJECT : <path to image, animation, SVG, directory or M3U playlist, or http(s) URL>[:<OFFSET>[:<DRAW_STRATEGY>]][:<OPTION>…]
+               (URLs end before the offset and options, a port is kept)
 OFFSET:        <x>:<y>  (default: 0:0)
 DRAW_STRATEGY: random   (random pixel order, default)
                up       (draw pixels from bottom to top)
                down     (draw pixels from top to bottom)
                left     (draw pixels from right to left)
-               right    (draw pixels from left to right)")]
+               right    (draw pixels from left to right)
+OPTION:        assign=roundrobin | dedicated | pixels | weighted
+                           (assignment policy of this object instead of --assignment,
+                            dedicated objects get connections of their own, the others share the rest)
+               weight=<n>  (share of connections or writes for Dedicated and Weighted, default: 1)
+               z=<n>       (layer of the media object with --composite, higher layers are drawn on top,
+                            equal layers in the given order, default: 0)
+               alpha=skip[,<threshold>] | opaque | pass
+                           (leave out pixels with alpha below the threshold, default: skip,1,
+                            send every pixel opaque, or send the alpha of translucent pixels,
+                            with --composite it decides how the layer covers lower ones)
+               brightness=<-1..1>, contrast=<factor>, saturation=<factor>, gamma=<g>, hue=<degrees>
+               invert=true, grayscale=true
+               key=<RRGGBB>[,<tolerance>]
+                           (make a color transparent, tolerance 0..1, default: 0.1)
+                           (color options apply before all others)
+               crop=<x>,<y>,<w>x<h>  (cut out a region)
+               rotate=<degrees>    (clockwise, multiples of 90 are exact)
+               flip=h | v          (mirror horizontally or vertically)
+                           (crop, rotate and flip apply in the given order, before scaling)
+               scale=<w>x<h> | <n>% | contain | cover | stretch
+                           (resize every frame, fit modes use the canvas right and below the offset,
+                            SVGs are rendered at the scaled size, also when cropped, rotated or flipped)
+               filter=nearest | linear | cubic | gaussian | lanczos
+                           (scaling filter, nearest for pixel art, default: lanczos)
+               palette=bw | gray4 | cga | pico8 | <path to GPL or hex list>
+                           (map colors to a palette, applies after all others)
+               dither=none | floyd | bayer
+                           (dithering for palette, bayer stays stable in animations, default: none)
+               tile=canvas | <w>x<h>
+                           (repeat the media object across the canvas right and below the offset or an area,
+                            all tiles share the decoded frames, applies after all others)
+               spacing=<px>[,<py>]  (gap between tiles, default: 0)
+               phase=<ms>  (how far every tile runs behind the previous one, default: 0)
+               refresh=<seconds>
+                           (download a URL again at an interval, e.g. webcam snapshots,
+                            and reload it when it changed)
+               duration=<seconds>
+                           (display time of playlist entries without #EXTINF,
+                            default: one cycle for animations, 10 for still images)
+               transition=none | crossfade | wipe[,<ms>]
+                           (between playlist entries, default duration: 500)
+               shuffle=true, loop=false
+                           (random playlist order, stay on the last entry instead of starting over)
+               move=<x>,<y>,<ms>[/<x>,<y>,<ms>…]
+                           (move the top left corner between keyframes, the first one at 0 ms,
+                            starts over after the last one, positions may be negative)
+               easing=linear | in | out | in-out  (speed curve between keyframes, default: linear)
+               bounce=<px/s>,<px/s>[,<x>,<y>,<w>x<h>]
+                           (move with a speed and bounce off the edges of the canvas or an area)
+               circle=<cx>,<cy>,<radius>,<ms>
+               lissajous=<cx>,<cy>,<ax>,<ay>,<a>,<b>,<ms>
+                           (move the center along a circle or Lissajous figure, one cycle per <ms>)
+                           (moving media objects ignore the offset)")]
     pub media_objects: Vec<MediaDescription>,
 }
 
@@ -193,34 +721,439 @@ pub struct MediaDescription {
     pub y: u16,
     pub path: PathBuf,
     pub draw_strategy: DrawStrategy,
+    pub assign: Option<ConnectionAssignment>,
+    pub weight: NonZeroU64,
+    pub z: i32,
+    pub alpha: AlphaPolicy,
+    pub color: ColorAdjustment,
+    pub transforms: Vec<Transform>,
+    pub scale: Option<Scale>,
+    pub filter: FilterType,
+    pub palette: Option<Palette>,
+    pub dithering: Dithering,
+    pub tile: Option<TileArea>,
+    pub spacing: (u16, u16),
+    pub phase: Duration,
+    pub refresh: Option<Duration>,
+    pub duration: Option<Duration>,
+    pub playlist: PlaylistOptions,
+    pub motion: Option<Motion>,
+    pub easing: Easing,
+}
+
+/// Movement of a media object
+#[derive(Debug, Clone)]
+pub enum Motion {
+    Keyframes(Vec<Keyframe>),
+    /// Bounces inside the canvas without an area
+    Bounce {
+        speed: (f32, f32),
+        area: Option<(i32, i32, u16, u16)>,
+    },
+    Lissajous {
+        center: (f32, f32),
+        amplitude: (f32, f32),
+        frequency: (u32, u32),
+        period: Duration,
+    },
+}
+
+impl MediaDescription {
+    /// Position of the media object over time
+    pub fn motion_path(&self, canvas_size: (u16, u16)) -> MotionPath {
+        match &self.motion {
+            None => MotionPath::Fixed(self.x as i32, self.y as i32),
+            Some(Motion::Keyframes(keyframes)) => MotionPath::Keyframes {
+                keyframes: keyframes.clone(),
+                easing: self.easing,
+            },
+            Some(Motion::Bounce { speed, area }) => MotionPath::Bounce {
+                area: area.unwrap_or((0, 0, canvas_size.0, canvas_size.1)),
+                speed: *speed,
+            },
+            Some(Motion::Lissajous {
+                center,
+                amplitude,
+                frequency,
+                period,
+            }) => MotionPath::Lissajous {
+                center: *center,
+                amplitude: *amplitude,
+                frequency: *frequency,
+                period: *period,
+            },
+        }
+    }
+}
+
+/// Area covered by a tiled media object
+#[derive(Debug, Copy, Clone)]
+pub enum TileArea {
+    /// The canvas right and below the offset
+    Canvas,
+    Size(u16, u16),
+}
+
+impl Media {
+    /// Checks the options that depend on several media objects, before anything is opened
+    pub fn validate(&self) -> eyre::Result<()> {
+        // frames shown while the decoder is behind would be cached in place of the decoded ones
+        if self.look_ahead.is_some()
+            && matches!(self.caching_strategy, CachingStrategy::KeepAllLazy)
+        {
+            return Err(eyre::eyre!(
+                "--look-ahead decodes frames while fluting, it can not be cached with KeepAllLazy"
+            ));
+        }
+
+        if self.max_download.checked_mul(1 << 20).is_none() {
+            return Err(eyre::eyre!(
+                "--max-download of {} MiB is more than fits into 64 bits",
+                self.max_download
+            ));
+        }
+
+        if self.composite {
+            let first = &self.media_objects[0];
+            if let Some(desc) = self
+                .media_objects
+                .iter()
+                .find(|desc| desc.draw_strategy != first.draw_strategy)
+            {
+                return Err(eyre::eyre!(
+                    "--composite draws a single scene, {} is drawn {} but {} {}",
+                    first.path.display(),
+                    first.draw_strategy,
+                    desc.path.display(),
+                    desc.draw_strategy
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// How media objects given as URL are downloaded
+    pub fn download(&self) -> Download {
+        Download {
+            max_size: self.max_download.saturating_mul(1 << 20),
+            timeout: Duration::from_secs(self.download_timeout),
+            cache: self.cache_dir.clone().or_else(remote::default_cache),
+        }
+    }
 }
 
 impl FromStr for MediaDescription {
     type Err = eyre::Error;
 
     fn from_str(s: &str) -> eyre::Result<Self> {
-        let splits = s.split(':').collect::<Vec<_>>();
+        let (url, s) = match remote::split_url(s) {
+            Some((url, rest)) => (Some(url), rest),
+            None => (None, s),
+        };
+        let (mut splits, options): (Vec<_>, Vec<_>) =
+            s.split(':').partition(|split| !split.contains('='));
+        if let Some(url) = url {
+            // the rest starts with ':' or is empty, both leave an empty split in place of the path
+            splits[0] = url;
+        }
 
-        match splits.as_slice() {
-            [path] => Ok(Self {
+        let mut desc = match splits.as_slice() {
+            [path, ..] => Self {
                 x: 0,
                 y: 0,
                 path: PathBuf::from(path),
                 draw_strategy: DrawStrategy::Random,
-            }),
-            [path, x, y] => Ok(Self {
-                x: u16::from_str(x)?,
-                y: u16::from_str(y)?,
-                path: PathBuf::from(path),
-                draw_strategy: DrawStrategy::Random,
-            }),
-            [path, x, y, strategy] => Ok(Self {
+                assign: None,
+                weight: NonZeroU64::MIN,
+                z: 0,
+                alpha: AlphaPolicy::default(),
+                color: ColorAdjustment::default(),
+                transforms: vec![],
+                scale: None,
+                filter: FilterType::Lanczos3,
+                palette: None,
+                dithering: Dithering::None,
+                tile: None,
+                spacing: (0, 0),
+                phase: Duration::ZERO,
+                refresh: None,
+                duration: None,
+                playlist: PlaylistOptions::default(),
+                motion: None,
+                easing: Easing::Linear,
+            },
+            _ => return Err(eyre::eyre!("unable to parse media object: {s}")),
+        };
+
+        match &splits[1..] {
+            [] => {}
+            [x, y] => {
+                desc.x = u16::from_str(x)?;
+                desc.y = u16::from_str(y)?;
+            }
+            [x, y, strategy] => {
+                desc.x = u16::from_str(x)?;
+                desc.y = u16::from_str(y)?;
+                desc.draw_strategy = DrawStrategy::from_str(strategy)?;
+            }
+            _ => return Err(eyre::eyre!("unable to parse media object: {s}")),
+        }
+
+        for option in options {
+            match option.split_once('=') {
+                Some(("assign", policy)) => desc.assign = Some(parse_assignment(policy)?),
+                Some(("weight", weight)) => desc.weight = NonZeroU64::from_str(weight)?,
+                Some(("z", z)) => desc.z = i32::from_str(z)?,
+                Some(("alpha", alpha)) => desc.alpha = AlphaPolicy::from_str(alpha)?,
+                Some(("brightness", v)) => desc.color.brightness = f32::from_str(v)?,
+                Some(("contrast", v)) => desc.color.contrast = f32::from_str(v)?,
+                Some(("saturation", v)) => desc.color.saturation = f32::from_str(v)?,
+                Some(("gamma", v)) => desc.color.gamma = f32::from_str(v)?,
+                Some(("hue", v)) => desc.color.hue = f32::from_str(v)?,
+                Some(("invert", v)) => desc.color.invert = bool::from_str(v)?,
+                Some(("grayscale", v)) => desc.color.grayscale = bool::from_str(v)?,
+                Some(("key", key)) => desc.color.chroma_key = Some(parse_key(key)?),
+                Some(("crop", crop)) => desc.transforms.push(parse_crop(crop)?),
+                Some(("rotate", degrees)) => desc
+                    .transforms
+                    .push(Transform::rotation(f32::from_str(degrees)?)?),
+                Some(("flip", "h")) => desc.transforms.push(Transform::FlipHorizontal),
+                Some(("flip", "v")) => desc.transforms.push(Transform::FlipVertical),
+                Some(("scale", scale)) => desc.scale = Some(parse_scale(scale)?),
+                Some(("filter", filter)) => desc.filter = parse_filter(filter)?,
+                Some(("palette", palette)) => desc.palette = Some(parse_palette(palette)?),
+                Some(("dither", dithering)) => desc.dithering = parse_dithering(dithering)?,
+                Some(("tile", "canvas")) => desc.tile = Some(TileArea::Canvas),
+                Some(("tile", size)) => {
+                    let CanvasSize(w, h) = CanvasSize::from_str(size)
+                        .map_err(|_| eyre::eyre!("invalid tiled area: \"{size}\""))?;
+                    desc.tile = Some(TileArea::Size(w.get(), h.get()));
+                }
+                Some(("spacing", spacing)) => desc.spacing = parse_spacing(spacing)?,
+                Some(("phase", ms)) => desc.phase = Duration::from_millis(u64::from_str(ms)?),
+                Some(("refresh", seconds)) => {
+                    desc.refresh = Some(
+                        Duration::try_from_secs_f64(f64::from_str(seconds)?)
+                            .ok()
+                            .filter(|refresh| !refresh.is_zero())
+                            .ok_or_else(|| {
+                                eyre::eyre!("invalid refresh interval: \"{seconds}\"")
+                            })?,
+                    )
+                }
+                Some(("duration", seconds)) => {
+                    desc.duration = Some(
+                        Duration::try_from_secs_f64(f64::from_str(seconds)?)
+                            .map_err(|_| eyre::eyre!("invalid duration: \"{seconds}\""))?,
+                    )
+                }
+                Some(("transition", transition)) => {
+                    desc.playlist.transition = parse_transition(transition)?
+                }
+                Some(("shuffle", v)) => desc.playlist.shuffle = bool::from_str(v)?,
+                Some(("loop", v)) => desc.playlist.looping = bool::from_str(v)?,
+                Some(("move", keyframes)) => desc.motion = Some(parse_keyframes(keyframes)?),
+                Some(("easing", easing)) => desc.easing = parse_easing(easing)?,
+                Some(("bounce", bounce)) => desc.motion = Some(parse_bounce(bounce)?),
+                Some(("circle", circle)) => desc.motion = Some(parse_circle(circle)?),
+                Some(("lissajous", lissajous)) => desc.motion = Some(parse_lissajous(lissajous)?),
+                _ => return Err(eyre::eyre!("invalid media object option: \"{option}\"")),
+            }
+        }
+
+        Ok(desc)
+    }
+}
+
+fn parse_key(s: &str) -> eyre::Result<ChromaKey> {
+    let (color, tolerance) = match s.split_once(',') {
+        Some((color, tolerance)) => (color, f32::from_str(tolerance)?),
+        None => (s, 0.1),
+    };
+    let Color([r, g, b, _]) = Color::from_str(color)?;
+
+    Ok(ChromaKey {
+        color: [r, g, b],
+        tolerance,
+    })
+}
+
+fn parse_crop(s: &str) -> eyre::Result<Transform> {
+    match s.split(',').collect::<Vec<_>>().as_slice() {
+        [x, y, size] => {
+            let CanvasSize(w, h) = CanvasSize::from_str(size)
+                .map_err(|_| eyre::eyre!("invalid crop size: \"{size}\""))?;
+            Ok(Transform::Crop {
                 x: u16::from_str(x)?,
                 y: u16::from_str(y)?,
-                path: PathBuf::from(path),
-                draw_strategy: DrawStrategy::from_str(strategy)?,
-            }),
-            _ => Err(eyre::eyre!("unable to parse media object: {s}")),
+                w: w.get(),
+                h: h.get(),
+            })
+        }
+        _ => Err(eyre::eyre!("invalid crop: \"{s}\"")),
+    }
+}
+
+fn parse_scale(s: &str) -> eyre::Result<Scale> {
+    Ok(match s {
+        "contain" => Scale::Contain,
+        "cover" => Scale::Cover,
+        "stretch" => Scale::Stretch,
+        _ => match s.strip_suffix('%') {
+            Some(percent) => Scale::Percent(f32::from_str(percent)?),
+            None => {
+                let CanvasSize(w, h) =
+                    CanvasSize::from_str(s).map_err(|_| eyre::eyre!("invalid scale: \"{s}\""))?;
+                Scale::Size(w.get(), h.get())
+            }
+        },
+    })
+}
+
+fn parse_assignment(s: &str) -> eyre::Result<ConnectionAssignment> {
+    Ok(match s {
+        "roundrobin" => ConnectionAssignment::RoundRobin,
+        "dedicated" => ConnectionAssignment::Dedicated,
+        "pixels" => ConnectionAssignment::Pixels,
+        "weighted" => ConnectionAssignment::Weighted,
+        _ => return Err(eyre::eyre!("invalid assignment policy: \"{s}\"")),
+    })
+}
+
+fn parse_filter(s: &str) -> eyre::Result<FilterType> {
+    Ok(match s {
+        "nearest" => FilterType::Nearest,
+        "linear" => FilterType::Triangle,
+        "cubic" => FilterType::CatmullRom,
+        "gaussian" => FilterType::Gaussian,
+        "lanczos" => FilterType::Lanczos3,
+        _ => return Err(eyre::eyre!("invalid scaling filter: \"{s}\"")),
+    })
+}
+
+fn parse_spacing(s: &str) -> eyre::Result<(u16, u16)> {
+    Ok(match s.split_once(',') {
+        Some((x, y)) => (u16::from_str(x)?, u16::from_str(y)?),
+        None => (u16::from_str(s)?, u16::from_str(s)?),
+    })
+}
+
+fn parse_palette(s: &str) -> eyre::Result<Palette> {
+    match Palette::preset(s) {
+        Some(palette) => Ok(palette),
+        None => Palette::open(s).map_err(|e| eyre::eyre!("invalid palette \"{s}\": {e}")),
+    }
+}
+
+fn parse_dithering(s: &str) -> eyre::Result<Dithering> {
+    Ok(match s {
+        "none" => Dithering::None,
+        "floyd" => Dithering::FloydSteinberg,
+        "bayer" => Dithering::Bayer,
+        _ => return Err(eyre::eyre!("invalid dithering: \"{s}\"")),
+    })
+}
+
+fn parse_transition(s: &str) -> eyre::Result<Transition> {
+    let (kind, duration) = match s.split_once(',') {
+        Some((kind, ms)) => (kind, Duration::from_millis(u64::from_str(ms)?)),
+        None => (s, Duration::from_millis(500)),
+    };
+    Ok(match kind {
+        "none" => Transition::Cut,
+        "crossfade" => Transition::Crossfade(duration),
+        "wipe" => Transition::Wipe(duration),
+        _ => return Err(eyre::eyre!("invalid transition: \"{s}\"")),
+    })
+}
+
+fn parse_keyframes(s: &str) -> eyre::Result<Motion> {
+    let keyframes = s
+        .split('/')
+        .map(
+            |keyframe| match keyframe.split(',').collect::<Vec<_>>().as_slice() {
+                [x, y, ms] => Ok(Keyframe {
+                    time: Duration::from_millis(u64::from_str(ms)?),
+                    position: (i32::from_str(x)?, i32::from_str(y)?),
+                }),
+                _ => Err(eyre::eyre!("invalid keyframe: \"{keyframe}\"")),
+            },
+        )
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    MotionPath::Keyframes {
+        keyframes: keyframes.clone(),
+        easing: Easing::Linear,
+    }
+    .validate()?;
+    Ok(Motion::Keyframes(keyframes))
+}
+
+fn parse_easing(s: &str) -> eyre::Result<Easing> {
+    Ok(match s {
+        "linear" => Easing::Linear,
+        "in" => Easing::In,
+        "out" => Easing::Out,
+        "in-out" => Easing::InOut,
+        _ => return Err(eyre::eyre!("invalid easing: \"{s}\"")),
+    })
+}
+
+fn parse_bounce(s: &str) -> eyre::Result<Motion> {
+    let (speed, area) = match s.split(',').collect::<Vec<_>>().as_slice() {
+        [sx, sy] => ((f32::from_str(sx)?, f32::from_str(sy)?), None),
+        [sx, sy, x, y, size] => {
+            let CanvasSize(w, h) = CanvasSize::from_str(size)
+                .map_err(|_| eyre::eyre!("invalid bounce area: \"{size}\""))?;
+            (
+                (f32::from_str(sx)?, f32::from_str(sy)?),
+                Some((i32::from_str(x)?, i32::from_str(y)?, w.get(), h.get())),
+            )
+        }
+        _ => return Err(eyre::eyre!("invalid bounce: \"{s}\"")),
+    };
+
+    MotionPath::Bounce {
+        area: (0, 0, 0, 0),
+        speed,
+    }
+    .validate()?;
+    Ok(Motion::Bounce { speed, area })
+}
+
+fn parse_circle(s: &str) -> eyre::Result<Motion> {
+    match s.split(',').collect::<Vec<_>>().as_slice() {
+        [cx, cy, radius, ms] => {
+            let radius = f32::from_str(radius)?;
+            parse_lissajous(&format!("{cx},{cy},{radius},{radius},1,1,{ms}"))
+        }
+        _ => Err(eyre::eyre!("invalid circle: \"{s}\"")),
+    }
+}
+
+fn parse_lissajous(s: &str) -> eyre::Result<Motion> {
+    match s.split(',').collect::<Vec<_>>().as_slice() {
+        [cx, cy, ax, ay, a, b, ms] => {
+            let (center, amplitude, frequency, period) = (
+                (f32::from_str(cx)?, f32::from_str(cy)?),
+                (f32::from_str(ax)?, f32::from_str(ay)?),
+                (u32::from_str(a)?, u32::from_str(b)?),
+                Duration::from_millis(u64::from_str(ms)?),
+            );
+            MotionPath::Lissajous {
+                center,
+                amplitude,
+                frequency,
+                period,
+            }
+            .validate()?;
+            Ok(Motion::Lissajous {
+                center,
+                amplitude,
+                frequency,
+                period,
+            })
         }
+        _ => Err(eyre::eyre!("invalid lissajous: \"{s}\"")),
     }
 }
@@ -1,26 +1,56 @@
-use std::io::{Read, Write};
-use std::net::{TcpStream, ToSocketAddrs};
-use std::num::NonZeroU32;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
 use std::ops::Add;
+use std::path::Path;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
 use clap::Parser;
-use tracing::{debug, error, info, Level};
+use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::EnvFilter;
 
+use epizentrum::alpha_policy::AlphaPolicy;
+use epizentrum::assignment::{Assignment, AssignmentPolicy, SourceWeights};
+use epizentrum::draw_strategy::DrawStrategy;
+use epizentrum::flut_op::epoll::EpollLoop;
+use epizentrum::flut_op::sink::SinkLoop;
 use epizentrum::flut_op::FlutOp;
 use epizentrum::frame_processing::gpu_processor::GpuProcessor;
 use epizentrum::frame_processing::rayon_processor::RayonProcessor;
 use epizentrum::frame_processing::FrameProcessor;
-use epizentrum::frame_source::media_source::MediaSource;
+use epizentrum::frame_source::color_source::{ColorAdjustment, ColorSource};
+use epizentrum::frame_source::expression_source::ExpressionSource;
+use epizentrum::frame_source::generator_source::{self, Animation, GeneratorSource};
+use epizentrum::frame_source::media_source::remote::{self, Download, Refresh};
+use epizentrum::frame_source::media_source::{MediaSource, MediaSourceError, StreamingMediaSource};
+use epizentrum::frame_source::playlist_source::{self, PlaylistEntry, PlaylistSource};
+use epizentrum::frame_source::quantized_source::QuantizedSource;
+use epizentrum::frame_source::scaled_source::ScaledSource;
+use epizentrum::frame_source::scene_source::{Layer, SceneSource};
+use epizentrum::frame_source::stream_source::{self, StreamInput, StreamSource};
+use epizentrum::frame_source::svg_source::{self, SvgSource};
+use epizentrum::frame_source::text_source::{Font, TextMode, TextSource, TextStyle};
+use epizentrum::frame_source::tiled_source::{TiledSource, Tiling};
+use epizentrum::frame_source::transformed_source::TransformedSource;
+#[cfg(feature = "video")]
+use epizentrum::frame_source::video_source::VideoSource;
 use epizentrum::frame_source::FrameSource;
+use epizentrum::motion_path::MotionPath;
+use epizentrum::precompiled::{self, PrecompiledShow};
+use epizentrum::reload::{FileWatcher, Reload, ReloadingSource, Trigger};
 use epizentrum::{
     tsunami_ring, CommandBufferSource, CompositeBufferSource, ComputeOnceCache, ControlFlowError,
     SetupError, SingleFrameCache, TeardownError,
 };
 
-use crate::cli::{CachingStrategy, CanvasSize, Commands, GpuMode};
+use strand::Server;
+
+use crate::cli::{
+    Args, Backend, CachingStrategy, CanvasSize, Commands, ConnectionAssignment, Generate, GpuMode,
+    GpuPreference, Media, MediaDescription, Pattern, Stream, StreamFormat, Text, TileArea,
+};
 
 mod cli;
 
@@ -73,6 +103,859 @@ fn get_size(stream: &mut TcpStream) -> eyre::Result<(u16, u16)> {
     }
 }
 
+fn visible_pixels(size: (u16, u16), offset: (u16, u16), canvas_size: (u16, u16)) -> NonZeroU64 {
+    let w = size.0.min(canvas_size.0.saturating_sub(offset.0)) as u64;
+    let h = size.1.min(canvas_size.1.saturating_sub(offset.1)) as u64;
+    NonZeroU64::new(w * h).unwrap_or(NonZeroU64::MIN)
+}
+
+/// Pixels of a media object visible on the canvas, moving objects count as if they were at the origin
+fn visible_media_pixels(
+    size: (u16, u16),
+    desc: &MediaDescription,
+    canvas_size: (u16, u16),
+) -> NonZeroU64 {
+    match desc.motion {
+        None => visible_pixels(size, (desc.x, desc.y), canvas_size),
+        Some(_) => visible_pixels(size, (0, 0), canvas_size),
+    }
+}
+
+fn targets(args: &Args) -> eyre::Result<Vec<SocketAddr>> {
+    if args.output.is_some() {
+        if !args.target_hosts.is_empty() {
+            warn!("--output is set, targets are ignored");
+        }
+        return Ok(vec![]);
+    }
+
+    args.target_hosts
+        .iter()
+        .map(|host| {
+            if let Ok(iter) = host.to_socket_addrs() {
+                Ok(iter)
+            } else if let Ok(iter) = format!("{host}:1337").to_socket_addrs() {
+                Ok(iter)
+            } else if let Ok(iter) = format!("[{host}]:1337").to_socket_addrs() {
+                Ok(iter)
+            } else if let Ok(iter) = format!("{host}:1234").to_socket_addrs() {
+                Ok(iter)
+            } else if let Ok(iter) = format!("[{host}]:1234").to_socket_addrs() {
+                Ok(iter)
+            } else {
+                Err(eyre::eyre!("invalid host: {host}"))
+            }
+        })
+        .collect::<eyre::Result<Vec<_>>>()
+        .map(|v| v.into_iter().flatten().collect::<Vec<_>>())
+}
+
+/// Returns the canvas size and the connection used to ask for it
+fn canvas_size(
+    args: &Args,
+    targets: &[SocketAddr],
+) -> eyre::Result<((u16, u16), Option<TcpStream>)> {
+    if let Some(CanvasSize(x, y)) = &args.canvas_size {
+        return Ok(((x.get(), y.get()), None));
+    }
+
+    match targets
+        .iter()
+        .filter_map(|addr| {
+            match TcpStream::connect(addr)
+                .map_err(eyre::Error::from)
+                .and_then(|mut socket| Ok((get_size(&mut socket)?, socket)))
+            {
+                Ok((size, socket)) => {
+                    info!("Canvas size: {}x{}", size.0, size.1);
+                    Some((size, socket))
+                }
+                Err(e) => {
+                    debug!("unable to request canvas size via \"{addr}\": {e:?}");
+                    None
+                }
+            }
+        })
+        .next()
+    {
+        None => {
+            error!("unable to get canvas size");
+            Err(eyre::eyre!("unable to get canvas size"))
+        }
+        Some((size, socket)) => Ok((size, Some(socket))),
+    }
+}
+
+fn processor(
+    gpu_preference: &GpuPreference,
+    size: (u16, u16),
+    canvas_size: (u16, u16),
+    draw_strategy: DrawStrategy,
+    alpha: AlphaPolicy,
+    skipped: Option<&[bool]>,
+) -> eyre::Result<Box<dyn FrameProcessor>> {
+    Ok(match gpu_preference.gpu_mode {
+        GpuMode::None => Box::new(RayonProcessor::new(
+            size,
+            canvas_size,
+            draw_strategy,
+            alpha,
+            skipped,
+        )),
+        GpuMode::Preferred | GpuMode::Required => {
+            let devices = GpuProcessor::devices();
+
+            let proc = devices
+                .iter()
+                .find_map(|(index, info)| {
+                    match GpuProcessor::new(
+                        *index,
+                        size,
+                        canvas_size,
+                        draw_strategy,
+                        alpha,
+                        skipped,
+                    ) {
+                        Ok(proc) => {
+                            info!("using GPU {index}");
+                            if let Some(info) = info {
+                                debug!("GPU Info: {info:#?}");
+                            }
+                            Some(proc)
+                        }
+                        Err(e) => {
+                            debug!("unable to use GPU {index}: {e}");
+                            None
+                        }
+                    }
+                })
+                .map(|proc| Box::new(proc) as Box<dyn FrameProcessor>);
+
+            if matches!(gpu_preference.gpu_mode, GpuMode::Required) && proc.is_none() {
+                error!("no GPU available");
+                return Err(eyre::eyre!("no GPU available"));
+            }
+
+            proc.unwrap_or(Box::new(RayonProcessor::new(
+                size,
+                canvas_size,
+                draw_strategy,
+                alpha,
+                skipped,
+            )))
+        }
+    })
+}
+
+fn pipeline<Src: FrameSource + 'static>(
+    source: Src,
+    processor: Box<dyn FrameProcessor>,
+    path: MotionPath,
+    caching_strategy: CachingStrategy,
+) -> Box<dyn CommandBufferSource> {
+    let pipeline = CompositeBufferSource {
+        source,
+        processor,
+        path,
+    };
+
+    match caching_strategy {
+        CachingStrategy::None => Box::new(pipeline),
+        CachingStrategy::KeepAllLazy => Box::new(ComputeOnceCache::new(pipeline)),
+        CachingStrategy::KeepLast => Box::new(SingleFrameCache::new(pipeline)),
+    }
+}
+
+/// An opened media object, vector graphics can be rendered again at the size they are shown at
+enum Opened {
+    Raster(Box<dyn FrameSource>),
+    Vector(SvgSource),
+}
+
+impl Opened {
+    fn into_source(self) -> Box<dyn FrameSource> {
+        match self {
+            Opened::Raster(source) => source,
+            Opened::Vector(source) => Box::new(source),
+        }
+    }
+}
+
+/// Opens an image, animation or SVG, files or URLs the `image` crate does not know are opened as video
+fn open_media(
+    path: &Path,
+    video: bool,
+    look_ahead: Option<NonZeroUsize>,
+    download: &Download,
+) -> eyre::Result<Opened> {
+    if !video {
+        let data = match remote::is_url(path) {
+            true => Some(download.fetch(&path.to_string_lossy())?),
+            false => None,
+        };
+        let source = match (&data, look_ahead) {
+            (Some(data), None) => {
+                MediaSource::from_memory(data.clone()).map(|s| Box::new(s) as Box<dyn FrameSource>)
+            }
+            (Some(data), Some(look_ahead)) => {
+                StreamingMediaSource::from_memory(data.clone(), look_ahead)
+                    .map(|s| Box::new(s) as Box<dyn FrameSource>)
+            }
+            (None, None) => MediaSource::new(path).map(|s| Box::new(s) as Box<dyn FrameSource>),
+            (None, Some(look_ahead)) => StreamingMediaSource::new(path, look_ahead)
+                .map(|s| Box::new(s) as Box<dyn FrameSource>),
+        };
+
+        match source {
+            Ok(source) => return Ok(Opened::Raster(source)),
+            Err(MediaSourceError::Format) => {
+                // only the start is read, unknown files may be large videos
+                let mut head = Vec::with_capacity(4096);
+                match &data {
+                    Some(data) => head.extend_from_slice(&data[..data.len().min(4096)]),
+                    None => {
+                        File::open(path)?.take(4096).read_to_end(&mut head)?;
+                    }
+                }
+
+                if svg_source::is_svg(&head) {
+                    let source = match data {
+                        Some(data) => SvgSource::new(&data)?,
+                        None => SvgSource::new(&std::fs::read(path)?)?,
+                    };
+                    return Ok(Opened::Vector(source));
+                }
+                if !cfg!(feature = "video") {
+                    return Err(MediaSourceError::Format.into());
+                }
+                debug!("{} is no image, trying to open it as video", path.display())
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    open_video(path).map(Opened::Raster)
+}
+
+/// Opens the entries of a directory or M3U playlist, entries that fail to open are left out
+fn open_playlist(
+    desc: &MediaDescription,
+    video: bool,
+    look_ahead: Option<NonZeroUsize>,
+    download: &Download,
+) -> eyre::Result<Box<dyn FrameSource>> {
+    let entries = playlist_source::read_playlist(&desc.path)?
+        .into_iter()
+        .filter_map(
+            |(path, duration)| match open_media(&path, video, look_ahead, download) {
+                Ok(opened) => Some(PlaylistEntry {
+                    source: opened.into_source(),
+                    duration: duration.or(desc.duration),
+                }),
+                Err(e) => {
+                    warn!("leaving {} out of the playlist: {e}", path.display());
+                    None
+                }
+            },
+        )
+        .collect();
+
+    Ok(Box::new(PlaylistSource::new(entries, desc.playlist)?))
+}
+
+#[cfg(feature = "video")]
+fn open_video(path: &Path) -> eyre::Result<Box<dyn FrameSource>> {
+    Ok(Box::new(VideoSource::new(path)?))
+}
+
+#[cfg(not(feature = "video"))]
+fn open_video(_path: &Path) -> eyre::Result<Box<dyn FrameSource>> {
+    Err(eyre::eyre!("tsunami was built without video support"))
+}
+
+/// The canvas right and below the offset of a media object
+fn area(desc: &MediaDescription, canvas_size: (u16, u16)) -> (u16, u16) {
+    (
+        canvas_size.0.saturating_sub(desc.x),
+        canvas_size.1.saturating_sub(desc.y),
+    )
+}
+
+/// Applies the color, transform, scale, palette and tile options of a media object
+///
+/// `scaled` sources already have the size of the scale option and are not scaled again.
+fn transformed(
+    source: Box<dyn FrameSource>,
+    desc: &MediaDescription,
+    canvas_size: (u16, u16),
+    scaled: bool,
+) -> eyre::Result<Box<dyn FrameSource>> {
+    let source = match desc.color == ColorAdjustment::default() {
+        true => source,
+        false => Box::new(ColorSource::new(source, desc.color)?),
+    };
+    let source = match desc.transforms.is_empty() {
+        true => source,
+        false => Box::new(TransformedSource::new(source, &desc.transforms)?),
+    };
+    let source = match desc.scale {
+        Some(scale) if !scaled => Box::new(ScaledSource::new(
+            source,
+            scale,
+            area(desc, canvas_size),
+            desc.filter,
+        )?),
+        _ => source,
+    };
+
+    let source = match &desc.palette {
+        None => source,
+        Some(palette) => Box::new(QuantizedSource::new(
+            source,
+            palette.clone(),
+            desc.dithering,
+        )),
+    };
+
+    let size = match desc.tile {
+        None => return Ok(source),
+        Some(TileArea::Canvas) => area(desc, canvas_size),
+        Some(TileArea::Size(w, h)) => (w, h),
+    };
+    let tiling = Tiling {
+        size,
+        spacing: desc.spacing,
+        phase: desc.phase,
+    };
+    Ok(Box::new(TiledSource::new(source, tiling)?))
+}
+
+/// Opens a media object with its options applied
+fn open_layer(
+    media: &Media,
+    desc: &MediaDescription,
+    canvas_size: (u16, u16),
+    video: bool,
+) -> eyre::Result<Box<dyn FrameSource>> {
+    if desc.tile.is_some() && !desc.phase.is_zero() && media.look_ahead.is_some() {
+        warn!(
+            "{} is tiled with a phase, --look-ahead decodes it again for every tile",
+            desc.path.display()
+        );
+    }
+
+    let download = media.download();
+    if playlist_source::is_playlist(&desc.path) {
+        let source = open_playlist(desc, video, media.look_ahead, &download)?;
+        return transformed(source, desc, canvas_size, false);
+    }
+
+    // SVGs are rendered at the scaled size instead of scaling pixels
+    match (
+        open_media(&desc.path, video, media.look_ahead, &download)?,
+        desc.scale,
+    ) {
+        (Opened::Vector(mut source), Some(scale)) if desc.transforms.is_empty() => {
+            source.rasterize(scale, area(desc, canvas_size))?;
+            transformed(Box::new(source), desc, canvas_size, true)
+        }
+        // transforms come first, the result is only scaled down to the exact size
+        (Opened::Vector(mut source), Some(scale)) => {
+            let transforms =
+                source.rasterize_transformed(scale, area(desc, canvas_size), &desc.transforms)?;
+            let desc = MediaDescription {
+                transforms,
+                ..desc.clone()
+            };
+            transformed(Box::new(source), &desc, canvas_size, false)
+        }
+        (opened, _) => transformed(opened.into_source(), desc, canvas_size, false),
+    }
+}
+
+/// Opens a media object and builds its command buffer source
+fn media_source(
+    media: &Media,
+    desc: &MediaDescription,
+    canvas_size: (u16, u16),
+    caching_strategy: CachingStrategy,
+    video: bool,
+) -> eyre::Result<(Box<dyn CommandBufferSource>, SourceWeights)> {
+    let mut source = open_layer(media, desc, canvas_size, video)?;
+    // streamed and video frames are decoded once, they are only dropped per frame
+    let skipped =
+        (!video && media.look_ahead.is_none()).then(|| desc.alpha.skipped_pixels(&mut source));
+    let processor = processor(
+        &media.gpu_preference,
+        source.size(),
+        canvas_size,
+        desc.draw_strategy,
+        desc.alpha,
+        skipped.as_deref(),
+    )?;
+
+    let weights = SourceWeights {
+        pixels: visible_media_pixels(source.size(), desc, canvas_size),
+        weight: desc.weight,
+        policy: desc.assign.map(assignment_policy),
+    };
+    let path = desc.motion_path(canvas_size);
+    path.validate()?;
+    if matches!(caching_strategy, CachingStrategy::KeepAllLazy) && path.endless(source.size()) {
+        warn!(
+            "{} never starts over, KeepAllLazy caches a command buffer for every step of its motion",
+            desc.path.display()
+        );
+    }
+    Ok((pipeline(source, processor, path, caching_strategy), weights))
+}
+
+/// Blends all media objects into one scene and builds its command buffer source
+fn scene_source(
+    media: &Media,
+    canvas_size: (u16, u16),
+    caching_strategy: CachingStrategy,
+    video: bool,
+) -> eyre::Result<(Box<dyn CommandBufferSource>, SourceWeights)> {
+    let mut descs = media.media_objects.iter().collect::<Vec<_>>();
+    descs.sort_by_key(|desc| desc.z);
+    let layers = descs
+        .into_iter()
+        .map(|desc| {
+            let path = desc.motion_path(canvas_size);
+            path.validate()?;
+            Ok(Layer {
+                source: open_layer(media, desc, canvas_size, video)?,
+                path,
+                alpha: desc.alpha,
+            })
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let moves = layers
+        .iter()
+        .any(|layer| layer.path.moves(layer.source.size()));
+    let endless = layers
+        .iter()
+        .any(|layer| layer.path.endless(layer.source.size()));
+    if matches!(caching_strategy, CachingStrategy::KeepAllLazy) && endless {
+        warn!("the scene never starts over, KeepAllLazy caches a command buffer for every step of its motion");
+    }
+
+    let mut source = SceneSource::new(layers, canvas_size)?;
+    let alpha = AlphaPolicy::default();
+    // moving layers change the scene every motion step, walking all of them can take forever
+    let skipped =
+        (!video && media.look_ahead.is_none() && !moves).then(|| alpha.skipped_pixels(&mut source));
+    let processor = processor(
+        &media.gpu_preference,
+        source.size(),
+        canvas_size,
+        media.media_objects[0].draw_strategy,
+        alpha,
+        skipped.as_deref(),
+    )?;
+
+    let weights = SourceWeights {
+        pixels: visible_pixels(source.size(), source.offset(), canvas_size),
+        weight: NonZeroU64::MIN,
+        policy: None,
+    };
+    let (x, y) = source.offset();
+    let path = MotionPath::Fixed(x as i32, y as i32);
+    Ok((pipeline(source, processor, path, caching_strategy), weights))
+}
+
+/// What rebuilds a media object: changes of its file with --watch or downloading its URL again
+///
+/// Playlists also rebuild when the files or URLs they list change, directory playlists
+/// when a file in the directory is added, removed or written.
+fn reload_triggers(desc: &MediaDescription, media: &Media) -> eyre::Result<Vec<Box<dyn Trigger>>> {
+    let url = remote::is_url(&desc.path);
+    // entries of M3U playlists can be anywhere, directories are watched as a whole
+    let entries = match playlist_source::is_playlist(&desc.path) && !desc.path.is_dir() {
+        true => playlist_source::read_playlist(&desc.path)?,
+        false => vec![],
+    };
+    let (urls, files): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .map(|(entry, _)| entry)
+        .partition(|entry| remote::is_url(entry));
+
+    let mut triggers: Vec<Box<dyn Trigger>> = vec![];
+    match (url, desc.refresh) {
+        (true, Some(interval)) => triggers.push(Box::new(Refresh::new(
+            media.download(),
+            &desc.path.to_string_lossy(),
+            interval,
+        ))),
+        (true, None) if media.watch => warn!(
+            "--watch has no effect on {}, refresh=<seconds> downloads it again",
+            desc.path.display()
+        ),
+        (false, Some(interval)) if !urls.is_empty() => {
+            for entry in &urls {
+                triggers.push(Box::new(Refresh::new(
+                    media.download(),
+                    &entry.to_string_lossy(),
+                    interval,
+                )));
+            }
+        }
+        (false, Some(_)) => warn!(
+            "refresh only applies to URLs, --watch reloads {} when it changes",
+            desc.path.display()
+        ),
+        _ => {}
+    }
+
+    if media.watch && !url {
+        let mut watcher = FileWatcher::new(&desc.path)?;
+        for file in &files {
+            watcher.add(file)?;
+        }
+        triggers.push(Box::new(watcher));
+    }
+    Ok(triggers)
+}
+
+/// Builds the sources of all media objects, with `reloading` they are rebuilt when their files or URLs change
+fn media_sources(
+    media: &Media,
+    canvas_size: (u16, u16),
+    caching_strategy: CachingStrategy,
+    video: bool,
+    reloading: bool,
+) -> eyre::Result<Vec<(Box<dyn CommandBufferSource>, SourceWeights)>> {
+    if media.composite {
+        if media
+            .media_objects
+            .iter()
+            .any(|desc| desc.weight != NonZeroU64::MIN || desc.assign.is_some())
+        {
+            warn!("--composite sends the scene as a single media object, weights and assign= are ignored");
+        }
+
+        let (source, weights) = scene_source(media, canvas_size, caching_strategy, video)?;
+        if !reloading {
+            return Ok(vec![(source, weights)]);
+        }
+
+        // any changed file or URL rebuilds the whole scene
+        let mut triggers = vec![];
+        for desc in &media.media_objects {
+            triggers.extend(reload_triggers(desc, media)?);
+        }
+        if triggers.is_empty() {
+            return Ok(vec![(source, weights)]);
+        }
+        let media = media.clone();
+        let reload: Reload = Box::new(move || {
+            scene_source(&media, canvas_size, caching_strategy, video)
+                .map(|(source, _)| source)
+                .map_err(Into::into)
+        });
+        let source = ReloadingSource::new(source, reload, triggers)?;
+        return Ok(vec![(Box::new(source), weights)]);
+    }
+
+    media
+        .media_objects
+        .iter()
+        .map(|desc| {
+            let (source, weights) =
+                media_source(media, desc, canvas_size, caching_strategy, video)?;
+            if !reloading {
+                return Ok((source, weights));
+            }
+            let triggers = reload_triggers(desc, media)?;
+            if triggers.is_empty() {
+                return Ok((source, weights));
+            }
+
+            // the canvas share of a media object stays as it was at startup
+            let (media, desc) = (media.clone(), desc.clone());
+            let reload: Reload = Box::new(move || {
+                media_source(&media, &desc, canvas_size, caching_strategy, video)
+                    .map(|(source, _)| source)
+                    .map_err(Into::into)
+            });
+            let source = ReloadingSource::new(source, reload, triggers)?;
+            Ok((Box::new(source) as Box<dyn CommandBufferSource>, weights))
+        })
+        .collect()
+}
+
+fn stream_source(
+    stream: &Stream,
+    canvas_size: (u16, u16),
+) -> eyre::Result<(Box<dyn CommandBufferSource>, SourceWeights)> {
+    let desc = &stream.input;
+    let input = match (stream.unix, desc.path.to_str()) {
+        (true, _) => StreamInput::Unix(desc.path.clone()),
+        (false, Some("-")) => StreamInput::Stdin,
+        (false, _) => StreamInput::Path(desc.path.clone()),
+    };
+
+    if !(stream.fps.is_finite() && stream.fps > 0.0) {
+        return Err(eyre::eyre!("invalid frame rate: {}", stream.fps));
+    }
+    let frame_time = Duration::from_secs_f64(1.0 / stream.fps);
+    let format = match (stream.format, stream.size) {
+        (StreamFormat::Y4m, _) => stream_source::StreamFormat::Y4m,
+        (StreamFormat::Rgba, Some(CanvasSize(w, h))) => stream_source::StreamFormat::Rgba {
+            size: (w.get(), h.get()),
+            frame_time,
+        },
+        (StreamFormat::Bgra, Some(CanvasSize(w, h))) => stream_source::StreamFormat::Bgra {
+            size: (w.get(), h.get()),
+            frame_time,
+        },
+        (format, None) => return Err(eyre::eyre!("{format} streams need --size")),
+    };
+
+    let source = transformed(
+        Box::new(StreamSource::new(input, format)?),
+        desc,
+        canvas_size,
+        false,
+    )?;
+    let processor = processor(
+        &stream.gpu_preference,
+        source.size(),
+        canvas_size,
+        desc.draw_strategy,
+        desc.alpha,
+        None,
+    )?;
+
+    let weights = SourceWeights {
+        pixels: visible_media_pixels(source.size(), desc, canvas_size),
+        weight: desc.weight,
+        policy: desc.assign.map(assignment_policy),
+    };
+    let path = desc.motion_path(canvas_size);
+    path.validate()?;
+    Ok((
+        pipeline(source, processor, path, CachingStrategy::KeepLast),
+        weights,
+    ))
+}
+
+fn text_source(
+    text: &Text,
+    canvas_size: (u16, u16),
+) -> eyre::Result<(Box<dyn CommandBufferSource>, SourceWeights)> {
+    let font = match &text.font {
+        Some(path) => Font::open(path)?,
+        None => Font::Bitmap,
+    };
+    let style = TextStyle {
+        size: text.size,
+        foreground: text.color.0,
+        background: text.background.0,
+        outline: text.outline.map(|color| (color.0, text.outline_width)),
+    };
+    let mode = match text.marquee {
+        None => TextMode::Static,
+        Some(width) => TextMode::Marquee {
+            width: width.get(),
+            speed: text.speed,
+        },
+    };
+
+    let mut source = TextSource::new(&text.text, &font, style, mode)?;
+    let offset = (text.placement.x, text.placement.y);
+    let skipped = text.placement.alpha.skipped_pixels(&mut source);
+    let processor = processor(
+        &text.gpu_preference,
+        source.size(),
+        canvas_size,
+        text.placement.draw_strategy,
+        text.placement.alpha,
+        Some(&skipped),
+    )?;
+
+    let weights = SourceWeights {
+        pixels: visible_pixels(source.size(), offset, canvas_size),
+        weight: NonZeroU64::MIN,
+        policy: None,
+    };
+    Ok((
+        pipeline(
+            source,
+            processor,
+            MotionPath::Fixed(offset.0 as i32, offset.1 as i32),
+            CachingStrategy::KeepLast,
+        ),
+        weights,
+    ))
+}
+
+fn generator_source(
+    generate: &Generate,
+    canvas_size: (u16, u16),
+) -> eyre::Result<(Box<dyn CommandBufferSource>, SourceWeights)> {
+    let offset = (generate.placement.x, generate.placement.y);
+    let size = match generate.size {
+        Some(CanvasSize(w, h)) => (w.get(), h.get()),
+        None => (
+            canvas_size.0.saturating_sub(offset.0),
+            canvas_size.1.saturating_sub(offset.1),
+        ),
+    };
+
+    // formulas using t never start over, patterns do after one animation cycle
+    let (mut source, animated, endless): (Box<dyn FrameSource>, bool, bool) =
+        match (&generate.expr, generate.pattern) {
+            (Some(program), _) => {
+                let source = ExpressionSource::new(size, program.clone(), generate.fps)?;
+                let animated = source.animated();
+                (Box::new(source), animated, animated)
+            }
+            (None, Some(pattern)) => {
+                let animation = (generate.speed != 0.0).then_some(Animation {
+                    speed: generate.speed,
+                    frame_rate: generate.fps,
+                });
+                let source =
+                    GeneratorSource::new(size, generator_pattern(generate, pattern), animation)?;
+                let animated = source.animated();
+                (Box::new(source), animated, false)
+            }
+            (None, None) => unreachable!("clap requires a pattern without --expr"),
+        };
+
+    // animations are rendered while fluting, not all of their frames up front
+    let skipped = (!animated).then(|| generate.placement.alpha.skipped_pixels(&mut source));
+    if endless && matches!(generate.caching_strategy, CachingStrategy::KeepAllLazy) {
+        warn!("the formula never starts over, KeepAllLazy caches a command buffer for every frame");
+    }
+    let processor = processor(
+        &generate.gpu_preference,
+        source.size(),
+        canvas_size,
+        generate.placement.draw_strategy,
+        generate.placement.alpha,
+        skipped.as_deref(),
+    )?;
+
+    let weights = SourceWeights {
+        pixels: visible_pixels(source.size(), offset, canvas_size),
+        weight: NonZeroU64::MIN,
+        policy: None,
+    };
+    Ok((
+        pipeline(
+            source,
+            processor,
+            MotionPath::Fixed(offset.0 as i32, offset.1 as i32),
+            generate.caching_strategy,
+        ),
+        weights,
+    ))
+}
+
+fn generator_pattern(generate: &Generate, pattern: Pattern) -> generator_source::Pattern {
+    let (color, to) = (generate.color.0, generate.to.0);
+    match pattern {
+        Pattern::Fill => generator_source::Pattern::Fill(color),
+        Pattern::Linear => generator_source::Pattern::LinearGradient {
+            from: color,
+            to,
+            angle: generate.angle,
+        },
+        Pattern::Radial => generator_source::Pattern::RadialGradient {
+            inner: color,
+            outer: to,
+        },
+        Pattern::Checkerboard => generator_source::Pattern::Checkerboard {
+            cell: generate.cell,
+            colors: [color, to],
+        },
+        Pattern::Test => generator_source::Pattern::TestPattern {
+            grid: generate.cell,
+        },
+        Pattern::Plasma => generator_source::Pattern::Plasma,
+        Pattern::Noise => generator_source::Pattern::Noise {
+            seed: generate.seed,
+        },
+    }
+}
+
+fn assignment_policy(assignment: ConnectionAssignment) -> AssignmentPolicy {
+    match assignment {
+        ConnectionAssignment::RoundRobin => AssignmentPolicy::RoundRobin,
+        ConnectionAssignment::Dedicated => AssignmentPolicy::Dedicated,
+        ConnectionAssignment::Pixels => AssignmentPolicy::Pixels,
+        ConnectionAssignment::Weighted => AssignmentPolicy::Weighted,
+    }
+}
+
+fn flut(
+    args: &Args,
+    targets: &[SocketAddr],
+    init_connection: Option<TcpStream>,
+    sources: Vec<(Box<dyn CommandBufferSource>, SourceWeights)>,
+) -> eyre::Result<()> {
+    let (sources, weights): (Vec<_>, Vec<_>) = sources.into_iter().unzip();
+
+    let flut_op = FlutOp::new(
+        targets,
+        Some(args.interfaces.as_slice()),
+        sources.into_boxed_slice(),
+        args.max_connections,
+        args.reconnect_backoff_limit
+            .map(|s| Duration::from_secs(s.get())),
+        args.reconnects.map(|r| r.get()),
+        init_connection.into_iter().collect(),
+        Instant::now().add(match args.time_offset {
+            n if n > 0 => Duration::from_secs(n as u64),
+            n => Duration::from_secs(-n as u64),
+        }),
+        Assignment::from_weights(assignment_policy(args.assignment), &weights),
+    );
+
+    if let Some(output) = &args.output {
+        let output: Box<dyn Write> = match output.to_str() {
+            Some("-") => Box::new(std::io::stdout().lock()),
+            _ => Box::new(File::create(output)?),
+        };
+
+        let mut sink = SinkLoop::new(
+            flut_op,
+            BufWriter::new(output),
+            args.cycles,
+            args.duration.map(|s| Duration::from_secs(s.get())),
+        );
+        return Ok(sink.run()?);
+    }
+
+    let ring = match args.backend {
+        Backend::Auto => match tsunami_ring::Ring::new_raw_ring(NonZeroU32::new(128).unwrap()) {
+            Ok(ring) => Some(ring),
+            Err(e) => {
+                warn!("io_uring is unavailable, falling back to epoll: {e}");
+                None
+            }
+        },
+        Backend::IoUring => Some(tsunami_ring::Ring::new_raw_ring(
+            NonZeroU32::new(128).unwrap(),
+        )?),
+        Backend::Epoll => None,
+    };
+
+    match ring {
+        Some(ring) => {
+            let mut ring = tsunami_ring::Ring::new(ring, None, flut_op);
+            ring.run::<SetupError, ControlFlowError, TeardownError>()?;
+        }
+        None => EpollLoop::new(flut_op)?.run()?,
+    }
+
+    Ok(())
+}
+
 fn setup_logging() -> eyre::Result<()> {
     if cfg!(debug_assertions) {
         let filter = EnvFilter::builder()
@@ -81,6 +964,7 @@ fn setup_logging() -> eyre::Result<()> {
 
         let subscriber = tracing_subscriber::fmt()
             .with_env_filter(filter)
+            .with_writer(std::io::stderr)
             .compact()
             //.with_file(true)
             .with_line_number(true)
@@ -95,6 +979,7 @@ fn setup_logging() -> eyre::Result<()> {
 
         let subscriber = tracing_subscriber::fmt()
             .with_env_filter(filter)
+            .with_writer(std::io::stderr)
             .compact()
             .with_target(false)
             .with_thread_names(true)
@@ -113,148 +998,108 @@ fn main() -> eyre::Result<()> {
     match &args.command {
         Commands::Gpus => GpuProcessor::list_devices(),
         Commands::Media(media) => {
-            let targets = args
-                .target_hosts
-                .iter()
-                .map(|host| {
-                    if let Ok(iter) = host.to_socket_addrs() {
-                        Ok(iter)
-                    } else if let Ok(iter) = format!("{host}:1337").to_socket_addrs() {
-                        Ok(iter)
-                    } else if let Ok(iter) = format!("[{host}]:1337").to_socket_addrs() {
-                        Ok(iter)
-                    } else if let Ok(iter) = format!("{host}:1234").to_socket_addrs() {
-                        Ok(iter)
-                    } else if let Ok(iter) = format!("[{host}]:1234").to_socket_addrs() {
-                        Ok(iter)
-                    } else {
-                        Err(eyre::eyre!("invalid host: {host}"))
-                    }
-                })
-                .collect::<eyre::Result<Vec<_>>>()
-                .map(|v| v.into_iter().flatten().collect::<Vec<_>>())?;
-
-            let mut init_connection = None;
-            let canvas_size = match &args.canvas_size {
-                None => {
-                    let (size, socket) = match targets
-                        .iter()
-                        .filter_map(|addr| {
-                            match TcpStream::connect(addr)
-                                .map_err(eyre::Error::from)
-                                .and_then(|mut socket| Ok((get_size(&mut socket)?, socket)))
-                            {
-                                Ok((size, socket)) => {
-                                    info!("Canvas size: {}x{}", size.0, size.1);
-                                    Some((size, socket))
-                                }
-                                Err(e) => {
-                                    debug!("unable to request canvas size via \"{addr}\": {e:?}");
-                                    None
-                                }
-                            }
-                        })
-                        .next()
-                    {
-                        None => {
-                            error!("unable to get canvas size");
-                            return Err(eyre::eyre!("unable to get canvas size"));
-                        }
-                        Some(v) => v,
-                    };
+            media.validate()?;
+            let targets = targets(&args)?;
+            let (canvas_size, init_connection) = canvas_size(&args, &targets)?;
+            let sources = media_sources(media, canvas_size, media.caching_strategy, false, true)?;
 
-                    init_connection = Some(socket);
-                    size
-                }
-                Some(CanvasSize(x, y)) => (x.get(), y.get()),
-            };
+            flut(&args, &targets, init_connection, sources)?;
+        }
+        #[cfg(feature = "video")]
+        Commands::Video(media) => {
+            media.validate()?;
+            let targets = targets(&args)?;
+            let (canvas_size, init_connection) = canvas_size(&args, &targets)?;
+            let sources = media_sources(media, canvas_size, media.caching_strategy, true, true)?;
 
-            let sources = media
+            flut(&args, &targets, init_connection, sources)?;
+        }
+        Commands::Compile(compile) => {
+            compile.media.validate()?;
+            let targets = targets(&args)?;
+            let (canvas_size, _) = canvas_size(&args, &targets)?;
+            if compile.media.watch {
+                warn!("--watch has no effect on compiled shows");
+            }
+            if compile
+                .media
                 .media_objects
                 .iter()
-                .map(|desc| {
-                    let source = MediaSource::new(&desc.path)?;
-                    let processor: Box<dyn FrameProcessor> = match media.gpu_preference.gpu_mode {
-                        GpuMode::None => Box::new(RayonProcessor::new(
-                            source.size(),
-                            (desc.x, desc.y),
-                            canvas_size,
-                            desc.draw_strategy,
-                        )),
-                        GpuMode::Preferred | GpuMode::Required => {
-                            let devices = GpuProcessor::devices();
-
-                            let proc = devices
-                                .iter()
-                                .find_map(|(index, info)| {
-                                    match GpuProcessor::new(
-                                        *index,
-                                        source.size(),
-                                        (desc.x, desc.y),
-                                        canvas_size,
-                                        desc.draw_strategy,
-                                    ) {
-                                        Ok(proc) => {
-                                            info!("using GPU {index}");
-                                            if let Some(info) = info {
-                                                debug!("GPU Info: {info:#?}");
-                                            }
-                                            Some(proc)
-                                        }
-                                        Err(e) => {
-                                            debug!("unable to use GPU {index}: {e}");
-                                            None
-                                        }
-                                    }
-                                })
-                                .map(|proc| Box::new(proc) as Box<dyn FrameProcessor>);
-
-                            if matches!(media.gpu_preference.gpu_mode, GpuMode::Required)
-                                && proc.is_none()
-                            {
-                                error!("no GPU available");
-                                return Err(eyre::eyre!("no GPU available"));
-                            }
-
-                            proc.unwrap_or(Box::new(RayonProcessor::new(
-                                source.size(),
-                                (desc.x, desc.y),
-                                canvas_size,
-                                desc.draw_strategy,
-                            )))
-                        }
-                    };
+                .any(|desc| desc.refresh.is_some())
+            {
+                warn!("refresh has no effect on compiled shows");
+            }
+            let mut sources = media_sources(
+                &compile.media,
+                canvas_size,
+                CachingStrategy::None,
+                false,
+                false,
+            )?;
 
-                    let pipeline = CompositeBufferSource { source, processor };
+            let mut file = BufWriter::new(File::create(&compile.file)?);
+            precompiled::compile(&mut file, canvas_size, &mut sources)?;
+            file.flush()?;
+        }
+        Commands::Play(play) => {
+            let targets = targets(&args)?;
+            let show = PrecompiledShow::open(&play.file)?;
+            let canvas_size = show.canvas_size();
+            info!("Canvas size: {}x{}", canvas_size.0, canvas_size.1);
+            if let Some(CanvasSize(x, y)) = &args.canvas_size {
+                if (x.get(), y.get()) != canvas_size {
+                    warn!(
+                        "{} was compiled for a different canvas size",
+                        play.file.display()
+                    );
+                }
+            }
 
-                    Ok(match media.caching_strategy {
-                        CachingStrategy::None => Box::new(pipeline) as Box<dyn CommandBufferSource>,
-                        CachingStrategy::KeepAllLazy => Box::new(ComputeOnceCache::new(pipeline)),
-                        CachingStrategy::KeepLast => Box::new(SingleFrameCache::new(pipeline)),
-                    })
+            let sources = show
+                .into_sources()
+                .into_iter()
+                .map(|(source, weights)| {
+                    (Box::new(source) as Box<dyn CommandBufferSource>, weights)
                 })
-                .collect::<eyre::Result<Vec<_>>>()?;
-
-            let ring = tsunami_ring::Ring::new_raw_ring(NonZeroU32::new(128).unwrap())?;
-            let flut_op = FlutOp::new(
-                targets.as_slice(),
-                Some(args.interfaces.as_slice()),
-                sources.into_boxed_slice(),
-                args.max_connections,
-                args.reconnect_backoff_limit
-                    .map(|s| Duration::from_secs(s.get())),
-                args.reconnects.map(|r| r.get()),
-                match init_connection {
-                    Some(c) => vec![c],
-                    None => vec![],
-                },
-                Instant::now().add(match args.time_offset {
-                    n if n > 0 => Duration::from_secs(n as u64),
-                    n => Duration::from_secs(-n as u64),
-                }),
-            );
-            let mut ring = tsunami_ring::Ring::new(ring, None, flut_op);
-            ring.run::<SetupError, ControlFlowError, TeardownError>()?;
+                .collect();
+            flut(&args, &targets, None, sources)?;
+        }
+        Commands::Stream(stream) => {
+            let targets = targets(&args)?;
+            let (canvas_size, init_connection) = canvas_size(&args, &targets)?;
+            let sources = vec![stream_source(stream, canvas_size)?];
+
+            flut(&args, &targets, init_connection, sources)?;
+        }
+        Commands::Text(text) => {
+            let targets = targets(&args)?;
+            let (canvas_size, init_connection) = canvas_size(&args, &targets)?;
+            let sources = vec![text_source(text, canvas_size)?];
+
+            flut(&args, &targets, init_connection, sources)?;
+        }
+        Commands::Generate(generate) => {
+            let targets = targets(&args)?;
+            let (canvas_size, init_connection) = canvas_size(&args, &targets)?;
+            let sources = vec![generator_source(generate, canvas_size)?];
+
+            flut(&args, &targets, init_connection, sources)?;
+        }
+        Commands::Serve(serve) => {
+            let server = Server::bind(&serve.listen, (serve.size.0.get(), serve.size.1.get()))?;
+            info!("listening on {}", server.local_addr()?);
+
+            let stats = server.stats();
+            let interval = Duration::from_secs(serve.report_interval.get());
+            std::thread::spawn(move || strand::report(&stats, interval));
+
+            if let Some(path) = serve.snapshot.clone() {
+                let canvas = server.canvas();
+                let interval = Duration::from_secs(serve.snapshot_interval.get());
+                std::thread::spawn(move || strand::snapshots(&canvas, &path, interval));
+            }
+
+            server.run()?;
         }
     }
 